@@ -41,6 +41,43 @@ pub enum PrinterErrorCode {
     FileReadError,
     /// file system has full capacity
     FileCapacityFull,
+    /// fault reported by the mcu, printer must be restarted
+    McuError,
+    /// job queue has reached its maximum length
+    JobQueueFull,
+    /// no http route matches the request path
+    RouteNotFound,
+    /// the http route does not accept the request method
+    MethodNotAllowed,
+    /// chunked upload does not exist or has expired
+    UploadNotFound,
+    /// chunk out of range, or the assembled file has the wrong size or checksum
+    UploadInvalid,
+    /// a request parameter is missing, malformed or out of range
+    InvalidParameter,
+    /// the axis must be homed before it can be moved
+    NotHomed,
+    /// target temperature is negative or above the max_temp of the heater
+    TemperatureOutOfRange,
+    /// filename is absolute or leaves its directory with '..'
+    PathTraversal,
+    /// a request to another host did not complete within its timeout
+    Timeout,
+}
+
+/// operation an error state originated from
+#[derive(Debug, Default, Serialize, Deserialize, Type, Clone, Copy)]
+pub enum PrinterErrorSource {
+    #[default]
+    None,
+    /// loading or parsing the printer config
+    Config,
+    /// executing gcode
+    Gcode,
+    /// running a print job
+    PrintJob,
+    /// reported by the printer hardware
+    Hardware,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Type, Clone)]
@@ -131,15 +168,49 @@ pub struct PrinterInfo {
     pub error_state_code: PrinterErrorCode,
     /// only used when in error state
     pub error_state_message: String,
+    /// only used when in error state, operation the error originated from
+    pub error_state_source: PrinterErrorSource,
+    /// only used when in error state, unix timestamp of the error
+    pub error_state_timestamp: u64,
     /// path where printer data is stored
     pub printer_path: String,
+    /// path where gcode files are stored
+    pub gcodes_path: String,
+    /// display name, the instance name unless configured
+    pub display_name: String,
+    /// location, empty unless configured
+    pub location: String,
+    /// printer model, empty unless configured
+    pub model: String,
+}
+
+/// compact printer status, the version increases whenever the status changes
+#[derive(Debug, Default, Serialize, Deserialize, Type, Clone)]
+pub struct PrinterStatus {
+    /// monotonic status version, bumped on state, job and temperature changes
+    pub version: u64,
+    /// printer state
+    pub state: PrinterState,
+    /// only used when in error state
+    pub state_message: String,
+    /// a gcode file is running
+    pub gcode_running: bool,
+    /// progress of the running gcode file from 0 to 1
+    pub progress: f64,
+    /// temperature of the active extruder in celsius
+    pub extruder_temperature: f64,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Type)]
 pub struct PrinterTemperatureInfo{
+    /// config section, e.g. 'extruder', 'extruder1', 'heater_bed' or 'chamber'
     pub name: String,
-    pub state: String,
-    pub temperature: f64
+    /// temperature in celsius
+    pub current: f64,
+    /// target in celsius, 0 if off or not a heater
+    pub target: f64,
+    /// heater duty from 0 to 1
+    pub power: f64,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Type)]
@@ -169,6 +240,27 @@ pub struct PrintJobStatus{
     pub layer: u64,
     /// total number of layers
     pub total_layers: u64,
+    /// number of commands completed, a paused job continues from this command
+    pub line: u64,
+    /// unix timestamp in seconds when the job started
+    pub start_timestamp: u64,
+    /// fraction of the estimated duration printed, 0 to 1. 0 if there is no estimate
+    pub progress: f64,
+    /// unix timestamp in seconds the job is estimated to finish at, 0 if there is no estimate
+    pub eta: u64,
+}
+
+/// execution timing statistics of a gcode command
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterGcodeTiming {
+    /// number of times the command ran
+    pub count: u64,
+    /// total time spent in microseconds
+    pub total_us: u64,
+    /// longest run in microseconds
+    pub max_us: u64,
+    /// number of runs exceeding the slow threshold
+    pub slow_count: u64,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Type)]
@@ -185,12 +277,178 @@ pub struct PrinterEndstopStatus {
     pub z_triggered: bool,
 }
 
+/// kind of issue found by the gcode linter
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq)]
+pub enum GcodeLintKind {
+    /// motion before the axes are homed with G28
+    MoveBeforeHome,
+    /// temperature set above the configured max_temp
+    TemperatureAboveMax,
+    /// extrusion before the extruder or bed is heated
+    ExtrudeBeforePreheat,
+    /// absolute or relative mode is never set before use
+    PositioningModeNotSet,
+}
+
+/// warning reported by the gcode linter
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct PrinterGcodeLintWarning {
+    /// line number in file, starting from 1
+    pub line: u64,
+    pub kind: GcodeLintKind,
+    pub message: String,
+}
+
+/// first and last lines of a gcode file
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterGcodeFilePreview {
+    /// first lines, without line endings
+    pub head: Vec<String>,
+    /// last lines, without line endings
+    pub tail: Vec<String>,
+}
+
+/// a move as planned by the trapezoid generator
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterPlannedMove {
+    pub start: PrinterPosition,
+    pub end: PrinterPosition,
+    /// xyz distance in mm, zero for extrusion only moves
+    pub distance: f32,
+    /// mm/s
+    pub start_velocity: f32,
+    /// peak velocity in mm/s
+    pub cruise_velocity: f32,
+    /// mm/s
+    pub end_velocity: f32,
+    /// mm/s^2
+    pub acceleration: f32,
+    /// seconds
+    pub duration: f32,
+}
+
+/// request for an external camera service to take a snapshot
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Type)]
+pub struct PrinterSnapshotRequest {
+    /// name of the printer
+    pub printer: String,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// excludable object defined in a gcode file by EXCLUDE_OBJECT_DEFINE
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterGcodeObject {
+    pub name: String,
+    /// center on the bed in mm
+    pub center_x: f32,
+    pub center_y: f32,
+    /// bounding box of the object polygon in mm
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+/// state of a filament switch sensor
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterFilamentSensor {
+    pub name: String,
+    /// true if filament is present
+    pub filament_detected: bool,
+    /// running print is paused on runout
+    pub pause_on_runout: bool,
+}
+
+/// outcome of a diagnostics check
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Copy, PartialEq)]
+pub enum PrinterDiagnosticStatus {
+    Passed,
+    Failed,
+    /// the check could not run, e.g. motion before the axes are homed
+    Skipped,
+}
+
+/// result of checking one subsystem in a diagnostics run
+#[derive(Debug, Serialize, Deserialize, Type)]
+pub struct PrinterDiagnostic {
+    /// checked subsystem, e.g. "endstops" or "heater extruder"
+    pub subsystem: String,
+    pub status: PrinterDiagnosticStatus,
+    /// reason of a failure or skip, empty if passed
+    pub message: String,
+}
+
+/// toolhead position in mm
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterPosition {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub e: f32,
+}
+
+/// toolhead position and positioning modes
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterMotionState {
+    pub position: PrinterPosition,
+    /// homed axes, e.g. 'xyz' or '' before homing
+    pub homed_axes: String,
+    /// feedrate in mm/s of moves without F, NaN until set
+    pub feedrate: f32,
+    /// G90 or G91
+    pub absolute_position: bool,
+    /// M82 or M83
+    pub absolute_extrusion: bool,
+}
+
+/// motion limits in effect, changed by SET_VELOCITY_LIMIT until restart
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterMotionLimits {
+    /// mm/s
+    pub max_velocity: f32,
+    /// mm/s^2
+    pub max_accel: f32,
+    /// mm/s
+    pub square_corner_velocity: f32,
+    pub minimum_cruise_ratio: f32,
+}
+
+/// range of an axis in mm
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterAxisRange {
+    pub min: f32,
+    /// infinite if position_max is not configured, null in json
+    pub max: f32,
+}
+
+/// reachable volume, from position_min and position_max of the steppers
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterBuildVolume {
+    pub x: PrinterAxisRange,
+    pub y: PrinterAxisRange,
+    pub z: PrinterAxisRange,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Type)]
 pub struct PrinterGcodeFile {
+    /// relative to the gcodes directory
     pub path: String,
+    /// unix timestamp in seconds
     pub modified: u64,
     pub size: u64,
+    /// as shown by ls, e.g. 'rw-r--r--'
     pub permissions: String,
+    /// user tags, lowercase
+    pub tags: Vec<String>,
+}
+
+/// outcome of deleting one file of a bulk delete
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
+pub struct PrinterDeleteFileResult {
+    pub path: String,
+    /// error code is none if the file was deleted
+    pub error: PrinterError,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Type)]
@@ -244,6 +502,27 @@ pub struct JobQueuePrintJob {
     pub filename: String,
     pub estimated_time: String,
     pub thumbnail_path: String,
+    /// who queued the job, empty if not given
+    pub operator: String,
+    /// notes given when queuing the job
+    pub notes: String,
+}
+
+/// a finished print job
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Type)]
+pub struct JobHistoryEntry {
+    pub id: String,
+    pub filename: String,
+    /// 'completed' or 'failed'
+    pub status: String,
+    /// error message of a failed job
+    pub message: String,
+    /// unix timestamp in seconds
+    pub end_time: u64,
+    /// who queued the job, empty if not given
+    pub operator: String,
+    /// notes given when queuing the job
+    pub notes: String,
 }
 
 /// zbus proxy
@@ -277,10 +556,49 @@ pub trait Printer {
     pub async fn emergency_stop(&self, token: &str) -> PrinterResult<()>;
     /// restart gantry
     pub async fn restart(&self, token: &str) -> PrinterResult<()>;
+    /// reload printer config and reset motion state, clears error state
+    pub async fn firmware_restart(&self, token: &str) -> PrinterResult<()>;
+    /// acknowledge a recoverable error and return to ready state
+    pub async fn clear_error(&self, token: &str) -> PrinterResult<()>;
     /// list objects loaded
     pub async fn list_objects(&self, token: &str) -> PrinterResult<HashMap<String, String>>;
+    /// cancel an object of the running gcode
+    pub async fn exclude_object(&self, token: &str, name: &str) -> PrinterResult<()>;
     /// query endstop status
     pub async fn query_endstops(&self, token: &str) -> PrinterResult<PrinterEndstopStatus>;
+    /// current toolhead position, interpolated while a move is executing
+    pub async fn get_position(&self, token: &str) -> PrinterResult<PrinterPosition>;
+    /// motion limits in effect
+    pub async fn get_motion_limits(&self, token: &str) -> PrinterResult<PrinterMotionLimits>;
+    /// build volume from the configured stepper ranges
+    pub async fn get_build_volume(&self, token: &str) -> PrinterResult<PrinterBuildVolume>;
+    /// check endstops, heaters and motion, refused while a job is running
+    pub async fn run_diagnostics(&self, token: &str) -> PrinterResult<Vec<PrinterDiagnostic>>;
+    /// list filament switch sensors
+    pub async fn list_filament_sensors(
+        &self,
+        token: &str,
+    ) -> PrinterResult<Vec<PrinterFilamentSensor>>;
+    /// move the toolhead by a distance in mm at 'speed' mm/s, moved axes must be homed
+    pub async fn jog(
+        &self,
+        token: &str,
+        x: f32,
+        y: f32,
+        z: f32,
+        speed: f32,
+    ) -> PrinterResult<()>;
+    /// home the given axes, all axes if none is given
+    pub async fn home(&self, token: &str, x: bool, y: bool, z: bool) -> PrinterResult<()>;
+    /// set the state of a filament switch sensor, pauses the running print on runout
+    pub async fn set_filament_sensor(
+        &self,
+        token: &str,
+        name: &str,
+        filament_detected: bool,
+    ) -> PrinterResult<()>;
+    /// request a snapshot from an external camera service, returns immediately
+    pub async fn snapshot(&self, token: &str) -> PrinterResult<()>;
 
     /////////////////////////////////////////////
     ///////////       Extensions      ///////////
@@ -312,8 +630,15 @@ pub trait Printer {
 
     /// execute a gcode script
     pub async fn run_gcode(&self, token: &str, script: String) -> PrinterResult<()>;
+    /// execute a gcode script, returns the responses of the commands, e.g. the position for M114
+    pub async fn run_gcode_response(&self, token: &str, script: String) -> PrinterResult<String>;
     /// Retrieves a list of registered GCode Command Descriptions.
     pub async fn get_gcode_help(&self, token: &str) -> PrinterResult<HashMap<String, String>>;
+    /// Retrieves execution timing statistics of gcode commands.
+    pub async fn get_gcode_timings(
+        &self,
+        token: &str,
+    ) -> PrinterResult<HashMap<String, PrinterGcodeTiming>>;
 
     /////////////////////////////////////////////
     ///////////       Print job       ///////////
@@ -325,16 +650,21 @@ pub trait Printer {
     pub async fn pause_print_job(&self, token: &str) -> PrinterResult<()>;
     /// resume the print job
     pub async fn resume_print_job(&self, token: &str) -> PrinterResult<()>;
+    /// continue a print interrupted by power loss from its saved position
+    pub async fn resume_interrupted(&self, token: &str) -> PrinterResult<StartPrintJobResult>;
     /// cancel the print job
     pub async fn cancel_print_job(&self, token: &str) -> PrinterResult<()>;
     /// get print job status
     pub async fn get_print_job_status(&self, token: &str) -> PrinterResult<PrintJobStatus>;
 
-    /// queue print job to run after current print job is finished
+    /// queue print job to run after current print job is finished.
+    /// empty operator or notes are not recorded
     pub async fn queue_print_job(
         &self,
         token: &str,
         filename: &str,
+        operator: &str,
+        notes: &str,
     ) -> PrinterResult<PrinterQueuePrintJob>;
     //// delete a print job in queue
     pub async fn delete_queue_print_job(&self, token: &str, id: &str) -> PrinterResult<()>;
@@ -344,6 +674,8 @@ pub trait Printer {
     pub async fn resume_job_queue(&self, token: &str) -> PrinterResult<()>;
     /// get a list of jobs in job queue
     pub async fn list_job_queue(&self, token: &str) -> PrinterResult<Vec<JobQueuePrintJob>>;
+    /// get finished jobs, oldest first
+    pub async fn list_history(&self, token: &str) -> PrinterResult<Vec<JobHistoryEntry>>;
 
     /////////////////////////////////////////////
     ///////////      Gcode files      ///////////
@@ -351,18 +683,73 @@ pub trait Printer {
 
     /// list avaliable gcode files
     pub async fn list_files(&self, token: &str) -> PrinterResult<Vec<PrinterGcodeFile>>;
+    /// replace the tags of a gcode file
+    pub async fn tag_file(&self, token: &str, filename: &str, tags: Vec<String>) -> PrinterResult<Vec<String>>;
+    /// list gcode files carrying the tag whose path contains the query
+    pub async fn search_files(&self, token: &str, tag: &str, query: &str) -> PrinterResult<Vec<PrinterGcodeFile>>;
     /// get metadata for a specified gcode file
     pub async fn get_file_metadata(&self, token: &str, filename: &str) -> PrinterResult<PrinterGcodeFileMetadata>;
     /// Initiate a metadata scan for a selected file. If the file has already been scanned the endpoint will force a re-scan.
     pub async fn scan_file_metadata(&self, token: &str, filename: &str) -> PrinterResult<()>;
-    /// upload a gcode file
+    /// check a gcode file for common slicer mistakes
+    pub async fn lint_gcode_file(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeLintWarning>>;
+    /// list the excludable objects defined in a gcode file
+    pub async fn list_file_objects(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeObject>>;
+    /// first 'head' and last 'tail' lines of a gcode file
+    pub async fn get_file_preview(
+        &self,
+        token: &str,
+        filename: &str,
+        head: u32,
+        tail: u32,
+    ) -> PrinterResult<PrinterGcodeFilePreview>;
+    /// run a gcode file through the planner in simulation, the printer does not move
+    pub async fn get_move_plan(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterPlannedMove>>;
+    /// upload a gcode file, 'filedata' is the base64 encoded file
     pub async fn upload_file(
         &self,
         token: &str,
         filename: &str,
         filedata: String,
     ) -> PrinterResult<()>;
-    /// download a gcode file
+    /// start a chunked upload of a gcode file, returns the upload id.
+    /// 'sha256' is the hex checksum of the file, empty to skip the check
+    pub async fn upload_init(
+        &self,
+        token: &str,
+        filename: &str,
+        size: u64,
+        sha256: &str,
+    ) -> PrinterResult<String>;
+    /// write a chunk of a chunked upload, returns the number of bytes received
+    pub async fn upload_chunk(
+        &self,
+        token: &str,
+        upload_id: &str,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> PrinterResult<u64>;
+    /// check and store a chunked upload once all chunks are received
+    pub async fn upload_finalize(&self, token: &str, upload_id: &str) -> PrinterResult<()>;
+    /// delete gcode files, the file being printed is not deleted
+    pub async fn delete_files(
+        &self,
+        token: &str,
+        filenames: Vec<String>,
+    ) -> PrinterResult<Vec<PrinterDeleteFileResult>>;
+    /// download a gcode file, the file is returned base64 encoded
     pub async fn download_file(&self, token: &str, filename: &str) -> PrinterResult<String>;
     /// download the printer config
     pub async fn download_printer_config(&self, token: &str) -> PrinterResult<String>;
@@ -0,0 +1,33 @@
+//! parser throughput over the bundled benchmark file, run with 'cargo bench'
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+// gantry is a binary crate, the parser is included directly
+#[allow(dead_code)]
+#[path = "../src/gcode/parser.rs"]
+mod parser;
+
+use parser::GcodeFile;
+
+const BENCHY: &str = include_str!("../tests/OrcaBenchy.gcode");
+
+fn parse(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("gcode_parser");
+    group.throughput(Throughput::Bytes(BENCHY.len() as u64));
+
+    group.bench_function("blocking_parse", |b| {
+        b.iter(|| GcodeFile::blocking_parse(BENCHY).unwrap())
+    });
+
+    group.bench_function("async_parse", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { GcodeFile::async_parse(BENCHY.as_bytes()).await.unwrap() })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,188 @@
+//! allocations of the gcode vm per command, run with 'cargo bench'.
+//! params are borrowed, allocations should not grow with the number of params
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+// gantry is a binary crate, the vm and its handlers are included directly
+#[allow(dead_code)]
+#[path = "../src/gcode"]
+mod gcode {
+    mod exclude_object;
+    mod g1;
+    mod g28;
+    mod g92;
+    mod gcode_state;
+    mod m104;
+    mod m105;
+    mod m109;
+    mod m114;
+    mod m140;
+    mod m190;
+    mod m220;
+    mod m302;
+    mod m400;
+    pub mod parser;
+    mod positioning;
+    mod set_velocity_limit;
+    mod tool;
+    pub mod vm;
+}
+
+#[allow(dead_code)]
+#[path = "../src/printer"]
+mod printer {
+    pub mod action;
+    pub mod heater;
+
+    /// stand in for the event loop channel of the printer
+    #[allow(clippy::module_inception)]
+    pub mod printer {
+        pub enum PrinterEvent {
+            Action(super::action::PrinterAction),
+        }
+    }
+}
+
+use gcode::parser::GcodeFile;
+use gcode::vm::GcodeVM;
+use printer::action::{ActionQueue, ActionState};
+
+/// number of commands in each script
+const COMMANDS: usize = 100;
+
+/// allocations since startup
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// measures the number of allocations instead of time
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &usize, v2: &usize) -> usize {
+        v1 + v2
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocationFormatter
+    }
+}
+
+struct AllocationFormatter;
+
+impl ValueFormatter for AllocationFormatter {
+    fn scale_values(&self, _typical: f64, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        match throughput {
+            Throughput::Elements(n) => {
+                for v in values {
+                    *v /= *n as f64;
+                }
+                "allocs/command"
+            }
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => {
+                for v in values {
+                    *v /= *n as f64;
+                }
+                "allocs/byte"
+            }
+        }
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+fn allocations(c: &mut Criterion<Allocations>) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let vm = GcodeVM::new(Arc::new(ActionQueue::new(state, sender)));
+
+    let scripts = [
+        ("few_params", "M302 P1\n".repeat(COMMANDS)),
+        (
+            "many_params",
+            "M302 P1 S170 P1 S170 P1 S170\n".repeat(COMMANDS),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("gcode_vm");
+    group.throughput(Throughput::Elements(COMMANDS as u64));
+
+    for (name, script) in &scripts {
+        let file = GcodeFile::blocking_parse(script).unwrap();
+
+        group.bench_function(format!("run_gcode_file/{}", name), |b| {
+            b.iter(|| runtime.block_on(vm.run_gcode_file(&file)).unwrap())
+        });
+
+        group.bench_function(format!("run_gcode_string/{}", name), |b| {
+            b.iter(|| runtime.block_on(vm.run_gcode_string(script)).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // the measurement budget is read as a number of allocations, not nanoseconds
+    config = Criterion::default()
+        .with_measurement(Allocations)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_nanos(5_000_000));
+    targets = allocations
+}
+criterion_main!(benches);
@@ -0,0 +1,131 @@
+//! trapezoid generator throughput over a curve of short segments, run with 'cargo bench'
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+
+// gantry is a binary crate, the action queue is included directly
+#[allow(dead_code)]
+#[path = "../src/printer"]
+mod printer {
+    pub mod action;
+    pub mod heater;
+
+    /// stand in for the event loop channel of the printer
+    #[allow(clippy::module_inception)]
+    pub mod printer {
+        pub enum PrinterEvent {
+            Action(super::action::PrinterAction),
+        }
+    }
+}
+
+use printer::action::{Action, ActionQueue, ActionState, Move, PrinterAction};
+use printer::printer::PrinterEvent;
+
+/// number of segments in the curve
+const SEGMENTS: usize = 2000;
+
+/// a circle of 20mm radius split into 0.5mm segments with a few sharp corners,
+/// like a sliced outer wall
+fn curve() -> Vec<Move> {
+    let radius = 20.0f32;
+    let step = 0.5 / radius;
+
+    (0..SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 * step;
+            // sharp corner every 100 segments
+            let angle = angle + (i / 100) as f32 * std::f32::consts::FRAC_PI_2;
+
+            Move {
+                start_velocity: f32::NAN,
+                target_velocity: 150.0,
+                x: -angle.sin() * radius * step,
+                y: angle.cos() * radius * step,
+                z: 0.0,
+                e: 0.02,
+            }
+        })
+        .collect()
+}
+
+fn queue() -> (
+    Arc<ActionState>,
+    ActionQueue,
+    tokio::sync::mpsc::UnboundedReceiver<PrinterEvent>,
+) {
+    let (sender, recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+
+    state.max_velocity.store(300.0, Ordering::SeqCst);
+    state.max_accel.store(5000.0, Ordering::SeqCst);
+    state.allow_cold_extrude.store(true, Ordering::SeqCst);
+
+    let queue = ActionQueue::new(state.clone(), sender);
+
+    (state, queue, recv)
+}
+
+async fn plan(queue: &ActionQueue, moves: &[Move]) {
+    for m in moves {
+        queue.push(Action::Move(*m)).await.unwrap();
+    }
+
+    queue.flush().await;
+}
+
+/// planned velocities stay within the configured limits
+fn check_limits(runtime: &tokio::runtime::Runtime, moves: &[Move]) {
+    let (state, queue, mut recv) = queue();
+    runtime.block_on(plan(&queue, moves));
+
+    let max_velocity = state.max_velocity.load(Ordering::SeqCst);
+    let max_accel = state.max_accel.load(Ordering::SeqCst);
+    let mut planned = 0;
+
+    while let Ok(PrinterEvent::Action(action)) = recv.try_recv() {
+        let PrinterAction::KinematicMove(m) = action else {
+            continue;
+        };
+
+        let end_v2 = m.start_velocity * m.start_velocity + 2.0 * m.acceleration * m.abs_distance();
+
+        assert!(m.start_velocity <= max_velocity + 1e-3);
+        assert!(end_v2.max(0.0).sqrt() <= max_velocity + 1e-3);
+        assert!(m.acceleration.abs() <= max_accel);
+
+        planned += 1;
+    }
+
+    assert!(planned >= moves.len());
+}
+
+fn trapezoid(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let moves = curve();
+
+    check_limits(&runtime, &moves);
+
+    let mut group = c.benchmark_group("planner");
+    group.throughput(Throughput::Elements(moves.len() as u64));
+
+    group.bench_function("curve", |b| {
+        b.to_async(&runtime).iter_batched(
+            queue,
+            |(_state, queue, recv)| {
+                // planned moves are discarded
+                drop(recv);
+                let moves = &moves;
+                async move { plan(&queue, moves).await }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, trapezoid);
+criterion_main!(benches);
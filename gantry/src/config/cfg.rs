@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+#[derive(Debug, Default, Serialize, Deserialize, Type)]
 pub struct Config {
     pub sections: Vec<Section>,
 }
@@ -9,16 +12,77 @@ impl Config {
     pub fn parse(file: &str) -> Result<Self, pest::error::Error<super::cfg_pest::Rule>> {
         return super::cfg_pest::parse_cfg(file);
     }
+
+    /// find a section by its prefix and suffix name, e.g. [extruder] or [stepper x]
+    pub fn get_section(&self, prefix_name: &str, suffix_name: Option<&str>) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|s| s.prefix_name == prefix_name && s.suffix_name.as_deref() == suffix_name)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize, Type)]
 pub struct Section {
     pub prefix_name: String,
     pub suffix_name: Option<String>,
     pub values: HashMap<String, Value>,
 }
 
-#[derive(Debug, PartialEq)]
+#[cfg(test)]
+thread_local! {
+    /// keys looked up by the accessors on this thread, (section prefix, key, type)
+    pub static READ_KEYS: std::cell::RefCell<Vec<(String, String, super::schema::ValueType)>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+impl Section {
+    #[cfg(test)]
+    fn record_read(&self, key: &str, value_type: super::schema::ValueType) {
+        READ_KEYS.with_borrow_mut(|keys| {
+            keys.push((self.prefix_name.clone(), key.to_string(), value_type))
+        });
+    }
+
+    /// get a number value, ratios are evaluated
+    pub fn get_number(&self, key: &str) -> Option<f64> {
+        #[cfg(test)]
+        self.record_read(key, super::schema::ValueType::Number);
+
+        match self.values.get(key)? {
+            Value::Number(n) | Value::Ratio(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// get a string value
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        #[cfg(test)]
+        self.record_read(key, super::schema::ValueType::String);
+
+        match self.values.get(key)? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// get a boolean value, accepts true/false or 1/0
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        #[cfg(test)]
+        self.record_read(key, super::schema::ValueType::Bool);
+
+        match self.values.get(key)? {
+            Value::Number(n) => Some(*n != 0.0),
+            Value::String(s) if s.eq_ignore_ascii_case("true") => Some(true),
+            Value::String(s) if s.eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// serialized as {"type": "number", "value": 1.0}, so editors keep the value type
+#[derive(Debug, PartialEq, Serialize, Deserialize, Type)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+#[zvariant(signature = "a{sv}")]
 pub enum Value {
     Number(f64),
     NumberArray(Vec<f64>),
@@ -27,3 +91,34 @@ pub enum Value {
     String(String),
     StringArray(Vec<String>),
 }
+
+#[test]
+fn test_json_round_trip() {
+    let config = Config::parse(
+        "[printer]\nkinematics: corexy\nmax_velocity: 300\n\n[stepper_x]\ngear_ratio: 80:16\nposition: 0, 120.5\n",
+    )
+    .unwrap();
+
+    let json = serde_json::to_value(&config).unwrap();
+
+    let printer = &json["sections"][0];
+    assert_eq!(printer["prefix_name"], "printer");
+    assert_eq!(printer["values"]["max_velocity"]["type"], "number");
+    assert_eq!(printer["values"]["kinematics"]["type"], "string");
+
+    let parsed: Config = serde_json::from_value(json).unwrap();
+
+    let printer = parsed.get_section("printer", None).unwrap();
+    assert_eq!(printer.values["max_velocity"], Value::Number(300.0));
+    assert_eq!(
+        printer.values["kinematics"],
+        Value::String("corexy".to_string())
+    );
+
+    let stepper = parsed.get_section("stepper_x", None).unwrap();
+    assert_eq!(stepper.values["gear_ratio"], Value::Ratio(5.0));
+    assert_eq!(
+        stepper.values["position"],
+        Value::NumberArray(vec![0.0, 120.5])
+    );
+}
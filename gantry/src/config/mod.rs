@@ -1,26 +1,510 @@
 mod cfg;
 mod cfg_pest;
+pub mod patch;
+pub mod schema;
 
 pub use cfg::Config as PrinterConfig;
+#[cfg(test)]
+pub use cfg::READ_KEYS;
 
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct GantryConfig {
     /// printer instances to boot up
     pub instances: HashMap<String, InstanceConfig>,
+    /// logging options
+    pub log: LogConfig,
+    /// publish printer status to an mqtt broker if set
+    pub mqtt: Option<MqttConfig>,
+    /// serve a moonraker compatible api for one instance if set
+    pub moonraker: Option<MoonrakerConfig>,
+    /// serve an octoprint compatible upload api for one instance if set
+    pub octoprint: Option<OctoPrintConfig>,
+    /// file transfer options
+    pub files: FilesConfig,
+    /// rest api options
+    pub http: HttpConfig,
+    /// timeouts of network operations
+    pub timeouts: TimeoutConfig,
+    /// tokio runtime options, read before the runtime is started
+    pub runtime: RuntimeConfig,
 }
 
+#[derive(Debug, Default, Deserialize)]
 pub struct InstanceConfig {
     /// uuid
+    #[serde(deserialize_with = "deserialize_uuid")]
     pub uuid: u128,
     /// path to the printer config for instance
+    #[serde(default)]
     pub config_path: String,
+    /// directory to store gcode files in, relative to the gantry path.
+    /// defaults to the printer directory
+    #[serde(default)]
+    pub data_path: Option<String>,
+    /// restart the printer when printer.cfg is changed on disk
+    #[serde(default)]
+    pub hot_reload: bool,
+    /// urls to post state changes and job events to
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// names shown on dashboards, set in the instance table
+    #[serde(flatten)]
+    pub metadata: InstanceMetadata,
+    /// periodically remove old gcode files, files are kept if not set
+    #[serde(default)]
+    pub file_cleanup: Option<FileCleanupConfig>,
+}
+
+/// optional descriptions of a printer, not used for routing
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct InstanceMetadata {
+    /// human friendly name, the instance name is shown if not set
+    pub display_name: Option<String>,
+    /// where the printer is, e.g. 'lab 2'
+    pub location: Option<String>,
+    /// printer model, e.g. 'Voron 2.4'
+    pub model: Option<String>,
+}
+
+/// removal of old gcode files, queued and printing files are never removed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FileCleanupConfig {
+    /// remove files not uploaded or printed within this many days
+    pub max_age_days: Option<f64>,
+    /// remove the least recently used files while free space in bytes is below this
+    pub min_free_space: Option<u64>,
+    /// files printed within this many days are kept
+    pub keep_recent_days: f64,
+    /// interval in seconds between cleanups
+    pub interval: f64,
+}
+
+impl Default for FileCleanupConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            min_free_space: None,
+            keep_recent_days: 1.0,
+            interval: 60.0 * 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// one of 'off', 'error', 'warn', 'info', 'debug' or 'trace'
+    pub level: String,
+    /// log file relative to the gantry path, logs are only written to stderr if none
+    pub file: Option<String>,
+    /// rotate log file when it exceeds this size in bytes
+    pub max_size: u64,
+    /// number of rotated log files to keep
+    pub max_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            file: None,
+            max_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFlavor {
+    /// tasks run on a pool of worker threads
+    #[default]
+    MultiThread,
+    /// all tasks run on the main thread, for single core boards
+    CurrentThread,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    pub flavor: RuntimeFlavor,
+    /// worker threads of the multi thread flavor, one per cpu core if not set
+    pub worker_threads: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FilesConfig {
+    /// uploads written at the same time, further uploads wait for a slot
+    pub max_concurrent_uploads: usize,
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_uploads: crate::files::DEFAULT_MAX_CONCURRENT_UPLOADS,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// answer failed requests with a 4xx or 5xx status code and the error as body,
+    /// instead of 200 and the result envelope. 'Accept: application/json; envelope=none'
+    /// or 'envelope=result' overrides this per request
+    pub error_status: bool,
+}
+
+/// timeouts in seconds of operations reaching other hosts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// downloading an extension from its repo
+    pub extension_install: f64,
+    /// a single webhook delivery attempt
+    pub webhook: f64,
+    /// connecting to the mqtt broker
+    pub mqtt_connect: f64,
+}
+
+impl TimeoutConfig {
+    pub fn extension_install(&self) -> Duration {
+        seconds(self.extension_install)
+    }
+
+    pub fn webhook(&self) -> Duration {
+        seconds(self.webhook)
+    }
+
+    pub fn mqtt_connect(&self) -> Duration {
+        seconds(self.mqtt_connect)
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            extension_install: 60.0,
+            webhook: 10.0,
+            mqtt_connect: 10.0,
+        }
+    }
+}
+
+/// a timeout in seconds, invalid values wait forever
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// broker host name or address
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// topics are published under '{topic_prefix}/{printer}/'
+    pub topic_prefix: String,
+    /// interval in seconds between telemetry messages
+    pub telemetry_interval: f64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "gantry".to_string(),
+            username: None,
+            password: None,
+            topic_prefix: "gantry".to_string(),
+            telemetry_interval: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoonrakerConfig {
+    /// name of the instance exposed, moonraker only serves a single printer
+    pub instance: String,
+    /// port to serve the api on, moonraker's port by default
+    #[serde(default = "MoonrakerConfig::default_port")]
+    pub port: u16,
+}
+
+impl MoonrakerConfig {
+    fn default_port() -> u16 {
+        7125
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OctoPrintConfig {
+    /// name of the instance exposed, octoprint only serves a single printer
+    pub instance: String,
+    /// port to serve the api on, octoprint's port by default
+    #[serde(default = "OctoPrintConfig::default_port")]
+    pub port: u16,
+    /// key clients send in the 'X-Api-Key' header
+    pub api_key: String,
+}
+
+impl OctoPrintConfig {
+    fn default_port() -> u16 {
+        5000
+    }
+}
+
+/// parse uuid in its string form
+fn deserialize_uuid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    let s = String::deserialize(deserializer)?;
+
+    uuid::Uuid::parse_str(&s)
+        .map(|u| u.as_u128())
+        .map_err(serde::de::Error::custom)
 }
 
 impl GantryConfig {
-    pub async fn parse(_file: &str) -> Result<Self, ()> {
-        return Ok(GantryConfig {
-            instances: HashMap::new(),
-        });
+    pub async fn parse(file: &str) -> Result<Self, toml::de::Error> {
+        return toml::from_str(file);
+    }
+
+    /// runtime options of a config file, parsed before the runtime exists.
+    /// an invalid file uses the defaults, it is reported once fully parsed
+    pub fn parse_runtime(file: &str) -> RuntimeConfig {
+        toml::from_str::<Self>(file)
+            .map(|c| c.runtime)
+            .unwrap_or_default()
+    }
+
+    /// checks that instances do not share any resources
+    pub fn validate(&self, gantry_path: &Path) -> anyhow::Result<()> {
+        if let Some(moonraker) = &self.moonraker
+            && !self.instances.contains_key(&moonraker.instance)
+        {
+            anyhow::bail!("moonraker instance '{}' does not exist", moonraker.instance);
+        }
+
+        if let Some(octoprint) = &self.octoprint
+            && !self.instances.contains_key(&octoprint.instance)
+        {
+            anyhow::bail!("octoprint instance '{}' does not exist", octoprint.instance);
+        }
+
+        validate_instances(
+            self.instances.iter().map(|(name, c)| (name.as_str(), c)),
+            gantry_path,
+        )
+    }
+}
+
+impl InstanceConfig {
+    /// directory gcode files of the instance are stored in
+    pub fn gcodes_path(&self, name: &str, gantry_path: &Path) -> PathBuf {
+        match &self.data_path {
+            Some(data_path) => gantry_path.join(data_path).join("gcodes"),
+            None => gantry_path.join(name).join("gcodes"),
+        }
+    }
+}
+
+/// resolve '.' and '..' without touching the filesystem,
+/// paths may not exist yet when the config is validated
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+
+    return out;
+}
+
+/// checks that no two instances share a uuid, name, printer config or data directory.
+/// sharing any of these would let instances corrupt each other's files
+pub fn validate_instances<'a>(
+    instances: impl IntoIterator<Item = (&'a str, &'a InstanceConfig)>,
+    gantry_path: &Path,
+) -> anyhow::Result<()> {
+    let mut seen: Vec<(&str, &InstanceConfig, Vec<PathBuf>)> = Vec::new();
+
+    for (name, config) in instances {
+        // directories owned by the instance
+        let dirs = vec![
+            normalize_path(&gantry_path.join(name)),
+            normalize_path(&config.gcodes_path(name, gantry_path)),
+        ];
+
+        for (other_name, other, other_dirs) in &seen {
+            // names map to directories, which may be case insensitive
+            if name.eq_ignore_ascii_case(other_name) {
+                anyhow::bail!("duplicate instance name '{}' and '{}'", other_name, name);
+            }
+
+            if config.uuid == other.uuid {
+                anyhow::bail!(
+                    "instances '{}' and '{}' have the same uuid",
+                    other_name,
+                    name
+                );
+            }
+
+            if !config.config_path.is_empty()
+                && normalize_path(&gantry_path.join(&config.config_path))
+                    == normalize_path(&gantry_path.join(&other.config_path))
+            {
+                anyhow::bail!(
+                    "instances '{}' and '{}' share the config '{}'",
+                    other_name,
+                    name,
+                    config.config_path
+                );
+            }
+
+            for dir in &dirs {
+                for other_dir in other_dirs {
+                    if dir.starts_with(other_dir) || other_dir.starts_with(dir) {
+                        anyhow::bail!(
+                            "instances '{}' and '{}' share the data directory '{}'",
+                            other_name,
+                            name,
+                            dir.display()
+                        );
+                    }
+                }
+            }
+        }
+
+        seen.push((name, config, dirs));
     }
+
+    return Ok(());
+}
+
+#[tokio::test]
+async fn test_parse_gantry_config() {
+    let config = GantryConfig::parse(
+        r#"
+        [log]
+        level = "debug"
+        file = "logs/gantry.log"
+
+        [instances.voron]
+        uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8"
+
+        [instances.voron.file_cleanup]
+        max_age_days = 30
+        "#,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(config.log.level, "debug");
+    assert_eq!(config.log.file.as_deref(), Some("logs/gantry.log"));
+    assert_eq!(
+        config.instances["voron"].uuid,
+        0x67e55044_10b1_426f_9247_bb680e5fe0c8
+    );
+
+    // unset cleanup options use the defaults
+    let cleanup = config.instances["voron"].file_cleanup.as_ref().unwrap();
+    assert_eq!(cleanup.max_age_days, Some(30.0));
+    assert_eq!(cleanup.min_free_space, None);
+    assert_eq!(cleanup.interval, 3600.0);
+
+    // empty file uses defaults
+    let config = GantryConfig::parse("").await.unwrap();
+
+    assert_eq!(config.log.level, "info");
+    assert!(config.instances.is_empty());
+}
+
+#[tokio::test]
+async fn test_shared_data_directory() {
+    let gantry_path = Path::new("/home/user/.gantry");
+
+    let config = GantryConfig::parse(
+        r#"
+        [instances.voron]
+        uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        data_path = "/mnt/data/printers"
+
+        [instances.ender]
+        uuid = "0d0a3a4e-8e0a-4d55-9a5c-3f2a4b1e2c7d"
+        data_path = "/mnt/data/printers/../printers"
+        "#,
+    )
+    .await
+    .unwrap();
+
+    let err = config.validate(gantry_path).unwrap_err();
+    assert!(err.to_string().contains("share the data directory"));
+
+    // distinct data directories are accepted
+    let config = GantryConfig::parse(
+        r#"
+        [instances.voron]
+        uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        data_path = "/mnt/data/voron"
+
+        [instances.ender]
+        uuid = "0d0a3a4e-8e0a-4d55-9a5c-3f2a4b1e2c7d"
+        "#,
+    )
+    .await
+    .unwrap();
+
+    assert!(config.validate(gantry_path).is_ok());
+
+    // data directory inside another printer's directory
+    let config = GantryConfig::parse(
+        r#"
+        [instances.voron]
+        uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        data_path = "ender"
+
+        [instances.ender]
+        uuid = "0d0a3a4e-8e0a-4d55-9a5c-3f2a4b1e2c7d"
+        "#,
+    )
+    .await
+    .unwrap();
+
+    assert!(config.validate(gantry_path).is_err());
+}
+
+#[test]
+fn test_duplicate_uuid() {
+    let config = |data_path: &str| InstanceConfig {
+        uuid: 1,
+        data_path: Some(data_path.to_string()),
+        ..Default::default()
+    };
+
+    let a = config("a");
+    let b = config("b");
+
+    let err = validate_instances([("voron", &a), ("ender", &b)], Path::new("/gantry")).unwrap_err();
+    assert!(err.to_string().contains("same uuid"));
+
+    let err = validate_instances([("voron", &a), ("Voron", &b)], Path::new("/gantry")).unwrap_err();
+    assert!(err.to_string().contains("duplicate instance name"));
 }
@@ -0,0 +1,215 @@
+use super::PrinterConfig;
+use super::schema::{CONFIG_SCHEMA, ValueType};
+
+/// change a single key of a printer config without touching other lines.
+/// 'section' is the section name as written in brackets, e.g. "printer" or "stepper x".
+/// the key is added if missing and removed if 'value' is none, a missing section is appended.
+/// comments after the old value are kept
+pub fn patch(
+    source: &str,
+    section: &str,
+    key: &str,
+    value: Option<&str>,
+) -> anyhow::Result<String> {
+    if let Some(value) = value
+        && value.contains(['\n', '\r', ';'])
+    {
+        anyhow::bail!("value of '{}' must be a single line without ';'", key);
+    }
+
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+
+    let Some(header) = lines
+        .iter()
+        .position(|l| section_name(l).as_deref() == Some(section))
+    else {
+        let Some(value) = value else {
+            anyhow::bail!("section [{}] not found", section);
+        };
+
+        let mut patched = source.to_string();
+
+        if !patched.is_empty() && !patched.ends_with('\n') {
+            patched.push('\n');
+        }
+        if !patched.is_empty() {
+            patched.push('\n');
+        }
+        patched.push_str(&format!("[{}]\n{}: {}\n", section, key, value));
+
+        return Ok(patched);
+    };
+
+    // lines of the section, up to the next section header
+    let end = lines[header + 1..]
+        .iter()
+        .position(|l| section_name(l).is_some())
+        .map(|i| header + 1 + i)
+        .unwrap_or(lines.len());
+
+    let found = (header + 1..end).find(|i| key_name(&lines[*i]) == Some(key));
+
+    match (found, value) {
+        (Some(i), Some(value)) => {
+            let line = &lines[i];
+            let separator = line.find([':', '=']).unwrap();
+
+            // keep spacing after the separator and comments after the value
+            let rest = &line[separator + 1..];
+            let spacing = &rest[..rest.len() - rest.trim_start().len()];
+            let comment = rest.find(';').map(|c| {
+                let before = rest[..c].trim_end();
+                &rest[before.len()..]
+            });
+
+            let spacing = match spacing.is_empty() {
+                true => " ",
+                false => spacing,
+            };
+
+            lines[i] = format!(
+                "{}{}{}{}",
+                &line[..=separator],
+                spacing,
+                value,
+                comment.unwrap_or("")
+            );
+
+            // a multiline value is replaced by the single line
+            let continuation = continuation_lines(&lines[i + 1..end]);
+            lines.drain(i + 1..i + 1 + continuation);
+        }
+        (Some(i), None) => {
+            let continuation = continuation_lines(&lines[i + 1..end]);
+            lines.drain(i..i + 1 + continuation);
+        }
+        (None, Some(value)) => {
+            // after the last value of the section, trailing blank lines and comments stay
+            let last = (header + 1..end)
+                .rev()
+                .find(|i| {
+                    let trimmed = lines[*i].trim_start();
+                    !trimmed.is_empty() && !trimmed.starts_with(['#', ';'])
+                })
+                .unwrap_or(header);
+
+            lines.insert(last + 1, format!("{}: {}", key, value));
+        }
+        (None, None) => anyhow::bail!("key '{}' not found in section [{}]", key, section),
+    }
+
+    let mut patched = lines.join("\n");
+
+    if source.ends_with('\n') || source.is_empty() {
+        patched.push('\n');
+    }
+
+    return Ok(patched);
+}
+
+/// parse a patched config and check the types of known keys
+pub fn validate(source: &str) -> anyhow::Result<PrinterConfig> {
+    let config = PrinterConfig::parse(source)?;
+
+    for section in &config.sections {
+        let Some(schema) = CONFIG_SCHEMA.iter().find(|s| s.name == section.prefix_name) else {
+            continue;
+        };
+
+        for key in schema.keys {
+            if !section.values.contains_key(key.name) {
+                continue;
+            }
+
+            let valid = match key.value_type {
+                ValueType::Number => section.get_number(key.name).is_some(),
+                ValueType::String => section.get_string(key.name).is_some(),
+                ValueType::Bool => section.get_bool(key.name).is_some(),
+            };
+
+            if !valid {
+                anyhow::bail!(
+                    "'{}' in [{}] must be a {:?}",
+                    key.name,
+                    section.prefix_name,
+                    key.value_type
+                );
+            }
+        }
+    }
+
+    return Ok(config);
+}
+
+/// name in brackets of a section header, whitespace between names is normalised
+fn section_name(line: &str) -> Option<String> {
+    let name = line.trim().strip_prefix('[')?;
+    let name = &name[..name.find(']')?];
+
+    Some(name.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// key of a 'key: value' line, indented lines continue the previous value
+fn key_name(line: &str) -> Option<&str> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let key = line[..line.find([':', '='])?].trim_end();
+
+    match !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        true => Some(key),
+        false => None,
+    }
+}
+
+/// number of indented lines continuing a multiline value
+fn continuation_lines(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .take_while(|l| l.starts_with(char::is_whitespace) && !l.trim().is_empty())
+        .count()
+}
+
+#[test]
+fn test_patch_key() {
+    let source = "# my printer\n[printer]\nkinematics: cartesian\nmax_velocity: 300 ; fast\nmax_accel = 3000\n\n[extruder]\ngcode:\n  G28\n  G1 Z10\nnozzle_diameter: 0.4\n";
+
+    let patched = patch(source, "printer", "max_velocity", Some("250")).unwrap();
+
+    // only the patched line differs
+    let changed: Vec<(&str, &str)> = source
+        .lines()
+        .zip(patched.lines())
+        .filter(|(a, b)| a != b)
+        .collect();
+
+    assert_eq!(source.lines().count(), patched.lines().count());
+    assert_eq!(
+        changed,
+        vec![("max_velocity: 300 ; fast", "max_velocity: 250 ; fast")]
+    );
+
+    let config = validate(&patched).unwrap();
+    let printer = config.get_section("printer", None).unwrap();
+    assert_eq!(printer.get_number("max_velocity"), Some(250.0));
+
+    // new keys are added at the end of the section
+    let patched = patch(source, "printer", "square_corner_velocity", Some("5")).unwrap();
+    assert!(patched.contains("max_accel = 3000\nsquare_corner_velocity: 5\n\n[extruder]"));
+
+    // removing a multiline value removes its lines
+    let patched = patch(source, "extruder", "gcode", None).unwrap();
+    assert!(patched.ends_with("[extruder]\nnozzle_diameter: 0.4\n"));
+
+    // missing sections are appended
+    let patched = patch(source, "job_queue", "mode", Some("manual")).unwrap();
+    assert!(patched.ends_with("nozzle_diameter: 0.4\n\n[job_queue]\nmode: manual\n"));
+
+    assert!(patch(source, "printer", "max_velocity", Some("1\n[x]")).is_err());
+    assert!(patch(source, "printer", "missing", None).is_err());
+
+    // known keys are type checked
+    let patched = patch(source, "printer", "max_velocity", Some("fast")).unwrap();
+    assert!(validate(&patched).is_err());
+}
@@ -0,0 +1,455 @@
+use serde::Serialize;
+
+/// type of a config value, matches the typed accessors on Section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    /// read by Section::get_number, ratios such as 80:8 are accepted
+    Number,
+    /// read by Section::get_string
+    String,
+    /// read by Section::get_bool, true/false or 1/0
+    Bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeySchema {
+    pub name: &'static str,
+    pub value_type: ValueType,
+    /// default value used if the key is not set
+    pub default: Option<&'static str>,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SectionSchema {
+    /// prefix name of the section, e.g. 'printer' for [printer]
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keys: &'static [KeySchema],
+}
+
+/// sections and keys understood by the printer.
+/// must be kept in sync with the keys read in Printer::restart and the gcode linter,
+/// keys read by restart are checked by test_config_keys_in_schema
+pub const CONFIG_SCHEMA: &[SectionSchema] = &[
+    SectionSchema {
+        name: "printer",
+        description: "kinematic limits of the printer",
+        keys: &[
+            KeySchema {
+                name: "kinematics",
+                value_type: ValueType::String,
+                default: Some("cartesian"),
+                description: "kinematics of the printer, cartesian, corexy or delta",
+            },
+            KeySchema {
+                name: "delta_radius",
+                value_type: ValueType::Number,
+                default: None,
+                description: "distance in mm of the delta towers from the center",
+            },
+            KeySchema {
+                name: "max_velocity",
+                value_type: ValueType::Number,
+                default: Some("100"),
+                description: "maximum velocity of the toolhead in mm/s",
+            },
+            KeySchema {
+                name: "max_accel",
+                value_type: ValueType::Number,
+                default: Some("3000"),
+                description: "maximum acceleration of the toolhead in mm/s^2",
+            },
+            KeySchema {
+                name: "square_corner_velocity",
+                value_type: ValueType::Number,
+                default: Some("5"),
+                description: "maximum velocity in mm/s when taking a 90 degree corner",
+            },
+            KeySchema {
+                name: "minimum_cruise_ratio",
+                value_type: ValueType::Number,
+                default: Some("0.5"),
+                description: "minimum portion of a move spent cruising",
+            },
+            KeySchema {
+                name: "cornering",
+                value_type: ValueType::String,
+                default: Some("square_corner_velocity"),
+                description: "junction velocity model, square_corner_velocity or jerk",
+            },
+            KeySchema {
+                name: "max_jerk",
+                value_type: ValueType::Number,
+                default: Some("10"),
+                description: "instantaneous velocity change in mm/s per axis with jerk cornering",
+            },
+            KeySchema {
+                name: "motion_thread",
+                value_type: ValueType::Bool,
+                default: Some("false"),
+                description: "run the event loop on a dedicated high priority thread",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_x",
+        description: "stepper of the x axis",
+        keys: &[
+            KeySchema {
+                name: "position_endstop",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "x position in mm of the toolhead after homing",
+            },
+            KeySchema {
+                name: "position_min",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "min x position in mm of jogs and the build volume",
+            },
+            KeySchema {
+                name: "position_max",
+                value_type: ValueType::Number,
+                default: None,
+                description: "max x position in mm of jogs and the build volume, none if unset",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the x axis per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_y",
+        description: "stepper of the y axis",
+        keys: &[
+            KeySchema {
+                name: "position_endstop",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "y position in mm of the toolhead after homing",
+            },
+            KeySchema {
+                name: "position_min",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "min y position in mm of jogs and the build volume",
+            },
+            KeySchema {
+                name: "position_max",
+                value_type: ValueType::Number,
+                default: None,
+                description: "max y position in mm of jogs and the build volume, none if unset",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the y axis per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_z",
+        description: "stepper of the z axis",
+        keys: &[
+            KeySchema {
+                name: "position_endstop",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "z position in mm of the toolhead after homing",
+            },
+            KeySchema {
+                name: "position_min",
+                value_type: ValueType::Number,
+                default: Some("0"),
+                description: "min z position in mm of jogs and the build volume",
+            },
+            KeySchema {
+                name: "position_max",
+                value_type: ValueType::Number,
+                default: None,
+                description: "max z position in mm of jogs and the build volume, none if unset",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the z axis per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_a",
+        description: "stepper of the first delta tower",
+        keys: &[
+            KeySchema {
+                name: "arm_length",
+                value_type: ValueType::Number,
+                default: None,
+                description: "length in mm of the arms from the carriages to the effector",
+            },
+            KeySchema {
+                name: "angle",
+                value_type: ValueType::Number,
+                default: Some("210"),
+                description: "angle in degrees of the tower around the center",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the carriage per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_b",
+        description: "stepper of the second delta tower",
+        keys: &[
+            KeySchema {
+                name: "angle",
+                value_type: ValueType::Number,
+                default: Some("330"),
+                description: "angle in degrees of the tower around the center",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the carriage per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "stepper_c",
+        description: "stepper of the third delta tower",
+        keys: &[
+            KeySchema {
+                name: "angle",
+                value_type: ValueType::Number,
+                default: Some("90"),
+                description: "angle in degrees of the tower around the center",
+            },
+            KeySchema {
+                name: "rotation_distance",
+                value_type: ValueType::Number,
+                default: Some("40"),
+                description: "travel in mm of the carriage per full rotation of the motor",
+            },
+            KeySchema {
+                name: "microsteps",
+                value_type: ValueType::Number,
+                default: Some("16"),
+                description: "microsteps per full step of the motor",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "extruder",
+        description: "the primary extruder, additional extruders are [extruder1], [extruder2]...",
+        keys: &[
+            KeySchema {
+                name: "max_temp",
+                value_type: ValueType::Number,
+                default: None,
+                description: "maximum temperature in celsius, higher targets are rejected",
+            },
+            KeySchema {
+                name: "min_extrude_temp",
+                value_type: ValueType::Number,
+                default: Some("170"),
+                description: "extrusion is refused below this temperature, M302 overrides",
+            },
+            KeySchema {
+                name: "max_extrude_only_distance",
+                value_type: ValueType::Number,
+                default: Some("50"),
+                description: "longest extrude only move in mm, longer moves are rejected",
+            },
+            KeySchema {
+                name: "max_extrude_cross_section",
+                value_type: ValueType::Number,
+                default: Some("0.64"),
+                description: "largest extruded cross section in mm^2, larger moves are rejected",
+            },
+            KeySchema {
+                name: "filament_diameter",
+                value_type: ValueType::Number,
+                default: Some("1.75"),
+                description: "filament diameter in mm, used to compute the cross section",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "heater_bed",
+        description: "the heated bed",
+        keys: &[KeySchema {
+            name: "max_temp",
+            value_type: ValueType::Number,
+            default: None,
+            description: "maximum temperature in celsius, higher targets are rejected",
+        }],
+    },
+    SectionSchema {
+        name: "temperature_sensor",
+        description: "temperature without a heater, e.g. [temperature_sensor chamber]",
+        keys: &[],
+    },
+    SectionSchema {
+        name: "filament_switch_sensor",
+        description: "filament runout sensor, named by the section suffix",
+        keys: &[
+            KeySchema {
+                name: "pause_on_runout",
+                value_type: ValueType::Bool,
+                default: Some("true"),
+                description: "pause the running print when filament runs out",
+            },
+            KeySchema {
+                name: "runout_gcode",
+                value_type: ValueType::String,
+                default: None,
+                description: "gcode to run when filament runs out during a print",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "exclude_object",
+        description: "objects of a print can be cancelled while printing",
+        keys: &[KeySchema {
+            name: "z_hop",
+            value_type: ValueType::Number,
+            default: Some("0"),
+            description: "lift in mm when travelling over a cancelled object",
+        }],
+    },
+    SectionSchema {
+        name: "job_queue",
+        description: "print jobs waiting to run after the current job",
+        keys: &[
+            KeySchema {
+                name: "max_queued_jobs",
+                value_type: ValueType::Number,
+                default: Some("32"),
+                description: "maximum number of waiting jobs, the running job is not counted",
+            },
+            KeySchema {
+                name: "mode",
+                value_type: ValueType::String,
+                default: Some("auto"),
+                description: "'auto' starts the next job immediately, \
+                    'manual' pauses the queue after each job until it is resumed",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "jog",
+        description: "manual moves requested through the api",
+        keys: &[KeySchema {
+            name: "max_distance",
+            value_type: ValueType::Number,
+            default: Some("50"),
+            description: "longest single jog in mm on any axis, longer jogs are refused",
+        }],
+    },
+    SectionSchema {
+        name: "print_start",
+        description: "gcode run before every print job, before the gcode of the job",
+        keys: &[KeySchema {
+            name: "gcode",
+            value_type: ValueType::String,
+            default: None,
+            description: "{BED_TEMP} and {EXTRUDER_TEMP} are replaced by the \
+                first layer temperatures from the slicer settings of the job",
+        }],
+    },
+    SectionSchema {
+        name: "power_loss_recovery",
+        description: "save print progress so an interrupted print can be resumed",
+        keys: &[
+            KeySchema {
+                name: "enabled",
+                value_type: ValueType::Bool,
+                default: Some("true"),
+                description: "save progress while printing",
+            },
+            KeySchema {
+                name: "save_interval",
+                value_type: ValueType::Number,
+                default: Some("5"),
+                description: "seconds between saves of the print progress",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "reporting",
+        description: "format of positions and temperatures in gcode responses and status",
+        keys: &[
+            KeySchema {
+                name: "unit",
+                value_type: ValueType::String,
+                default: Some("mm"),
+                description: "'mm' or 'inch', unit of reported positions",
+            },
+            KeySchema {
+                name: "precision",
+                value_type: ValueType::Number,
+                default: Some("3"),
+                description: "decimals of reported positions and temperatures, up to 6",
+            },
+        ],
+    },
+    SectionSchema {
+        name: "debug",
+        description: "diagnostics, not intended for normal printing",
+        keys: &[
+            KeySchema {
+                name: "gcode_timing",
+                value_type: ValueType::Bool,
+                default: Some("false"),
+                description: "record execution time of each gcode command",
+            },
+            KeySchema {
+                name: "slow_gcode_threshold",
+                value_type: ValueType::Number,
+                default: Some("50"),
+                description: "commands slower than this in milliseconds are logged",
+            },
+        ],
+    },
+];
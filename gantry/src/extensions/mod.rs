@@ -1 +1,95 @@
+use std::path::Path;
+use std::time::Duration;
 
+use gantry_api::PrinterExtension;
+use serde::Deserialize;
+
+use crate::outbound::with_timeout;
+
+/// Gantry.toml at the root of an extension repo
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    /// directory the extension is installed to
+    name: String,
+    version: String,
+}
+
+/// download Gantry.toml and main.wasm from the 'repo' url into '<dir>/<name>'.
+/// nothing is written unless both files are downloaded within 'timeout'
+pub async fn install(
+    repo: &str,
+    dir: &Path,
+    timeout: Duration,
+) -> anyhow::Result<PrinterExtension> {
+    let repo = repo.trim_end_matches('/');
+    let client = reqwest::Client::new();
+
+    let fetch = async |file: &str| -> anyhow::Result<Vec<u8>> {
+        let response = client
+            .get(format!("{}/{}", repo, file))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.bytes().await?.to_vec())
+    };
+
+    let (manifest, wasm) = with_timeout("extension install", timeout, async {
+        Ok((fetch("Gantry.toml").await?, fetch("main.wasm").await?))
+    })
+    .await?;
+
+    let parsed: Manifest = toml::from_str(std::str::from_utf8(&manifest)?)?;
+
+    // the name becomes a directory
+    if parsed.name.is_empty() || parsed.name.starts_with('.') || parsed.name.contains(['/', '\\']) {
+        anyhow::bail!("invalid extension name '{}'", parsed.name);
+    }
+
+    let path = dir.join(&parsed.name);
+
+    tokio::fs::create_dir_all(&path).await?;
+    crate::files::write_atomic(&path.join("Gantry.toml"), &manifest).await?;
+    crate::files::write_atomic(&path.join("main.wasm"), &wasm).await?;
+
+    return Ok(PrinterExtension {
+        name: parsed.name,
+        repo: repo.to_string(),
+        version: parsed.version,
+    });
+}
+
+#[tokio::test]
+async fn test_install_timeout() {
+    use crate::outbound::TimeoutError;
+
+    // accepts connections but never responds
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            connections.push(socket);
+        }
+    });
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+
+    let start = std::time::Instant::now();
+    let result = install(
+        &format!("http://{}/ext", addr),
+        &dir,
+        Duration::from_millis(200),
+    )
+    .await;
+
+    let err = result.unwrap_err();
+    assert!(err.is::<TimeoutError>(), "{}", err);
+    assert_eq!(err.to_string(), "extension install timed out after 200ms");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // nothing is installed
+    assert!(!dir.exists());
+}
@@ -1,19 +1,115 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use notify::Watcher;
 
 use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{Mutex, Semaphore, broadcast};
 
 use crate::gcode::GcodeFile;
 
+/// maximum number of parsed gcode files kept in cache
+const GCODE_CACHE_CAP: usize = 16;
+
+/// uploads written at the same time unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_UPLOADS: usize = 2;
+
+/// permits of uploads being written, set once at startup
+static UPLOAD_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+/// path requests, parse results, watch requests and prewarm requests
+type FileChannels = (
+    UnboundedSender<PathBuf>,
+    Mutex<UnboundedReceiver<anyhow::Result<Arc<GcodeFile>>>>,
+    UnboundedSender<PathBuf>,
+    UnboundedSender<Vec<PathBuf>>,
+);
+
 lazy_static::lazy_static! {
-    /// channel to send requests to file watching tokio runtime
-    static ref RW: (UnboundedSender<PathBuf>, Mutex<UnboundedReceiver<anyhow::Result<Arc<GcodeFile>>>>) = init();
+    /// channel to send requests to file watching tokio runtime,
+    /// the third sender requests a path to be watched, the last requests files to be prewarmed
+    static ref RW: FileChannels = init();
+
+    /// parsed gcode files by canonical path
+    static ref CACHE: std::sync::Mutex<GcodeCache> = std::sync::Mutex::new(GcodeCache::default());
+
+    /// changes of watched files, sent after the cache is updated
+    static ref FILE_CHANGES: broadcast::Sender<FileChange> = broadcast::channel(64).0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChangeKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+/// a watched file has changed
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub kind: FileChangeKind,
+    pub path: PathBuf,
+}
+
+/// subscribe to changes of watched files.
+/// a changed file is evicted from the gcode cache before its change is sent,
+/// so opening the file again returns the new content
+pub fn subscribe_file_changes() -> broadcast::Receiver<FileChange> {
+    FILE_CHANGES.subscribe()
+}
+
+/// watch a directory for created, modified and removed files
+pub fn watch_dir(path: PathBuf) {
+    let _ = RW.2.send(path);
+}
+
+/// cache of parsed gcode files, least recently used files are evicted above the cap.
+/// files still referenced elsewhere, e.g. by a running print job, are never evicted
+#[derive(Default)]
+struct GcodeCache {
+    /// file and the tick it was last used
+    files: HashMap<PathBuf, (Arc<GcodeFile>, u64)>,
+    tick: u64,
+}
+
+impl GcodeCache {
+    fn get(&mut self, path: &Path) -> Option<Arc<GcodeFile>> {
+        self.tick += 1;
+
+        let (file, used) = self.files.get_mut(path)?;
+        *used = self.tick;
+
+        return Some(file.clone());
+    }
+
+    fn insert(&mut self, path: PathBuf, file: Arc<GcodeFile>) {
+        self.tick += 1;
+        self.files.insert(path.clone(), (file, self.tick));
+
+        while self.files.len() > GCODE_CACHE_CAP {
+            // least recently used file not referenced outside the cache
+            let evict = self
+                .files
+                .iter()
+                .filter(|(p, (f, _))| **p != path && Arc::strong_count(f) == 1)
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(p, _)| p.clone());
+
+            match evict {
+                Some(p) => self.files.remove(&p),
+                // every file is in use
+                None => break,
+            };
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
 }
 
 /// regestered handlers for watched paths
@@ -25,16 +121,17 @@ static HANDLERS: Mutex<
 > = Mutex::const_new(Vec::new());
 
 /// initialise local thread tokio to monitor and parse gcode files
-fn init() -> (
-    UnboundedSender<PathBuf>,
-    Mutex<UnboundedReceiver<anyhow::Result<Arc<GcodeFile>>>>,
-) {
+fn init() -> FileChannels {
     use notify::EventKind;
 
     // channel for recieving path requests
     let (sender, mut recv) = unbounded_channel::<PathBuf>();
     // channel for sending results
     let (re_sender, re_recv) = unbounded_channel::<anyhow::Result<Arc<GcodeFile>>>();
+    // channel for recieving watch requests
+    let (watch_sender, mut watch_recv) = unbounded_channel::<PathBuf>();
+    // channel for recieving prewarm requests
+    let (prewarm_sender, mut prewarm_recv) = unbounded_channel::<Vec<PathBuf>>();
 
     // create local thread tokio runtime
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -46,11 +143,6 @@ fn init() -> (
         // create a local set of tasks
         let local = tokio::task::LocalSet::new();
 
-        // cache to store gcode files
-        let cache = Arc::new(RwLock::new(HashMap::<PathBuf, Arc<GcodeFile>>::new()));
-        // create reference to cache
-        let cache1 = cache.clone();
-
         // create channel for watcher
         let (no_sender, mut no_recv) = unbounded_channel();
 
@@ -64,31 +156,46 @@ fn init() -> (
 
         // create reference to watcher
         let watcher1 = watcher.clone();
+        let watcher2 = watcher.clone();
+        let watcher3 = watcher.clone();
 
-        // spawn task to handle file parsing
+        // spawn task to handle watch requests
         local.spawn_local(async move {
-            while let Some(filename) = recv.recv().await {
-                if let Some(g) = cache1.read().await.get(&filename) {
-                    let _ = re_sender.send(Ok(g.clone()));
-                    continue;
-                }
-
-                let re = try_parse_file(&filename).await;
-
-                if let Ok(g) = &re {
-                    cache1.write().await.insert(filename.clone(), g.clone());
-                }
-
-                let watch_re = watcher1
+            while let Some(path) = watch_recv.recv().await {
+                let watch_re = watcher2
                     .lock()
                     .await
-                    .watch(filename.as_path(), notify::RecursiveMode::NonRecursive);
+                    .watch(path.as_path(), notify::RecursiveMode::NonRecursive);
 
                 if let Err(e) = watch_re {
                     log::warn!("Filsystem watcher: {}", e);
                 }
+            }
+        });
+
+        // spawn task to handle file parsing
+        local.spawn_local(async move {
+            while let Some(filename) = recv.recv().await {
+                let _ = re_sender.send(parse_and_cache(&filename, &watcher1).await);
+            }
+        });
 
-                let _ = re_sender.send(re);
+        // spawn task to parse prewarmed files in the background
+        local.spawn_local(async move {
+            while let Some(paths) = prewarm_recv.recv().await {
+                for path in paths {
+                    let path = match tokio::fs::canonicalize(&path).await {
+                        Ok(p) => p,
+                        Err(e) => {
+                            log::debug!("prewarm {}: {}", path.display(), e);
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = parse_and_cache(&path, &watcher3).await {
+                        log::debug!("prewarm {}: {}", path.display(), e);
+                    }
+                }
             }
         });
 
@@ -103,14 +210,23 @@ fn init() -> (
                     }
                 };
 
-                match event.kind {
-                    EventKind::Modify(_) | EventKind::Remove(_) => {
-                        // uncache gcode files if modified or removed
-                        for path in &event.paths {
-                            cache.write().await.remove(path);
-                        }
+                let kind = match event.kind {
+                    EventKind::Create(_) => Some(FileChangeKind::Created),
+                    EventKind::Modify(_) => Some(FileChangeKind::Modified),
+                    EventKind::Remove(_) => Some(FileChangeKind::Removed),
+                    _ => None,
+                };
+
+                if let Some(kind) = kind {
+                    for path in &event.paths {
+                        // uncache gcode files before notifying, a replaced file is created
+                        CACHE.lock().unwrap().remove(path);
+
+                        let _ = FILE_CHANGES.send(FileChange {
+                            kind,
+                            path: path.clone(),
+                        });
                     }
-                    _ => {}
                 }
                 // invoke handlers
                 // acquire lock
@@ -147,7 +263,37 @@ fn init() -> (
         rt.block_on(local);
     });
 
-    return (sender, Mutex::new(re_recv));
+    return (sender, Mutex::new(re_recv), watch_sender, prewarm_sender);
+}
+
+/// returns the cached file or parses it, parsed files are cached and watched for changes
+async fn parse_and_cache(
+    filename: &Path,
+    watcher: &Mutex<notify::RecommendedWatcher>,
+) -> anyhow::Result<Arc<GcodeFile>> {
+    if let Some(g) = CACHE.lock().unwrap().get(filename) {
+        return Ok(g);
+    }
+
+    let re = try_parse_file(filename).await;
+
+    if let Ok(g) = &re {
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(filename.to_path_buf(), g.clone());
+    }
+
+    let watch_re = watcher
+        .lock()
+        .await
+        .watch(filename, notify::RecursiveMode::NonRecursive);
+
+    if let Err(e) = watch_re {
+        log::warn!("Filsystem watcher: {}", e);
+    }
+
+    return re;
 }
 
 /// util function to parse gcode file
@@ -164,6 +310,11 @@ async fn try_parse_file(filename: &Path) -> anyhow::Result<Arc<GcodeFile>> {
 pub async fn open_gcode_file(filename: PathBuf) -> anyhow::Result<Arc<GcodeFile>> {
     let path = filename.canonicalize()?;
 
+    // cached files do not wait for other files to be parsed
+    if let Some(g) = CACHE.lock().unwrap().get(&path) {
+        return Ok(g);
+    }
+
     // acquire lock
     let mut recv = RW.1.lock().await;
 
@@ -174,11 +325,333 @@ pub async fn open_gcode_file(filename: PathBuf) -> anyhow::Result<Arc<GcodeFile>
     recv.recv().await.unwrap()
 }
 
+/// parse and cache gcode files in the background, returns without waiting.
+/// at most as many files as the cache holds are parsed, missing files are skipped
+pub fn prewarm(mut paths: Vec<PathBuf>) {
+    paths.truncate(GCODE_CACHE_CAP);
+
+    let _ = RW.3.send(paths);
+}
+
+/// limit the number of uploads written at the same time, at least one.
+/// must be called before the first upload, later calls are ignored
+pub fn set_max_concurrent_uploads(max: usize) {
+    if UPLOAD_PERMITS.set(Semaphore::new(max.max(1))).is_err() {
+        log::warn!("upload concurrency is already set, ignoring new limit");
+    }
+}
+
+/// write an uploaded file, parent directories are created.
+/// waits while the maximum number of uploads are being written.
+/// file io runs on the blocking thread pool, the motion loop is not stalled
+pub async fn write_upload(path: &Path, data: Vec<u8>) -> std::io::Result<()> {
+    let _permit = UPLOAD_PERMITS
+        .get_or_init(|| Semaphore::new(DEFAULT_MAX_CONCURRENT_UPLOADS))
+        .acquire()
+        .await
+        .map_err(std::io::Error::other)?;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    // readers never see a partially uploaded file
+    write_atomic(path, &data).await
+}
+
+/// write a file atomically.
+/// data is written to a temporary file in the same directory and renamed over the target,
+/// so the target is never left partially written
+pub async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    write_atomic_with(path, async |file: &mut File| file.write_all(data).await).await
+}
+
+/// write a file atomically with a custom writer.
+/// if the writer fails, the temporary file is removed and the target is untouched
+pub async fn write_atomic_with<F>(path: &Path, write: F) -> std::io::Result<()>
+where
+    F: AsyncFnOnce(&mut File) -> std::io::Result<()>,
+{
+    let filename = path
+        .file_name()
+        .ok_or(std::io::Error::other("path has no file name"))?;
+
+    // temporary file must be on the same filesystem for rename to be atomic
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(filename);
+    tmp_name.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let re: std::io::Result<()> = async {
+        let mut file = File::create(&tmp_path).await?;
+
+        write(&mut file).await?;
+
+        // make sure data reaches disk before rename
+        file.sync_all().await?;
+
+        tokio::fs::rename(&tmp_path, path).await
+    }
+    .await;
+
+    // clean up temporary file on failure
+    if re.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+
+    return re;
+}
+
+/// block size read backwards from the end of a file when looking for its last lines
+const TAIL_BLOCK_SIZE: u64 = 4096;
+
+/// the first 'count' lines of a file, without line endings.
+/// reading stops once the lines are found
+pub async fn read_head_lines(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut reader = tokio::io::BufReader::new(File::open(path).await?);
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+
+    while lines.len() < count {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            break;
+        }
+
+        lines.push(line_to_string(&line));
+    }
+
+    return Ok(lines);
+}
+
+/// the last 'count' lines of a file, without line endings.
+/// the file is read backwards from the end until the lines are found
+pub async fn read_tail_lines(path: &Path, count: usize) -> std::io::Result<Vec<String>> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = File::open(path).await?;
+    let len = file.metadata().await?.len();
+
+    if count == 0 || len == 0 {
+        return Ok(Vec::new());
+    }
+
+    // bytes from 'start' to the end of the file
+    let mut start = len;
+    let mut data = Vec::new();
+
+    loop {
+        // a newline ending the file does not start another line
+        let body = match data.last() {
+            Some(b'\n') => &data[..data.len() - 1],
+            _ => &data[..],
+        };
+
+        let newlines = body.iter().filter(|b| **b == b'\n').count();
+
+        if newlines >= count || start == 0 {
+            break;
+        }
+
+        let block = TAIL_BLOCK_SIZE.min(start);
+        start -= block;
+
+        let mut buf = vec![0; block as usize];
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+        file.read_exact(&mut buf).await?;
+
+        buf.extend_from_slice(&data);
+        data = buf;
+    }
+
+    let body = data.strip_suffix(b"\n").unwrap_or(&data);
+
+    let mut lines: Vec<String> = body
+        .rsplit(|b| *b == b'\n')
+        .take(count)
+        .map(line_to_string)
+        .collect();
+
+    lines.reverse();
+
+    return Ok(lines);
+}
+
+/// strip the line ending, invalid utf8 is replaced
+fn line_to_string(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    String::from_utf8_lossy(line).into_owned()
+}
+
+/// permission bits of a file as shown by ls, e.g. 'rw-r--r--'
+#[cfg(unix)]
+pub fn permissions_string(metadata: &std::fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
+
+    return (0..9)
+        .map(|i| match mode & (0o400 >> i) != 0 {
+            true => ['r', 'w', 'x'][i % 3],
+            false => '-',
+        })
+        .collect();
+}
+
+/// only the readonly flag is known, it applies to every class
+#[cfg(not(unix))]
+pub fn permissions_string(metadata: &std::fs::Metadata) -> String {
+    match metadata.permissions().readonly() {
+        true => "r--r--r--".to_string(),
+        false => "rw-rw-rw-".to_string(),
+    }
+}
+
+/// register a handler to be called when a file changes.
+/// the parent directory is watched so that the handler survives
+/// the file being replaced, e.g. by write_atomic.
+/// the handler is removed when it returns false
 pub async fn watch<F>(path: PathBuf, handler: F)
 where
     F: Fn(&notify::Event) -> Pin<Box<dyn Future<Output = bool>>> + Sync + Send + 'static,
 {
     let mut handlers = HANDLERS.lock().await;
 
+    // request the file watching runtime to watch the directory
+    let dir = match path.parent() {
+        Some(p) if path.is_file() => p.to_path_buf(),
+        _ => path.clone(),
+    };
+    let _ = RW.2.send(dir);
+
     handlers.push((path, Box::new(handler)));
 }
+
+#[tokio::test]
+async fn test_write_atomic_interrupted() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    let path = dir.join("printer.cfg");
+
+    write_atomic(&path, b"[printer]\nmax_velocity: 300\n")
+        .await
+        .unwrap();
+
+    // writer fails after writing part of the new config
+    let re = write_atomic_with(&path, async |file: &mut File| {
+        file.write_all(b"[printer]\nmax_vel").await?;
+        Err(std::io::Error::other("interrupted"))
+    })
+    .await;
+
+    assert!(re.is_err());
+
+    // original config is intact
+    let content = tokio::fs::read_to_string(&path).await.unwrap();
+    assert_eq!(content, "[printer]\nmax_velocity: 300\n");
+
+    // temporary file is cleaned up
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = tokio::fs::remove_dir_all(dir).await;
+}
+
+#[tokio::test]
+async fn test_prewarm() {
+    use std::time::Duration;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    // a file in use by a print job
+    let job_path = dir.join("job.gcode");
+    tokio::fs::write(&job_path, "G28\n").await.unwrap();
+    let job_file = open_gcode_file(job_path.clone()).await.unwrap();
+
+    let mut paths = Vec::new();
+
+    for i in 0..GCODE_CACHE_CAP {
+        let path = dir.join(format!("{}.gcode", i));
+        tokio::fs::write(&path, format!("G1 X{}\n", i))
+            .await
+            .unwrap();
+        paths.push(path);
+    }
+
+    // missing files are skipped
+    paths.insert(0, dir.join("missing.gcode"));
+
+    prewarm(paths.clone());
+
+    let last = paths[GCODE_CACHE_CAP - 1].canonicalize().unwrap();
+
+    let cached = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(g) = CACHE.lock().unwrap().get(&last) {
+                return g;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // opened from cache
+    let opened = open_gcode_file(last).await.unwrap();
+    assert!(Arc::ptr_eq(&cached, &opened));
+
+    // the file in use is not evicted
+    let job_path = job_path.canonicalize().unwrap();
+    let cached_job = CACHE.lock().unwrap().get(&job_path).unwrap();
+    assert!(Arc::ptr_eq(&cached_job, &job_file));
+
+    let _ = tokio::fs::remove_dir_all(dir).await;
+}
+
+#[tokio::test]
+async fn test_modified_file_evicted() {
+    use std::time::Duration;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    let path = dir.join("cube.gcode");
+    tokio::fs::write(&path, "G28\n").await.unwrap();
+
+    let file = open_gcode_file(path.clone()).await.unwrap();
+    assert_eq!(file.commands.len(), 1);
+
+    let path = path.canonicalize().unwrap();
+    assert!(CACHE.lock().unwrap().get(&path).is_some());
+
+    let mut changes = subscribe_file_changes();
+
+    tokio::fs::write(&path, "G28\nG1 X10\n").await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let change = changes.recv().await.unwrap();
+
+            if change.path == path && change.kind == FileChangeKind::Modified {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    // evicted before the event is sent
+    assert!(CACHE.lock().unwrap().get(&path).is_none());
+
+    let file = open_gcode_file(path).await.unwrap();
+    assert_eq!(file.commands.len(), 2);
+
+    let _ = tokio::fs::remove_dir_all(dir).await;
+}
@@ -0,0 +1,274 @@
+use std::f32::consts::PI;
+
+use super::GcodeFile;
+
+/// arcs are split into segments no longer than this, in mm
+const ARC_SEGMENT_LENGTH: f32 = 0.5;
+
+/// feedrate in mm/s used until the file sets one
+const DEFAULT_FEEDRATE: f32 = 25.0;
+
+/// extents and duration of a gcode file computed from its moves
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// minimum [x, y, z] of extruding moves, travel moves are ignored
+    pub min: [f32; 3],
+    /// maximum [x, y, z] of extruding moves
+    pub max: [f32; 3],
+    /// print time in seconds, acceleration is ignored
+    pub time: f32,
+}
+
+impl Estimate {
+    /// height of the printed object
+    pub fn object_height(&self) -> f32 {
+        self.max[2].max(0.0)
+    }
+}
+
+/// state tracked while walking through the moves
+struct Estimator {
+    position: [f32; 4],
+    absolute_position: bool,
+    absolute_extrusion: bool,
+    /// mm/s
+    feedrate: f32,
+    estimate: Estimate,
+}
+
+impl Estimator {
+    /// a straight segment, arcs are fed as multiple segments
+    fn segment(&mut self, to: [f32; 4]) {
+        let from = self.position;
+        let distance =
+            ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2) + (to[2] - from[2]).powi(2))
+                .sqrt();
+
+        // extrude only moves take the time of the extruded length
+        let length = match distance == 0.0 {
+            true => (to[3] - from[3]).abs(),
+            false => distance,
+        };
+
+        self.estimate.time += length / self.feedrate;
+
+        if to[3] > from[3] && distance > 0.0 {
+            for p in [from, to] {
+                let extents = self.estimate.min.iter_mut().zip(&mut self.estimate.max);
+
+                // p also holds e, zip stops after z
+                for ((min, max), v) in extents.zip(p) {
+                    *min = min.min(v);
+                    *max = max.max(v);
+                }
+            }
+        }
+
+        self.position = to;
+    }
+
+    /// target position of a move, 'param' returns the value of a parameter letter
+    fn target(&self, param: &impl Fn(char) -> Option<f32>) -> [f32; 4] {
+        let mut to = self.position;
+
+        for (i, axis) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+            if let Some(v) = param(axis) {
+                to[i] = match self.absolute_position {
+                    true => v,
+                    false => self.position[i] + v,
+                };
+            }
+        }
+
+        if let Some(e) = param('E') {
+            to[3] = match self.absolute_extrusion {
+                true => e,
+                false => self.position[3] + e,
+            };
+        }
+
+        return to;
+    }
+
+    /// G2 and G3, the center is given by I J offsets or by the radius R
+    fn arc(&mut self, to: [f32; 4], clockwise: bool, param: &impl Fn(char) -> Option<f32>) {
+        let from = self.position;
+
+        let center = match (param('I'), param('J'), param('R')) {
+            (None, None, Some(r)) => match arc_center(from, to, r, clockwise) {
+                Some(c) => c,
+                // radius too small for the chord, treat as a straight move
+                None => return self.segment(to),
+            },
+            (i, j, _) => [from[0] + i.unwrap_or(0.0), from[1] + j.unwrap_or(0.0)],
+        };
+
+        let radius = (from[0] - center[0]).hypot(from[1] - center[1]);
+        let start = (from[1] - center[1]).atan2(from[0] - center[0]);
+        let end = (to[1] - center[1]).atan2(to[0] - center[0]);
+
+        // a full circle if start and end are the same point
+        let mut sweep = end - start;
+
+        if clockwise && sweep >= 0.0 {
+            sweep -= 2.0 * PI;
+        }
+        if !clockwise && sweep <= 0.0 {
+            sweep += 2.0 * PI;
+        }
+
+        let segments = ((sweep.abs() * radius) / ARC_SEGMENT_LENGTH)
+            .ceil()
+            .max(1.0) as usize;
+
+        for n in 1..=segments {
+            let t = n as f32 / segments as f32;
+            let angle = start + sweep * t;
+
+            let point = match n == segments {
+                // land exactly on the target
+                true => to,
+                false => [
+                    center[0] + radius * angle.cos(),
+                    center[1] + radius * angle.sin(),
+                    from[2] + (to[2] - from[2]) * t,
+                    from[3] + (to[3] - from[3]) * t,
+                ],
+            };
+
+            self.segment(point);
+        }
+    }
+
+    fn command(&mut self, cmd: &str, params: &[String]) {
+        let param = |letter: char| -> Option<f32> {
+            params
+                .iter()
+                .find(|p| p.starts_with(letter) || p.starts_with(letter.to_ascii_lowercase()))
+                .and_then(|p| p[1..].parse().ok())
+        };
+
+        if let Some(f) = param('F')
+            && cmd.starts_with(['G', 'g'])
+            && f > 0.0
+        {
+            self.feedrate = f / 60.0;
+        }
+
+        match cmd.to_ascii_uppercase().as_str() {
+            "G0" | "G1" => {
+                let to = self.target(&param);
+                self.segment(to);
+            }
+            "G2" | "G3" => {
+                let to = self.target(&param);
+                self.arc(to, cmd.ends_with('2'), &param);
+            }
+            "G28" => {
+                // only the named axes are homed, all axes without parameters
+                let all = !['X', 'Y', 'Z'].into_iter().any(|a| param(a).is_some());
+
+                for (i, axis) in ['X', 'Y', 'Z'].into_iter().enumerate() {
+                    if all || param(axis).is_some() {
+                        self.position[i] = 0.0;
+                    }
+                }
+            }
+            "G90" => self.absolute_position = true,
+            "G91" => self.absolute_position = false,
+            "M82" => self.absolute_extrusion = true,
+            "M83" => self.absolute_extrusion = false,
+            "G92" => {
+                for (i, axis) in ['X', 'Y', 'Z', 'E'].into_iter().enumerate() {
+                    if let Some(v) = param(axis) {
+                        self.position[i] = v;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// center of an arc given by radius, a negative radius selects the longer arc
+fn arc_center(from: [f32; 4], to: [f32; 4], radius: f32, clockwise: bool) -> Option<[f32; 2]> {
+    let dx = to[0] - from[0];
+    let dy = to[1] - from[1];
+    let chord = dx.hypot(dy);
+
+    if chord == 0.0 || chord > 2.0 * radius.abs() {
+        return None;
+    }
+
+    // distance from the chord midpoint to the center
+    let h = (radius * radius - chord * chord / 4.0).sqrt();
+
+    // the center lies right of the chord for clockwise arcs shorter than half a circle
+    let side = match clockwise == (radius > 0.0) {
+        true => -1.0,
+        false => 1.0,
+    };
+
+    Some([
+        from[0] + dx / 2.0 - side * h * dy / chord,
+        from[1] + dy / 2.0 + side * h * dx / chord,
+    ])
+}
+
+/// walk through the moves of a file to compute its extents and print time.
+/// arcs are interpolated so their extents are included, not just the chord
+pub fn estimate(file: &GcodeFile) -> Estimate {
+    let mut estimator = Estimator {
+        position: [0.0; 4],
+        absolute_position: true,
+        absolute_extrusion: true,
+        feedrate: DEFAULT_FEEDRATE,
+        estimate: Estimate {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+            time: 0.0,
+        },
+    };
+
+    for cmd in &file.commands {
+        estimator.command(&cmd.cmd, &cmd.params);
+    }
+
+    return estimator.estimate;
+}
+
+#[test]
+fn test_arc_extents() {
+    // half circle around (10, 10) from (10, 0) to (10, 20)
+    let gcode = "G90\nM83\nG1 X10 Y0 Z0.2 F3000\n";
+
+    // clockwise passes through x = 0
+    let file = GcodeFile::blocking_parse(&format!("{}G2 X10 Y20 I0 J10 E5\n", gcode)).unwrap();
+    let e = estimate(&file);
+
+    assert!(e.min[0] < 0.01, "{:?}", e);
+    assert!((e.max[0] - 10.0).abs() < 0.01);
+    assert!((e.max[1] - 20.0).abs() < 0.01);
+    assert_eq!(e.object_height(), 0.2);
+
+    // counter clockwise passes through x = 20
+    let file = GcodeFile::blocking_parse(&format!("{}G3 X10 Y20 I0 J10 E5\n", gcode)).unwrap();
+    let e = estimate(&file);
+
+    assert!((e.min[0] - 10.0).abs() < 0.01);
+    assert!(e.max[0] > 19.99);
+
+    // radius form selects the same arc
+    let file = GcodeFile::blocking_parse(&format!("{}G2 X10 Y20 R10 E5\n", gcode)).unwrap();
+    assert!(estimate(&file).min[0] < 0.01);
+
+    // time includes the arc length, not the chord
+    let travel = (10.0f32.powi(2) + 0.2f32.powi(2)).sqrt();
+    let expected = (travel + PI * 10.0) / 50.0;
+    assert!(
+        (e.time - expected).abs() < 0.01,
+        "{} vs {}",
+        e.time,
+        expected
+    );
+}
@@ -0,0 +1,89 @@
+use std::pin::Pin;
+
+use super::vm::GcodeVM;
+
+type HandlerFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>>;
+
+/// value of the 'NAME=' parameter
+fn object_name<'a>(params: &[&'a str]) -> Option<&'a str> {
+    params.iter().find_map(|p| {
+        let (key, value) = p.split_once('=')?;
+        key.eq_ignore_ascii_case("NAME").then_some(value)
+    })
+}
+
+/// EXCLUDE_OBJECT_DEFINE NAME=<name>: objects of a print job are read from the file
+/// before printing, objects defined by other gcode are added when the command runs
+pub fn define_handler<'a>(vm: &'a GcodeVM, params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let Some(name) = object_name(params) else {
+            return Err(anyhow::Error::msg("EXCLUDE_OBJECT_DEFINE requires NAME"));
+        };
+
+        let mut objects = vm.action_queue.state.objects.lock().unwrap();
+
+        if !objects.iter().any(|o| o.eq_ignore_ascii_case(name)) {
+            objects.push(name.to_string());
+        }
+
+        return Ok(String::new());
+    })
+}
+
+/// EXCLUDE_OBJECT_START NAME=<name>: moves are skipped until the object ends if it is excluded
+pub fn start_handler<'a>(vm: &'a GcodeVM, params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let Some(name) = object_name(params) else {
+            return Err(anyhow::Error::msg("EXCLUDE_OBJECT_START requires NAME"));
+        };
+
+        let queue = &vm.action_queue;
+
+        *queue.state.current_object.lock().unwrap() = Some(name.to_string());
+
+        let excluded = queue
+            .state
+            .exclude_objects
+            .read()
+            .await
+            .iter()
+            .any(|o| o.eq_ignore_ascii_case(name));
+
+        if excluded {
+            queue.start_exclusion();
+        }
+
+        return Ok(String::new());
+    })
+}
+
+/// EXCLUDE_OBJECT_END: travel to the end of a skipped object
+pub fn end_handler<'a>(vm: &'a GcodeVM, _params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let queue = &vm.action_queue;
+
+        *queue.state.current_object.lock().unwrap() = None;
+
+        queue.end_exclusion().await;
+
+        return Ok(String::new());
+    })
+}
+
+/// EXCLUDE_OBJECT NAME=<name>: cancel an object while printing.
+/// without parameters, reports the excluded objects
+pub fn exclude_handler<'a>(vm: &'a GcodeVM, params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let queue = &vm.action_queue;
+
+        let Some(name) = object_name(params) else {
+            let excluded = queue.state.exclude_objects.read().await.join(" ");
+
+            return Ok(format!("Excluded objects: {}", excluded));
+        };
+
+        queue.exclude_object(name).await;
+
+        return Ok(String::new());
+    })
+}
@@ -6,12 +6,12 @@ use super::vm::GcodeVM;
 
 pub fn handler<'a>(
     vm: &'a GcodeVM,
-    params: &'a [String],
+    params: &'a [&'a str],
 ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
     Box::pin(handler_inner(vm, params))
 }
 
-async fn handler_inner(vm: &GcodeVM, params: &[String]) -> anyhow::Result<String> {
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
     let mut move_ = Move {
         start_velocity: f32::NAN,
         target_velocity: f32::NAN,
@@ -43,14 +43,14 @@ async fn handler_inner(vm: &GcodeVM, params: &[String]) -> anyhow::Result<String
         if !move_.target_velocity.is_nan() {
             vm.action_queue
                 .push(Action::SetVelocity(move_.target_velocity))
-                .await;
+                .await?;
             return Ok(String::new());
         }
 
         return Ok(String::new());
     }
 
-    vm.action_queue.push(Action::Move(move_)).await;
+    vm.action_queue.push(Action::Move(move_)).await?;
 
     return Ok(String::new());
 }
@@ -0,0 +1,39 @@
+use std::pin::Pin;
+
+use crate::printer::action::Action;
+
+use super::vm::GcodeVM;
+
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let mut x = false;
+    let mut y = false;
+    let mut z = false;
+
+    // values are ignored, 'G28 X0' homes x like 'G28 X'
+    for param in params {
+        match param.chars().next() {
+            Some('X' | 'x') => x = true,
+            Some('Y' | 'y') => y = true,
+            Some('Z' | 'z') => z = true,
+            _ => {}
+        }
+    }
+
+    // no axis homes all axes
+    if !x && !y && !z {
+        x = true;
+        y = true;
+        z = true;
+    }
+
+    vm.action_queue.push(Action::Home { x, y, z }).await?;
+
+    return Ok(String::new());
+}
@@ -0,0 +1,55 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// G92: set the current position of the given axes without moving.
+/// x, y and z shift the origin of following absolute moves, e resets the extruder position.
+/// axes without a value are unchanged
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    let axes = [
+        ('X', &state.x_origin, &state.x_position),
+        ('Y', &state.y_origin, &state.y_position),
+        ('Z', &state.z_origin, &state.z_position),
+    ];
+
+    for param in params {
+        let Some(letter) = param.chars().next() else {
+            continue;
+        };
+        let letter = letter.to_ascii_uppercase();
+
+        if letter == 'E' {
+            let value: f32 = fast_float::parse(&param[1..])?;
+
+            state.e_position.store(value, Ordering::SeqCst);
+            continue;
+        }
+
+        let Some((_, origin, position)) = axes.iter().find(|(l, _, _)| *l == letter) else {
+            continue;
+        };
+
+        let value: f32 = fast_float::parse(&param[1..])?;
+        let current = position.load(Ordering::SeqCst);
+
+        // an unhomed axis is declared to be at the value
+        if current.is_nan() {
+            position.store(value, Ordering::SeqCst);
+            origin.store(0.0, Ordering::SeqCst);
+        } else {
+            origin.store(current - value, Ordering::SeqCst);
+        }
+    }
+
+    return Ok(String::new());
+}
@@ -0,0 +1,133 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use crate::printer::action::{Action, Move};
+
+use super::vm::GcodeVM;
+
+type HandlerFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>>;
+
+/// state saved by SAVE_GCODE_STATE
+#[derive(Debug, Clone, Copy)]
+pub struct SavedGcodeState {
+    absolute_position: bool,
+    absolute_extrusion: bool,
+    /// feedrate in mm/s set by the F parameter
+    feedrate: f32,
+    origin: [f32; 3],
+    /// [x, y, z, e]
+    position: [f32; 4],
+}
+
+/// value of a 'KEY=value' parameter
+fn param<'a>(params: &[&'a str], name: &str) -> Option<&'a str> {
+    params.iter().find_map(|p| {
+        let (key, value) = p.split_once('=')?;
+        key.eq_ignore_ascii_case(name).then_some(value)
+    })
+}
+
+/// SAVE_GCODE_STATE [NAME=<name>]: save positioning modes, speed, offsets and position
+pub fn save_handler<'a>(vm: &'a GcodeVM, params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let name = param(params, "NAME").unwrap_or("default");
+        let state = &vm.action_queue.state;
+
+        let saved = SavedGcodeState {
+            absolute_position: state.absolute_position.load(Ordering::SeqCst),
+            absolute_extrusion: state.absolute_extrution.load(Ordering::SeqCst),
+            feedrate: state.feedrate.load(Ordering::SeqCst),
+            origin: [
+                state.x_origin.load(Ordering::SeqCst),
+                state.y_origin.load(Ordering::SeqCst),
+                state.z_origin.load(Ordering::SeqCst),
+            ],
+            position: [
+                state.x_position.load(Ordering::SeqCst),
+                state.y_position.load(Ordering::SeqCst),
+                state.z_position.load(Ordering::SeqCst),
+                state.e_position.load(Ordering::SeqCst),
+            ],
+        };
+
+        vm.saved_states
+            .lock()
+            .unwrap()
+            .insert(name.to_ascii_lowercase(), saved);
+
+        Ok(String::new())
+    })
+}
+
+/// RESTORE_GCODE_STATE [NAME=<name>] [MOVE=1 [MOVE_SPEED=<mm/s>]]:
+/// restore a state saved by SAVE_GCODE_STATE, MOVE=1 moves the toolhead back to the saved position
+pub fn restore_handler<'a>(vm: &'a GcodeVM, params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let name = param(params, "NAME").unwrap_or("default");
+
+        let Some(saved) = vm
+            .saved_states
+            .lock()
+            .unwrap()
+            .get(&name.to_ascii_lowercase())
+            .copied()
+        else {
+            return Err(anyhow::Error::msg(format!(
+                "Unknown g-code state: {}",
+                name
+            )));
+        };
+
+        let move_back = match param(params, "MOVE") {
+            Some(m) => fast_float::parse::<f32, _>(m)? != 0.0,
+            None => false,
+        };
+        let move_speed = match param(params, "MOVE_SPEED") {
+            Some(s) => fast_float::parse::<f32, _>(s)?,
+            None => f32::NAN,
+        };
+
+        let queue = &vm.action_queue;
+        let state = &queue.state;
+
+        // restored first, the move back is relative to the saved origin
+        state.x_origin.store(saved.origin[0], Ordering::SeqCst);
+        state.y_origin.store(saved.origin[1], Ordering::SeqCst);
+        state.z_origin.store(saved.origin[2], Ordering::SeqCst);
+
+        if move_back {
+            // the saved position is absolute
+            state.absolute_position.store(true, Ordering::SeqCst);
+
+            let re = queue
+                .push(Action::Move(Move {
+                    start_velocity: f32::NAN,
+                    target_velocity: move_speed,
+                    x: saved.position[0] - saved.origin[0],
+                    y: saved.position[1] - saved.origin[1],
+                    z: saved.position[2] - saved.origin[2],
+                    e: f32::NAN,
+                }))
+                .await;
+
+            state
+                .absolute_position
+                .store(saved.absolute_position, Ordering::SeqCst);
+
+            re?;
+        }
+
+        state
+            .absolute_position
+            .store(saved.absolute_position, Ordering::SeqCst);
+        state
+            .absolute_extrution
+            .store(saved.absolute_extrusion, Ordering::SeqCst);
+        state.feedrate.store(saved.feedrate, Ordering::SeqCst);
+
+        // the extruder does not move back, only its coordinate is restored
+        state.e_position.store(saved.position[3], Ordering::SeqCst);
+
+        Ok(String::new())
+    })
+}
@@ -0,0 +1,206 @@
+use gantry_api::{GcodeLintKind, PrinterGcodeLintWarning};
+
+use crate::config::PrinterConfig;
+
+/// limits from the printer config the gcode is checked against
+#[derive(Debug, Default)]
+pub struct LintLimits {
+    /// [extruder] max_temp
+    pub extruder_max_temp: Option<f64>,
+    /// [heater_bed] max_temp
+    pub bed_max_temp: Option<f64>,
+}
+
+impl LintLimits {
+    pub fn from_config(config: &PrinterConfig) -> Self {
+        Self {
+            extruder_max_temp: config
+                .get_section("extruder", None)
+                .and_then(|s| s.get_number("max_temp")),
+            bed_max_temp: config
+                .get_section("heater_bed", None)
+                .and_then(|s| s.get_number("max_temp")),
+        }
+    }
+}
+
+/// state tracked while walking through the file
+#[derive(Default)]
+struct Linter {
+    homed: bool,
+    positioning_set: bool,
+    extrusion_mode_set: bool,
+    extruder_heated: bool,
+    bed_heated: bool,
+    /// messages of warnings that are only reported once
+    reported: Vec<&'static str>,
+    warnings: Vec<PrinterGcodeLintWarning>,
+}
+
+impl Linter {
+    fn warn(&mut self, line: u64, kind: GcodeLintKind, message: String) {
+        self.warnings.push(PrinterGcodeLintWarning {
+            line,
+            kind,
+            message,
+        });
+    }
+
+    /// report a warning only for the first occurrence
+    fn warn_once(&mut self, line: u64, kind: GcodeLintKind, message: &'static str) {
+        if self.reported.contains(&message) {
+            return;
+        }
+        self.reported.push(message);
+        self.warn(line, kind, message.to_string());
+    }
+
+    fn check_temperature(&mut self, line: u64, cmd: &str, target: f64, max: Option<f64>) {
+        if let Some(max) = max
+            && target > max
+        {
+            self.warn(
+                line,
+                GcodeLintKind::TemperatureAboveMax,
+                format!("{} sets temperature {} above max_temp {}", cmd, target, max),
+            );
+        }
+    }
+
+    fn lint_line(&mut self, line: u64, text: &str, limits: &LintLimits) {
+        // remove comment at line end
+        let text = match text.split_once(';') {
+            Some((l, _)) => l,
+            None => text,
+        };
+
+        let Some(cmd) = text.split_whitespace().next() else {
+            return;
+        };
+        let cmd = cmd.to_ascii_uppercase();
+
+        // parameters are a letter followed by a number
+        let param = |letter: char| -> Option<f64> {
+            text.split_whitespace()
+                .skip(1)
+                .find(|p| p.starts_with(letter) || p.starts_with(letter.to_ascii_lowercase()))
+                .map(|p| p[1..].parse::<f64>().unwrap_or(0.0))
+        };
+
+        match cmd.as_str() {
+            "G28" => self.homed = true,
+            "G90" | "G91" => self.positioning_set = true,
+            "M82" | "M83" => self.extrusion_mode_set = true,
+            "M104" | "M109" => {
+                let target = param('S').unwrap_or(0.0);
+                self.extruder_heated |= target > 0.0;
+                self.check_temperature(line, &cmd, target, limits.extruder_max_temp);
+            }
+            "M140" | "M190" => {
+                let target = param('S').unwrap_or(0.0);
+                self.bed_heated |= target > 0.0;
+                self.check_temperature(line, &cmd, target, limits.bed_max_temp);
+            }
+            "G0" | "G1" | "G2" | "G3" => {
+                let moves_axis =
+                    param('X').is_some() || param('Y').is_some() || param('Z').is_some();
+                let extrudes = param('E').is_some_and(|e| e > 0.0);
+
+                if !self.positioning_set && (moves_axis || extrudes) {
+                    self.warn_once(
+                        line,
+                        GcodeLintKind::PositioningModeNotSet,
+                        "move before positioning mode is set with G90 or G91",
+                    );
+                }
+
+                if moves_axis && !self.homed {
+                    self.warn_once(
+                        line,
+                        GcodeLintKind::MoveBeforeHome,
+                        "move before axes are homed with G28",
+                    );
+                }
+
+                if extrudes {
+                    self.lint_extrusion(line);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lint_extrusion(&mut self, line: u64) {
+        if !self.extruder_heated {
+            self.warn_once(
+                line,
+                GcodeLintKind::ExtrudeBeforePreheat,
+                "extrusion before the extruder is heated with M104 or M109",
+            );
+        }
+
+        if !self.bed_heated {
+            self.warn_once(
+                line,
+                GcodeLintKind::ExtrudeBeforePreheat,
+                "extrusion before the bed is heated with M140 or M190",
+            );
+        }
+
+        if !self.extrusion_mode_set {
+            self.warn_once(
+                line,
+                GcodeLintKind::PositioningModeNotSet,
+                "extrusion before extrusion mode is set with M82 or M83",
+            );
+        }
+    }
+}
+
+/// check gcode for common slicer mistakes, warnings are ordered by line
+pub fn lint(input: &str, limits: &LintLimits) -> Vec<PrinterGcodeLintWarning> {
+    let mut linter = Linter::default();
+
+    for (i, line) in input.lines().enumerate() {
+        linter.lint_line(i as u64 + 1, line, limits);
+    }
+
+    return linter.warnings;
+}
+
+#[test]
+fn test_lint_move_before_home() {
+    let gcode = "\
+; generated by slicer
+G90
+M83
+M140 S60
+M104 S300
+G1 X10 Y10 F3000
+G28
+G1 X20 Y20 E1.5
+";
+
+    let limits = LintLimits {
+        extruder_max_temp: Some(250.0),
+        bed_max_temp: Some(110.0),
+    };
+
+    let warnings = lint(gcode, &limits);
+
+    assert_eq!(warnings.len(), 2);
+
+    assert_eq!(warnings[0].line, 5);
+    assert_eq!(warnings[0].kind, GcodeLintKind::TemperatureAboveMax);
+
+    assert_eq!(warnings[1].line, 6);
+    assert_eq!(warnings[1].kind, GcodeLintKind::MoveBeforeHome);
+
+    // cold extrusion without positioning mode
+    let warnings = lint("G28\nG1 X1 E2\n", &LintLimits::default());
+    let kinds: Vec<_> = warnings.iter().map(|w| w.kind).collect();
+
+    assert!(kinds.contains(&GcodeLintKind::ExtrudeBeforePreheat));
+    assert!(kinds.contains(&GcodeLintKind::PositioningModeNotSet));
+    assert!(!kinds.contains(&GcodeLintKind::MoveBeforeHome));
+}
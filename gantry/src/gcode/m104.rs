@@ -0,0 +1,38 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use crate::printer::action::Action;
+
+use super::vm::GcodeVM;
+
+/// M104: set the target temperature of an extruder without waiting.
+/// 'T' selects the extruder, the active extruder by default
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let (index, temp) = super::m104::parse_params(vm, params)?;
+
+    vm.action_queue
+        .push(Action::SetExtruderTemp { index, temp })
+        .await?;
+
+    return Ok(String::new());
+}
+
+/// 'S' target temperature and 'T' extruder index of M104 and M109
+pub(super) fn parse_params(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<(usize, f32)> {
+    let mut index = vm.action_queue.state.active_extruder.load(Ordering::SeqCst);
+
+    for param in params {
+        if param.starts_with('T') || param.starts_with('t') {
+            index = param[1..].parse()?;
+        }
+    }
+
+    return Ok((index, super::m140::parse_temperature(params)?));
+}
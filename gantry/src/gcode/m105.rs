@@ -0,0 +1,21 @@
+use std::pin::Pin;
+
+use super::vm::GcodeVM;
+
+/// M105: report the temperature of the active extruder,
+/// with the precision set in [reporting]
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    _params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm))
+}
+
+async fn handler_inner(vm: &GcodeVM) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    return Ok(format!(
+        "T:{}",
+        state.format_temperature(state.active_extruder_temperature())
+    ));
+}
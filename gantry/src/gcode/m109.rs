@@ -0,0 +1,24 @@
+use std::pin::Pin;
+
+use crate::printer::action::Action;
+
+use super::vm::GcodeVM;
+
+/// M109: set the target temperature of an extruder and wait until it is reached.
+/// queued moves complete before waiting, 'T' selects the extruder, the active extruder by default
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let (index, temp) = super::m104::parse_params(vm, params)?;
+
+    vm.action_queue
+        .push(Action::SetExtruderTempWait { index, temp })
+        .await?;
+
+    return Ok(String::new());
+}
@@ -0,0 +1,48 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// M114: report the commanded position of the toolhead,
+/// in the unit and precision set in [reporting]. unhomed axes are omitted
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    _params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm))
+}
+
+async fn handler_inner(vm: &GcodeVM) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    // positions are reported relative to the origin set by G92,
+    // unhomed axes have no position and are left out
+    let axes = [
+        ("X", &state.x_position, &state.x_origin),
+        ("Y", &state.y_position, &state.y_origin),
+        ("Z", &state.z_position, &state.z_origin),
+    ];
+
+    let mut report = String::new();
+
+    for (name, position, origin) in axes {
+        let position = position.load(Ordering::SeqCst);
+
+        if position.is_nan() {
+            continue;
+        }
+
+        report += &format!(
+            "{}:{} ",
+            name,
+            state.format_length(position - origin.load(Ordering::SeqCst))
+        );
+    }
+
+    report += &format!(
+        "E:{}",
+        state.format_length(state.e_position.load(Ordering::SeqCst))
+    );
+
+    return Ok(report);
+}
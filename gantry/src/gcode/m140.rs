@@ -0,0 +1,44 @@
+use std::pin::Pin;
+
+use gantry_api::PrinterErrorCode;
+
+use crate::printer::action::{Action, ValidationError};
+
+use super::vm::GcodeVM;
+
+/// M140: set the target temperature of the heated bed without waiting
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let temp = super::m140::parse_temperature(params)?;
+
+    vm.action_queue.push(Action::SetBedTemp(temp)).await?;
+
+    return Ok(String::new());
+}
+
+/// 'S' target temperature in celsius, zero turns the heater off
+pub(super) fn parse_temperature(params: &[&str]) -> anyhow::Result<f32> {
+    for param in params {
+        if param.starts_with('S') || param.starts_with('s') {
+            let temp: f32 = fast_float::parse(&param[1..])?;
+
+            if temp.is_nan() || temp < 0.0 {
+                return Err(ValidationError::new(
+                    PrinterErrorCode::TemperatureOutOfRange,
+                    "temperature must not be negative",
+                )
+                .into());
+            }
+
+            return Ok(temp);
+        }
+    }
+
+    return Err(anyhow::Error::msg("missing target temperature 'S'"));
+}
@@ -0,0 +1,22 @@
+use std::pin::Pin;
+
+use crate::printer::action::Action;
+
+use super::vm::GcodeVM;
+
+/// M190: set the target temperature of the heated bed and wait until it is reached.
+/// queued moves complete before waiting
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let temp = super::m140::parse_temperature(params)?;
+
+    vm.action_queue.push(Action::SetBedTempWait(temp)).await?;
+
+    return Ok(String::new());
+}
@@ -0,0 +1,35 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// M220: set the speed factor override.
+/// 'S' is the percentage of the commanded feedrate, moves stay within max_velocity.
+/// without parameters, reports the current factor
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    for param in params {
+        if param.starts_with('S') || param.starts_with('s') {
+            let percent: f32 = fast_float::parse(&param[1..])?;
+
+            if percent.is_nan() || percent <= 0.0 {
+                return Err(anyhow::Error::msg("speed factor must be positive"));
+            }
+
+            state.speed_factor.store(percent / 100.0, Ordering::SeqCst);
+        }
+    }
+
+    return Ok(format!(
+        "Speed factor {}%",
+        state.speed_factor.load(Ordering::SeqCst) * 100.0
+    ));
+}
@@ -0,0 +1,43 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// M302: allow cold extrusion for maintenance.
+/// 'P1' allows and 'P0' disallows cold extrusion, 'S' sets min_extrude_temp.
+/// without parameters, reports the current setting
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    for param in params {
+        if param.starts_with('P') || param.starts_with('p') {
+            let allow: f32 = fast_float::parse(&param[1..])?;
+            state
+                .allow_cold_extrude
+                .store(allow != 0.0, Ordering::SeqCst);
+        }
+        if param.starts_with('S') || param.starts_with('s') {
+            let temp: f32 = fast_float::parse(&param[1..])?;
+            state.min_extrude_temp.store(temp, Ordering::SeqCst);
+        }
+    }
+
+    let allowed = if state.allow_cold_extrude.load(Ordering::SeqCst) {
+        "allowed"
+    } else {
+        "disallowed"
+    };
+
+    return Ok(format!(
+        "Cold extrudes are {} (min temp {})",
+        allowed,
+        state.min_extrude_temp.load(Ordering::SeqCst)
+    ));
+}
@@ -0,0 +1,15 @@
+use std::pin::Pin;
+
+use super::vm::GcodeVM;
+
+/// M400: wait until all queued moves and actions have finished
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    _params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(async move {
+        vm.action_queue.wait_drained().await;
+
+        Ok(String::new())
+    })
+}
@@ -1,5 +1,24 @@
+pub mod estimate;
+mod exclude_object;
 mod g1;
+mod g28;
+mod g92;
+mod gcode_state;
+pub mod lint;
+mod m104;
+mod m105;
+mod m109;
+mod m114;
+mod m140;
+mod m190;
+mod m220;
+mod m302;
+mod m400;
+pub mod objects;
 mod parser;
+mod positioning;
+mod set_velocity_limit;
+mod tool;
 pub mod vm;
 
 pub use parser::GcodeFile;
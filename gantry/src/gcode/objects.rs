@@ -0,0 +1,102 @@
+use gantry_api::PrinterGcodeObject;
+
+use super::GcodeFile;
+
+/// parse a point 'x,y'
+fn parse_point(s: &str) -> Option<(f32, f32)> {
+    let (x, y) = s.split_once(',')?;
+
+    return Some((x.trim().parse().ok()?, y.trim().parse().ok()?));
+}
+
+/// parse a polygon '[[x,y],[x,y],...]'
+fn parse_polygon(s: &str) -> Vec<(f32, f32)> {
+    s.split("],")
+        .filter_map(|p| parse_point(p.trim_matches(|c| c == '[' || c == ']')))
+        .collect()
+}
+
+/// returns the objects defined by EXCLUDE_OBJECT_DEFINE in file order.
+/// the center falls back to the middle of the bounding box,
+/// the bounding box falls back to the center if no polygon is given
+pub fn defined_objects(file: &GcodeFile) -> Vec<PrinterGcodeObject> {
+    let mut objects = Vec::new();
+
+    for cmd in &file.commands {
+        if !cmd.cmd.eq_ignore_ascii_case("EXCLUDE_OBJECT_DEFINE") {
+            continue;
+        }
+
+        let mut name = None;
+        let mut center = None;
+        let mut polygon = Vec::new();
+
+        for param in &cmd.params {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+
+            match key.to_ascii_uppercase().as_str() {
+                "NAME" => name = Some(value.to_string()),
+                "CENTER" => center = parse_point(value),
+                "POLYGON" => polygon = parse_polygon(value),
+                _ => {}
+            }
+        }
+
+        // an object without name cannot be excluded
+        let Some(name) = name else {
+            continue;
+        };
+
+        let (min_x, min_y, max_x, max_y) = match polygon.first() {
+            Some(&(x, y)) => polygon.iter().fold((x, y, x, y), |b, &(x, y)| {
+                (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y))
+            }),
+            None => {
+                let (x, y) = center.unwrap_or_default();
+                (x, y, x, y)
+            }
+        };
+
+        let (center_x, center_y) = center.unwrap_or(((min_x + max_x) / 2.0, (min_y + max_y) / 2.0));
+
+        objects.push(PrinterGcodeObject {
+            name,
+            center_x,
+            center_y,
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        });
+    }
+
+    return objects;
+}
+
+#[test]
+fn test_defined_objects() {
+    let file = GcodeFile::blocking_parse(include_str!("../../tests/OrcaBenchy.gcode")).unwrap();
+    let objects = defined_objects(&file);
+
+    assert_eq!(objects.len(), 1);
+    assert_eq!(objects[0].name, "3dbenchy.stl_id_0_copy_0");
+    assert_eq!((objects[0].center_x, objects[0].center_y), (150.0, 150.0));
+    assert_eq!((objects[0].min_x, objects[0].max_x), (120.0, 180.0));
+
+    // center is derived from the polygon if missing
+    let file = GcodeFile::blocking_parse(
+        "EXCLUDE_OBJECT_DEFINE NAME=part_1 POLYGON=[[10,20],[30,20],[30,60],[10,60]]\n\
+         EXCLUDE_OBJECT_DEFINE NAME=part_2 CENTER=100,100\n",
+    )
+    .unwrap();
+    let objects = defined_objects(&file);
+
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[0].name, "part_1");
+    assert_eq!((objects[0].center_x, objects[0].center_y), (20.0, 40.0));
+    assert_eq!((objects[0].min_y, objects[0].max_y), (20.0, 60.0));
+    assert_eq!(objects[1].name, "part_2");
+    assert_eq!((objects[1].min_x, objects[1].max_x), (100.0, 100.0));
+}
@@ -13,6 +13,16 @@ use tokio::io::BufReader;
 #[grammar = "gcode/gcode.pest"]
 struct GcodeParser;
 
+/// options for parsing a gcode file
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseOptions {
+    /// keep the source text of every command in 'GcodeCommand::raw'
+    pub raw: bool,
+    /// keep unrecognized metadata and config comments in 'GcodeFile::extra'
+    /// instead of discarding them, parsing is strict by default
+    pub lenient: bool,
+}
+
 #[derive(Debug, Default)]
 pub struct GcodeFile {
     pub slicer: SlicerInfo,
@@ -20,37 +30,87 @@ pub struct GcodeFile {
     pub meta: Meta,
     pub config: SlicerConfig,
     pub commands: Vec<GcodeCommand>,
+    /// unrecognized '; key = value' comments, only filled by lenient parsing
+    pub extra: HashMap<String, String>,
 }
 
 impl GcodeFile {
     pub fn blocking_parse(input: &str) -> anyhow::Result<GcodeFile> {
+        Self::blocking_parse_with(input, ParseOptions::default())
+    }
+
+    pub fn blocking_parse_with(input: &str, options: ParseOptions) -> anyhow::Result<GcodeFile> {
+        // create gcode file
+        let mut gcode_file = GcodeFile::default();
+
+        // start of the lines not yet parsed by pest
+        let mut chunk_start = 0;
+        let mut offset = 0;
+
+        // moves take the fast path, the lines between them are parsed by pest
+        for line in input.split_inclusive('\n') {
+            if let Some(cmd) = GcodeCommand::fast_parse(line, options.raw) {
+                gcode_file.parse_chunk(&input[chunk_start..offset], options)?;
+                gcode_file.commands.push(cmd);
+
+                chunk_start = offset + line.len();
+            }
+
+            offset += line.len();
+        }
+
+        gcode_file.parse_chunk(&input[chunk_start..], options)?;
+
+        gcode_file.detect_slicer();
+
+        return Ok(gcode_file);
+    }
+
+    /// parse whole lines with the pest grammar
+    fn parse_chunk(&mut self, input: &str, options: ParseOptions) -> anyhow::Result<()> {
+        if input.is_empty() {
+            return Ok(());
+        }
+
         let mut pairs = GcodeParser::parse(Rule::GcodeFile, input)?;
 
         let pair = pairs.next().unwrap();
 
-        // create gcode file
-        let mut gcode_file = GcodeFile::default();
+        let gcode_file = self;
 
         for p in pair.into_inner() {
             match p.as_rule() {
                 Rule::SlicerInfo => gcode_file.slicer = SlicerInfo::parse_pairs(p),
                 // thumbnail info, begin thumbnail
-                Rule::Thumbnail => gcode_file.thumbnails.push(Thumbnail::parse_pairs(p)),
+                Rule::Thumbnail => gcode_file.thumbnails.extend(Thumbnail::parse_pairs(p)),
                 // a gcode line
-                Rule::GcodeLine => gcode_file.commands.push(GcodeCommand::parse_pairs(p)),
+                Rule::GcodeLine => gcode_file
+                    .commands
+                    .push(GcodeCommand::parse_pairs(p, options.raw)),
                 // a metadata line
                 Rule::Meta => gcode_file.meta.append_pair(p),
                 // a config line
                 Rule::Config => gcode_file.config.append_pair(p),
+                // an unrecognized metadata line
+                Rule::Extra => gcode_file.append_extra(p, options),
                 Rule::EOI => {}
-                _ => unreachable!(),
+                // grammar and parser out of sync
+                _ if options.lenient => gcode_file.append_unknown(p),
+                rule => log::debug!("skipped gcode rule {:?}", rule),
             }
         }
 
-        return Ok(gcode_file);
+        return Ok(());
     }
 
     pub async fn async_parse<R: AsyncRead + Unpin>(file: R) -> anyhow::Result<GcodeFile> {
+        Self::async_parse_with(file, ParseOptions::default()).await
+    }
+
+    pub async fn async_parse_with<R: AsyncRead + Unpin>(
+        file: R,
+        options: ParseOptions,
+    ) -> anyhow::Result<GcodeFile> {
         // reader
         let mut reader = BufReader::new(file);
         // buffer for reader
@@ -63,6 +123,13 @@ impl GcodeFile {
             // decode utf8
             let line = core::str::from_utf8(&buffer)?;
 
+            // moves do not need pest
+            if let Some(cmd) = GcodeCommand::fast_parse(line, options.raw) {
+                gcode_file.commands.push(cmd);
+                buffer.clear();
+                continue;
+            }
+
             // parse a line
             let mut pairs = GcodeParser::parse(Rule::GcodeFileLine, &line)?;
 
@@ -107,24 +174,29 @@ impl GcodeFile {
                         }
                         // if thumbnail is not ended, treat it as comment and discard
                         if ended {
-                            // decode base64 data
-                            let data = base64::prelude::BASE64_STANDARD
-                                .decode(base64_data)
-                                .unwrap();
-                            // push thumbnail to file
-                            gcode_file
-                                .thumbnails
-                                .push(Thumbnail::new(width, height, data));
+                            // decode base64 data, corrupt thumbnails are discarded
+                            match base64::prelude::BASE64_STANDARD.decode(base64_data) {
+                                Ok(data) => gcode_file
+                                    .thumbnails
+                                    .push(Thumbnail::new(width, height, data)),
+                                Err(e) => log::warn!("invalid thumbnail data: {}", e),
+                            }
                         }
                     }
                     // a gcode line
-                    Rule::GcodeLine => gcode_file.commands.push(GcodeCommand::parse_pairs(p)),
+                    Rule::GcodeLine => gcode_file
+                        .commands
+                        .push(GcodeCommand::parse_pairs(p, options.raw)),
                     // a metadata line
                     Rule::Meta => gcode_file.meta.append_pair(p),
                     // a config line
                     Rule::Config => gcode_file.config.append_pair(p),
+                    // an unrecognized metadata line
+                    Rule::Extra => gcode_file.append_extra(p, options),
                     Rule::EOI => {}
-                    _ => unreachable!(),
+                    // grammar and parser out of sync
+                    _ if options.lenient => gcode_file.append_unknown(p),
+                    rule => log::debug!("skipped gcode rule {:?}", rule),
                 }
             }
 
@@ -132,19 +204,95 @@ impl GcodeFile {
             buffer.clear();
         }
 
+        gcode_file.detect_slicer();
+
         return Ok(gcode_file);
     }
+
+    /// keep an unrecognized '; key = value' comment if parsing is lenient
+    fn append_extra(&mut self, pair: Pair<Rule>, options: ParseOptions) {
+        if !options.lenient {
+            return;
+        }
+
+        let mut p = pair.into_inner();
+
+        let key = p.next().unwrap().as_str().trim().to_string();
+        let value = p.next().unwrap().as_str().trim().to_string();
+
+        self.extra.insert(key, value);
+    }
+
+    /// keep a line the parser does not handle, keyed by its rule
+    fn append_unknown(&mut self, pair: Pair<Rule>) {
+        log::debug!("unhandled gcode rule {:?}", pair.as_rule());
+
+        self.extra.insert(
+            format!("{:?}", pair.as_rule()),
+            pair.as_str().trim().to_string(),
+        );
+    }
+
+    /// infer the slicer if the file has no 'generated by' line
+    fn detect_slicer(&mut self) {
+        if self.slicer.slicer.is_some() {
+            return;
+        }
+
+        // config keys only written by a single slicer family, in order of precedence
+        const SLICER_CONFIG_KEYS: &[(&str, &str)] = &[
+            ("prusaslicer_config", "PrusaSlicer"),
+            ("superslicer_config", "SuperSlicer"),
+            ("wall_loops", "OrcaSlicer"),
+            ("perimeters", "PrusaSlicer"),
+        ];
+
+        let slicer = SLICER_CONFIG_KEYS
+            .iter()
+            .find(|(key, _)| self.config.properties.contains_key(*key))
+            .map(|(_, slicer)| *slicer)
+            .or(self.meta.dialect);
+
+        self.slicer.slicer = slicer.map(String::from);
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct GcodeCommand {
     pub cmd: String,
     pub params: Vec<String>,
+    /// trimmed source line including comments, only kept if 'ParseOptions::raw' is set
+    pub raw: Option<String>,
 }
 
 impl GcodeCommand {
-    fn parse_pairs(pair: Pair<Rule>) -> Self {
-        let mut line = pair.as_str();
+    /// parse G0, G1 and G92 lines without pest, they make up most of a file.
+    /// returns none for any other line
+    fn fast_parse(line: &str, keep_raw: bool) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        let rest = line
+            .strip_prefix("G0")
+            .or_else(|| line.strip_prefix("G1"))
+            .or_else(|| line.strip_prefix("G92"))?;
+
+        // 'G10', 'G1X10' and the like take the pest path
+        if !(rest.is_empty() || rest.starts_with([' ', '\t', ';'])) {
+            return None;
+        }
+
+        return Some(Self::parse_line(line, keep_raw));
+    }
+
+    fn parse_pairs(pair: Pair<Rule>, keep_raw: bool) -> Self {
+        Self::parse_line(pair.as_str(), keep_raw)
+    }
+
+    fn parse_line(mut line: &str, keep_raw: bool) -> Self {
+        let raw = match keep_raw {
+            true => Some(line.trim().to_string()),
+            false => None,
+        };
 
         // remove comment at line end
         if let Some((l, _)) = line.split_once(';') {
@@ -171,6 +319,7 @@ impl GcodeCommand {
         return Self {
             cmd: command.to_string(),
             params,
+            raw,
         };
     }
 }
@@ -193,7 +342,7 @@ impl SlicerInfo {
                 Rule::SlicerVersion => info.version = Some(p.as_str().to_string()),
                 Rule::Date => info.date = Some(p.as_str().to_string()),
                 Rule::Time => info.time = Some(p.as_str().to_string()),
-                _ => unreachable!(),
+                rule => log::debug!("skipped gcode rule {:?}", rule),
             }
         }
 
@@ -218,7 +367,8 @@ impl Thumbnail {
         }
     }
 
-    fn parse_pairs(pair: Pair<Rule>) -> Self {
+    /// none if the data is not valid base64
+    fn parse_pairs(pair: Pair<Rule>) -> Option<Self> {
         let mut width = 0;
         let mut height = 0;
         let mut base64_data = Vec::new();
@@ -232,11 +382,11 @@ impl Thumbnail {
                             // e.g. 300x300
                             Rule::ThumbnailPixels => {
                                 let (w, h) = i.as_str().split_once('x').unwrap();
-                                width = w.parse().unwrap();
-                                height = h.parse().unwrap();
+                                width = w.parse().unwrap_or_default();
+                                height = h.parse().unwrap_or_default();
                             }
                             Rule::ThumbnailBytes => {}
-                            _ => unreachable!(),
+                            rule => log::debug!("skipped gcode rule {:?}", rule),
                         }
                     }
                 }
@@ -244,20 +394,24 @@ impl Thumbnail {
                     // push the base64 data
                     base64_data.extend_from_slice(p.as_str()[1..].trim().as_bytes());
                 }
-                _ => unreachable!(),
+                rule => log::debug!("skipped gcode rule {:?}", rule),
             }
         }
 
         // decode base64 data
-        let data = base64::prelude::BASE64_STANDARD
-            .decode(base64_data)
-            .unwrap();
+        let data = match base64::prelude::BASE64_STANDARD.decode(base64_data) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("invalid thumbnail data: {}", e);
+                return None;
+            }
+        };
 
-        return Thumbnail {
+        return Some(Thumbnail {
             width,
             height,
             data,
-        };
+        });
     }
 
     fn parse_info(pair: Pair<Rule>) -> (u32, u32) {
@@ -270,11 +424,11 @@ impl Thumbnail {
                 // e.g. 300x300
                 Rule::ThumbnailPixels => {
                     let (w, h) = i.as_str().split_once('x').unwrap();
-                    width = w.parse().unwrap();
-                    height = h.parse().unwrap();
+                    width = w.parse().unwrap_or_default();
+                    height = h.parse().unwrap_or_default();
                 }
                 Rule::ThumbnailBytes => {}
-                _ => unreachable!(),
+                rule => log::debug!("skipped gcode rule {:?}", rule),
             }
         }
 
@@ -305,100 +459,141 @@ pub struct Meta {
     pub estimated_print_time: Option<u64>,
     /// estimated first layer print time in seconds
     pub estimated_first_layer_print_time: Option<u64>,
+    /// slicer inferred from the metadata comment format
+    pub dialect: Option<&'static str>,
 }
 
 impl Meta {
     fn append_pair(&mut self, pair: Pair<Rule>) {
         for p in pair.into_inner() {
+            match p.as_rule() {
+                Rule::CuraPrintTime | Rule::CuraFilamentUsed | Rule::CuraLayerCount => {
+                    self.dialect = Some("Cura")
+                }
+                Rule::S3dBuildTime
+                | Rule::S3dFilamentLength
+                | Rule::S3dPlasticVolume
+                | Rule::S3dPlasticWeight
+                | Rule::S3dMaterialCost => self.dialect = Some("Simplify3D"),
+                _ => {}
+            }
+
             match p.as_rule() {
                 Rule::FilamentLengthUsed => {
                     self.filament_length_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::FilamentVolumeUsed => {
                     self.filament_volume_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::FilamentWeightUsed => {
                     self.filament_weight_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::FilamentCost => {
                     self.filament_cost =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::TotalFilamentLengthUsed => {
                     self.total_filament_length_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::TotalFilamentVolumeUsed => {
                     self.filament_volume_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::TotalFilamentWeightUsed => {
                     self.filament_weight_used =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::TotalLayersCount => {
-                    self.total_layers_count =
-                        Some(p.into_inner().next().unwrap().as_str().parse().unwrap());
+                    self.total_layers_count = p.into_inner().next().unwrap().as_str().parse().ok();
                 }
                 Rule::TotalFilamentWeightUsedWipeTower => {
                     self.total_filament_used_wipe_tower =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::TotalFilamentCost => {
                     self.total_filament_cost =
-                        Some(fast_float::parse(p.into_inner().next().unwrap().as_str()).unwrap());
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
                 }
                 Rule::EstimatedPrintTime => {
-                    let time = p.into_inner().next().unwrap();
-
-                    let mut t = 0;
-
-                    for i in time.into_inner() {
-                        match i.as_rule() {
-                            Rule::PrintTimeHour => {
-                                t += i.as_str().parse::<u64>().unwrap() * 60 * 60;
-                            }
-                            Rule::PrintTimeMinute => {
-                                t += i.as_str().parse::<u64>().unwrap() * 60;
-                            }
-                            Rule::PrintTimeSeconds => {
-                                t += i.as_str().parse::<u64>().unwrap();
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-
-                    self.estimated_print_time = Some(t)
+                    self.estimated_print_time =
+                        Self::parse_print_time(p.into_inner().next().unwrap());
                 }
                 Rule::EstimatedFirstLayerPrintTime => {
-                    let time = p.into_inner().next().unwrap();
-
-                    let mut t = 0;
+                    self.estimated_first_layer_print_time =
+                        Self::parse_print_time(p.into_inner().next().unwrap());
+                }
+                Rule::CuraPrintTime => {
+                    self.estimated_print_time =
+                        p.into_inner().next().unwrap().as_str().parse().ok();
+                }
+                Rule::CuraFilamentUsed => {
+                    // one length in meters for each extruder
+                    let mut meters = 0.0;
 
-                    for i in time.into_inner() {
-                        match i.as_rule() {
-                            Rule::PrintTimeHour => {
-                                t += i.as_str().parse::<u64>().unwrap() * 60 * 60;
-                            }
-                            Rule::PrintTimeMinute => {
-                                t += i.as_str().parse::<u64>().unwrap() * 60;
-                            }
-                            Rule::PrintTimeSeconds => {
-                                t += i.as_str().parse::<u64>().unwrap();
-                            }
-                            _ => unreachable!(),
-                        }
+                    for i in p.into_inner() {
+                        meters +=
+                            fast_float::parse::<f32, _>(i.into_inner().next().unwrap().as_str())
+                                .unwrap_or_default();
                     }
 
-                    self.estimated_first_layer_print_time = Some(t)
+                    self.filament_length_used = Some(meters * 1000.0);
+                }
+                Rule::CuraLayerCount => {
+                    self.total_layers_count = p.into_inner().next().unwrap().as_str().parse().ok();
+                }
+                Rule::S3dBuildTime => {
+                    self.estimated_print_time =
+                        Self::parse_print_time(p.into_inner().next().unwrap());
                 }
-                _ => unreachable!(),
+                Rule::S3dFilamentLength => {
+                    self.filament_length_used =
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
+                }
+                Rule::S3dPlasticVolume => {
+                    // mm^3 to cm3
+                    let volume: Option<f32> =
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
+                    self.filament_volume_used = volume.map(|v| v / 1000.0);
+                }
+                Rule::S3dPlasticWeight => {
+                    self.filament_weight_used =
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
+                }
+                Rule::S3dMaterialCost => {
+                    self.filament_cost =
+                        fast_float::parse(p.into_inner().next().unwrap().as_str()).ok();
+                }
+                rule => log::debug!("skipped gcode rule {:?}", rule),
             }
         }
     }
+
+    /// returns the print time in seconds, none if it overflows
+    fn parse_print_time(time: Pair<Rule>) -> Option<u64> {
+        let mut t: u64 = 0;
+
+        for i in time.into_inner() {
+            let unit = match i.as_rule() {
+                Rule::PrintTimeHour => 60 * 60,
+                Rule::PrintTimeMinute => 60,
+                Rule::PrintTimeSeconds => 1,
+                rule => {
+                    log::debug!("skipped gcode rule {:?}", rule);
+                    continue;
+                }
+            };
+
+            let value: u64 = i.as_str().parse().ok()?;
+
+            t = t.checked_add(value.checked_mul(unit)?)?;
+        }
+
+        return Some(t);
+    }
 }
 
 #[derive(Debug, Default)]
@@ -415,6 +610,88 @@ impl SlicerConfig {
 
         self.properties.insert(name, value);
     }
+
+    /// first layer bed temperature in celsius, key names differ between slicers
+    pub fn bed_temperature(&self) -> Option<f32> {
+        self.first_number(&[
+            "first_layer_bed_temperature",
+            "hot_plate_temp_initial_layer",
+            "bed_temperature",
+            "hot_plate_temp",
+            "material_bed_temperature_layer_0",
+            "material_bed_temperature",
+        ])
+    }
+
+    /// first layer temperature of the first extruder in celsius
+    pub fn extruder_temperature(&self) -> Option<f32> {
+        self.first_number(&[
+            "first_layer_temperature",
+            "nozzle_temperature_initial_layer",
+            "temperature",
+            "nozzle_temperature",
+            "material_print_temperature_layer_0",
+            "material_print_temperature",
+        ])
+    }
+
+    /// chamber temperature in celsius, zero if not controlled
+    pub fn chamber_temperature(&self) -> Option<f32> {
+        self.first_number(&[
+            "chamber_temperature",
+            "chamber_temperatures",
+            "build_volume_temperature",
+        ])
+    }
+
+    /// nozzle diameter of the first extruder in mm
+    pub fn nozzle_diameter(&self) -> Option<f32> {
+        self.first_number(&["nozzle_diameter", "machine_nozzle_size"])
+    }
+
+    /// layer height in mm
+    pub fn layer_height(&self) -> Option<f32> {
+        self.first_number(&["layer_height"])
+    }
+
+    /// first layer height in mm
+    pub fn first_layer_height(&self) -> Option<f32> {
+        self.first_number(&[
+            "first_layer_height",
+            "initial_layer_print_height",
+            "layer_height_0",
+        ])
+    }
+
+    /// material of the first extruder, e.g. 'PLA'
+    pub fn filament_type(&self) -> Option<&str> {
+        self.first_string(&["filament_type", "material_type"])
+    }
+
+    /// filament profile of the first extruder
+    pub fn filament_name(&self) -> Option<&str> {
+        self.first_string(&["filament_settings_id", "material_name"])
+    }
+
+    /// value of the first key found without quotes, multi extruder values are separated
+    fn first_string(&self, keys: &[&str]) -> Option<&str> {
+        keys.iter().find_map(|key| {
+            let value = self.properties.get(*key)?;
+            let first = value.split(';').next()?.trim().trim_matches('"');
+
+            (!first.is_empty()).then_some(first)
+        })
+    }
+
+    /// number of the first key found, multi extruder values are comma separated
+    fn first_number(&self, keys: &[&str]) -> Option<f32> {
+        keys.iter().find_map(|key| {
+            let value = self.properties.get(*key)?;
+            let first = value.split([',', ';']).next()?.trim();
+
+            fast_float::parse::<f32, _>(first).ok()
+        })
+    }
 }
 
 #[tokio::test]
@@ -461,3 +738,220 @@ fn test_blocking() {
         assert!(gf.meta.estimated_print_time == Some(*est_time));
     }
 }
+
+#[tokio::test]
+async fn test_cura_meta() {
+    const HEADER: &str = "\
+;FLAVOR:Marlin
+;TIME:6666
+;Filament used: 1.5m, 0.25m
+;Layer height: 0.2
+;LAYER_COUNT:120
+G28
+G1 X10 Y10 E1
+";
+
+    let gf = GcodeFile::blocking_parse(HEADER).unwrap();
+
+    assert_eq!(gf.meta.estimated_print_time, Some(6666));
+    assert_eq!(gf.meta.filament_length_used, Some(1750.0));
+    assert_eq!(gf.meta.total_layers_count, Some(120));
+    assert_eq!(gf.commands.len(), 2);
+
+    let gf = GcodeFile::async_parse(HEADER.as_bytes()).await.unwrap();
+
+    assert_eq!(gf.meta.estimated_print_time, Some(6666));
+    assert_eq!(gf.meta.filament_length_used, Some(1750.0));
+}
+
+#[tokio::test]
+async fn test_simplify3d_meta() {
+    const FOOTER: &str = "\
+G28
+G1 X10 Y10 E1
+; Build Summary
+;   Build time: 1 hours 23 minutes
+;   Filament length: 4567.8 mm (4.57 m)
+;   Plastic volume: 10987.65 mm^3 (10.99 cc)
+;   Plastic weight: 13.74 g (0.03 lb)
+;   Material cost: 0.27
+";
+
+    let gf = GcodeFile::blocking_parse(FOOTER).unwrap();
+
+    assert_eq!(gf.meta.estimated_print_time, Some(60 * 60 + 23 * 60));
+    assert_eq!(gf.meta.filament_length_used, Some(4567.8));
+    assert_eq!(gf.meta.filament_weight_used, Some(13.74));
+    assert_eq!(gf.meta.filament_cost, Some(0.27));
+
+    let gf = GcodeFile::async_parse(FOOTER.as_bytes()).await.unwrap();
+
+    assert_eq!(gf.meta.estimated_print_time, Some(60 * 60 + 23 * 60));
+    assert_eq!(gf.meta.filament_length_used, Some(4567.8));
+}
+
+#[test]
+fn test_detect_slicer() {
+    // PrusaSlicer config block without the 'generated by' line
+    const GCODE: &str = "\
+M107
+G28
+G1 X10 Y10 E1
+; prusaslicer_config = begin
+; perimeters = 2
+; layer_height = 0.2
+; prusaslicer_config = end
+";
+
+    let gf = GcodeFile::blocking_parse(GCODE).unwrap();
+
+    assert_eq!(gf.slicer.slicer.as_deref(), Some("PrusaSlicer"));
+
+    // explicit header takes precedence
+    let gf = GcodeFile::blocking_parse(include_str!("../../tests/OrcaBenchy.gcode")).unwrap();
+
+    assert_eq!(gf.slicer.slicer.as_deref(), Some("OrcaSlicer"));
+
+    let gf = GcodeFile::blocking_parse(";TIME:60\nG28\n").unwrap();
+
+    assert_eq!(gf.slicer.slicer.as_deref(), Some("Cura"));
+}
+
+#[tokio::test]
+async fn test_raw_text() {
+    const GCODE: &str = "\
+G28
+G1  X10 Y10   E1 ; outer wall
+  M104 S200
+";
+
+    let options = ParseOptions {
+        raw: true,
+        ..Default::default()
+    };
+    let expected = ["G28", "G1  X10 Y10   E1 ; outer wall", "M104 S200"];
+
+    let gf = GcodeFile::blocking_parse_with(GCODE, options).unwrap();
+    let raw: Vec<_> = gf
+        .commands
+        .iter()
+        .map(|c| c.raw.as_deref().unwrap())
+        .collect();
+    assert_eq!(raw, expected);
+
+    let gf = GcodeFile::async_parse_with(GCODE.as_bytes(), options)
+        .await
+        .unwrap();
+    let raw: Vec<_> = gf
+        .commands
+        .iter()
+        .map(|c| c.raw.as_deref().unwrap())
+        .collect();
+    assert_eq!(raw, expected);
+
+    // not kept by default
+    let gf = GcodeFile::blocking_parse(GCODE).unwrap();
+    assert!(gf.commands.iter().all(|c| c.raw.is_none()));
+    assert_eq!(gf.commands[1].params, ["X10", "Y10", "E1"]);
+}
+
+#[tokio::test]
+async fn test_fast_path() {
+    let input = include_str!("../../tests/OrcaBenchy.gcode");
+
+    // everything through pest
+    let mut expected = GcodeFile::default();
+    expected
+        .parse_chunk(input, ParseOptions::default())
+        .unwrap();
+    expected.detect_slicer();
+
+    let gf = GcodeFile::blocking_parse(input).unwrap();
+
+    assert_eq!(gf.commands, expected.commands);
+    assert_eq!(format!("{:?}", gf.meta), format!("{:?}", expected.meta));
+    assert_eq!(gf.config.properties, expected.config.properties);
+    assert_eq!(gf.slicer.slicer, expected.slicer.slicer);
+    assert_eq!(gf.thumbnails.len(), expected.thumbnails.len());
+
+    // the async parser matches pest line by line, config and thumbnail lines
+    // may differ from a whole file parse
+    let gf = GcodeFile::async_parse(input.as_bytes()).await.unwrap();
+
+    assert_eq!(gf.commands, expected.commands);
+    assert_eq!(format!("{:?}", gf.meta), format!("{:?}", expected.meta));
+
+    // lines that look like moves but are not
+    let gf = GcodeFile::blocking_parse("G10\nG1X10\nG1 X10 ; move\nG92 E0\nG0\n").unwrap();
+    let cmds: Vec<_> = gf.commands.iter().map(|c| c.cmd.as_str()).collect();
+    assert_eq!(cmds, ["G10", "G1X10", "G1", "G92", "G0"]);
+}
+
+#[tokio::test]
+async fn test_lenient() {
+    const GCODE: &str = "\
+; custom_field = 1
+; custom field: two words
+; filament used [mm] = unknown
+G28
+";
+
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+
+    let gf = GcodeFile::blocking_parse_with(GCODE, options).unwrap();
+
+    // key value comments are slicer config
+    assert_eq!(gf.config.properties["custom_field"], "1");
+    // other metadata is kept
+    assert_eq!(gf.extra["custom field"], "two words");
+    assert_eq!(gf.extra["filament used [mm]"], "unknown");
+    assert_eq!(gf.meta.filament_length_used, None);
+    assert_eq!(gf.commands.len(), 1);
+
+    let gf = GcodeFile::async_parse_with(GCODE.as_bytes(), options)
+        .await
+        .unwrap();
+
+    assert_eq!(gf.config.properties["custom_field"], "1");
+    assert_eq!(gf.extra["custom field"], "two words");
+    assert_eq!(gf.extra["filament used [mm]"], "unknown");
+
+    // strict parsing discards them
+    let gf = GcodeFile::blocking_parse(GCODE).unwrap();
+
+    assert!(gf.extra.is_empty());
+    assert_eq!(gf.commands.len(), 1);
+}
+
+#[tokio::test]
+async fn test_unexpected_lines() {
+    // valid grammar the parser used to panic on
+    const GCODE: &str = "\
+; thumbnail begin 16x16 8
+
+; thumbnail begin 99999999999x16 8
+; abc=def
+; thumbnail end
+;TIME:99999999999999999999999
+;LAYER_COUNT:99999999999
+; estimated printing time (normal mode) = 99999999999999999h
+G28
+";
+
+    let gf = GcodeFile::blocking_parse(GCODE).unwrap();
+
+    assert!(gf.thumbnails.is_empty());
+    assert_eq!(gf.meta.estimated_print_time, None);
+    assert_eq!(gf.meta.total_layers_count, None);
+    assert_eq!(gf.commands.len(), 1);
+
+    let gf = GcodeFile::async_parse(GCODE.as_bytes()).await.unwrap();
+
+    assert!(gf.thumbnails.is_empty());
+    assert_eq!(gf.meta.estimated_print_time, None);
+    assert_eq!(gf.meta.total_layers_count, None);
+    assert_eq!(gf.commands.len(), 1);
+}
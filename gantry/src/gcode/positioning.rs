@@ -0,0 +1,54 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+type HandlerFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>>;
+
+/// G90: absolute positioning, extrusion becomes absolute as well until M83
+pub fn g90_handler<'a>(vm: &'a GcodeVM, _params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let state = &vm.action_queue.state;
+
+        state.absolute_position.store(true, Ordering::SeqCst);
+        state.absolute_extrution.store(true, Ordering::SeqCst);
+
+        Ok(String::new())
+    })
+}
+
+/// G91: relative positioning, extrusion becomes relative as well until M82
+pub fn g91_handler<'a>(vm: &'a GcodeVM, _params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        let state = &vm.action_queue.state;
+
+        state.absolute_position.store(false, Ordering::SeqCst);
+        state.absolute_extrution.store(false, Ordering::SeqCst);
+
+        Ok(String::new())
+    })
+}
+
+/// M82: absolute extrusion, x, y and z are not affected
+pub fn m82_handler<'a>(vm: &'a GcodeVM, _params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        vm.action_queue
+            .state
+            .absolute_extrution
+            .store(true, Ordering::SeqCst);
+
+        Ok(String::new())
+    })
+}
+
+/// M83: relative extrusion, x, y and z are not affected
+pub fn m83_handler<'a>(vm: &'a GcodeVM, _params: &'a [&'a str]) -> HandlerFuture<'a> {
+    Box::pin(async move {
+        vm.action_queue
+            .state
+            .absolute_extrution
+            .store(false, Ordering::SeqCst);
+
+        Ok(String::new())
+    })
+}
@@ -0,0 +1,63 @@
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// SET_VELOCITY_LIMIT [VELOCITY=<mm/s>] [ACCEL=<mm/s^2>] [SQUARE_CORNER_VELOCITY=<mm/s>]
+/// [MINIMUM_CRUISE_RATIO=<ratio>]: change the motion limits until the next restart.
+/// reports the current limits
+pub fn handler<'a>(
+    vm: &'a GcodeVM,
+    params: &'a [&'a str],
+) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>> {
+    Box::pin(handler_inner(vm, params))
+}
+
+async fn handler_inner(vm: &GcodeVM, params: &[&str]) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    let limits = [
+        ("VELOCITY", &state.max_velocity),
+        ("ACCEL", &state.max_accel),
+        ("SQUARE_CORNER_VELOCITY", &state.square_corner_velocity),
+        ("MINIMUM_CRUISE_RATIO", &state.minimum_cruise_ratio),
+    ];
+
+    // parsed first, so an invalid parameter changes nothing
+    let mut values = Vec::new();
+
+    for param in params {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let Some((name, limit)) = limits.iter().find(|(n, _)| key.eq_ignore_ascii_case(n)) else {
+            continue;
+        };
+
+        let value: f32 = fast_float::parse(value)?;
+
+        let valid = match *name {
+            "VELOCITY" | "ACCEL" => value > 0.0,
+            "SQUARE_CORNER_VELOCITY" => value >= 0.0,
+            _ => (0.0..1.0).contains(&value),
+        };
+
+        if !valid {
+            return Err(anyhow::Error::msg(format!("invalid {}: {}", name, value)));
+        }
+
+        values.push((*limit, value));
+    }
+
+    for (limit, value) in values {
+        limit.store(value, Ordering::SeqCst);
+    }
+
+    return Ok(format!(
+        "max_velocity: {}\nmax_accel: {}\nsquare_corner_velocity: {}\nminimum_cruise_ratio: {}",
+        state.max_velocity.load(Ordering::SeqCst),
+        state.max_accel.load(Ordering::SeqCst),
+        state.square_corner_velocity.load(Ordering::SeqCst),
+        state.minimum_cruise_ratio.load(Ordering::SeqCst),
+    ));
+}
@@ -0,0 +1,28 @@
+use std::sync::atomic::Ordering;
+
+use super::vm::GcodeVM;
+
+/// index of a lowercase tool change command, e.g. 2 for 't2'
+pub fn tool_index(command: &str) -> Option<usize> {
+    let index = command.strip_prefix('t')?;
+
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    index.parse().ok()
+}
+
+/// T<n>: select the active extruder, the index must be configured
+pub async fn select(vm: &GcodeVM, index: usize) -> anyhow::Result<String> {
+    let state = &vm.action_queue.state;
+
+    state.check_extruder(index)?;
+
+    // moves already queued are extruded by the previous extruder
+    vm.action_queue.flush().await;
+
+    state.active_extruder.store(index, Ordering::SeqCst);
+
+    return Ok(String::new());
+}
@@ -1,28 +1,52 @@
 use std::pin::Pin;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use ahash::AHashMap;
-use tokio::fs::File;
 
 use crate::printer::action::ActionQueue;
 
-use super::parser::GcodeFile;
+use super::gcode_state::SavedGcodeState;
+use super::parser::{GcodeCommand, GcodeFile};
 
 pub type GcodeHandler = Box<
     dyn for<'a> Fn(
             &'a GcodeVM,
-            &'a [String],
+            &'a [&'a str],
         )
             -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + Sync + 'a>>
         + Send
         + Sync,
 >;
 
+/// execution time statistics of a gcode command
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcodeTiming {
+    /// number of times the command ran
+    pub count: u64,
+    /// total time spent in handler
+    pub total: Duration,
+    /// longest run
+    pub max: Duration,
+    /// number of runs exceeding the slow threshold
+    pub slow_count: u64,
+}
+
 pub struct GcodeVM {
     suspended: AtomicBool,
+    /// gcode files wait between commands while paused
+    paused: tokio::sync::watch::Sender<bool>,
     pub(super) action_queue: Arc<ActionQueue>,
     functions: AHashMap<String, GcodeHandler>,
+    /// records handler execution time when enabled
+    timing_enabled: AtomicBool,
+    /// runs longer than this are counted as slow, in microseconds
+    slow_threshold_us: AtomicU64,
+    /// timing statistics keyed by lowercase command
+    timings: std::sync::Mutex<AHashMap<String, GcodeTiming>>,
+    /// states saved by SAVE_GCODE_STATE, keyed by lowercase name
+    pub(super) saved_states: std::sync::Mutex<AHashMap<String, SavedGcodeState>>,
 }
 
 impl GcodeVM {
@@ -31,17 +55,115 @@ impl GcodeVM {
 
         functions.insert("g0".into(), Box::new(super::g1::handler));
         functions.insert("g1".into(), Box::new(super::g1::handler));
+        functions.insert("g28".into(), Box::new(super::g28::handler));
+        functions.insert("g90".into(), Box::new(super::positioning::g90_handler));
+        functions.insert("g91".into(), Box::new(super::positioning::g91_handler));
+        functions.insert("g92".into(), Box::new(super::g92::handler));
+        functions.insert("m82".into(), Box::new(super::positioning::m82_handler));
+        functions.insert("m83".into(), Box::new(super::positioning::m83_handler));
+        functions.insert("m104".into(), Box::new(super::m104::handler));
+        functions.insert("m105".into(), Box::new(super::m105::handler));
+        functions.insert("m109".into(), Box::new(super::m109::handler));
+        functions.insert("m114".into(), Box::new(super::m114::handler));
+        functions.insert("m140".into(), Box::new(super::m140::handler));
+        functions.insert("m190".into(), Box::new(super::m190::handler));
+        functions.insert("m220".into(), Box::new(super::m220::handler));
+        functions.insert("m302".into(), Box::new(super::m302::handler));
+        functions.insert("m400".into(), Box::new(super::m400::handler));
+        functions.insert(
+            "set_velocity_limit".into(),
+            Box::new(super::set_velocity_limit::handler),
+        );
+        functions.insert(
+            "save_gcode_state".into(),
+            Box::new(super::gcode_state::save_handler),
+        );
+        functions.insert(
+            "restore_gcode_state".into(),
+            Box::new(super::gcode_state::restore_handler),
+        );
+        functions.insert(
+            "exclude_object_define".into(),
+            Box::new(super::exclude_object::define_handler),
+        );
+        functions.insert(
+            "exclude_object_start".into(),
+            Box::new(super::exclude_object::start_handler),
+        );
+        functions.insert(
+            "exclude_object_end".into(),
+            Box::new(super::exclude_object::end_handler),
+        );
+        functions.insert(
+            "exclude_object".into(),
+            Box::new(super::exclude_object::exclude_handler),
+        );
 
         Self {
             suspended: AtomicBool::new(false),
+            paused: tokio::sync::watch::Sender::new(false),
             action_queue,
             functions,
+            timing_enabled: AtomicBool::new(false),
+            slow_threshold_us: AtomicU64::new(50_000),
+            timings: std::sync::Mutex::new(AHashMap::new()),
+            saved_states: std::sync::Mutex::new(AHashMap::new()),
+        }
+    }
+
+    /// enable or disable execution timing, clears collected statistics
+    pub fn configure_timing(&self, enabled: bool, slow_threshold: Duration) {
+        self.timing_enabled.store(enabled, Ordering::SeqCst);
+        self.slow_threshold_us
+            .store(slow_threshold.as_micros() as u64, Ordering::SeqCst);
+        self.timings.lock().unwrap().clear();
+    }
+
+    /// returns timing statistics keyed by command
+    pub fn timings(&self) -> AHashMap<String, GcodeTiming> {
+        self.timings.lock().unwrap().clone()
+    }
+
+    fn record_timing(&self, command: &str, elapsed: Duration) {
+        let slow_threshold = Duration::from_micros(self.slow_threshold_us.load(Ordering::SeqCst));
+
+        let mut timings = self.timings.lock().unwrap();
+
+        let timing = match timings.get_mut(command) {
+            Some(t) => t,
+            None => timings.entry(command.to_string()).or_default(),
+        };
+
+        timing.count += 1;
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+
+        if elapsed > slow_threshold {
+            timing.slow_count += 1;
         }
     }
 
     /// abort the vm, abort any running gcodes
     pub fn suspend(&self) {
         self.suspended.store(true, Ordering::SeqCst);
+        // wake paused files so they can abort
+        self.paused.send_replace(false);
+    }
+
+    /// pause gcode files before their next command.
+    /// gcode strings still run, so macros can move the toolhead while paused
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// continue paused gcode files
+    pub fn unpause(&self) {
+        self.paused.send_replace(false);
+    }
+
+    #[cfg(test)]
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
     }
 
     /// resume the vm
@@ -53,18 +175,62 @@ impl GcodeVM {
         self.suspended.load(Ordering::SeqCst)
     }
 
-    pub async fn run_gcode_file(&self, file: File) -> anyhow::Result<()> {
-        let file = GcodeFile::async_parse(file).await?;
+    /// runs a parsed gcode file, gcode_running is set until it returns
+    pub async fn run_gcode_file(&self, file: &GcodeFile) -> anyhow::Result<()> {
+        self.run_gcode_file_from(file, 0).await
+    }
+
+    /// runs a parsed gcode file starting at command 'start', used to resume a print
+    pub async fn run_gcode_file_from(&self, file: &GcodeFile, start: usize) -> anyhow::Result<()> {
+        let state = &self.action_queue.state;
+
+        state.gcode_running.store(true, Ordering::SeqCst);
+        state
+            .gcode_lines
+            .store(file.commands.len(), Ordering::SeqCst);
+
+        let start = start.min(file.commands.len());
+        let re = self
+            .run_gcode_commands(&file.commands[start..], start)
+            .await;
 
-        let mut count = 0;
+        state.gcode_running.store(false, Ordering::SeqCst);
 
+        return re;
+    }
+
+    /// 'count' is the number of commands completed before the first command
+    async fn run_gcode_commands(
+        &self,
+        commands: &[GcodeCommand],
+        mut count: usize,
+    ) -> anyhow::Result<()> {
         self.action_queue
             .state
             .gcode_line
             .store(count, Ordering::SeqCst);
 
-        for cmd in file.commands {
-            self.run_gcode(&cmd.cmd, &cmd.params).await?;
+        let mut paused = self.paused.subscribe();
+
+        // reused for every command, params are borrowed from the file
+        let mut params = Vec::new();
+
+        for cmd in commands {
+            // wait for resume if paused, moves before the pause are completed first
+            if *paused.borrow() {
+                self.flush().await;
+            }
+            let _ = paused.wait_for(|p| !*p).await;
+
+            // stop the file when aborted
+            if self.is_suspended() {
+                return Err(anyhow::Error::msg("gcode execution aborted"));
+            }
+
+            params.clear();
+            params.extend(cmd.params.iter().map(String::as_str));
+
+            self.run_gcode(&cmd.cmd, &params).await?;
 
             count += 1;
 
@@ -77,25 +243,61 @@ impl GcodeVM {
         return Ok(());
     }
 
-    async fn run_gcode(&self, cmd: &str, params: &[String]) -> anyhow::Result<()> {
+    /// runs a command, returns the response of the handler
+    async fn run_gcode(&self, cmd: &str, params: &[&str]) -> anyhow::Result<String> {
         // ignore gcode if suspended
         if self.is_suspended() {
-            return Ok(());
+            return Ok(String::new());
         }
 
-        let command = cmd.to_lowercase();
+        // lowercase on the stack, commands are short
+        let mut buffer = [0u8; 64];
 
-        let handler = self
-            .functions
-            .get(&command)
-            .ok_or(anyhow::Error::msg(format!("Unknown command: {}", cmd)))?;
+        let command = match buffer.get_mut(..cmd.len()) {
+            Some(buf) => {
+                buf.copy_from_slice(cmd.as_bytes());
+                buf.make_ascii_lowercase();
+                // ascii lowercase keeps the string valid utf8
+                core::str::from_utf8(buf).unwrap()
+            }
+            None => return Err(anyhow::Error::msg(format!("Unknown command: {}", cmd))),
+        };
+
+        let Some(handler) = self.functions.get(command) else {
+            // T0, T1... are not registered individually
+            if let Some(index) = super::tool::tool_index(command) {
+                return super::tool::select(self, index).await;
+            }
 
-        (handler)(self, &params).await?;
+            return Err(anyhow::Error::msg(format!("Unknown command: {}", cmd)));
+        };
 
-        return Ok(());
+        // fast path without instrumentation
+        if !self.timing_enabled.load(Ordering::Relaxed) {
+            return (handler)(self, params).await;
+        }
+
+        let start = Instant::now();
+        let re = (handler)(self, params).await;
+
+        self.record_timing(command, start.elapsed());
+
+        return re;
     }
 
     pub async fn run_gcode_string(&self, input: &str) -> anyhow::Result<()> {
+        self.run_gcode_string_with(input, |_, _| {}).await
+    }
+
+    /// like run_gcode_string, 'on_line' is called with every line run and its response
+    pub async fn run_gcode_string_with(
+        &self,
+        input: &str,
+        mut on_line: impl FnMut(&str, Result<&str, &anyhow::Error>),
+    ) -> anyhow::Result<()> {
+        // reused for every line, params are borrowed from the input
+        let mut params = Vec::new();
+
         // split each line
         for line in input.split_terminator('\n') {
             // return immediately when abort
@@ -103,19 +305,38 @@ impl GcodeVM {
                 return Ok(());
             }
             // run a line of gcode
-            self.run_single_line_gcode_string(line.trim()).await?;
+            let re = self.run_gcode_line_with(line, &mut params).await;
+            on_line(line, re.as_ref().map(|r| r.as_str()));
+            re?;
         }
         // flush the action queue
-        self.action_queue.flush().await;
+        self.flush().await;
         // return
         return Ok(());
     }
 
-    /// runs a single line of gcode
-    async fn run_single_line_gcode_string(&self, mut line: &str) -> anyhow::Result<()> {
+    /// wait for queued actions to be sent
+    pub async fn flush(&self) {
+        self.action_queue.flush().await;
+    }
+
+    /// runs a single line of gcode, returns the response of the command.
+    /// moves are queued, call flush to send them
+    pub async fn run_gcode_line(&self, line: &str) -> anyhow::Result<String> {
+        self.run_gcode_line_with(line, &mut Vec::new()).await
+    }
+
+    /// 'params' is a buffer for the params of the line, reused between lines
+    async fn run_gcode_line_with<'l>(
+        &self,
+        line: &'l str,
+        params: &mut Vec<&'l str>,
+    ) -> anyhow::Result<String> {
+        let mut line = line.trim();
+
         // either it is empty or a comment
         if line == "" || line.starts_with(';') {
-            return Ok(());
+            return Ok(String::new());
         }
         // remove comment at line end
         if let Some((l, _)) = line.split_once(';') {
@@ -128,7 +349,7 @@ impl GcodeVM {
         // get the command
         let command = iter.next().unwrap();
 
-        let mut params = Vec::new();
+        params.clear();
 
         for p in iter {
             // multiple whitespace will result in empty string
@@ -136,9 +357,254 @@ impl GcodeVM {
                 continue;
             }
             // push param
-            params.push(p.to_string());
+            params.push(p);
         }
 
-        return self.run_gcode(command, &params).await;
+        return self.run_gcode(command, params).await;
     }
 }
+
+#[tokio::test]
+async fn test_gcode_timing() {
+    use crate::printer::action::ActionState;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let queue = Arc::new(ActionQueue::new(Arc::new(ActionState::new()), sender));
+    let vm = GcodeVM::new(queue);
+
+    vm.configure_timing(true, Duration::from_millis(50));
+
+    vm.run_gcode_string("G1 X1 F600\nG1 X2\nG0 Y1\n")
+        .await
+        .unwrap();
+
+    let timings = vm.timings();
+
+    assert_eq!(timings.get("g1").map(|t| t.count), Some(2));
+    assert_eq!(timings.get("g0").map(|t| t.count), Some(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_min_extrude_temp() {
+    use crate::printer::action::ActionState;
+    use crate::printer::heater::Heater;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    *state.heaters.lock().unwrap() = vec![Heater::new("extruder")];
+
+    // cold extruder
+    let err = vm.run_gcode_string("G1 E5 F300\n").await.unwrap_err();
+    assert!(err.to_string().contains("min_extrude_temp"));
+    // refused move does not change position
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 0.0);
+
+    // retraction is always allowed
+    vm.run_gcode_string("G1 E-1\n").await.unwrap();
+
+    // M104 does not wait, the extruder is still cold
+    vm.run_gcode_string("M104 S200\n").await.unwrap();
+    assert!(vm.run_gcode_string("G1 E5\n").await.is_err());
+
+    // M109 waits until the extruder is hot
+    vm.run_gcode_string("M109 S200\nG1 E5\n").await.unwrap();
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 4.0);
+
+    // maintenance override
+    vm.run_gcode_string("M104 S0\n").await.unwrap();
+    tokio::time::advance(Duration::from_secs(600)).await;
+    vm.run_gcode_string("M302 P1\nG1 E1\n").await.unwrap();
+    vm.run_gcode_string("M302 P0\n").await.unwrap();
+    assert!(vm.run_gcode_string("G1 E1\n").await.is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_max_extrusion() {
+    use crate::printer::action::ActionState;
+    use crate::printer::heater::Heater;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    *state.heaters.lock().unwrap() = vec![Heater::new("extruder")];
+    vm.run_gcode_string("M109 S200\n").await.unwrap();
+
+    // normal moves pass
+    vm.run_gcode_string("G1 E5 F300\nG1 X10 E0.5\n")
+        .await
+        .unwrap();
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 5.5);
+
+    // corrupt extrude only move
+    let err = vm.run_gcode_string("G1 E9999\n").await.unwrap_err();
+    assert!(err.to_string().contains("max_extrude_only_distance"));
+
+    // corrupt extrusion along a move
+    let err = vm.run_gcode_string("G1 X20 E9999\n").await.unwrap_err();
+    assert!(err.to_string().contains("max_extrude_cross_section"));
+
+    // refused moves do not change position
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 5.5);
+}
+
+#[tokio::test]
+async fn test_repeated_params() {
+    use crate::printer::action::ActionState;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    // allocations per command are measured in benches/gcode_vm.rs
+    let file = GcodeFile::blocking_parse("M302 P1 S170 P1 S170 P1 S170\n").unwrap();
+    vm.run_gcode_file(&file).await.unwrap();
+
+    assert!(state.allow_cold_extrude.load(Ordering::SeqCst));
+    assert_eq!(state.min_extrude_temp.load(Ordering::SeqCst), 170.0);
+}
+
+#[tokio::test]
+async fn test_save_restore_gcode_state() {
+    use crate::printer::action::ActionState;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    // homed, absolute positioning
+    state.x_position.store(10.0, Ordering::SeqCst);
+    state.y_position.store(20.0, Ordering::SeqCst);
+    state.z_position.store(1.0, Ordering::SeqCst);
+    state.absolute_position.store(true, Ordering::SeqCst);
+
+    vm.run_gcode_line("SAVE_GCODE_STATE NAME=pause")
+        .await
+        .unwrap();
+
+    // a macro switches to relative mode and moves away
+    state.absolute_position.store(false, Ordering::SeqCst);
+    vm.run_gcode_string("G1 X5 Y5 F600\n").await.unwrap();
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 15.0);
+
+    vm.run_gcode_line("RESTORE_GCODE_STATE NAME=pause MOVE=1")
+        .await
+        .unwrap();
+
+    assert!(state.absolute_position.load(Ordering::SeqCst));
+    assert_eq!(state.max_velocity.load(Ordering::SeqCst), 100.0);
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 10.0);
+    assert_eq!(state.y_position.load(Ordering::SeqCst), 20.0);
+
+    let err = vm
+        .run_gcode_line("RESTORE_GCODE_STATE NAME=missing")
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown g-code state"));
+}
+
+#[tokio::test]
+async fn test_m400_waits_for_executor() {
+    use crate::printer::action::{Action, ActionState};
+    use std::sync::atomic::AtomicUsize;
+
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    // slow executor
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let executor = {
+        let queue = queue.clone();
+        let consumed = consumed.clone();
+
+        tokio::spawn(async move {
+            while events.recv().await.is_some() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                consumed.fetch_add(1, Ordering::SeqCst);
+                queue.action_completed();
+            }
+        })
+    };
+
+    for temp in [50.0, 60.0, 70.0] {
+        queue.push(Action::SetBedTemp(temp)).await.unwrap();
+    }
+
+    // actions are sent but not consumed yet
+    assert_eq!(consumed.load(Ordering::SeqCst), 0);
+
+    vm.run_gcode_line("M400").await.unwrap();
+
+    assert_eq!(consumed.load(Ordering::SeqCst), 3);
+
+    executor.abort();
+}
+
+#[tokio::test]
+async fn test_feedrate_persists() {
+    use crate::printer::action::ActionState;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    vm.run_gcode_string("G1 X1 F600\nG1 X2\nG1 X3\n")
+        .await
+        .unwrap();
+
+    // the feed of the first move is kept, the config limit is not touched
+    assert_eq!(state.feedrate.load(Ordering::SeqCst), 10.0);
+    assert_eq!(state.max_velocity.load(Ordering::SeqCst), 100.0);
+
+    // the override scales the feed without replacing it
+    vm.run_gcode_string("M220 S50\nG1 F1200\nG1 X4\n")
+        .await
+        .unwrap();
+
+    assert_eq!(state.feedrate.load(Ordering::SeqCst), 20.0);
+    assert_eq!(state.speed_factor.load(Ordering::SeqCst), 0.5);
+    assert_eq!(state.max_velocity.load(Ordering::SeqCst), 100.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_report_precision() {
+    use crate::printer::action::ActionState;
+    use crate::printer::heater::Heater;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue);
+
+    state.x_position.store(10.0, Ordering::SeqCst);
+    state.y_position.store(20.125, Ordering::SeqCst);
+    state.z_position.store(0.2, Ordering::SeqCst);
+    *state.heaters.lock().unwrap() = vec![Heater::new("extruder")];
+    vm.run_gcode_line("M109 S210.456").await.unwrap();
+
+    state.report_precision.store(2, Ordering::SeqCst);
+
+    assert_eq!(
+        vm.run_gcode_line("M114").await.unwrap(),
+        "X:10.00 Y:20.13 Z:0.20 E:0.00"
+    );
+    assert_eq!(vm.run_gcode_line("M105").await.unwrap(), "T:210.46");
+
+    state.report_inches.store(true, Ordering::SeqCst);
+
+    assert_eq!(
+        vm.run_gcode_line("M114").await.unwrap(),
+        "X:0.39 Y:0.79 Z:0.01 E:0.00"
+    );
+    // temperatures are not converted
+    assert_eq!(vm.run_gcode_line("M105").await.unwrap(), "T:210.46");
+}
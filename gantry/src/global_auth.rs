@@ -1,10 +1,144 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
 use axum::extract::{Query, Request};
 use axum::http::StatusCode;
 use axum::middleware::Next;
 use axum::response::Response;
 use axum_auth::AuthBearer;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use serde::{Deserialize, Serialize};
+
+/// bearer tokens expire after this duration, the refresh token issues a new one
+const BEARER_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// refresh tokens expire after this duration, the user must login again
+const REFRESH_TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// rotated refresh tokens remembered to detect their reuse, oldest are forgotten first
+const MAX_USED_REFRESH_TOKENS: usize = 1024;
+
+/// password of a user until it is reset
+pub const DEFAULT_PASSWORD: &str = "gantry";
+
+/// header of every token, only HS256 is issued and accepted
+const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+type HmacSha256 = Hmac<Sha256>;
+
+lazy_static::lazy_static! {
+    /// credentials of registered users and logged out sessions
+    static ref STORE: RwLock<AuthStore> = RwLock::new(AuthStore::default());
+
+    /// hash of the default password, shared by users that never reset it
+    static ref DEFAULT_PASSWORD_HASH: String = hash_password(DEFAULT_PASSWORD);
+}
+
+#[derive(Default)]
+struct AuthStore {
+    users: HashMap<String, Credentials>,
+    /// logged in sessions by session id
+    sessions: HashMap<String, Session>,
+    /// logged out sessions with the time their last token expires
+    revoked_sessions: HashMap<String, u64>,
+    /// ids of rotated refresh tokens, presenting one again revokes its session
+    used_refresh_tokens: VecDeque<String>,
+}
+
+impl AuthStore {
+    /// tokens of the session are refused until they expire
+    fn revoke_session(&mut self, session: &str) {
+        let now = crate::timestamp::unix_timestamp();
+
+        // forget sessions whose tokens have all expired
+        self.revoked_sessions.retain(|_, expiry| *expiry > now);
+
+        self.sessions.remove(session);
+        self.revoked_sessions
+            .insert(session.to_string(), now + REFRESH_TOKEN_LIFETIME.as_secs());
+    }
+
+    /// bearer and refresh token of a session, the refresh token replaces the previous one
+    fn issue_pair(&mut self, username: &str, session: &str) -> Option<(String, String)> {
+        let now = crate::timestamp::unix_timestamp();
+        let secret = &self.users.get(username)?.secret;
+
+        let claims = |typ, lifetime: Duration| Claims {
+            sub: username.to_string(),
+            sid: session.to_string(),
+            jti: new_id(),
+            iat: now,
+            exp: now + lifetime.as_secs(),
+            typ,
+        };
+
+        let refresh_claims = claims(TokenType::Refresh, REFRESH_TOKEN_LIFETIME);
+
+        let bearer = encode(secret, &claims(TokenType::Bearer, BEARER_TOKEN_LIFETIME));
+        let refresh = encode(secret, &refresh_claims);
+
+        self.sessions.insert(
+            session.to_string(),
+            Session {
+                refresh_jti: refresh_claims.jti,
+                expiry: refresh_claims.exp,
+            },
+        );
+
+        return Some((bearer, refresh));
+    }
+}
+
+struct Session {
+    /// id of the only refresh token accepted for the session
+    refresh_jti: String,
+    /// expiry of that refresh token
+    expiry: u64,
+}
+
+struct Credentials {
+    /// HS256 signing secret of the user's tokens
+    secret: [u8; 32],
+    /// argon2 hash in PHC string format
+    password_hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TokenType {
+    Bearer,
+    Refresh,
+}
 
-use serde::Deserialize;
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// user the token is issued to
+    sub: String,
+    /// login session, shared by a bearer token and its refresh token
+    sid: String,
+    /// unique id of the token
+    jti: String,
+    /// issued at, unix timestamp in seconds
+    iat: u64,
+    /// expiry, unix timestamp in seconds
+    exp: u64,
+    typ: TokenType,
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenError {
+    /// malformed, wrong signature, unknown user or logged out
+    Invalid,
+    /// well formed and signed, but expired
+    Expired,
+}
 
 /// query printer name
 #[derive(Deserialize)]
@@ -15,31 +149,326 @@ pub struct PrinterNameQuery {
 
 pub async fn auth_middleware(
     AuthBearer(bearer_token): AuthBearer,
-    query: Query<PrinterNameQuery>,
-    mut request: Request,
+    _query: Query<PrinterNameQuery>,
+    request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    todo!()
+    match validate_token(&bearer_token) {
+        (true, _) => Ok(next.run(request).await),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// register a user with a password hash, the hash of a registered user is replaced.
+/// a new signing secret is generated, tokens do not outlive the process
+pub fn register(username: &str, password_hash: String) {
+    let mut store = STORE.write().unwrap();
+
+    if let Some(credentials) = store.users.get_mut(username) {
+        credentials.password_hash = password_hash;
+        return;
+    }
+
+    let mut secret = [0u8; 32];
+    getrandom::fill(&mut secret).expect("no system random source");
+
+    store.users.insert(
+        username.to_string(),
+        Credentials {
+            secret,
+            password_hash,
+        },
+    );
+}
+
+/// hash of the default password, for users that never reset it
+pub fn default_password_hash() -> String {
+    DEFAULT_PASSWORD_HASH.clone()
 }
 
 /// login a user, returns bearer and refresh token
 pub fn login(username: &str, password: &str) -> Option<(String, String)> {
-    todo!()
+    let password_hash = STORE
+        .read()
+        .unwrap()
+        .users
+        .get(username)?
+        .password_hash
+        .clone();
+
+    // verified without holding the lock, hashing is slow
+    if !verify_password(&password_hash, password) {
+        return None;
+    }
+
+    let now = crate::timestamp::unix_timestamp();
+    let mut store = STORE.write().unwrap();
+
+    // forget sessions that can no longer be refreshed
+    store.sessions.retain(|_, s| s.expiry > now);
+
+    return store.issue_pair(username, &new_id());
 }
 
+/// logout the session of a bearer token, its refresh token is invalidated as well
 pub fn logout(token: &str) -> bool {
-    todo!()
+    // an expired token may still logout its refresh token
+    let claims = match decode(token) {
+        Ok(claims) => claims,
+        Err(TokenError::Expired) => match decode_expired(token) {
+            Some(claims) => claims,
+            None => return false,
+        },
+        Err(TokenError::Invalid) => return false,
+    };
+
+    if claims.typ != TokenType::Bearer {
+        return false;
+    }
+
+    STORE.write().unwrap().revoke_session(&claims.sid);
+
+    return true;
 }
 
-pub fn reset_password(token: &str, password: &str) -> bool {
-    todo!()
+/// set the password hash of the user a valid bearer token is issued to
+pub fn reset_password(token: &str, password_hash: String) -> bool {
+    let Ok(claims) = decode(token) else {
+        return false;
+    };
+
+    if claims.typ != TokenType::Bearer {
+        return false;
+    }
+
+    match STORE.write().unwrap().users.get_mut(&claims.sub) {
+        Some(credentials) => credentials.password_hash = password_hash,
+        None => return false,
+    }
+
+    return true;
 }
 
-/// refresh bearer token using refresh token
+/// refresh bearer token using refresh token.
+/// the refresh token is rotated, it cannot be used again
 pub fn refresh_token(refresh_token: &str) -> Option<(String, String)> {
-    todo!()
+    let claims = decode(refresh_token).ok()?;
+
+    if claims.typ != TokenType::Refresh {
+        return None;
+    }
+
+    let mut store = STORE.write().unwrap();
+
+    let is_current = store
+        .sessions
+        .get(&claims.sid)
+        .is_some_and(|s| s.refresh_jti == claims.jti);
+
+    if !is_current {
+        // a rotated token is presented again, it may have leaked
+        if store
+            .used_refresh_tokens
+            .iter()
+            .any(|jti| *jti == claims.jti)
+        {
+            store.revoke_session(&claims.sid);
+        }
+
+        return None;
+    }
+
+    if store.used_refresh_tokens.len() >= MAX_USED_REFRESH_TOKENS {
+        store.used_refresh_tokens.pop_front();
+    }
+    store.used_refresh_tokens.push_back(claims.jti);
+
+    return store.issue_pair(&claims.sub, &claims.sid);
 }
 
+/// returns (is_valid, is_timeout) of a bearer token
 pub fn validate_token(token: &str) -> (bool, bool) {
-    todo!()
+    match decode(token) {
+        Ok(claims) => (claims.typ == TokenType::Bearer, false),
+        Err(TokenError::Expired) => (false, true),
+        Err(TokenError::Invalid) => (false, false),
+    }
+}
+
+/// user a token is issued to, the signature is not verified
+pub fn token_subject(token: &str) -> Option<String> {
+    let payload = token.split('.').nth(1)?;
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+
+    return Some(claims.sub);
+}
+
+/// argon2 hash in PHC string format with a random salt
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    getrandom::fill(&mut salt).expect("no system random source");
+
+    let salt = SaltString::encode_b64(&salt).unwrap();
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+fn verify_password(password_hash: &str, password: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
+
+/// random id for sessions and tokens
+fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn encode(secret: &[u8], claims: &Claims) -> String {
+    let header = URL_SAFE_NO_PAD.encode(JWT_HEADER);
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap());
+
+    let message = format!("{}.{}", header, payload);
+
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(message.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    return format!("{}.{}", message, signature);
+}
+
+/// verify the signature, expiry and session of a token
+fn decode(token: &str) -> Result<Claims, TokenError> {
+    let claims = decode_expired(token).ok_or(TokenError::Invalid)?;
+
+    if STORE
+        .read()
+        .unwrap()
+        .revoked_sessions
+        .contains_key(&claims.sid)
+    {
+        return Err(TokenError::Invalid);
+    }
+
+    if claims.exp <= crate::timestamp::unix_timestamp() {
+        return Err(TokenError::Expired);
+    }
+
+    return Ok(claims);
+}
+
+/// verify the signature of a token, expiry is not checked
+fn decode_expired(token: &str) -> Option<Claims> {
+    let mut parts = token.split('.');
+
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+
+    let header: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+
+    if header["alg"] != "HS256" {
+        return None;
+    }
+
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let store = STORE.read().unwrap();
+    let secret = &store.users.get(&claims.sub)?.secret;
+
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(&token.as_bytes()[..token.rfind('.')?]);
+    mac.verify_slice(&signature).ok()?;
+
+    return Some(claims);
+}
+
+#[test]
+fn test_token_validation() {
+    let username = new_id();
+    register(&username, default_password_hash());
+
+    assert!(login(&username, "wrong password").is_none());
+
+    // a fresh token validates
+    let (bearer, refresh) = login(&username, DEFAULT_PASSWORD).unwrap();
+    assert_eq!(validate_token(&bearer), (true, false));
+    // a refresh token is not a bearer token
+    assert_eq!(validate_token(&refresh), (false, false));
+
+    // an expired but signed token reports timeout
+    let now = crate::timestamp::unix_timestamp();
+    let secret = STORE.read().unwrap().users[&username].secret;
+    let expired = encode(
+        &secret,
+        &Claims {
+            sub: username.clone(),
+            sid: new_id(),
+            jti: new_id(),
+            iat: now - 120,
+            exp: now - 60,
+            typ: TokenType::Bearer,
+        },
+    );
+    assert_eq!(validate_token(&expired), (false, true));
+
+    // a tampered token is invalid
+    let (header, rest) = bearer.split_once('.').unwrap();
+    let (payload, signature) = rest.split_once('.').unwrap();
+    let mut claims: Claims =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+    claims.exp += 3600;
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+    let tampered = format!("{}.{}.{}", header, payload, signature);
+    assert_eq!(validate_token(&tampered), (false, false));
+
+    // a token signed with another secret is invalid
+    let forged = encode(&[0u8; 32], &claims);
+    assert_eq!(validate_token(&forged), (false, false));
+
+    // logout invalidates the bearer and refresh token
+    assert!(logout(&bearer));
+    assert_eq!(validate_token(&bearer), (false, false));
+    assert!(refresh_token(&refresh).is_none());
+}
+
+#[test]
+fn test_refresh_token_rotation() {
+    let username = new_id();
+    register(&username, default_password_hash());
+
+    let (bearer, refresh) = login(&username, DEFAULT_PASSWORD).unwrap();
+
+    // rotation issues a new pair and invalidates the presented refresh token
+    let (rotated_bearer, rotated_refresh) = refresh_token(&refresh).unwrap();
+    assert_ne!(rotated_refresh, refresh);
+    assert_eq!(validate_token(&rotated_bearer), (true, false));
+
+    let (_, latest_refresh) = refresh_token(&rotated_refresh).unwrap();
+
+    // reusing a rotated token revokes the whole session
+    assert!(refresh_token(&refresh).is_none());
+    assert!(refresh_token(&latest_refresh).is_none());
+    assert_eq!(validate_token(&bearer), (false, false));
+    assert_eq!(validate_token(&rotated_bearer), (false, false));
+
+    // other sessions are not affected, logout ends only its own session
+    let (bearer, refresh) = login(&username, DEFAULT_PASSWORD).unwrap();
+    let (other_bearer, _) = login(&username, DEFAULT_PASSWORD).unwrap();
+
+    assert!(logout(&bearer));
+    assert_eq!(validate_token(&bearer), (false, false));
+    assert!(refresh_token(&refresh).is_none());
+    assert_eq!(validate_token(&other_bearer), (true, false));
 }
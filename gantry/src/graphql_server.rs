@@ -4,23 +4,21 @@ use std::task::Poll;
 
 use juniper_graphql_ws::ConnectionConfig;
 use tokio::sync::broadcast::Receiver;
-use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 
 use axum::routing::{MethodFilter, get, on};
 use axum::{Extension, Router};
 use futures::{Stream, stream::BoxStream};
-use juniper::{
-    EmptyMutation, FieldError, GraphQLEnum, GraphQLObject, graphql_object, graphql_subscription,
-};
+use juniper::{FieldError, GraphQLEnum, GraphQLObject, graphql_object, graphql_subscription};
 
 use crate::printer::Instance;
 
 /// define type for schema
-type Schema = juniper::RootNode<'static, Query, EmptyMutation, Subscription>;
+type Schema = juniper::RootNode<'static, Query, Mutation, Subscription>;
 
 /// create router for graphql service
 pub fn create_router() -> Router {
-    let schema = juniper::RootNode::new(Query, EmptyMutation::<()>::new(), Subscription);
+    let schema = juniper::RootNode::new(Query, Mutation, Subscription);
 
     Router::new()
         .route(
@@ -84,6 +82,21 @@ impl Query {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Mutation;
+
+#[graphql_object]
+impl Mutation {
+    /// request a snapshot from an external camera service.
+    /// returns immediately, false if the printer does not exist
+    pub async fn snapshot(&self, printer: String) -> bool {
+        match find_instance(&printer).await {
+            Some(inst) => inst.snapshot().result.is_some(),
+            None => false,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Server;
 
@@ -121,11 +134,170 @@ impl Printer {
         }
     }
 
+    /// configured display name, or the instance name
+    pub fn display_name(&self) -> &str {
+        self.instance.display_name()
+    }
+
+    /// location of the printer, if configured
+    pub fn location(&self) -> Option<&str> {
+        self.instance.metadata.location.as_deref()
+    }
+
+    /// printer model, if configured
+    pub fn model(&self) -> Option<&str> {
+        self.instance.metadata.model.as_deref()
+    }
+
     /// emergency stop, stops the printer immediately
     pub async fn emergency_stop(&self) -> bool {
         self.instance.emergency_stop().await;
         return true;
     }
+
+    /// current toolhead position, interpolated while a move is executing
+    pub async fn position(&self) -> Position {
+        Position::from(
+            self.instance
+                .get_position()
+                .await
+                .result
+                .unwrap_or_default(),
+        )
+    }
+
+    /// position, homed axes, feedrate and positioning modes
+    pub async fn motion(&self) -> Motion {
+        Motion::from(
+            self.instance
+                .get_motion_state()
+                .await
+                .result
+                .unwrap_or_default(),
+        )
+    }
+
+    /// motion limits in effect, including changes by SET_VELOCITY_LIMIT
+    pub async fn motion_limits(&self) -> MotionLimits {
+        MotionLimits::from(
+            self.instance
+                .get_motion_limits()
+                .await
+                .result
+                .unwrap_or_default(),
+        )
+    }
+
+    /// xyz bounds from position_min and position_max of the steppers
+    pub async fn build_volume(&self) -> BuildVolume {
+        BuildVolume::from(
+            self.instance
+                .get_build_volume()
+                .await
+                .result
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// toolhead position in mm
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub e: f64,
+}
+
+impl From<gantry_api::PrinterPosition> for Position {
+    fn from(p: gantry_api::PrinterPosition) -> Self {
+        Self {
+            x: p.x as f64,
+            y: p.y as f64,
+            z: p.z as f64,
+            e: p.e as f64,
+        }
+    }
+}
+
+/// toolhead position and positioning modes
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct Motion {
+    pub position: Position,
+    /// homed axes, e.g. 'xyz' or '' before homing
+    pub homed_axes: String,
+    /// feedrate in mm/s of moves without F, null until set
+    pub feedrate: Option<f64>,
+    /// G90 or G91
+    pub absolute_positioning: bool,
+    /// M82 or M83
+    pub absolute_extrusion: bool,
+}
+
+impl From<gantry_api::PrinterMotionState> for Motion {
+    fn from(m: gantry_api::PrinterMotionState) -> Self {
+        Self {
+            position: Position::from(m.position),
+            homed_axes: m.homed_axes,
+            feedrate: (!m.feedrate.is_nan()).then_some(m.feedrate as f64),
+            absolute_positioning: m.absolute_position,
+            absolute_extrusion: m.absolute_extrusion,
+        }
+    }
+}
+
+/// velocities in mm/s and acceleration in mm/s^2
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct MotionLimits {
+    pub max_velocity: f64,
+    pub max_accel: f64,
+    pub square_corner_velocity: f64,
+    pub minimum_cruise_ratio: f64,
+}
+
+impl From<gantry_api::PrinterMotionLimits> for MotionLimits {
+    fn from(l: gantry_api::PrinterMotionLimits) -> Self {
+        Self {
+            max_velocity: l.max_velocity as f64,
+            max_accel: l.max_accel as f64,
+            square_corner_velocity: l.square_corner_velocity as f64,
+            minimum_cruise_ratio: l.minimum_cruise_ratio as f64,
+        }
+    }
+}
+
+/// range of an axis in mm
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct AxisRange {
+    pub min: f64,
+    /// null if position_max is not configured
+    pub max: Option<f64>,
+}
+
+impl From<gantry_api::PrinterAxisRange> for AxisRange {
+    fn from(r: gantry_api::PrinterAxisRange) -> Self {
+        Self {
+            min: r.min as f64,
+            max: r.max.is_finite().then_some(r.max as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct BuildVolume {
+    pub x: AxisRange,
+    pub y: AxisRange,
+    pub z: AxisRange,
+}
+
+impl From<gantry_api::PrinterBuildVolume> for BuildVolume {
+    fn from(v: gantry_api::PrinterBuildVolume) -> Self {
+        Self {
+            x: AxisRange::from(v.x),
+            y: AxisRange::from(v.y),
+            z: AxisRange::from(v.z),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, GraphQLEnum)]
@@ -186,10 +358,10 @@ impl Subscription {
     /// if argument 'printer' is specified, only notify for that printer
     async fn printer_ready(&self, printer: Option<String>) -> SubStream<Printer> {
         // only subscibe to one printer
-        if let Some(name) = &printer{
-            match find_instance(name).await{
+        if let Some(name) = &printer {
+            match find_instance(name).await {
                 Some(inst) => todo!(),
-                None => return Box::pin(futures::stream::empty())
+                None => return Box::pin(futures::stream::empty()),
             }
         }
 
@@ -210,8 +382,110 @@ impl Subscription {
         todo!()
     }
 
+    /// reports toolhead position of a printer every interval
+    async fn printer_position(
+        &self,
+        printer: String,
+        #[graphql(default = 100, desc = "interval in ms at which position is sampled")]
+        interval: i32,
+    ) -> SubStream<Position> {
+        let instance = match find_instance(&printer).await {
+            Some(inst) => inst,
+            None => return Box::pin(futures::stream::empty()),
+        };
+
+        // sample no faster than every 10ms
+        let interval = std::time::Duration::from_millis(interval.max(10) as u64);
+
+        let stream = async_stream::stream! {
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                ticker.tick().await;
+
+                let position = instance.get_position().await.result.unwrap_or_default();
+
+                yield Ok(Position::from(position))
+            }
+        };
+
+        return Box::pin(stream);
+    }
+
+    /// snapshot is requested.
+    /// if argument 'printer' is specified, only notify for that printer
+    async fn snapshot_requested(&self, printer: Option<String>) -> SubStream<SnapshotRequest> {
+        let receivers = match &printer {
+            Some(name) => match find_instance(name).await {
+                Some(inst) => vec![inst.subscribe_snapshots()],
+                None => return Box::pin(futures::stream::empty()),
+            },
+            None => crate::INSTANCES
+                .read()
+                .await
+                .values()
+                .map(|i| i.subscribe_snapshots())
+                .collect(),
+        };
+
+        let streams = receivers.into_iter().map(|mut recv| {
+            Box::pin(async_stream::stream! {
+                loop {
+                    match recv.recv().await {
+                        Ok(request) => yield Ok(SnapshotRequest::from(request)),
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            })
+        });
+
+        return Box::pin(futures::stream::select_all(streams));
+    }
+
+    /// a gcode file is created, modified or removed, cached metadata of the file is stale.
+    /// if argument 'printer' is specified, only notify for that printer
     async fn file_changed(&self, printer: Option<String>) -> SubStream<FileChangeEvent> {
-        todo!()
+        let instances = match &printer {
+            Some(name) => match find_instance(name).await {
+                Some(inst) => vec![inst],
+                None => return Box::pin(futures::stream::empty()),
+            },
+            None => crate::INSTANCES.read().await.values().cloned().collect(),
+        };
+
+        // gcode directories of the printers, events report paths relative to them
+        let dirs: Vec<_> = instances
+            .iter()
+            .filter_map(|inst| inst.gcodes_path().canonicalize().ok())
+            .collect();
+
+        for dir in &dirs {
+            crate::files::watch_dir(dir.clone());
+        }
+
+        let mut recv = crate::files::subscribe_file_changes();
+
+        let stream = async_stream::stream! {
+            loop {
+                let change = match recv.recv().await {
+                    Ok(change) => change,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                let Some(path) = dirs.iter().find_map(|d| change.path.strip_prefix(d).ok()) else {
+                    continue;
+                };
+
+                yield Ok(FileChangeEvent {
+                    kind: change.kind.into(),
+                    path: path.to_string_lossy().to_string(),
+                })
+            }
+        };
+
+        return Box::pin(stream);
     }
 
     async fn print_job_start(&self, printer: Option<String>) -> SubStream<PrintJob> {
@@ -244,7 +518,7 @@ impl Subscription {
             }
         };
 
-        return Box::pin(stream)
+        return Box::pin(stream);
     }
 }
 
@@ -258,12 +532,22 @@ pub enum FileChangeEventKind {
     Removed,
 }
 
+impl From<crate::files::FileChangeKind> for FileChangeEventKind {
+    fn from(kind: crate::files::FileChangeKind) -> Self {
+        match kind {
+            crate::files::FileChangeKind::Created => Self::Create,
+            crate::files::FileChangeKind::Modified => Self::Modified,
+            crate::files::FileChangeKind::Removed => Self::Removed,
+        }
+    }
+}
+
 /// a file change event
 #[derive(Debug, Clone, GraphQLObject)]
 pub struct FileChangeEvent {
     /// kind of file change
     pub kind: FileChangeEventKind,
-    /// file name
+    /// file name relative to the gcodes directory
     pub path: String,
 }
 
@@ -272,3 +556,209 @@ pub struct PrintJob {
     /// gcode filename of the print job
     pub path: String,
 }
+
+/// a snapshot request for an external camera service
+#[derive(Debug, Clone, GraphQLObject)]
+pub struct SnapshotRequest {
+    /// name of the printer
+    pub printer: String,
+    /// unix timestamp in seconds
+    pub timestamp: f64,
+}
+
+impl From<gantry_api::PrinterSnapshotRequest> for SnapshotRequest {
+    fn from(r: gantry_api::PrinterSnapshotRequest) -> Self {
+        Self {
+            printer: r.printer,
+            timestamp: r.timestamp as f64,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_snapshot_mutation() {
+    use crate::config::InstanceConfig;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let name = format!("snapshot-{}", uuid::Uuid::new_v4());
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            name.clone(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    crate::INSTANCES
+        .write()
+        .await
+        .insert(name.clone(), inst.clone());
+
+    let mut recv = inst.subscribe_snapshots();
+
+    let schema = juniper::RootNode::new(Query, Mutation, Subscription);
+    let query = format!("mutation {{ snapshot(printer: \"{}\") }}", name);
+
+    let (value, errors) = juniper::execute(&query, None, &schema, &juniper::Variables::new(), &())
+        .await
+        .unwrap();
+
+    assert!(errors.is_empty());
+    assert_eq!(value, juniper::graphql_value!({ "snapshot": true }));
+
+    // exactly one event
+    let request = recv.try_recv().unwrap();
+    assert_eq!(request.printer, name);
+    assert!(matches!(recv.try_recv(), Err(TryRecvError::Empty)));
+
+    crate::INSTANCES.write().await.remove(&name);
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_motion_query() {
+    use crate::config::InstanceConfig;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n",
+    )
+    .unwrap();
+
+    let name = format!("motion-{}", uuid::Uuid::new_v4());
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    while !matches!(inst.state().await, crate::printer::State::Ready) {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    crate::INSTANCES
+        .write()
+        .await
+        .insert(name.clone(), inst.clone());
+
+    // M400 waits until the moves have finished
+    let re = inst
+        .run_gcode_response("G28 X Y\nG90\nG1 X10 Y5 F1200\nM400\n".to_string())
+        .await;
+    assert!(re.result.is_some());
+
+    let schema = juniper::RootNode::new(Query, Mutation, Subscription);
+    let query = format!(
+        "{{ printer(name: \"{}\") {{ motion {{ position {{ x y }} homedAxes feedrate absolutePositioning absoluteExtrusion }} }} }}",
+        name
+    );
+
+    let (value, errors) = juniper::execute(&query, None, &schema, &juniper::Variables::new(), &())
+        .await
+        .unwrap();
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        value,
+        juniper::graphql_value!({
+            "printer": {
+                "motion": {
+                    "position": { "x": 10.0, "y": 5.0 },
+                    "homedAxes": "xy",
+                    "feedrate": 20.0,
+                    "absolutePositioning": true,
+                    "absoluteExtrusion": true,
+                }
+            }
+        })
+    );
+
+    crate::INSTANCES.write().await.remove(&name);
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_build_volume_query() {
+    use crate::config::InstanceConfig;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[stepper_x]\nposition_min: -5\nposition_max: 235\n\n\
+         [stepper_y]\nposition_max: 220\n\n\
+         [stepper_z]\nposition_min: -2\n",
+    )
+    .unwrap();
+
+    let name = format!("volume-{}", uuid::Uuid::new_v4());
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    while !matches!(inst.state().await, crate::printer::State::Ready) {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    crate::INSTANCES
+        .write()
+        .await
+        .insert(name.clone(), inst.clone());
+
+    let volume = inst.get_build_volume().await.result.unwrap();
+    assert_eq!((volume.x.min, volume.x.max), (-5.0, 235.0));
+    assert_eq!((volume.y.min, volume.y.max), (0.0, 220.0));
+    assert_eq!((volume.z.min, volume.z.max), (-2.0, f32::INFINITY));
+
+    let schema = juniper::RootNode::new(Query, Mutation, Subscription);
+    let query = format!(
+        "{{ printer(name: \"{}\") {{ buildVolume {{ x {{ min max }} y {{ min max }} z {{ min max }} }} }} }}",
+        name
+    );
+
+    let (value, errors) = juniper::execute(&query, None, &schema, &juniper::Variables::new(), &())
+        .await
+        .unwrap();
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        value,
+        juniper::graphql_value!({
+            "printer": {
+                "buildVolume": {
+                    "x": { "min": -5.0, "max": 235.0 },
+                    "y": { "min": 0.0, "max": 220.0 },
+                    "z": { "min": -2.0, "max": None },
+                }
+            }
+        })
+    );
+
+    crate::INSTANCES.write().await.remove(&name);
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
@@ -0,0 +1,14 @@
+use super::{Kinematics, MotorSteps};
+
+/// each motor drives one axis
+pub struct Cartesian;
+
+impl Kinematics for Cartesian {
+    fn cartesian_to_motor(&self, x: f32, y: f32, z: f32) -> MotorSteps {
+        MotorSteps { a: x, b: y, c: z }
+    }
+
+    fn motor_to_cartesian(&self, motors: MotorSteps) -> [f32; 3] {
+        [motors.a, motors.b, motors.c]
+    }
+}
@@ -0,0 +1,36 @@
+use super::{Kinematics, MotorSteps};
+
+/// x and y are driven together by the a and b motors, c drives z
+pub struct CoreXY;
+
+impl Kinematics for CoreXY {
+    fn cartesian_to_motor(&self, x: f32, y: f32, z: f32) -> MotorSteps {
+        MotorSteps {
+            a: x + y,
+            b: x - y,
+            c: z,
+        }
+    }
+
+    fn motor_to_cartesian(&self, motors: MotorSteps) -> [f32; 3] {
+        [
+            (motors.a + motors.b) / 2.0,
+            (motors.a - motors.b) / 2.0,
+            motors.c,
+        ]
+    }
+}
+
+#[test]
+fn test_corexy_motors() {
+    // +x drives both motors equally
+    let motors = CoreXY.cartesian_to_motor(10.0, 0.0, 0.0);
+    assert_eq!((motors.a, motors.b, motors.c), (10.0, 10.0, 0.0));
+
+    // +y drives them oppositely
+    let motors = CoreXY.cartesian_to_motor(0.0, 10.0, 0.0);
+    assert_eq!((motors.a, motors.b, motors.c), (10.0, -10.0, 0.0));
+
+    let motors = CoreXY.cartesian_to_motor(3.0, -4.5, 2.0);
+    assert_eq!(CoreXY.motor_to_cartesian(motors), [3.0, -4.5, 2.0]);
+}
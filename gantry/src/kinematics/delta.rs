@@ -0,0 +1,180 @@
+use super::{Kinematics, MotorMove, MotorSteps};
+use crate::printer::action::KinematicMove;
+
+/// longest toolhead travel in mm translated as one motor move.
+/// carriage heights are not linear in the toolhead position,
+/// a longer move would bow away from the straight line
+const SEGMENT_LENGTH: f32 = 1.0;
+
+/// carriages of three towers move vertically, the toolhead hangs from them on arms.
+/// motor positions are the carriage heights
+pub struct Delta {
+    /// length of the arms from the carriages to the effector in mm
+    arm_length: f32,
+    /// tower positions [x, y] in mm
+    towers: [[f32; 2]; 3],
+}
+
+impl Delta {
+    /// towers are placed 'radius' mm from the center at 'angles' in degrees
+    pub fn new(arm_length: f32, radius: f32, angles: [f32; 3]) -> Self {
+        Self {
+            arm_length,
+            towers: angles.map(|a| {
+                let a = a.to_radians();
+                [radius * a.cos(), radius * a.sin()]
+            }),
+        }
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+impl Kinematics for Delta {
+    fn stepper_sections(&self) -> [&'static str; 3] {
+        ["stepper_a", "stepper_b", "stepper_c"]
+    }
+
+    fn cartesian_to_motor(&self, x: f32, y: f32, z: f32) -> MotorSteps {
+        let l2 = self.arm_length * self.arm_length;
+
+        let [a, b, c] = self.towers.map(|[tx, ty]| {
+            let dx = x - tx;
+            let dy = y - ty;
+            z + (l2 - dx * dx - dy * dy).sqrt()
+        });
+
+        MotorSteps { a, b, c }
+    }
+
+    fn motor_to_cartesian(&self, motors: MotorSteps) -> [f32; 3] {
+        // the toolhead is where spheres of arm_length around the carriages intersect
+        let heights = [motors.a, motors.b, motors.c];
+        let [p1, p2, p3] = [0, 1, 2].map(|i| [self.towers[i][0], self.towers[i][1], heights[i]]);
+
+        let s21 = sub(p2, p1);
+        let s31 = sub(p3, p1);
+
+        let d = dot(s21, s21).sqrt();
+        let ex = scale(s21, 1.0 / d);
+        let i = dot(ex, s31);
+        let ey = sub(s31, scale(ex, i));
+        let ey = scale(ey, 1.0 / dot(ey, ey).sqrt());
+        let ez = cross(ex, ey);
+        let j = dot(ey, s31);
+
+        // the arms have equal length
+        let x = d / 2.0;
+        let y = (i * i + j * j - 2.0 * i * x) / (2.0 * j);
+        let z = (self.arm_length * self.arm_length - x * x - y * y).sqrt();
+
+        let base = [0, 1, 2].map(|k| p1[k] + ex[k] * x + ey[k] * y);
+
+        // of the two intersections, the toolhead hangs below the carriages
+        let up = [0, 1, 2].map(|k| base[k] + ez[k] * z);
+        let down = [0, 1, 2].map(|k| base[k] - ez[k] * z);
+
+        if up[2] < down[2] { up } else { down }
+    }
+
+    fn motor_moves(&self, start: [f32; 3], m: &KinematicMove) -> Vec<MotorMove> {
+        let count = (m.abs_distance() / SEGMENT_LENGTH).ceil().max(1.0) as usize;
+        let mut from = self.cartesian_to_motor(start[0], start[1], start[2]);
+
+        (1..=count)
+            .map(|i| {
+                let t = i as f32 / count as f32;
+                let to = self.cartesian_to_motor(
+                    start[0] + m.x * t,
+                    start[1] + m.y * t,
+                    start[2] + m.z * t,
+                );
+                let motors = to - from;
+                from = to;
+
+                // extrusion is spread evenly over the segments
+                MotorMove {
+                    motors,
+                    e: m.e / count as f32,
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_delta_round_trip() {
+    let delta = Delta::new(250.0, 120.0, [210.0, 330.0, 90.0]);
+
+    // the center and a point near the c tower at 90 degrees
+    for point in [[0.0, 0.0, 0.0], [0.0, 100.0, 20.0], [-30.0, 15.5, 150.0]] {
+        let motors = delta.cartesian_to_motor(point[0], point[1], point[2]);
+        let re = delta.motor_to_cartesian(motors);
+
+        for k in 0..3 {
+            assert!((re[k] - point[k]).abs() < 1e-2, "{:?} != {:?}", re, point);
+        }
+    }
+
+    // carriages are at equal height with the toolhead at the center
+    let motors = delta.cartesian_to_motor(0.0, 0.0, 0.0);
+    assert!((motors.a - motors.b).abs() < 1e-3 && (motors.b - motors.c).abs() < 1e-3);
+}
+
+#[test]
+fn test_delta_straight_move() {
+    let delta = Delta::new(250.0, 120.0, [210.0, 330.0, 90.0]);
+
+    let start = [-30.0, -40.0, 10.0];
+    let m = KinematicMove {
+        start_velocity: 100.0,
+        acceleration: 3000.0,
+        x: 60.0,
+        y: 80.0,
+        z: 0.0,
+        e: 4.0,
+    };
+
+    // 100mm in 1mm segments
+    let moves = delta.motor_moves(start, &m);
+    assert_eq!(moves.len(), 100);
+    assert!((moves.iter().map(|m| m.e).sum::<f32>() - 4.0).abs() < 1e-3);
+
+    // follow the carriages through the segments to the middle of the move
+    let mut motors = delta.cartesian_to_motor(start[0], start[1], start[2]);
+    for m in &moves[..moves.len() / 2] {
+        motors.a += m.motors.a;
+        motors.b += m.motors.b;
+        motors.c += m.motors.c;
+    }
+
+    let midpoint = delta.motor_to_cartesian(motors);
+    let expected = [start[0] + m.x / 2.0, start[1] + m.y / 2.0, start[2]];
+
+    for k in 0..3 {
+        assert!(
+            (midpoint[k] - expected[k]).abs() < 1e-2,
+            "{:?} != {:?}",
+            midpoint,
+            expected
+        );
+    }
+}
@@ -1 +1,114 @@
+//! mapping between toolhead coordinates and motor positions
 
+mod cartesian;
+mod corexy;
+mod delta;
+
+use std::sync::Arc;
+
+pub use cartesian::Cartesian;
+pub use corexy::CoreXY;
+pub use delta::Delta;
+
+use crate::config::PrinterConfig;
+use crate::printer::action::KinematicMove;
+
+/// travel of the a, b and c motors in mm, converted to steps by the mcu
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MotorSteps {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+impl std::ops::Sub for MotorSteps {
+    type Output = MotorSteps;
+
+    fn sub(self, rhs: MotorSteps) -> MotorSteps {
+        MotorSteps {
+            a: self.a - rhs.a,
+            b: self.b - rhs.b,
+            c: self.c - rhs.c,
+        }
+    }
+}
+
+/// a kinematic move in motor coordinates.
+/// velocities are planned in toolhead coordinates, the mcu does not time steps yet
+#[derive(Debug, Clone, Copy)]
+pub struct MotorMove {
+    pub motors: MotorSteps,
+    /// extruder travel in mm
+    pub e: f32,
+}
+
+pub trait Kinematics: Send + Sync {
+    /// config sections of the a, b and c motors
+    fn stepper_sections(&self) -> [&'static str; 3] {
+        ["stepper_x", "stepper_y", "stepper_z"]
+    }
+
+    /// motor positions of a toolhead position
+    fn cartesian_to_motor(&self, x: f32, y: f32, z: f32) -> MotorSteps;
+
+    /// toolhead position [x, y, z] of motor positions
+    fn motor_to_cartesian(&self, motors: MotorSteps) -> [f32; 3];
+
+    /// translate a relative move starting at toolhead position 'start'.
+    /// only the end position is translated, which keeps the toolhead on a straight
+    /// line as long as the motor positions are linear in the toolhead position
+    fn motor_moves(&self, start: [f32; 3], m: &KinematicMove) -> Vec<MotorMove> {
+        let from = self.cartesian_to_motor(start[0], start[1], start[2]);
+        let to = self.cartesian_to_motor(start[0] + m.x, start[1] + m.y, start[2] + m.z);
+
+        vec![MotorMove {
+            motors: to - from,
+            e: m.e,
+        }]
+    }
+}
+
+/// kinematics selected by [printer] kinematics, cartesian if not set.
+/// returns an error message if the kinematics is unknown or its keys are missing
+pub fn from_config(config: &PrinterConfig) -> Result<Arc<dyn Kinematics>, String> {
+    let printer = config.get_section("printer", None);
+    let name = printer
+        .and_then(|s| s.get_string("kinematics"))
+        .unwrap_or("cartesian");
+
+    match name {
+        "cartesian" => Ok(Arc::new(Cartesian)),
+        "corexy" => Ok(Arc::new(CoreXY)),
+        "delta" => {
+            let radius = printer
+                .and_then(|s| s.get_number("delta_radius"))
+                .ok_or("delta kinematics requires [printer] delta_radius")?;
+
+            // all towers use the arm length of tower a
+            let stepper_a = config.get_section("stepper_a", None);
+            let arm_length = stepper_a
+                .and_then(|s| s.get_number("arm_length"))
+                .ok_or("delta kinematics requires [stepper_a] arm_length")?;
+
+            // angle of each tower around the center in degrees
+            let towers = [
+                ("stepper_a", 210.0),
+                ("stepper_b", 330.0),
+                ("stepper_c", 90.0),
+            ];
+            let angles = towers.map(|(name, default)| {
+                config
+                    .get_section(name, None)
+                    .and_then(|s| s.get_number("angle"))
+                    .unwrap_or(default) as f32
+            });
+
+            Ok(Arc::new(Delta::new(
+                arm_length as f32,
+                radius as f32,
+                angles,
+            )))
+        }
+        name => Err(format!("unknown kinematics '{}'", name)),
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::config::LogConfig;
+
+/// A log file that is rotated when it exceeds a size limit.
+/// rotated files are renamed to 'name.1', 'name.2' ... with 'name.1' being the newest
+pub struct RotatingFile {
+    path: PathBuf,
+    /// rotate when file size exceeds max size in bytes
+    max_size: u64,
+    /// number of rotated files to keep
+    max_files: usize,
+    file: File,
+    /// current size of file
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: PathBuf, max_size: u64, max_files: usize) -> std::io::Result<Self> {
+        // create parent directory if not exist
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// append a line to the file, rotating it first if full
+    pub fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.max_size > 0 && self.size + line.len() as u64 + 1 > self.max_size && self.size > 0 {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += line.len() as u64 + 1;
+
+        return Ok(());
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            // no backups, simply truncate
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        // shift older files, the oldest is overwritten
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated_path(i);
+
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(i + 1))?;
+            }
+        }
+
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        return Ok(());
+    }
+}
+
+/// formats a log line with unix timestamp
+pub fn format_line(level: &str, target: &str, message: &std::fmt::Arguments) -> String {
+    let now = crate::timestamp::unix_duration();
+
+    format!(
+        "{}.{:03} {:<5} {}: {}",
+        now.as_secs(),
+        now.subsec_millis(),
+        level,
+        target,
+        message
+    )
+}
+
+/// logger writing to stderr and optionally to a rotating file
+pub struct GantryLogger {
+    level: LevelFilter,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl GantryLogger {
+    pub fn new(config: &LogConfig, gantry_path: &Path) -> std::io::Result<Self> {
+        let level = config.level.parse().unwrap_or(LevelFilter::Info);
+
+        let file = match &config.file {
+            Some(f) => Some(Mutex::new(RotatingFile::open(
+                gantry_path.join(f),
+                config.max_size,
+                config.max_files,
+            )?)),
+            None => None,
+        };
+
+        Ok(Self { level, file })
+    }
+}
+
+impl Log for GantryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format_line(record.level().as_str(), record.target(), record.args());
+
+        eprintln!("{}", line);
+
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.write_line(&line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// install the global logger
+pub fn init(config: &LogConfig, gantry_path: &Path) -> anyhow::Result<()> {
+    let logger = GantryLogger::new(config, gantry_path)?;
+    let level = logger.level;
+
+    // logger lives for the rest of the program
+    log::set_logger(Box::leak(Box::new(logger))).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+    log::set_max_level(level);
+
+    return Ok(());
+}
+
+#[test]
+fn test_log_to_file() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+
+    let config = LogConfig {
+        level: "info".to_string(),
+        file: Some("logs/gantry.log".to_string()),
+        max_size: 256,
+        max_files: 2,
+    };
+
+    let logger = GantryLogger::new(&config, &dir).unwrap();
+
+    for i in 0..20 {
+        logger.log(
+            &Record::builder()
+                .args(format_args!("line {}", i))
+                .level(log::Level::Warn)
+                .target("gantry")
+                .build(),
+        );
+    }
+
+    // filtered by level
+    logger.log(
+        &Record::builder()
+            .args(format_args!("hidden"))
+            .level(log::Level::Debug)
+            .target("gantry")
+            .build(),
+    );
+
+    let content = std::fs::read_to_string(dir.join("logs/gantry.log")).unwrap();
+
+    assert!(content.contains("line 19"));
+    assert!(!content.contains("hidden"));
+    assert!(content.len() <= 256);
+    // rotated file exists
+    assert!(dir.join("logs/gantry.log.1").exists());
+    // only 2 rotated files are kept
+    assert!(!dir.join("logs/gantry.log.3").exists());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -6,14 +6,20 @@ mod gcode;
 mod global_auth;
 mod graphql_server;
 mod kinematics;
+mod logger;
+mod moonraker;
+mod mqtt;
+mod octoprint;
+mod outbound;
 mod printer;
 mod server;
+mod timestamp;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use tokio::fs::OpenOptions;
+use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use tokio::sync::RwLock;
 
@@ -22,11 +28,13 @@ pub const API_VERSION: (u8, u8, u8) = (0, 0, 1);
 pub const DEFAULT_HTTP_PORT: u16 = 8080;
 
 lazy_static::lazy_static! {
+    /// instances by name. only filled on startup, after Gantry.toml passed
+    /// config::validate_instances. adding an instance at runtime must validate
+    /// it against the running instances first
     pub static ref INSTANCES: RwLock<HashMap<String, Arc<printer::Instance>>> = RwLock::new(HashMap::new());
 }
 
-#[tokio::main]
-pub async fn main() {
+pub fn main() {
     // parse command line arguments
     let cli_args = clap::Command::new("Gantry")
         .about("3D printer firmware")
@@ -35,6 +43,7 @@ pub async fn main() {
         .arg(clap::arg!(-p --port <PORT> "port for http server, default is port 80"))
         .arg(clap::arg!(--tls_cert <CERT> "path to tls cert pem file"))
         .arg(clap::arg!(--tls_key <KEY> "path to tls private key pem file"))
+        .arg(clap::arg!(--debug "serve debug routes exposing internal state, not for production"))
         .get_matches();
 
     // get the port to serve at
@@ -62,20 +71,48 @@ pub async fn main() {
         .canonicalize()
         .expect("path error");
 
+    // the runtime is configured before anything async runs, a missing config uses defaults
+    let runtime_config = std::fs::read_to_string(gantry_path.join("Gantry.toml"))
+        .map(|c| config::GantryConfig::parse_runtime(&c))
+        .unwrap_or_default();
+
+    build_runtime(&runtime_config)
+        .expect("failed to start tokio runtime")
+        .block_on(run(port, gantry_path, cli_args.get_flag("debug")));
+}
+
+/// tokio runtime with the configured flavor and number of worker threads
+fn build_runtime(config: &config::RuntimeConfig) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = match config.flavor {
+        config::RuntimeFlavor::MultiThread => tokio::runtime::Builder::new_multi_thread(),
+        config::RuntimeFlavor::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+    };
+
+    // ignored by the current thread flavor
+    if let Some(n) = config.worker_threads {
+        builder.worker_threads(n.max(1));
+    }
+
+    builder.enable_all().build()
+}
+
+async fn run(port: u16, gantry_path: PathBuf, debug: bool) {
     // buffer for reading config file
     let mut config_file = String::new();
 
-    // open the config file in write mode and read to string
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(gantry_path.join("Gantry.toml"))
+    let config_path = gantry_path.join("Gantry.toml");
+
+    // create an empty config if not exist
+    if !config_path.exists() {
+        files::write_atomic(&config_path, b"")
+            .await
+            .unwrap_or_else(|e| panic!("cannot create file '{}': {}", config_path.display(), e));
+    }
+
+    // open the config file in read mode and read to string
+    File::open(&config_path)
         .await
-        .expect(&format!(
-            "cannot open file '{}'",
-            gantry_path.join("Gantry.toml").display()
-        ))
+        .unwrap_or_else(|e| panic!("cannot open file '{}': {}", config_path.display(), e))
         .read_to_string(&mut config_file)
         .await
         .expect("read error");
@@ -83,6 +120,30 @@ pub async fn main() {
     // parse config file
     let config = config::GantryConfig::parse(&config_file).await.unwrap();
 
+    // initialise logger before anything else logs
+    logger::init(&config.log, &gantry_path).expect("failed to initialise logger");
+
+    if debug {
+        log::warn!("debug routes are enabled, internal state is exposed over http");
+    }
+
+    printer::enable_debug_routes(debug);
+
+    // uploads are limited before any instance serves them
+    files::set_max_concurrent_uploads(config.files.max_concurrent_uploads);
+
+    // network operations are bounded before any instance starts them
+    outbound::set_timeouts(config.timeouts.clone());
+
+    // errors are answered with the result envelope unless configured
+    server::set_error_status(config.http.error_status);
+
+    // instances must not share files
+    if let Err(e) = config.validate(&gantry_path) {
+        log::error!("invalid Gantry.toml: {}", e);
+        std::process::exit(1);
+    }
+
     // construct root dbus service
     let dbus = zbus::connection::Builder::session()
         .expect("failed to connect dbus")
@@ -100,12 +161,24 @@ pub async fn main() {
         .await
         .unwrap();
 
+    let mut instances = Vec::new();
+
     // spawn instances
     for (i, (name, inst_cfg)) in config.instances.into_iter().enumerate() {
+        let hot_reload = inst_cfg.hot_reload;
+        let file_cleanup = inst_cfg.file_cleanup.clone();
         let inst = Arc::new(
             printer::Instance::create(i, name.clone(), inst_cfg, gantry_path.clone()).await,
         );
 
+        if hot_reload {
+            inst.enable_config_hot_reload().await;
+        }
+
+        if let Some(file_cleanup) = file_cleanup {
+            inst.enable_file_cleanup(file_cleanup);
+        }
+
         // create dbus service
         let dbus_service = inst.clone().create_dbus_service();
 
@@ -114,11 +187,60 @@ pub async fn main() {
             .at(format!("/org/gantry/instance{}", i), dbus_service)
             .await;
 
+        instances.push(inst.clone());
+
         // add instance to global
         INSTANCES.write().await.insert(name, inst);
     }
 
-    // construct axum server
+    // serve the moonraker compatible api, the instance is checked by validate
+    if let Some(moonraker) = &config.moonraker {
+        let inst = INSTANCES.read().await.get(&moonraker.instance).cloned();
+
+        if let Some(inst) = inst {
+            let port = moonraker.port;
+
+            tokio::spawn(async move {
+                if let Err(e) = moonraker::serve(inst, port).await {
+                    log::error!("moonraker api failed on port {}: {}", port, e);
+                }
+            });
+        }
+    }
+
+    // serve the octoprint compatible api, the instance is checked by validate
+    if let Some(octoprint) = config.octoprint {
+        let inst = INSTANCES.read().await.get(&octoprint.instance).cloned();
+
+        if let Some(inst) = inst {
+            let port = octoprint.port;
+
+            tokio::spawn(async move {
+                if let Err(e) = octoprint::serve(inst, octoprint.api_key, port).await {
+                    log::error!("octoprint api failed on port {}: {}", port, e);
+                }
+            });
+        }
+    }
+
+    // publish printer status to mqtt
+    if let Some(mqtt) = &config.mqtt {
+        mqtt::spawn_publisher(mqtt, instances).await;
+    }
+
+    let app = create_router();
+
+    // run our app with hyper, listening globally
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .expect("failed to bind TCP port");
+
+    // serve axum
+    axum::serve(listener, app).await.unwrap();
+}
+
+/// routes of the web ui, the rest api and graphql
+fn create_router() -> axum::Router {
     let app = axum::Router::<()>::new()
         .route(
             "/",
@@ -157,16 +279,151 @@ pub async fn main() {
     let graphql_router = graphql_server::create_router();
 
     // all graphql actions must be authorised
-    let graphql_router = graphql_router.layer(axum::middleware::from_fn(global_auth::auth_middleware));
+    let graphql_router =
+        graphql_router.layer(axum::middleware::from_fn(global_auth::auth_middleware));
 
     // merge routers
     let app = app.merge(graphql_router);
 
-    // run our app with hyper, listening globally
-    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+    // unknown routes and methods are answered with a json error
+    let app = app
+        .fallback(server::route_not_found)
+        .method_not_allowed_fallback(server::method_not_allowed);
+
+    // errors may be answered with a status code instead of the envelope
+    return app.layer(axum::middleware::from_fn(server::response_envelope));
+}
+
+#[tokio::test]
+async fn test_unknown_route() {
+    use tower::ServiceExt;
+
+    let response = create_router()
+        .oneshot(
+            axum::http::Request::get("/printer/bogus")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
         .await
-        .expect("failed to bind TCP port");
+        .unwrap();
 
-    // serve axum
-    axum::serve(listener, app).await.unwrap();
+    assert_eq!(response.status(), axum::http::StatusCode::NOT_FOUND);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(body["error"]["code"], "RouteNotFound");
+    assert_eq!(body["error"]["message"], "no route for GET /printer/bogus");
+
+    // known route with the wrong method
+    let response = create_router()
+        .oneshot(
+            axum::http::Request::get("/printer/login")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.status(),
+        axum::http::StatusCode::METHOD_NOT_ALLOWED
+    );
+}
+
+#[tokio::test]
+async fn test_error_status_mode() {
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = config::InstanceConfig {
+        uuid: uuid::Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        printer::Instance::create(0, "envelope".to_string(), config, gantry_path.clone()).await;
+
+    INSTANCES
+        .write()
+        .await
+        .insert("envelope".to_string(), Arc::new(inst));
+
+    let request = |accept: Option<&str>| {
+        let mut request = axum::http::Request::post("/printer/envelope/login")
+            .header("content-type", "application/json");
+
+        if let Some(accept) = accept {
+            request = request.header("accept", accept);
+        }
+
+        request
+            .body(axum::body::Body::from(r#"{"password":"wrong"}"#))
+            .unwrap()
+    };
+
+    let json = async |response: axum::response::Response| {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    // envelope by default
+    let response = create_router().oneshot(request(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json(response).await["error"]["code"], "AuthFailed");
+
+    // status code with the bare error
+    let response = create_router()
+        .oneshot(request(Some("application/json; envelope=none")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let body = json(response).await;
+    assert_eq!(body["code"], "AuthFailed");
+    assert!(body.get("result").is_none());
+
+    // rejected bearer token
+    let response = create_router()
+        .oneshot(
+            axum::http::Request::get("/printer/envelope/info")
+                .header("authorization", "Bearer invalid")
+                .header("accept", "application/json; envelope=none")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(json(response).await["code"], "AuthTokenInvalid");
+
+    INSTANCES.write().await.remove("envelope");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[test]
+fn test_build_runtime() {
+    let config = config::GantryConfig::parse_runtime("[runtime]\nworker_threads = 2\n");
+    let runtime = build_runtime(&config).unwrap();
+
+    assert_eq!(runtime.metrics().num_workers(), 2);
+    assert_eq!(runtime.block_on(async { 1 + 1 }), 2);
+
+    let config = config::GantryConfig::parse_runtime("[runtime]\nflavor = \"current_thread\"\n");
+    let runtime = build_runtime(&config).unwrap();
+
+    assert_eq!(runtime.metrics().num_workers(), 1);
+
+    // defaults to a worker per cpu core
+    let config = config::GantryConfig::parse_runtime("");
+    assert_eq!(config.flavor, config::RuntimeFlavor::MultiThread);
+    assert_eq!(config.worker_threads, None);
 }
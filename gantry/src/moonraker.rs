@@ -0,0 +1,424 @@
+//! subset of the moonraker api, so existing web interfaces such as Mainsail and Fluidd can connect.
+//! moonraker serves a single printer, the instance is chosen in the gantry config
+
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, RawQuery};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use gantry_api::{PrinterResult, PrinterState};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+use crate::printer::Instance;
+
+/// printer objects that can be queried
+const OBJECTS: &[&str] = &[
+    "webhooks",
+    "toolhead",
+    "extruder",
+    "print_stats",
+    "virtual_sdcard",
+];
+
+/// error returned to the client, 'code' is the http status
+#[derive(Debug)]
+pub struct MoonrakerError {
+    code: u16,
+    message: String,
+}
+
+impl MoonrakerError {
+    fn new(code: u16, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for MoonrakerError {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.code).unwrap_or(StatusCode::BAD_REQUEST);
+        let body = json!({"error": {"code": self.code, "message": self.message}});
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// convert a gantry result, errors are reported as bad requests
+fn into_result<T>(result: PrinterResult<T>) -> Result<T, MoonrakerError>
+where
+    T: zbus::zvariant::Type,
+{
+    match result.result {
+        Some(r) => Ok(r),
+        None => Err(MoonrakerError::new(400, result.error.message)),
+    }
+}
+
+/// moonraker routes for the instance
+pub fn create_router(instance: Arc<Instance>) -> Router {
+    Router::new()
+        .route("/server/info", get(rest_server_info))
+        .route("/server/files/list", get(rest_files_list))
+        .route("/printer/info", get(rest_printer_info))
+        .route("/printer/objects/list", get(rest_objects_list))
+        .route("/printer/objects/query", get(rest_objects_query))
+        .route("/printer/gcode/script", post(rest_gcode_script))
+        .route("/printer/emergency_stop", post(rest_emergency_stop))
+        .route("/websocket", get(websocket))
+        .layer(Extension(instance))
+}
+
+/// serve the moonraker api on its own port
+pub async fn serve(instance: Arc<Instance>, port: u16) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+
+    axum::serve(listener, create_router(instance)).await
+}
+
+/// dispatch a json-rpc method, shared by the rest and websocket api
+async fn call(instance: &Instance, method: &str, params: Value) -> Result<Value, MoonrakerError> {
+    match method {
+        "server.info" => server_info(instance).await,
+        "server.files.list" => files_list(instance).await,
+        "printer.info" => printer_info(instance).await,
+        "printer.objects.list" => Ok(json!({ "objects": OBJECTS })),
+        "printer.objects.query" => {
+            let objects = params
+                .get("objects")
+                .and_then(|o| o.as_object())
+                .cloned()
+                .unwrap_or_default();
+
+            objects_query(instance, objects).await
+        }
+        "printer.gcode.script" => {
+            let Some(script) = params.get("script").and_then(|s| s.as_str()) else {
+                return Err(MoonrakerError::new(400, "missing argument 'script'"));
+            };
+
+            into_result(instance.run_gcode(script.to_string()).await)?;
+
+            Ok(json!("ok"))
+        }
+        "printer.emergency_stop" => {
+            into_result(instance.emergency_stop().await)?;
+
+            Ok(json!("ok"))
+        }
+        _ => Err(MoonrakerError::new(
+            404,
+            format!("method '{}' not found", method),
+        )),
+    }
+}
+
+/// klippy state names
+fn state_name(state: PrinterState) -> &'static str {
+    match state {
+        PrinterState::Ready => "ready",
+        PrinterState::Startup => "startup",
+        PrinterState::Error => "error",
+        PrinterState::Shutdown => "shutdown",
+    }
+}
+
+async fn server_info(instance: &Instance) -> Result<Value, MoonrakerError> {
+    let info = into_result(instance.get_info().await)?;
+    let (major, minor, patch) = crate::VERSION;
+
+    Ok(json!({
+        "klippy_connected": true,
+        "klippy_state": state_name(info.state),
+        "components": [],
+        "failed_components": [],
+        "registered_directories": ["gcodes"],
+        "warnings": [],
+        "websocket_count": 0,
+        "moonraker_version": format!("gantry-{}.{}.{}", major, minor, patch),
+        "api_version": [1, 5, 0],
+        "api_version_string": "1.5.0",
+    }))
+}
+
+async fn printer_info(instance: &Instance) -> Result<Value, MoonrakerError> {
+    let info = into_result(instance.get_info().await)?;
+    let (major, minor, patch) = crate::VERSION;
+
+    Ok(json!({
+        "state": state_name(info.state),
+        "state_message": info.error_state_message,
+        "hostname": instance.name,
+        "software_version": format!("gantry-{}.{}.{}", major, minor, patch),
+        "cpu_info": "",
+        "klipper_path": info.printer_path,
+        "python_path": "",
+        "log_file": format!("{}/logs/printer.log", info.printer_path),
+        "config_file": format!("{}/printer.cfg", info.printer_path),
+    }))
+}
+
+async fn files_list(instance: &Instance) -> Result<Value, MoonrakerError> {
+    let files = into_result(instance.list_files().await)?;
+
+    let files: Vec<Value> = files
+        .into_iter()
+        .map(|f| {
+            json!({
+                "path": f.path,
+                "modified": f.modified as f64,
+                "size": f.size,
+                // moonraker only reports whether the owner may write
+                "permissions": match f.permissions.get(1..2) {
+                    Some("w") => "rw",
+                    _ => "r",
+                },
+            })
+        })
+        .collect();
+
+    Ok(Value::Array(files))
+}
+
+/// 'objects' maps object names to the requested attributes, null for all attributes
+async fn objects_query(
+    instance: &Instance,
+    objects: Map<String, Value>,
+) -> Result<Value, MoonrakerError> {
+    let info = into_result(instance.get_info().await)?;
+    let position = into_result(instance.get_position().await)?;
+    let telemetry = instance.telemetry().await;
+
+    let mut status = Map::new();
+
+    for (name, attributes) in objects {
+        let object = match name.as_str() {
+            "webhooks" => json!({
+                "state": state_name(info.state),
+                "state_message": info.error_state_message,
+            }),
+            "toolhead" => json!({
+                "position": [position.x, position.y, position.z, position.e],
+            }),
+            "extruder" => json!({
+                "temperature": telemetry.extruder_temperature,
+                "target": 0.0,
+            }),
+            "print_stats" => json!({
+                "state": match telemetry.gcode_running {
+                    true => "printing",
+                    false => "standby",
+                },
+            }),
+            "virtual_sdcard" => json!({
+                "progress": telemetry.progress,
+                "is_active": telemetry.gcode_running,
+            }),
+            // unknown objects are omitted like moonraker does
+            _ => continue,
+        };
+
+        // keep only the requested attributes
+        let object = match attributes.as_array() {
+            Some(attributes) => {
+                let mut filtered = Map::new();
+                for a in attributes.iter().filter_map(|a| a.as_str()) {
+                    if let Some(v) = object.get(a) {
+                        filtered.insert(a.to_string(), v.clone());
+                    }
+                }
+                Value::Object(filtered)
+            }
+            None => object,
+        };
+
+        status.insert(name, object);
+    }
+
+    let eventtime = crate::timestamp::unix_timestamp_f64();
+
+    Ok(json!({ "eventtime": eventtime, "status": status }))
+}
+
+/// rest responses are wrapped in 'result'
+fn rest_result(result: Result<Value, MoonrakerError>) -> Result<Json<Value>, MoonrakerError> {
+    result.map(|r| Json(json!({ "result": r })))
+}
+
+async fn rest_server_info(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<Value>, MoonrakerError> {
+    rest_result(call(&instance, "server.info", Value::Null).await)
+}
+
+async fn rest_files_list(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<Value>, MoonrakerError> {
+    rest_result(call(&instance, "server.files.list", Value::Null).await)
+}
+
+async fn rest_printer_info(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<Value>, MoonrakerError> {
+    rest_result(call(&instance, "printer.info", Value::Null).await)
+}
+
+async fn rest_objects_list(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<Value>, MoonrakerError> {
+    rest_result(call(&instance, "printer.objects.list", Value::Null).await)
+}
+
+/// query string is 'toolhead&extruder=target,temperature'
+async fn rest_objects_query(
+    Extension(instance): Extension<Arc<Instance>>,
+    RawQuery(query): RawQuery,
+) -> Result<Json<Value>, MoonrakerError> {
+    let mut objects = Map::new();
+
+    for object in query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|o| !o.is_empty())
+    {
+        match object.split_once('=') {
+            Some((name, attributes)) => {
+                let attributes = attributes.split(',').map(|a| json!(a)).collect();
+                objects.insert(name.to_string(), Value::Array(attributes));
+            }
+            None => {
+                objects.insert(object.to_string(), Value::Null);
+            }
+        }
+    }
+
+    rest_result(objects_query(&instance, objects).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct GcodeScriptParams {
+    script: String,
+}
+
+async fn rest_gcode_script(
+    Extension(instance): Extension<Arc<Instance>>,
+    Query(params): Query<GcodeScriptParams>,
+) -> Result<Json<Value>, MoonrakerError> {
+    let params = json!({ "script": params.script });
+
+    rest_result(call(&instance, "printer.gcode.script", params).await)
+}
+
+async fn rest_emergency_stop(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<Value>, MoonrakerError> {
+    rest_result(call(&instance, "printer.emergency_stop", Value::Null).await)
+}
+
+async fn websocket(
+    Extension(instance): Extension<Arc<Instance>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, instance))
+}
+
+/// answer json-rpc 2.0 requests until the socket is closed
+async fn handle_socket(mut socket: WebSocket, instance: Arc<Instance>) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+
+        let response = match serde_json::from_str::<Value>(&text) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let method = request.get("method").and_then(|m| m.as_str()).unwrap_or("");
+                let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+                match call(&instance, method, params).await {
+                    Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+                    Err(e) => json!({
+                        "jsonrpc": "2.0",
+                        "error": {"code": e.code, "message": e.message},
+                        "id": id,
+                    }),
+                }
+            }
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32700, "message": e.to_string()},
+                "id": null,
+            }),
+        };
+
+        if socket
+            .send(Message::Text(response.to_string().into()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_printer_info() {
+    use crate::config::InstanceConfig;
+    use tower::ServiceExt;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    let response = create_router(inst)
+        .oneshot(
+            axum::http::Request::get("/printer/info")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let result = &body["result"];
+
+    assert!(["ready", "startup", "error", "shutdown"].contains(&result["state"].as_str().unwrap()));
+    assert!(result["state_message"].is_string());
+    assert_eq!(result["hostname"], "printer");
+    assert!(
+        result["software_version"]
+            .as_str()
+            .unwrap()
+            .starts_with("gantry-")
+    );
+    assert!(
+        result["config_file"]
+            .as_str()
+            .unwrap()
+            .ends_with("printer.cfg")
+    );
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
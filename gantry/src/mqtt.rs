@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::MqttConfig;
+use crate::printer::Instance;
+use crate::printer::notify::Notification;
+
+/// requests waiting to be sent to the broker, newer messages are dropped when full
+const MQTT_QUEUE_SIZE: usize = 64;
+
+/// publish state transitions, job events and telemetry of every instance to the broker.
+/// topics are '{prefix}/{printer}/state', '{prefix}/{printer}/job' and '{prefix}/{printer}/telemetry'.
+/// publishing never waits on the broker, messages are dropped while it is unreachable
+pub async fn spawn_publisher(config: &MqttConfig, instances: Vec<Arc<Instance>>) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, MQTT_QUEUE_SIZE);
+
+    let connect_timeout = crate::outbound::timeouts().mqtt_connect();
+
+    // drive the connection, polling again after an error reconnects
+    tokio::spawn(async move {
+        let mut connected = true;
+        // no event received since the last error, the next poll connects
+        let mut connecting = true;
+
+        loop {
+            // an unreachable broker may never answer the connect
+            let event = match connecting {
+                true => {
+                    crate::outbound::with_timeout("mqtt connect", connect_timeout, async {
+                        Ok(eventloop.poll().await?)
+                    })
+                    .await
+                }
+                false => eventloop.poll().await.map_err(anyhow::Error::from),
+            };
+
+            match event {
+                Ok(_) => {
+                    connected = true;
+                    connecting = false;
+                }
+                Err(e) => {
+                    if connected {
+                        log::warn!("mqtt connection lost: {}", e);
+                        connected = false;
+                    }
+                    connecting = true;
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+
+    let interval = Duration::from_secs_f64(config.telemetry_interval.max(0.1));
+
+    for instance in instances {
+        let prefix = format!("{}/{}", config.topic_prefix, instance.name);
+
+        tokio::spawn(publish_notifications(
+            client.clone(),
+            prefix.clone(),
+            instance.subscribe().await,
+        ));
+        tokio::spawn(publish_telemetry(
+            client.clone(),
+            prefix,
+            instance,
+            interval,
+        ));
+    }
+}
+
+/// queue a message without waiting for the broker
+fn publish<T: serde::Serialize>(client: &AsyncClient, topic: String, retain: bool, payload: &T) {
+    let payload = serde_json::to_vec(payload).unwrap_or_default();
+
+    if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, retain, payload) {
+        log::debug!("mqtt message to '{}' dropped: {}", topic, e);
+    }
+}
+
+async fn publish_notifications(
+    client: AsyncClient,
+    prefix: String,
+    mut notifications: tokio::sync::broadcast::Receiver<Notification>,
+) {
+    loop {
+        let notification = match notifications.recv().await {
+            Ok(n) => n,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+
+        match &notification {
+            // retained so new subscribers get the current state
+            Notification::StateChanged { .. } => {
+                publish(&client, format!("{}/state", prefix), true, &notification)
+            }
+            _ => publish(&client, format!("{}/job", prefix), false, &notification),
+        }
+    }
+}
+
+async fn publish_telemetry(
+    client: AsyncClient,
+    prefix: String,
+    instance: Arc<Instance>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        let telemetry = instance.telemetry().await;
+
+        publish(&client, format!("{}/telemetry", prefix), false, &telemetry);
+    }
+}
+
+/// minimal broker accepting a single client, forwards published (topic, payload)
+#[cfg(test)]
+async fn mock_broker(
+    listener: tokio::net::TcpListener,
+    sender: tokio::sync::mpsc::UnboundedSender<(String, serde_json::Value)>,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (mut stream, _) = listener.accept().await?;
+
+    loop {
+        let header = stream.read_u8().await?;
+
+        // remaining length is a variable length integer
+        let mut len = 0usize;
+        let mut shift = 0;
+        loop {
+            let b = stream.read_u8().await?;
+            len |= ((b & 0x7f) as usize) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+
+        match header >> 4 {
+            // connect, accept
+            1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).await?,
+            // publish
+            3 => {
+                let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let topic = String::from_utf8_lossy(&body[2..2 + topic_len]).to_string();
+                let mut offset = 2 + topic_len;
+
+                // acknowledge qos 1
+                if (header >> 1) & 0x03 == 1 {
+                    stream
+                        .write_all(&[0x40, 0x02, body[offset], body[offset + 1]])
+                        .await?;
+                    offset += 2;
+                }
+
+                let payload = serde_json::from_slice(&body[offset..]).unwrap_or_default();
+                let _ = sender.send((topic, payload));
+            }
+            // ping
+            12 => stream.write_all(&[0xd0, 0x00]).await?,
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_state_change_published() {
+    use crate::config::InstanceConfig;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let (sender, mut messages) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(mock_broker(listener, sender));
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    let config = MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        telemetry_interval: 60.0,
+        ..Default::default()
+    };
+
+    spawn_publisher(&config, vec![inst.clone()]).await;
+
+    inst.emergency_stop().await;
+
+    // skip telemetry
+    let payload = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let (topic, payload) = messages.recv().await.unwrap();
+
+            if topic == "gantry/printer/state" {
+                return payload;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(payload["event"], "state_changed");
+    assert_eq!(payload["state"], "Shutdown");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
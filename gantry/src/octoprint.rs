@@ -0,0 +1,471 @@
+//! subset of the octoprint api, so slicers with octoprint upload support can send files.
+//! octoprint serves a single printer, the instance is chosen in the gantry config
+
+use std::sync::Arc;
+
+use axum::extract::{Multipart, Query, Request};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use gantry_api::PrinterResult;
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+
+use crate::printer::Instance;
+
+/// state shared by the octoprint routes
+struct OctoPrint {
+    instance: Arc<Instance>,
+    api_key: String,
+    /// file selected by an upload, started by the 'start' job command
+    selected: Mutex<Option<String>>,
+}
+
+/// error returned to the client, octoprint reports errors as plain text
+#[derive(Debug)]
+pub struct OctoPrintError {
+    status: StatusCode,
+    message: String,
+}
+
+impl OctoPrintError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for OctoPrintError {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+/// convert a gantry result, errors are reported as conflicts like octoprint does
+fn into_result<T>(result: PrinterResult<T>) -> Result<T, OctoPrintError>
+where
+    T: zbus::zvariant::Type,
+{
+    match result.result {
+        Some(r) => Ok(r),
+        None => Err(OctoPrintError::new(
+            StatusCode::CONFLICT,
+            result.error.message,
+        )),
+    }
+}
+
+/// octoprint routes for the instance, every route requires the api key
+pub fn create_router(instance: Arc<Instance>, api_key: String) -> Router {
+    let state = Arc::new(OctoPrint {
+        instance,
+        api_key,
+        selected: Mutex::new(None),
+    });
+
+    Router::new()
+        .route("/api/files/local", post(upload_file))
+        .route("/api/job", post(job_command))
+        .layer(axum::middleware::from_fn(authenticate))
+        .layer(Extension(state))
+}
+
+/// serve the octoprint api on its own port
+pub async fn serve(instance: Arc<Instance>, api_key: String, port: u16) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+
+    axum::serve(listener, create_router(instance, api_key)).await
+}
+
+#[derive(Deserialize)]
+struct ApiKeyQuery {
+    apikey: Option<String>,
+}
+
+/// api key is sent in the 'X-Api-Key' header or the 'apikey' query
+async fn authenticate(
+    Extension(state): Extension<Arc<OctoPrint>>,
+    headers: HeaderMap,
+    Query(query): Query<ApiKeyQuery>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let key = match headers.get("X-Api-Key") {
+        Some(key) => key.to_str().ok().map(|k| k.to_string()),
+        None => query.apikey,
+    };
+
+    // constant time, the comparison must not reveal how much of the key matched
+    let valid = key.is_some_and(|k| bool::from(k.as_bytes().ct_eq(state.api_key.as_bytes())));
+
+    if !valid {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    return Ok(next.run(request).await);
+}
+
+/// multipart upload with a 'file' field and optional 'path', 'select' and 'print' fields
+async fn upload_file(
+    Extension(state): Extension<Arc<OctoPrint>>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<serde_json::Value>), OctoPrintError> {
+    let mut file = None;
+    let mut path = String::new();
+    let mut select = false;
+    let mut print = false;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| OctoPrintError::new(StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+
+        // gcode is stored as is, it may not be valid utf-8
+        if name == "file" {
+            let Some(filename) = field.file_name().map(|f| f.to_string()) else {
+                return Err(OctoPrintError::new(
+                    StatusCode::BAD_REQUEST,
+                    "file has no filename",
+                ));
+            };
+
+            let data = field
+                .bytes()
+                .await
+                .map_err(|e| OctoPrintError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+            file = Some((filename, data.to_vec()));
+            continue;
+        }
+
+        let text = field
+            .text()
+            .await
+            .map_err(|e| OctoPrintError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+        match name.as_str() {
+            "path" => path = text.trim_matches('/').to_string(),
+            "select" => select = text == "true",
+            "print" => print = text == "true",
+            _ => {}
+        }
+    }
+
+    let Some((filename, data)) = file else {
+        return Err(OctoPrintError::new(
+            StatusCode::BAD_REQUEST,
+            "no file included",
+        ));
+    };
+
+    let filename = match path.is_empty() {
+        true => filename,
+        false => format!("{}/{}", path, filename),
+    };
+
+    into_result(state.instance.store_file(&filename, data).await)?;
+
+    // printing implies selecting the file
+    if select || print {
+        *state.selected.lock().await = Some(filename.clone());
+    }
+
+    if print {
+        into_result(
+            state
+                .instance
+                .start_print_job(&filename, Vec::new(), None)
+                .await,
+        )?;
+    }
+
+    let body = json!({
+        "files": {
+            "local": {
+                "name": filename.rsplit('/').next().unwrap_or_default(),
+                "path": filename,
+                "origin": "local",
+            }
+        },
+        "done": true,
+    });
+
+    return Ok((StatusCode::CREATED, Json(body)));
+}
+
+#[derive(Deserialize)]
+struct JobCommand {
+    command: String,
+    /// 'pause', 'resume' or 'toggle' for the pause command
+    action: Option<String>,
+}
+
+/// 'start', 'cancel', 'restart' and 'pause' job commands
+async fn job_command(
+    Extension(state): Extension<Arc<OctoPrint>>,
+    Json(command): Json<JobCommand>,
+) -> Result<StatusCode, OctoPrintError> {
+    let instance = &state.instance;
+
+    match command.command.as_str() {
+        "start" | "restart" => {
+            let Some(filename) = state.selected.lock().await.clone() else {
+                return Err(OctoPrintError::new(
+                    StatusCode::CONFLICT,
+                    "no file selected",
+                ));
+            };
+
+            into_result(instance.start_print_job(&filename, Vec::new(), None).await)?;
+        }
+        "cancel" => into_result(instance.cancel_print_job().await)?,
+        "pause" => match command.action.as_deref().unwrap_or("toggle") {
+            "pause" => into_result(instance.pause_print_job().await)?,
+            "resume" => into_result(instance.resume_print_job().await)?,
+            "toggle" => {
+                let status = into_result(instance.get_print_job_status().await)?;
+
+                match status.state == "paused" {
+                    true => into_result(instance.resume_print_job().await)?,
+                    false => into_result(instance.pause_print_job().await)?,
+                }
+            }
+            action => {
+                return Err(OctoPrintError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown pause action '{}'", action),
+                ));
+            }
+        },
+        command => {
+            return Err(OctoPrintError::new(
+                StatusCode::BAD_REQUEST,
+                format!("unknown command '{}'", command),
+            ));
+        }
+    }
+
+    return Ok(StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn test_upload_and_print() {
+    use std::time::Duration;
+
+    use crate::config::InstanceConfig;
+    use crate::printer::notify::Notification;
+    use tower::ServiceExt;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n",
+    )
+    .unwrap();
+
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    // wait for the printer to start
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !matches!(inst.state().await, crate::printer::State::Ready) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut notifications = inst.subscribe().await;
+    let router = create_router(inst.clone(), "secret".to_string());
+
+    let upload = |key: &str, print: bool| {
+        let body = format!(
+            "--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"cube.gcode\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            G1 X10 F3000\n\r\n\
+            --boundary\r\n\
+            Content-Disposition: form-data; name=\"print\"\r\n\r\n\
+            {}\r\n\
+            --boundary--\r\n",
+            print
+        );
+
+        axum::http::Request::post("/api/files/local")
+            .header("X-Api-Key", key)
+            .header("Content-Type", "multipart/form-data; boundary=boundary")
+            .body(axum::body::Body::from(body))
+            .unwrap()
+    };
+
+    // wrong api key is refused
+    let response = router
+        .clone()
+        .oneshot(upload("wrong", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // upload only
+    let response = router
+        .clone()
+        .oneshot(upload("secret", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let path = gantry_path
+        .join("printer")
+        .join("gcodes")
+        .join("cube.gcode");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "G1 X10 F3000\n");
+
+    // gcode is stored as is, slicer comments may be latin-1
+    let body: &[u8] = b"--boundary\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"latin1.gcode\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n\
+        ; temp\xe9rature\nG1 X10\n\r\n\
+        --boundary--\r\n";
+    let request = axum::http::Request::post("/api/files/local")
+        .header("X-Api-Key", "secret")
+        .header("Content-Type", "multipart/form-data; boundary=boundary")
+        .body(axum::body::Body::from(body))
+        .unwrap();
+
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(
+        std::fs::read(path.with_file_name("latin1.gcode")).unwrap(),
+        b"; temp\xe9rature\nG1 X10\n"
+    );
+
+    // upload and print
+    let response = router
+        .clone()
+        .oneshot(upload("secret", true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let started = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Notification::JobStarted { filename, .. } = notifications.recv().await.unwrap() {
+                return filename;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(started, "cube.gcode");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_job_start_command() {
+    use std::time::Duration;
+
+    use crate::config::InstanceConfig;
+    use crate::printer::notify::Notification;
+    use tower::ServiceExt;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: uuid::Uuid::new_v4().as_u128(),
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !matches!(inst.state().await, crate::printer::State::Ready) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut notifications = inst.subscribe().await;
+    let router = create_router(inst.clone(), "secret".to_string());
+
+    let start = || {
+        axum::http::Request::post("/api/job")
+            .header("X-Api-Key", "secret")
+            .header("Content-Type", "application/json")
+            .body(axum::body::Body::from(r#"{"command":"start"}"#))
+            .unwrap()
+    };
+
+    // nothing selected yet
+    let response = router.clone().oneshot(start()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // upload and select without printing
+    let body = "--boundary\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"cube.gcode\"\r\n\
+        Content-Type: application/octet-stream\r\n\r\n\
+        G1 X10 F3000\n\r\n\
+        --boundary\r\n\
+        Content-Disposition: form-data; name=\"select\"\r\n\r\n\
+        true\r\n\
+        --boundary--\r\n";
+
+    let response = router
+        .clone()
+        .oneshot(
+            axum::http::Request::post("/api/files/local")
+                .header("X-Api-Key", "secret")
+                .header("Content-Type", "multipart/form-data; boundary=boundary")
+                .body(axum::body::Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // starts the selected file
+    let response = router.clone().oneshot(start()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let started = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Notification::JobStarted { filename, .. } = notifications.recv().await.unwrap() {
+                return filename;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(started, "cube.gcode");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
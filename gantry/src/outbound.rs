@@ -0,0 +1,53 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::TimeoutConfig;
+
+/// timeouts of network operations, set once from Gantry.toml
+static TIMEOUTS: OnceLock<TimeoutConfig> = OnceLock::new();
+
+/// must be called before any outbound operation, later calls are ignored
+pub fn set_timeouts(config: TimeoutConfig) {
+    if TIMEOUTS.set(config).is_err() {
+        log::warn!("outbound timeouts are already set, ignoring new timeouts");
+    }
+}
+
+/// configured timeouts, defaults if not set
+pub fn timeouts() -> &'static TimeoutConfig {
+    TIMEOUTS.get_or_init(TimeoutConfig::default)
+}
+
+/// an outbound operation did not complete in time
+#[derive(Debug)]
+pub struct TimeoutError {
+    /// what timed out, e.g. 'extension install'
+    pub operation: String,
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} timed out after {:?}", self.operation, self.timeout)
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// run a network operation, aborting it with a TimeoutError once 'timeout' elapses
+pub async fn with_timeout<T>(
+    operation: &str,
+    timeout: Duration,
+    future: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => return result,
+        Err(_) => {
+            return Err(TimeoutError {
+                operation: operation.to_string(),
+                timeout,
+            }
+            .into());
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::VecDeque;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+use gantry_api::{PrinterErrorCode, PrinterPlannedMove, PrinterPosition};
 use portable_atomic::AtomicF32;
 
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{Mutex, RwLock};
 
+use super::heater::{AMBIENT_TEMPERATURE, Heater, extruder_name};
 use super::printer::PrinterEvent;
 
 #[derive(Debug, Clone, Copy)]
@@ -25,6 +28,13 @@ pub struct Move {
     pub e: f32,
 }
 
+impl Move {
+    /// returns absolute distance of move in xyz
+    pub fn distance(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct KinematicMove {
     /// this will be the extrusion velocity if distance is 0
@@ -42,10 +52,10 @@ impl KinematicMove {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
-    /// returns duration in ms
+    /// returns duration in seconds
     pub fn duration(&self) -> f32 {
-        // s = ut + at^2
-        // att + ut - s = 0
+        // s = ut + 1/2 at^2
+        // 1/2 att + ut - s = 0
 
         let s = self.abs_distance();
 
@@ -58,10 +68,11 @@ impl KinematicMove {
             return s / self.start_velocity;
         }
 
-        // t = (-u + sqrt(u*u + 2as)) / 2a
+        // t = (-u + sqrt(u*u + 2as)) / a
         let u = self.start_velocity;
         let a = self.acceleration;
-        (-u + (u * u + 2.0 * a * s).sqrt()) / (2.0 * a)
+        // a deceleration to zero may round below zero
+        (-u + (u * u + 2.0 * a * s).max(0.0).sqrt()) / a
     }
 }
 
@@ -76,7 +87,7 @@ pub struct ExtrusionMove {
 
 pub enum Action {
     Move(Move),
-    /// sets velocity in mm/s
+    /// sets the feedrate in mm/s of the following moves
     SetVelocity(f32),
     SetBedTemp(f32),
     SetBedTempWait(f32),
@@ -88,8 +99,38 @@ pub enum Action {
         index: usize,
         temp: f32,
     },
+    /// home the axes, queued moves are sent first
+    Home {
+        x: bool,
+        y: bool,
+        z: bool,
+    },
 }
 
+/// an action or command refused by validation, the code is reported by the api
+#[derive(Debug)]
+pub struct ValidationError {
+    pub code: PrinterErrorCode,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(code: PrinterErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
 #[derive(Debug)]
 pub enum PrinterAction {
     KinematicMove(KinematicMove),
@@ -98,24 +139,76 @@ pub enum PrinterAction {
     SetBedTempWait(f32),
     SetExtruderTemp { index: usize, temp: f32 },
     SetExtruderTempWait { index: usize, temp: f32 },
+    Home { x: bool, y: bool, z: bool },
+}
+
+/// model limiting the velocity at the junction of two moves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cornering {
+    /// corners are taken as arcs, a 90 degree corner at square_corner_velocity
+    #[default]
+    SquareCornerVelocity,
+    /// the velocity of each axis changes by at most max_jerk
+    Jerk,
 }
 
 pub struct ActionState {
-    /// max velocity in mm/s
+    /// max velocity in mm/s, the limit from config
     pub max_velocity: AtomicF32,
+    /// feedrate in mm/s last commanded by F, kept across moves. NaN uses max_velocity
+    pub feedrate: AtomicF32,
+    /// feedrate multiplier set by M220, 1.0 is 100%
+    pub speed_factor: AtomicF32,
     /// max accel
     pub max_accel: AtomicF32,
     /// square corner velocity in mm/s
     pub square_corner_velocity: AtomicF32,
     /// minimum cruise ratio
     pub minimum_cruise_ratio: AtomicF32,
+    /// cornering model used by the planner
+    pub cornering: std::sync::Mutex<Cornering>,
+    /// instantaneous velocity change in mm/s allowed per axis, used by jerk cornering
+    pub max_jerk: AtomicF32,
+    /// extrusion is refused below this temperature in celsius
+    pub min_extrude_temp: AtomicF32,
+    /// allow extrusion below min_extrude_temp, set by M302 for maintenance
+    pub allow_cold_extrude: AtomicBool,
+    /// max_temp of each extruder in celsius, unlimited if not configured
+    pub extruder_max_temp: std::sync::Mutex<Vec<f32>>,
+    /// max_temp of the heated bed in celsius
+    pub bed_max_temp: AtomicF32,
+    /// extruders, heated bed and temperature sensors, in config order
+    pub heaters: std::sync::Mutex<Vec<Heater>>,
+    /// number of [extruder], [extruder1]... sections in config
+    pub extruder_count: AtomicUsize,
+    /// extruder selected by T<n>
+    pub active_extruder: AtomicUsize,
+    /// longest extrusion in mm allowed without moving the toolhead
+    pub max_extrude_only_distance: AtomicF32,
+    /// largest extruded cross section in mm^2 allowed in a move
+    pub max_extrude_cross_section: AtomicF32,
+    /// filament diameter in mm
+    pub filament_diameter: AtomicF32,
     /// use absolute positioning
     pub absolute_position: AtomicBool,
     pub absolute_extrution: AtomicBool,
     /// current running gcode line number
     pub gcode_line: AtomicUsize,
+    /// number of lines in the running gcode file
+    pub gcode_lines: AtomicUsize,
     pub gcode_running: AtomicBool,
+    /// objects defined by the running gcode, in definition order
+    pub objects: std::sync::Mutex<Vec<String>>,
+    /// objects cancelled by EXCLUDE_OBJECT or the api
     pub exclude_objects: RwLock<Vec<String>>,
+    /// object the running gcode is printing, set by EXCLUDE_OBJECT_START
+    pub current_object: std::sync::Mutex<Option<String>>,
+    /// moves are skipped while printing an excluded object
+    pub excluding: AtomicBool,
+    /// toolhead position [x, y, z] when the excluded object was entered
+    pub exclude_start: std::sync::Mutex<[f32; 3]>,
+    /// lift in mm when travelling over an excluded object
+    pub exclude_z_hop: AtomicF32,
     /// x origin
     pub x_origin: AtomicF32,
     /// y origin
@@ -130,20 +223,53 @@ pub struct ActionState {
     pub z_position: AtomicF32,
     /// e position
     pub e_position: AtomicF32,
+    /// x position after homing, position_endstop of [stepper_x]
+    pub x_endstop: AtomicF32,
+    /// y position after homing, position_endstop of [stepper_y]
+    pub y_endstop: AtomicF32,
+    /// z position after homing, position_endstop of [stepper_z]
+    pub z_endstop: AtomicF32,
+    /// moves dispatched to the motion backend, used to report live position
+    pub motion: std::sync::Mutex<MotionTimeline>,
+    /// decimals of reported positions and temperatures, set in [reporting]
+    pub report_precision: AtomicUsize,
+    /// report lengths in inches instead of mm
+    pub report_inches: AtomicBool,
 }
 
 impl ActionState {
     pub const fn new() -> Self {
         Self {
             max_velocity: AtomicF32::new(100.0),
+            feedrate: AtomicF32::new(f32::NAN),
+            speed_factor: AtomicF32::new(1.0),
             max_accel: AtomicF32::new(3000.0),
             square_corner_velocity: AtomicF32::new(5.0),
             minimum_cruise_ratio: AtomicF32::new(0.5),
+            cornering: std::sync::Mutex::new(Cornering::SquareCornerVelocity),
+            max_jerk: AtomicF32::new(10.0),
+            min_extrude_temp: AtomicF32::new(170.0),
+            allow_cold_extrude: AtomicBool::new(false),
+            extruder_max_temp: std::sync::Mutex::new(Vec::new()),
+            bed_max_temp: AtomicF32::new(f32::INFINITY),
+            heaters: std::sync::Mutex::new(Vec::new()),
+            extruder_count: AtomicUsize::new(1),
+            active_extruder: AtomicUsize::new(0),
+            max_extrude_only_distance: AtomicF32::new(50.0),
+            // 4 * nozzle_diameter^2 for a 0.4mm nozzle
+            max_extrude_cross_section: AtomicF32::new(0.64),
+            filament_diameter: AtomicF32::new(1.75),
             absolute_position: AtomicBool::new(false),
             absolute_extrution: AtomicBool::new(false),
             gcode_line: AtomicUsize::new(0),
+            gcode_lines: AtomicUsize::new(0),
             gcode_running: AtomicBool::new(false),
+            objects: std::sync::Mutex::new(Vec::new()),
             exclude_objects: RwLock::const_new(Vec::new()),
+            current_object: std::sync::Mutex::new(None),
+            excluding: AtomicBool::new(false),
+            exclude_start: std::sync::Mutex::new([0.0; 3]),
+            exclude_z_hop: AtomicF32::new(0.0),
             x_origin: AtomicF32::new(0.0),
             y_origin: AtomicF32::new(0.0),
             z_origin: AtomicF32::new(0.0),
@@ -151,16 +277,427 @@ impl ActionState {
             y_position: AtomicF32::new(f32::NAN),
             z_position: AtomicF32::new(f32::NAN),
             e_position: AtomicF32::new(0.0),
+            x_endstop: AtomicF32::new(0.0),
+            y_endstop: AtomicF32::new(0.0),
+            z_endstop: AtomicF32::new(0.0),
+            motion: std::sync::Mutex::new(MotionTimeline::new()),
+            report_precision: AtomicUsize::new(3),
+            report_inches: AtomicBool::new(false),
+        }
+    }
+
+    /// a state with the same limits, homed at the endstops and allowed to extrude cold.
+    /// used to simulate gcode without touching the printer state
+    pub fn simulation(&self) -> Self {
+        let state = Self::new();
+
+        let values = [
+            (&state.max_velocity, &self.max_velocity),
+            (&state.max_accel, &self.max_accel),
+            (&state.square_corner_velocity, &self.square_corner_velocity),
+            (&state.minimum_cruise_ratio, &self.minimum_cruise_ratio),
+            (&state.max_jerk, &self.max_jerk),
+            (
+                &state.max_extrude_only_distance,
+                &self.max_extrude_only_distance,
+            ),
+            (
+                &state.max_extrude_cross_section,
+                &self.max_extrude_cross_section,
+            ),
+            (&state.filament_diameter, &self.filament_diameter),
+            (&state.exclude_z_hop, &self.exclude_z_hop),
+            (&state.x_endstop, &self.x_endstop),
+            (&state.y_endstop, &self.y_endstop),
+            (&state.z_endstop, &self.z_endstop),
+        ];
+
+        for (value, source) in values {
+            value.store(source.load(Ordering::SeqCst), Ordering::SeqCst);
+        }
+
+        *state.cornering.lock().unwrap() = *self.cornering.lock().unwrap();
+        state
+            .extruder_count
+            .store(self.extruder_count.load(Ordering::SeqCst), Ordering::SeqCst);
+        state.allow_cold_extrude.store(true, Ordering::SeqCst);
+        state.home(true, true, true);
+
+        return state;
+    }
+
+    /// forget the toolhead position, axes must be homed again
+    pub fn reset_position(&self) {
+        self.x_origin.store(0.0, Ordering::SeqCst);
+        self.y_origin.store(0.0, Ordering::SeqCst);
+        self.z_origin.store(0.0, Ordering::SeqCst);
+        self.x_position.store(f32::NAN, Ordering::SeqCst);
+        self.y_position.store(f32::NAN, Ordering::SeqCst);
+        self.z_position.store(f32::NAN, Ordering::SeqCst);
+        self.e_position.store(0.0, Ordering::SeqCst);
+
+        self.motion
+            .lock()
+            .unwrap()
+            .reset([f32::NAN, f32::NAN, f32::NAN, 0.0]);
+    }
+
+    /// set the homed axes to their endstop position and clear their origin
+    pub fn home(&self, x: bool, y: bool, z: bool) {
+        let axes = [
+            (x, &self.x_origin, &self.x_position, &self.x_endstop),
+            (y, &self.y_origin, &self.y_position, &self.y_endstop),
+            (z, &self.z_origin, &self.z_position, &self.z_endstop),
+        ];
+
+        for (homed, origin, position, endstop) in axes {
+            if homed {
+                origin.store(0.0, Ordering::SeqCst);
+                position.store(endstop.load(Ordering::SeqCst), Ordering::SeqCst);
+            }
+        }
+
+        // the toolhead rests at the endstops once homing completes
+        self.motion.lock().unwrap().reset([
+            self.x_position.load(Ordering::SeqCst),
+            self.y_position.load(Ordering::SeqCst),
+            self.z_position.load(Ordering::SeqCst),
+            self.e_position.load(Ordering::SeqCst),
+        ]);
+    }
+
+    /// returns an error if no extruder with the index is configured
+    pub fn check_extruder(&self, index: usize) -> anyhow::Result<()> {
+        let count = self.extruder_count.load(Ordering::SeqCst);
+
+        if index >= count {
+            anyhow::bail!(
+                "extruder {} does not exist, {} extruder(s) configured",
+                index,
+                count
+            );
+        }
+
+        return Ok(());
+    }
+
+    /// returns an error if the target is above the max_temp of the extruder
+    pub fn check_extruder_temperature(&self, index: usize, temp: f32) -> anyhow::Result<()> {
+        let max = self
+            .extruder_max_temp
+            .lock()
+            .unwrap()
+            .get(index)
+            .copied()
+            .unwrap_or(f32::INFINITY);
+
+        return check_temperature(temp, max);
+    }
+
+    /// returns an error if the target is above the max_temp of the heated bed
+    pub fn check_bed_temperature(&self, temp: f32) -> anyhow::Result<()> {
+        check_temperature(temp, self.bed_max_temp.load(Ordering::SeqCst))
+    }
+
+    /// set the target of a configured heater, unknown heaters are ignored
+    pub fn set_heater_target(&self, name: &str, target: f32) {
+        let mut heaters = self.heaters.lock().unwrap();
+
+        if let Some(heater) = heaters.iter_mut().find(|h| h.name == name) {
+            heater.set_target(target, tokio::time::Instant::now());
+        }
+    }
+
+    /// turn all heaters off
+    pub fn heaters_off(&self) {
+        let now = tokio::time::Instant::now();
+
+        for heater in self.heaters.lock().unwrap().iter_mut() {
+            heater.set_target(0.0, now);
+        }
+    }
+
+    /// returns true while a heater is below the target it was set to
+    pub fn is_heating(&self, name: &str, target: f32) -> bool {
+        let now = tokio::time::Instant::now();
+
+        self.heaters
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.name == name)
+            .is_some_and(|h| h.target == target && h.temperature(now) < target)
+    }
+
+    /// modeled temperature of the active extruder, ambient if it is not configured
+    pub fn active_extruder_temperature(&self) -> f32 {
+        let name = extruder_name(self.active_extruder.load(Ordering::SeqCst));
+        let now = tokio::time::Instant::now();
+
+        self.heaters
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.name == name)
+            .map(|h| h.temperature(now))
+            .unwrap_or(AMBIENT_TEMPERATURE)
+    }
+
+    /// target of the active extruder in celsius, 0 if it is off or not configured
+    pub fn active_extruder_target(&self) -> f32 {
+        let name = extruder_name(self.active_extruder.load(Ordering::SeqCst));
+
+        self.heaters
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.name == name)
+            .map(|h| h.target)
+            .unwrap_or(0.0)
+    }
+
+    /// returns the current toolhead position [x, y, z, e],
+    /// interpolated from the move currently executing
+    pub fn current_position(&self) -> [f32; 4] {
+        self.motion.lock().unwrap().position_at(Instant::now())
+    }
+
+    /// a length in mm converted to the reporting unit and rounded to the reporting precision
+    pub fn report_length(&self, mm: f32) -> f32 {
+        let length = match self.report_inches.load(Ordering::SeqCst) {
+            true => mm / 25.4,
+            false => mm,
+        };
+
+        self.report_round(length)
+    }
+
+    /// a temperature rounded to the reporting precision
+    pub fn report_temperature(&self, celsius: f32) -> f32 {
+        self.report_round(celsius)
+    }
+
+    fn report_round(&self, value: f32) -> f32 {
+        let scale = 10f64.powi(self.report_precision.load(Ordering::SeqCst) as i32);
+
+        ((value as f64 * scale).round() / scale) as f32
+    }
+
+    /// format a length in mm in the reporting unit with the reporting precision
+    pub fn format_length(&self, mm: f32) -> String {
+        format!(
+            "{:.*}",
+            self.report_precision.load(Ordering::SeqCst),
+            self.report_length(mm)
+        )
+    }
+
+    /// format a temperature with the reporting precision
+    pub fn format_temperature(&self, celsius: f32) -> String {
+        format!(
+            "{:.*}",
+            self.report_precision.load(Ordering::SeqCst),
+            self.report_temperature(celsius)
+        )
+    }
+}
+
+/// a kinematic move scheduled on the timeline
+#[derive(Debug, Clone, Copy)]
+struct ScheduledMove {
+    start: Instant,
+    duration: Duration,
+    start_position: [f32; 4],
+    kinematic: KinematicMove,
+}
+
+impl ScheduledMove {
+    fn end_position(&self) -> [f32; 4] {
+        let m = &self.kinematic;
+        let p = &self.start_position;
+
+        [p[0] + m.x, p[1] + m.y, p[2] + m.z, p[3] + m.e]
+    }
+
+    /// position at time elapsed since start of move
+    fn position_after(&self, elapsed: Duration) -> [f32; 4] {
+        if elapsed >= self.duration {
+            return self.end_position();
+        }
+
+        let m = &self.kinematic;
+        let t = elapsed.as_secs_f32();
+        let s = m.abs_distance();
+
+        // fraction of the move completed
+        let fraction = if s == 0.0 {
+            // extrusion only move runs at constant velocity
+            t / self.duration.as_secs_f32()
+        } else {
+            // s = ut + 1/2 at^2
+            ((m.start_velocity * t + 0.5 * m.acceleration * t * t) / s).clamp(0.0, 1.0)
+        };
+
+        let p = &self.start_position;
+
+        [
+            p[0] + m.x * fraction,
+            p[1] + m.y * fraction,
+            p[2] + m.z * fraction,
+            p[3] + m.e * fraction,
+        ]
+    }
+}
+
+/// Tracks when each dispatched kinematic move executes so that
+/// the position can be reported while a move is in progress.
+#[derive(Debug)]
+pub struct MotionTimeline {
+    /// moves not yet completed, in execution order
+    moves: VecDeque<ScheduledMove>,
+    /// position after the last scheduled move
+    tail_position: [f32; 4],
+    /// time at which the last scheduled move ends
+    tail_time: Option<Instant>,
+}
+
+impl MotionTimeline {
+    pub const fn new() -> Self {
+        Self {
+            moves: VecDeque::new(),
+            tail_position: [f32::NAN, f32::NAN, f32::NAN, 0.0],
+            tail_time: None,
+        }
+    }
+
+    /// schedule a move to start when the previous move ends
+    pub fn schedule(&mut self, kinematic: KinematicMove, now: Instant) {
+        // start immediately if the timeline has drained
+        let start = match self.tail_time {
+            Some(t) if t > now => t,
+            _ => now,
+        };
+
+        let duration = Duration::from_secs_f32(kinematic.duration().max(0.0));
+
+        let scheduled = ScheduledMove {
+            start,
+            duration,
+            start_position: self.tail_position,
+            kinematic,
+        };
+
+        self.tail_position = scheduled.end_position();
+        self.tail_time = Some(start + duration);
+        self.moves.push_back(scheduled);
+    }
+
+    /// time the last scheduled move ends, none if nothing was scheduled
+    pub fn end_time(&self) -> Option<Instant> {
+        self.tail_time
+    }
+
+    /// drops all scheduled moves and sets the resting position
+    pub fn reset(&mut self, position: [f32; 4]) {
+        self.moves.clear();
+        self.tail_position = position;
+        self.tail_time = None;
+    }
+
+    /// returns the position at time 'now', discarding completed moves
+    pub fn position_at(&mut self, now: Instant) -> [f32; 4] {
+        while let Some(m) = self.moves.front() {
+            // move has not started, toolhead rests at its start
+            if now < m.start {
+                return m.start_position;
+            }
+
+            let elapsed = now - m.start;
+
+            // move in progress
+            if elapsed < m.duration {
+                return m.position_after(elapsed);
+            }
+
+            // move completed
+            self.moves.pop_front();
         }
+
+        return self.tail_position;
     }
 }
 
+/// relative travel moves from where an excluded object was entered to where it ended.
+/// the toolhead is lifted by z_hop over the object, zero distance moves are omitted
+fn exclusion_travel(start: [f32; 3], end: [f32; 3], z_hop: f32, velocity: f32) -> Vec<Move> {
+    let travel = |x: f32, y: f32, z: f32| Move {
+        start_velocity: 0.0,
+        target_velocity: velocity,
+        x,
+        y,
+        z,
+        e: 0.0,
+    };
+
+    // unknown position, axes are not homed
+    let [x, y, z] = [0, 1, 2].map(|i| match (end[i] - start[i]).is_nan() {
+        true => 0.0,
+        false => end[i] - start[i],
+    });
+
+    let moves = [
+        travel(0.0, 0.0, z_hop),
+        travel(x, y, 0.0),
+        travel(0.0, 0.0, z - z_hop),
+    ];
+
+    moves
+        .into_iter()
+        .filter(|m| m.x != 0.0 || m.y != 0.0 || m.z != 0.0)
+        .collect()
+}
+
+/// number of moves held for look-ahead before the first one is encoded
+const LOOKAHEAD_MOVES: usize = 16;
+
+/// interval M109 and M190 check the heater at while waiting
+const HEATER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// a move waiting in the look-ahead window
+struct QueuedMove {
+    /// relative position, the start velocity is set once the previous move is encoded
+    move_: Move,
+    /// max accel when the move was queued
+    accel: f32,
+    /// actions queued after this move, sent once it is encoded
+    next_actions: Vec<PrinterAction>,
+}
+
 #[derive(Default)]
 struct ActionQueueInner {
-    /// first move in queue, relative position
-    first_move: Option<Move>,
-    first_move_accel: f32,
-    next_actions: VecDeque<PrinterAction>,
+    /// look-ahead window of moves in execution order
+    moves: VecDeque<QueuedMove>,
+}
+
+/// zero turns a heater off, so only the upper limit is checked
+fn check_temperature(temp: f32, max: f32) -> anyhow::Result<()> {
+    if temp > max {
+        return Err(ValidationError::new(
+            PrinterErrorCode::TemperatureOutOfRange,
+            format!(
+                "target temperature {} exceeds max_temp {}, see the 'max_temp' config option",
+                temp, max
+            ),
+        )
+        .into());
+    }
+
+    return Ok(());
+}
+
+/// a non positive limit is treated as unlimited acceleration
+fn accel_limit(accel: f32) -> f32 {
+    if accel > 0.0 { accel } else { f32::INFINITY }
 }
 
 /// The action queue functions as a trapezoid generator.
@@ -174,6 +711,19 @@ pub struct ActionQueue {
     suspended: AtomicBool,
     event_sender: UnboundedSender<PrinterEvent>,
     inner: Mutex<ActionQueueInner>,
+    /// number of actions sent to the executor
+    dispatched: AtomicU64,
+    /// number of actions the executor has consumed
+    completed: tokio::sync::watch::Sender<u64>,
+    /// records planned moves instead of dispatching actions, set in simulation
+    plan: Option<std::sync::Mutex<MovePlan>>,
+}
+
+/// moves recorded by a simulated queue
+struct MovePlan {
+    /// toolhead position [x, y, z, e] at the end of the last recorded move
+    position: [f32; 4],
+    moves: Vec<PrinterPlannedMove>,
 }
 
 impl ActionQueue {
@@ -183,6 +733,38 @@ impl ActionQueue {
             suspended: AtomicBool::new(false),
             event_sender,
             inner: Default::default(),
+            dispatched: AtomicU64::new(0),
+            completed: tokio::sync::watch::Sender::new(0),
+            plan: None,
+        }
+    }
+
+    /// a queue that records the planned moves instead of sending them to an executor,
+    /// actions complete immediately
+    pub fn simulation(state: Arc<ActionState>) -> Self {
+        let (event_sender, _) = tokio::sync::mpsc::unbounded_channel();
+
+        let position = [
+            state.x_position.load(Ordering::SeqCst),
+            state.y_position.load(Ordering::SeqCst),
+            state.z_position.load(Ordering::SeqCst),
+            state.e_position.load(Ordering::SeqCst),
+        ];
+
+        return Self {
+            plan: Some(std::sync::Mutex::new(MovePlan {
+                position,
+                moves: Vec::new(),
+            })),
+            ..Self::new(state, event_sender)
+        };
+    }
+
+    /// returns the moves recorded so far, empty unless simulated
+    pub fn take_plan(&self) -> Vec<PrinterPlannedMove> {
+        match &self.plan {
+            Some(plan) => std::mem::take(&mut plan.lock().unwrap().moves),
+            None => Vec::new(),
         }
     }
 
@@ -190,6 +772,39 @@ impl ActionQueue {
     /// any push when suspended is ignored
     pub fn suspend(&self) {
         self.suspended.store(true, Ordering::SeqCst);
+
+        // dropped actions are never consumed, release anyone waiting for them
+        self.completed
+            .send_replace(self.dispatched.load(Ordering::SeqCst));
+    }
+
+    /// called by the executor after an action is consumed
+    pub fn action_completed(&self) {
+        self.completed.send_modify(|n| *n += 1);
+    }
+
+    /// flush the queue and wait until the executor has consumed every action
+    /// and the scheduled moves have finished
+    pub async fn wait_drained(&self) {
+        self.flush().await;
+
+        let dispatched = self.dispatched.load(Ordering::SeqCst);
+
+        let _ = self
+            .completed
+            .subscribe()
+            .wait_for(|n| *n >= dispatched || self.is_suspended())
+            .await;
+
+        if self.is_suspended() {
+            return;
+        }
+
+        let end = self.state.motion.lock().unwrap().end_time();
+
+        if let Some(end) = end {
+            tokio::time::sleep_until(end.into()).await;
+        }
     }
 
     /// resume the action queue, start listening to pushes
@@ -201,39 +816,63 @@ impl ActionQueue {
         self.suspended.load(Ordering::SeqCst)
     }
 
-    pub async fn push(&self, action: Action) {
+    /// returns an error if the action is refused
+    pub async fn push(&self, action: Action) -> anyhow::Result<()> {
         // does not accept push when suspended
         if self.is_suspended() {
-            return;
+            return Ok(());
         }
 
         match action {
             Action::Move(mut next_move) => {
                 let mut inner = self.inner.lock().await;
 
-                // set the max velocity
                 let max_velocity = self.state.max_velocity.load(Ordering::SeqCst);
 
-                // set the target velocity if not provided
+                // the feedrate of a move is used by the following moves
+                if next_move.target_velocity.is_nan() {
+                    next_move.target_velocity = self.state.feedrate.load(Ordering::SeqCst);
+                } else {
+                    self.state
+                        .feedrate
+                        .store(next_move.target_velocity, Ordering::SeqCst);
+                }
+
+                // no feedrate commanded yet
                 if next_move.target_velocity.is_nan() {
                     next_move.target_velocity = max_velocity;
                 }
 
-                // clamp the velocity
+                // scale by M220 and clamp to the configured limit
+                next_move.target_velocity *= self.state.speed_factor.load(Ordering::SeqCst);
                 next_move.target_velocity = next_move.target_velocity.clamp(0.1, max_velocity);
 
-                // convert move to relative position
+                // convert move to relative position,
+                // absolute coordinates are relative to the origin set by G92
                 if self.state.absolute_position.load(Ordering::SeqCst) {
-                    if !next_move.x.is_nan() {
-                        next_move.x -= self.state.x_position.load(Ordering::SeqCst);
-                    }
+                    let axes = [
+                        (
+                            &mut next_move.x,
+                            &self.state.x_origin,
+                            &self.state.x_position,
+                        ),
+                        (
+                            &mut next_move.y,
+                            &self.state.y_origin,
+                            &self.state.y_position,
+                        ),
+                        (
+                            &mut next_move.z,
+                            &self.state.z_origin,
+                            &self.state.z_position,
+                        ),
+                    ];
 
-                    if !next_move.y.is_nan() {
-                        next_move.y -= self.state.y_position.load(Ordering::SeqCst);
-                    }
-
-                    if !next_move.z.is_nan() {
-                        next_move.z -= self.state.z_position.load(Ordering::SeqCst);
+                    for (target, origin, position) in axes {
+                        if !target.is_nan() {
+                            *target +=
+                                origin.load(Ordering::SeqCst) - position.load(Ordering::SeqCst);
+                        }
                     }
                 }
 
@@ -257,62 +896,59 @@ impl ActionQueue {
                     next_move.e = 0.0;
                 }
 
-                // add the distances to state
-                self.state
-                    .x_position
-                    .fetch_add(next_move.x, Ordering::SeqCst);
-                self.state
-                    .y_position
-                    .fetch_add(next_move.y, Ordering::SeqCst);
-                self.state
-                    .z_position
-                    .fetch_add(next_move.z, Ordering::SeqCst);
-                self.state
-                    .e_position
-                    .fetch_add(next_move.e, Ordering::SeqCst);
-
-                // encode the first move in queue if any
-                if let Some(first_move) = inner.first_move.take() {
-                    // encode and send the first move
-                    self.encode_and_send(first_move, Some(&next_move)).await;
-                    // send the remaining actions
-                    while let Some(action) = inner.next_actions.pop_front() {
-                        self.send_action(action).await;
-                    }
+                // moves of an excluded object only update the position,
+                // the toolhead travels to it when the object ends
+                if self.state.excluding.load(Ordering::SeqCst) {
+                    self.add_position(&next_move);
+                    return Ok(());
+                }
 
-                    return;
+                // refuse the whole move, position is not updated
+                if next_move.e > 0.0 && !self.can_extrude() {
+                    return Err(anyhow::Error::msg(
+                        "Extrude below minimum temp, see the 'min_extrude_temp' config option",
+                    ));
                 }
 
-                // queue is cleared.
-                // next move is the new first move
-                inner.first_move = Some(next_move);
-                inner.first_move_accel = self.state.max_accel.load(Ordering::SeqCst);
+                self.check_extrusion(&next_move)?;
+
+                // add the distances to state
+                self.add_position(&next_move);
+
+                self.queue_move(&mut inner, next_move).await;
             }
             Action::SetVelocity(f) => {
-                self.state.max_velocity.store(f, Ordering::SeqCst);
+                self.state.feedrate.store(f, Ordering::SeqCst);
             }
             Action::SetBedTemp(t) => {
+                self.state.check_bed_temperature(t)?;
+
                 let mut inner = self.inner.lock().await;
 
-                if inner.first_move.is_some() {
-                    inner.next_actions.push_back(PrinterAction::SetBedTemp(t));
+                if let Some(last) = inner.moves.back_mut() {
+                    last.next_actions.push(PrinterAction::SetBedTemp(t));
                 } else {
                     // send action immediatly if queue is empty
                     self.send_action(PrinterAction::SetBedTemp(t)).await;
                 }
             }
             Action::SetBedTempWait(t) => {
+                self.state.check_bed_temperature(t)?;
+
                 self.flush().await;
                 self.send_action(PrinterAction::SetBedTempWait(t)).await;
+                self.wait_for_heater("heater_bed", t).await;
             }
             Action::SetExtruderTemp { index, temp } => {
+                self.state.check_extruder(index)?;
+                self.state.check_extruder_temperature(index, temp)?;
+
                 // acquire lock
                 let mut inner = self.inner.lock().await;
                 // push to queue if queue is not empty
-                if inner.first_move.is_some() {
-                    inner
-                        .next_actions
-                        .push_back(PrinterAction::SetExtruderTemp { index, temp });
+                if let Some(last) = inner.moves.back_mut() {
+                    last.next_actions
+                        .push(PrinterAction::SetExtruderTemp { index, temp });
                 } else {
                     // send immediately if queue is empty
                     self.send_action(PrinterAction::SetExtruderTemp { index, temp })
@@ -320,11 +956,178 @@ impl ActionQueue {
                 }
             }
             Action::SetExtruderTempWait { index, temp } => {
+                self.state.check_extruder(index)?;
+                self.state.check_extruder_temperature(index, temp)?;
+
                 self.flush().await;
                 self.send_action(PrinterAction::SetExtruderTempWait { index, temp })
                     .await;
+                self.wait_for_heater(&extruder_name(index), temp).await;
             }
+            Action::Home { x, y, z } => {
+                // moves queued before homing are sent first
+                self.flush().await;
+                self.send_action(PrinterAction::Home { x, y, z }).await;
+
+                self.state.home(x, y, z);
+
+                // queue is flushed, the recorded position follows the state
+                if let Some(plan) = &self.plan {
+                    let mut plan = plan.lock().unwrap();
+
+                    plan.position[0] = self.state.x_position.load(Ordering::SeqCst);
+                    plan.position[1] = self.state.y_position.load(Ordering::SeqCst);
+                    plan.position[2] = self.state.z_position.load(Ordering::SeqCst);
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// add a relative move to the position in state
+    fn add_position(&self, next_move: &Move) {
+        self.state
+            .x_position
+            .fetch_add(next_move.x, Ordering::SeqCst);
+        self.state
+            .y_position
+            .fetch_add(next_move.y, Ordering::SeqCst);
+        self.state
+            .z_position
+            .fetch_add(next_move.z, Ordering::SeqCst);
+        self.state
+            .e_position
+            .fetch_add(next_move.e, Ordering::SeqCst);
+    }
+
+    /// queue a relative move, the position in state is not updated
+    async fn queue_move(&self, inner: &mut ActionQueueInner, mut next_move: Move) {
+        // the toolhead is at rest unless a move is queued
+        if inner.moves.is_empty() {
+            next_move.start_velocity = 0.0;
+        }
+
+        inner.moves.push_back(QueuedMove {
+            move_: next_move,
+            accel: self.state.max_accel.load(Ordering::SeqCst),
+            next_actions: Vec::new(),
+        });
+
+        // encode the first move once the window is full
+        if inner.moves.len() > LOOKAHEAD_MOVES {
+            self.encode_moves(inner, 1).await;
+        }
+    }
+
+    /// skip moves until end_exclusion, does nothing if already excluding
+    pub fn start_exclusion(&self) {
+        if self.state.excluding.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        *self.state.exclude_start.lock().unwrap() = [
+            self.state.x_position.load(Ordering::SeqCst),
+            self.state.y_position.load(Ordering::SeqCst),
+            self.state.z_position.load(Ordering::SeqCst),
+        ];
+    }
+
+    /// cancel an object, if it is printing its moves are skipped from now on.
+    /// names are case insensitive
+    pub async fn exclude_object(&self, name: &str) {
+        {
+            let mut excluded = self.state.exclude_objects.write().await;
+
+            if !excluded.iter().any(|o| o.eq_ignore_ascii_case(name)) {
+                excluded.push(name.to_string());
+            }
+        }
+
+        let is_current = self
+            .state
+            .current_object
+            .lock()
+            .unwrap()
+            .as_deref()
+            .is_some_and(|o| o.eq_ignore_ascii_case(name));
+
+        if is_current {
+            self.start_exclusion();
+        }
+    }
+
+    /// stop skipping moves and travel to the position the skipped moves ended at
+    pub async fn end_exclusion(&self) {
+        if !self.state.excluding.swap(false, Ordering::SeqCst) || self.is_suspended() {
+            return;
+        }
+
+        let start = *self.state.exclude_start.lock().unwrap();
+        let end = [
+            self.state.x_position.load(Ordering::SeqCst),
+            self.state.y_position.load(Ordering::SeqCst),
+            self.state.z_position.load(Ordering::SeqCst),
+        ];
+        let z_hop = self.state.exclude_z_hop.load(Ordering::SeqCst);
+        let velocity = self.state.max_velocity.load(Ordering::SeqCst);
+
+        let mut inner = self.inner.lock().await;
+
+        for travel in exclusion_travel(start, end, z_hop, velocity) {
+            self.queue_move(&mut inner, travel).await;
+        }
+    }
+
+    /// returns true if the active extruder is hot enough to extrude
+    fn can_extrude(&self) -> bool {
+        if self.state.allow_cold_extrude.load(Ordering::SeqCst) {
+            return true;
+        }
+
+        let temperature = self.state.active_extruder_temperature();
+
+        return temperature >= self.state.min_extrude_temp.load(Ordering::SeqCst);
+    }
+
+    /// refuse implausibly large extrusion, e.g. from a corrupt gcode line
+    fn check_extrusion(&self, next_move: &Move) -> anyhow::Result<()> {
+        let distance =
+            (next_move.x * next_move.x + next_move.y * next_move.y + next_move.z * next_move.z)
+                .sqrt();
+
+        // extrude only move, retractions are checked as well
+        if distance == 0.0 {
+            let max_distance = self.state.max_extrude_only_distance.load(Ordering::SeqCst);
+
+            if next_move.e.abs() > max_distance {
+                return Err(anyhow::Error::msg(format!(
+                    "Extrude only move too long ({:.3}mm vs {:.3}mm), see the 'max_extrude_only_distance' config option",
+                    next_move.e.abs(),
+                    max_distance
+                )));
+            }
+
+            return Ok(());
+        }
+
+        if next_move.e <= 0.0 {
+            return Ok(());
         }
+
+        let diameter = self.state.filament_diameter.load(Ordering::SeqCst);
+        let filament_area = std::f32::consts::PI * (diameter / 2.0) * (diameter / 2.0);
+        let cross_section = next_move.e * filament_area / distance;
+        let max_cross_section = self.state.max_extrude_cross_section.load(Ordering::SeqCst);
+
+        if cross_section > max_cross_section {
+            return Err(anyhow::Error::msg(format!(
+                "Move exceeds maximum extrusion ({:.3}mm^2 vs {:.3}mm^2), see the 'max_extrude_cross_section' config option",
+                cross_section, max_cross_section
+            )));
+        }
+
+        return Ok(());
     }
 
     /// encodes the remaining moves in queue.
@@ -336,27 +1139,810 @@ impl ActionQueue {
         }
 
         let mut inner = self.inner.lock().await;
+        let count = inner.moves.len();
+
+        self.encode_moves(&mut inner, count).await;
+    }
+
+    /// wait until a heater reaches 'target'. returns early if the target is
+    /// changed, e.g. by an emergency stop, or the queue is suspended
+    async fn wait_for_heater(&self, name: &str, target: f32) {
+        // nothing heats in simulation
+        if self.plan.is_some() {
+            return;
+        }
+
+        while !self.is_suspended() && self.state.is_heating(name, target) {
+            tokio::time::sleep(HEATER_POLL_INTERVAL).await;
+        }
+    }
+
+    /// plan the window and encode its first 'count' moves
+    /// along with the actions queued after them
+    async fn encode_moves(&self, inner: &mut ActionQueueInner, count: usize) {
+        let exit_velocities = self.plan(&inner.moves);
+
+        for exit_velocity in exit_velocities.into_iter().take(count) {
+            let Some(queued) = inner.moves.pop_front() else {
+                return;
+            };
+
+            self.encode_and_send(&queued.move_, queued.accel, exit_velocity)
+                .await;
+
+            // the next move starts where this one ends
+            if let Some(next) = inner.moves.front_mut() {
+                next.move_.start_velocity = exit_velocity;
+            }
+
+            for action in queued.next_actions {
+                self.send_action(action).await;
+            }
+        }
+    }
+
+    /// returns the velocity at the end of each move in the window.
+    /// nothing is known past the window, so the toolhead must be able to stop at its end
+    fn plan(&self, moves: &VecDeque<QueuedMove>) -> Vec<f32> {
+        let Some(first) = moves.front() else {
+            return Vec::new();
+        };
+
+        // a move must be able to accelerate
+        let cruise_ratio = self
+            .state
+            .minimum_cruise_ratio
+            .load(Ordering::SeqCst)
+            .clamp(0.0, 0.99);
+
+        // change of velocity squared over the part of each move not reserved for cruising
+        let delta_v2: Vec<f32> = moves
+            .iter()
+            .map(|m| {
+                let distance = m.move_.distance();
+
+                match distance > 0.0 {
+                    true => 2.0 * accel_limit(m.accel) * distance * (1.0 - cruise_ratio),
+                    false => 0.0,
+                }
+            })
+            .collect();
+
+        // limit at the junction with the next move
+        let mut exit_v2: Vec<f32> = moves
+            .iter()
+            .zip(moves.iter().skip(1).map(Some).chain([None]))
+            .map(|(m, next)| match next {
+                Some(next) => self.junction_v2(&m.move_, &next.move_),
+                None => 0.0,
+            })
+            .collect();
+
+        // reverse pass, each move must be able to slow down to the exit of the next
+        for i in (0..exit_v2.len() - 1).rev() {
+            exit_v2[i] = exit_v2[i].min(exit_v2[i + 1] + delta_v2[i + 1]);
+        }
+
+        // forward pass, each move can only speed up by its own acceleration
+        let mut start_v2 = first.move_.start_velocity * first.move_.start_velocity;
+
+        for (exit, delta) in exit_v2.iter_mut().zip(&delta_v2) {
+            *exit = exit.min(start_v2 + delta);
+            start_v2 = *exit;
+        }
+
+        exit_v2.into_iter().map(f32::sqrt).collect()
+    }
+
+    /// encodes the move into acceleration, cruise and deceleration phases
+    /// ending at the planned end velocity
+    async fn encode_and_send(&self, move_: &Move, accel: f32, end_velocity: f32) {
+        let distance = move_.distance();
+
+        // extrusion only move, the toolhead stops
+        if distance == 0.0 {
+            if move_.e != 0.0 {
+                let duration = (move_.e / move_.target_velocity).abs();
+                self.record_move(move_, [0.0; 3], 0.0, duration);
+
+                self.send_action(PrinterAction::ExtrusionMove(ExtrusionMove {
+                    flow: move_.target_velocity,
+                    distance: move_.e,
+                }))
+                .await;
+            }
+
+            return;
+        }
+
+        let accel = accel_limit(accel);
+        // a move must be able to accelerate
+        let cruise_ratio = self
+            .state
+            .minimum_cruise_ratio
+            .load(Ordering::SeqCst)
+            .clamp(0.0, 0.99);
+
+        let start_v2 = move_.start_velocity * move_.start_velocity;
+        let target_v2 = move_.target_velocity * move_.target_velocity;
+        // change of velocity squared over the part of the move not reserved for cruising
+        let delta_v2 = 2.0 * accel * distance * (1.0 - cruise_ratio);
+
+        let end_v2 = end_velocity * end_velocity;
+
+        // accelerate and decelerate within the distance not reserved for cruising
+        let peak_v2 = ((delta_v2 + start_v2 + end_v2) / 2.0)
+            .min(target_v2)
+            .max(start_v2)
+            .max(end_v2);
+        let peak_v = peak_v2.sqrt();
+
+        let accel_distance = ((peak_v2 - start_v2) / (2.0 * accel)).max(0.0);
+        let decel_distance = ((peak_v2 - end_v2) / (2.0 * accel)).max(0.0);
+        let cruise_distance = (distance - accel_distance - decel_distance).max(0.0);
+
+        let phases = [
+            (accel_distance, move_.start_velocity, accel),
+            (cruise_distance, peak_v, 0.0),
+            (decel_distance, peak_v, -accel),
+        ];
+        let phases: Vec<_> = phases.into_iter().filter(|(d, _, _)| *d > 0.0).collect();
+
+        // the last phase takes the remainder, so the phases add up to the move
+        let mut remaining = [move_.x, move_.y, move_.z, move_.e];
+        let mut duration = 0.0;
+
+        for (i, (d, start_velocity, acceleration)) in phases.iter().enumerate() {
+            let [x, y, z, e] = match i + 1 == phases.len() {
+                true => remaining,
+                false => [move_.x, move_.y, move_.z, move_.e].map(|v| v * d / distance),
+            };
+
+            remaining = [
+                remaining[0] - x,
+                remaining[1] - y,
+                remaining[2] - z,
+                remaining[3] - e,
+            ];
+
+            let kinematic = KinematicMove {
+                start_velocity: *start_velocity,
+                acceleration: *acceleration,
+                x,
+                y,
+                z,
+                e,
+            };
+
+            duration += kinematic.duration();
+
+            self.send_action(PrinterAction::KinematicMove(kinematic))
+                .await;
+        }
+
+        self.record_move(
+            move_,
+            [move_.start_velocity, peak_v, end_velocity],
+            accel,
+            duration,
+        );
+    }
+
+    /// record a move in simulation, 'velocities' are the start, cruise and end velocity
+    fn record_move(&self, move_: &Move, velocities: [f32; 3], acceleration: f32, duration: f32) {
+        let Some(plan) = &self.plan else {
+            return;
+        };
+
+        let mut plan = plan.lock().unwrap();
+
+        let start = plan.position;
+        let end = [
+            start[0] + move_.x,
+            start[1] + move_.y,
+            start[2] + move_.z,
+            start[3] + move_.e,
+        ];
+
+        plan.position = end;
 
-        if let Some(current) = inner.first_move.take() {
-            self.encode_and_send(current, None).await;
+        let [start_velocity, cruise_velocity, end_velocity] = velocities;
+        let position = |[x, y, z, e]: [f32; 4]| PrinterPosition { x, y, z, e };
+
+        plan.moves.push(PrinterPlannedMove {
+            start: position(start),
+            end: position(end),
+            distance: move_.distance(),
+            start_velocity,
+            cruise_velocity,
+            end_velocity,
+            acceleration,
+            duration,
+        });
+    }
+
+    /// maximum velocity squared at the junction of two moves,
+    /// the toolhead stops before and after extrusion only moves
+    fn junction_v2(&self, move_: &Move, next_move: &Move) -> f32 {
+        if move_.distance() == 0.0 || next_move.distance() == 0.0 {
+            return 0.0;
         }
 
-        while let Some(action) = inner.next_actions.pop_front() {
-            self.send_action(action).await;
+        let junction_v = self.junction_velocity(move_, next_move);
+
+        return (junction_v * junction_v)
+            .min(move_.target_velocity * move_.target_velocity)
+            .min(next_move.target_velocity * next_move.target_velocity);
+    }
+
+    /// maximum velocity at the junction of two moves with xyz distance
+    fn junction_velocity(&self, move_: &Move, next_move: &Move) -> f32 {
+        let cornering = *self.state.cornering.lock().unwrap();
+
+        match cornering {
+            Cornering::SquareCornerVelocity => self.square_corner_junction(move_, next_move),
+            Cornering::Jerk => self.jerk_junction(move_, next_move),
         }
     }
 
-    /// encodes the move with provided next move
-    async fn encode_and_send(&self, move_: Move, next_move: Option<&Move>) {}
+    /// approximates the corner as an arc deviating by the junction deviation
+    fn square_corner_junction(&self, move_: &Move, next_move: &Move) -> f32 {
+        let dot = move_.x * next_move.x + move_.y * next_move.y + move_.z * next_move.z;
+        // cosine of the angle between the reversed move and the next move
+        let cos_theta = -dot / (move_.distance() * next_move.distance());
+
+        // collinear moves are not limited at the junction
+        if cos_theta < -0.999999 {
+            return f32::INFINITY;
+        }
+
+        // the toolhead reverses
+        if cos_theta > 0.999999 {
+            return 0.0;
+        }
+
+        let sin_theta_d2 = (0.5 * (1.0 - cos_theta)).sqrt();
+        let square_corner_velocity = self.state.square_corner_velocity.load(Ordering::SeqCst);
+
+        // junction deviation is scv^2 * (sqrt(2) - 1) / accel and the arc radius is
+        // deviation * sin / (1 - sin), v^2 = r * accel so the acceleration cancels out
+        let v2 = square_corner_velocity
+            * square_corner_velocity
+            * (std::f32::consts::SQRT_2 - 1.0)
+            * sin_theta_d2
+            / (1.0 - sin_theta_d2);
+
+        return v2.sqrt();
+    }
+
+    /// the fastest velocity at which no axis changes velocity by more than max_jerk
+    fn jerk_junction(&self, move_: &Move, next_move: &Move) -> f32 {
+        let max_jerk = self.state.max_jerk.load(Ordering::SeqCst);
+        let distance = move_.distance();
+        let next_distance = next_move.distance();
+
+        // change of each axis velocity per unit of toolhead velocity
+        let changes = [
+            next_move.x / next_distance - move_.x / distance,
+            next_move.y / next_distance - move_.y / distance,
+            next_move.z / next_distance - move_.z / distance,
+        ];
+
+        changes
+            .into_iter()
+            .map(f32::abs)
+            // collinear moves are not limited at the junction
+            .filter(|change| *change > 1e-6)
+            .map(|change| max_jerk / change)
+            .fold(f32::INFINITY, f32::min)
+    }
 
     async fn send_action(&self, action: PrinterAction) {
+        // nothing executes in simulation, actions complete immediately
+        if self.plan.is_some() {
+            self.dispatched.fetch_add(1, Ordering::SeqCst);
+            self.action_completed();
+            return;
+        }
+
+        match &action {
+            // record the move on the timeline for position reporting
+            PrinterAction::KinematicMove(m) => {
+                self.state
+                    .motion
+                    .lock()
+                    .unwrap()
+                    .schedule(*m, Instant::now());
+            }
+            // heaters follow the commanded targets
+            PrinterAction::SetExtruderTemp { index, temp }
+            | PrinterAction::SetExtruderTempWait { index, temp } => {
+                self.state.set_heater_target(&extruder_name(*index), *temp);
+            }
+            PrinterAction::SetBedTemp(temp) | PrinterAction::SetBedTempWait(temp) => {
+                self.state.set_heater_target("heater_bed", *temp);
+            }
+            _ => {}
+        }
+
+        self.dispatched.fetch_add(1, Ordering::SeqCst);
+
         let _ = self.event_sender.send(PrinterEvent::Action(action));
     }
 
-    /// clear the action queue
+    /// discard the moves and actions waiting in the look-ahead window.
+    /// the position is rolled back to the end of the last dispatched move
     pub async fn clear(&self) {
         let mut inner = self.inner.lock().await;
-        inner.first_move = None;
-        inner.next_actions.clear();
+
+        for queued in inner.moves.drain(..) {
+            let m = &queued.move_;
+
+            self.state.x_position.fetch_sub(m.x, Ordering::SeqCst);
+            self.state.y_position.fetch_sub(m.y, Ordering::SeqCst);
+            self.state.z_position.fetch_sub(m.z, Ordering::SeqCst);
+            self.state.e_position.fetch_sub(m.e, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn test_interpolated_position() {
+    let mut timeline = MotionTimeline::new();
+    timeline.reset([0.0, 0.0, 0.0, 0.0]);
+
+    let start = Instant::now();
+
+    // 10mm move in x at constant 10mm/s, takes 1 second
+    timeline.schedule(
+        KinematicMove {
+            start_velocity: 10.0,
+            acceleration: 0.0,
+            x: 10.0,
+            y: 0.0,
+            z: 0.0,
+            e: 1.0,
+        },
+        start,
+    );
+
+    let [x, y, _, e] = timeline.position_at(start + Duration::from_millis(500));
+
+    assert!(x > 0.0 && x < 10.0);
+    assert!(e > 0.0 && e < 1.0);
+    assert_eq!(y, 0.0);
+
+    // move is completed
+    let [x, _, _, e] = timeline.position_at(start + Duration::from_secs(2));
+
+    assert_eq!(x, 10.0);
+    assert_eq!(e, 1.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_exclude_object_z_hop() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    // homed and hot
+    state.x_position.store(0.0, Ordering::SeqCst);
+    state.y_position.store(0.0, Ordering::SeqCst);
+    state.z_position.store(0.2, Ordering::SeqCst);
+    *state.heaters.lock().unwrap() = vec![Heater::new("extruder")];
+    vm.run_gcode_line("M109 S200").await.unwrap();
+    state.absolute_position.store(true, Ordering::SeqCst);
+    state.exclude_z_hop.store(0.5, Ordering::SeqCst);
+
+    let lines = [
+        "EXCLUDE_OBJECT_DEFINE NAME=part_1",
+        "G1 X10 Y10",
+        "EXCLUDE_OBJECT_START NAME=part_1",
+        "G1 X20 Y10 E1",
+        // cancelled while printing it
+        "EXCLUDE_OBJECT NAME=part_1",
+        "G1 X30 Y30 E1",
+        "G1 X40 Y30 E1",
+        "EXCLUDE_OBJECT_END NAME=part_1",
+    ];
+
+    for line in lines {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    // skipped moves still update the position
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 40.0);
+    assert_eq!(state.y_position.load(Ordering::SeqCst), 30.0);
+    assert!(!state.excluding.load(Ordering::SeqCst));
+
+    // last queued move lowers the toolhead after the travel
+    let last = queue.inner.lock().await.moves.back().unwrap().move_;
+    assert_eq!((last.x, last.y, last.z, last.e), (0.0, 0.0, -0.5, 0.0));
+
+    // travel from where the object was cancelled, lifted by z_hop
+    let travel = exclusion_travel([20.0, 10.0, 0.2], [40.0, 30.0, 0.2], 0.5, 100.0);
+    let travel: Vec<_> = travel.iter().map(|m| (m.x, m.y, m.z, m.e)).collect();
+    assert_eq!(
+        travel,
+        [
+            (0.0, 0.0, 0.5, 0.0),
+            (20.0, 20.0, 0.0, 0.0),
+            (0.0, 0.0, -0.5, 0.0)
+        ]
+    );
+
+    // object stays excluded when printed again
+    vm.run_gcode_line("EXCLUDE_OBJECT_START NAME=PART_1")
+        .await
+        .unwrap();
+    assert!(state.excluding.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_home() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.z_endstop.store(0.5, Ordering::SeqCst);
+
+    // only x is homed
+    vm.run_gcode_line("G28 X").await.unwrap();
+
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 0.0);
+    assert!(state.y_position.load(Ordering::SeqCst).is_nan());
+    assert!(state.z_position.load(Ordering::SeqCst).is_nan());
+
+    // a queued move is flushed before homing
+    vm.run_gcode_string("G1 X10\nG28\n").await.unwrap();
+
+    assert!(queue.inner.lock().await.moves.is_empty());
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 0.0);
+    assert_eq!(state.y_position.load(Ordering::SeqCst), 0.0);
+    assert_eq!(state.z_position.load(Ordering::SeqCst), 0.5);
+
+    let mut homes = Vec::new();
+
+    while let Ok(PrinterEvent::Action(action)) = recv.try_recv() {
+        if let PrinterAction::Home { x, y, z } = action {
+            homes.push((x, y, z));
+        }
+    }
+
+    assert_eq!(homes, [(true, false, false), (true, true, true)]);
+}
+
+#[tokio::test]
+async fn test_set_temperature() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.extruder_count.store(2, Ordering::SeqCst);
+    state.allow_cold_extrude.store(true, Ordering::SeqCst);
+
+    // waiting flushes the queued move first
+    vm.run_gcode_string("G1 X10\nM109 S210 T1\n").await.unwrap();
+    assert!(queue.inner.lock().await.moves.is_empty());
+
+    vm.run_gcode_string("M104 S200\nM190 S60\n").await.unwrap();
+
+    let mut actions = Vec::new();
+
+    while let Ok(PrinterEvent::Action(action)) = recv.try_recv() {
+        if !matches!(action, PrinterAction::KinematicMove(_)) {
+            actions.push(format!("{:?}", action));
+        }
+    }
+
+    assert_eq!(
+        actions,
+        [
+            "SetExtruderTempWait { index: 1, temp: 210.0 }",
+            "SetExtruderTemp { index: 0, temp: 200.0 }",
+            "SetBedTempWait(60.0)",
+        ]
+    );
+
+    // unknown extruder and missing target are refused
+    assert!(vm.run_gcode_line("M104 S200 T2").await.is_err());
+    assert!(vm.run_gcode_line("M140").await.is_err());
+}
+
+#[tokio::test]
+async fn test_positioning_modes() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.allow_cold_extrude.store(true, Ordering::SeqCst);
+    vm.run_gcode_line("G28").await.unwrap();
+
+    // absolute target, the queued move is relative to the current position
+    for line in ["G90", "G1 X5", "G1 X10 E1"] {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    let last = queue.inner.lock().await.moves.back().unwrap().move_;
+    assert_eq!((last.x, last.e), (5.0, 1.0));
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 10.0);
+
+    // relative extrusion only, x stays absolute
+    for line in ["M83", "G1 X12 E0.5"] {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    let last = queue.inner.lock().await.moves.back().unwrap().move_;
+    assert_eq!((last.x, last.e), (2.0, 0.5));
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 1.5);
+
+    // relative positioning
+    vm.run_gcode_string("G91\nG1 X10\n").await.unwrap();
+
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 22.0);
+    assert!(!state.absolute_extrution.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_set_position() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.allow_cold_extrude.store(true, Ordering::SeqCst);
+
+    for line in ["G28", "G90", "G1 X10 E1", "G1 X20 E2"] {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    // resets the extruder without moving
+    vm.run_gcode_line("G92 E0").await.unwrap();
+    assert_eq!(queue.inner.lock().await.moves.back().unwrap().move_.e, 1.0);
+    assert_eq!(state.e_position.load(Ordering::SeqCst), 0.0);
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 20.0);
+
+    // absolute extrusion continues from the new zero
+    vm.run_gcode_line("G1 X30 E0.5").await.unwrap();
+    assert_eq!(queue.inner.lock().await.moves.back().unwrap().move_.e, 0.5);
+
+    // the current x becomes 0, absolute moves are relative to it
+    vm.run_gcode_line("G92 X0").await.unwrap();
+    vm.run_gcode_line("G1 X5").await.unwrap();
+
+    assert_eq!(queue.inner.lock().await.moves.back().unwrap().move_.x, 5.0);
+    assert_eq!(state.x_position.load(Ordering::SeqCst), 35.0);
+    assert_eq!(
+        vm.run_gcode_line("M114").await.unwrap(),
+        "X:5.000 Y:0.000 Z:0.000 E:0.500"
+    );
+}
+
+#[cfg(test)]
+fn received_moves(
+    recv: &mut tokio::sync::mpsc::UnboundedReceiver<PrinterEvent>,
+) -> Vec<KinematicMove> {
+    let mut moves = Vec::new();
+
+    while let Ok(PrinterEvent::Action(action)) = recv.try_recv() {
+        if let PrinterAction::KinematicMove(m) = action {
+            moves.push(m);
+        }
+    }
+
+    return moves;
+}
+
+#[tokio::test]
+async fn test_collinear_moves_cruise() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    vm.run_gcode_string("G1 X10 F3000\nG1 X10\nG1 X10\n")
+        .await
+        .unwrap();
+
+    let moves = received_moves(&mut recv);
+
+    // accelerate once, cruise through the junctions and decelerate once
+    assert!(moves.len() >= 3);
+    assert!(moves[0].start_velocity == 0.0 && moves[0].acceleration > 0.0);
+    assert!(moves[moves.len() - 1].acceleration < 0.0);
+
+    for m in &moves[1..moves.len() - 1] {
+        assert_eq!(m.acceleration, 0.0);
+        assert!((m.start_velocity - 50.0).abs() < 1e-3);
     }
+
+    let distance: f32 = moves.iter().map(|m| m.x).sum();
+    assert!((distance - 30.0).abs() < 1e-3);
+}
+
+#[tokio::test]
+async fn test_corner_junction_velocity() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    let square_corner_velocity = state.square_corner_velocity.load(Ordering::SeqCst);
+
+    vm.run_gcode_string("G1 X20 F6000\nG1 Y20\n").await.unwrap();
+
+    let moves = received_moves(&mut recv);
+
+    // the first move decelerates into the corner
+    let corner = moves.iter().position(|m| m.y != 0.0).unwrap();
+    assert!(moves[corner - 1].acceleration < 0.0);
+
+    // and the second accelerates out of it
+    let junction_v = moves[corner].start_velocity;
+    assert!(moves[corner].acceleration > 0.0);
+    assert!((junction_v - square_corner_velocity).abs() < 1e-3);
+
+    // a shallower corner is taken faster
+    vm.run_gcode_string("G1 X20\nG1 X20 Y5\n").await.unwrap();
+
+    let moves = received_moves(&mut recv);
+    let corner = moves.iter().position(|m| m.y != 0.0).unwrap();
+
+    assert!(moves[corner].start_velocity > square_corner_velocity);
+}
+
+#[tokio::test]
+async fn test_lookahead_ramp_down() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.max_accel.store(1000.0, Ordering::SeqCst);
+    state.minimum_cruise_ratio.store(0.0, Ordering::SeqCst);
+
+    // a long move followed by two short ones before stopping
+    for line in ["G1 X10 F6000", "G1 X0.5", "G1 X0.5"] {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    let exits = queue.plan(&queue.inner.lock().await.moves);
+
+    // v^2 = 2as backwards from the stop, over 0.5mm then 1mm at 1000mm/s^2
+    let expected = [2000.0f32.sqrt(), 1000.0f32.sqrt(), 0.0];
+
+    assert_eq!(exits.len(), expected.len());
+
+    for (exit, expected) in exits.iter().zip(expected) {
+        assert!((exit - expected).abs() < 1e-3, "{} != {}", exit, expected);
+    }
+
+    // the first move is encoded to end at its planned exit
+    queue.flush().await;
+
+    let moves = received_moves(&mut recv);
+    let junction = moves
+        .iter()
+        .scan(0.0, |x, m| {
+            *x += m.x;
+            Some((*x, m))
+        })
+        .find(|(x, _)| (*x - 10.0).abs() < 1e-3)
+        .map(|(_, m)| (m.start_velocity.powi(2) + 2.0 * m.acceleration * m.abs_distance()).sqrt())
+        .unwrap();
+
+    assert!((junction - expected[0]).abs() < 1e-2);
+}
+
+#[tokio::test]
+async fn test_cornering_algorithms() {
+    use crate::gcode::vm::GcodeVM;
+
+    let (sender, _recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+    let queue = Arc::new(ActionQueue::new(state.clone(), sender));
+    let vm = GcodeVM::new(queue.clone());
+
+    state.square_corner_velocity.store(5.0, Ordering::SeqCst);
+    state.max_jerk.store(8.0, Ordering::SeqCst);
+
+    // 90 degree corner, then a reversal
+    for line in ["G1 X20 F6000", "G1 Y20", "G1 Y-20"] {
+        vm.run_gcode_line(line).await.unwrap();
+    }
+
+    let cases = [
+        (Cornering::SquareCornerVelocity, [5.0, 0.0]),
+        // x stops and y starts, the reversal changes y by twice the velocity
+        (Cornering::Jerk, [8.0, 4.0]),
+    ];
+
+    for (cornering, expected) in cases {
+        *state.cornering.lock().unwrap() = cornering;
+
+        let exits = queue.plan(&queue.inner.lock().await.moves);
+
+        for (exit, expected) in exits.iter().zip(expected) {
+            assert!(
+                (exit - expected).abs() < 1e-3,
+                "{:?}: {} != {}",
+                cornering,
+                exit,
+                expected
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_planner_velocity_limits() {
+    let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let state = Arc::new(ActionState::new());
+
+    state.max_velocity.store(300.0, Ordering::SeqCst);
+    state.max_accel.store(5000.0, Ordering::SeqCst);
+    state.allow_cold_extrude.store(true, Ordering::SeqCst);
+
+    let queue = ActionQueue::new(state.clone(), sender);
+
+    // a circle of 20mm radius in 0.5mm segments with a sharp corner every 100 segments,
+    // the same curve as benches/planner.rs
+    let radius = 20.0f32;
+    let step = 0.5 / radius;
+    let segments = 2000;
+
+    for i in 0..segments {
+        let angle = i as f32 * step + (i / 100) as f32 * std::f32::consts::FRAC_PI_2;
+
+        let segment = Move {
+            start_velocity: f32::NAN,
+            target_velocity: 150.0,
+            x: -angle.sin() * radius * step,
+            y: angle.cos() * radius * step,
+            z: 0.0,
+            e: 0.02,
+        };
+
+        queue.push(Action::Move(segment)).await.unwrap();
+    }
+
+    queue.flush().await;
+
+    let mut planned = 0;
+
+    while let Ok(PrinterEvent::Action(action)) = recv.try_recv() {
+        let PrinterAction::KinematicMove(m) = action else {
+            continue;
+        };
+
+        let end_v2 = m.start_velocity * m.start_velocity + 2.0 * m.acceleration * m.abs_distance();
+
+        assert!(m.start_velocity <= 300.0 + 1e-3);
+        assert!(end_v2.max(0.0).sqrt() <= 300.0 + 1e-3);
+        assert!(m.acceleration.abs() <= 5000.0);
+
+        planned += 1;
+    }
+
+    assert!(planned >= segments);
 }
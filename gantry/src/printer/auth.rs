@@ -1,33 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// content of the credentials file
+#[derive(Debug, Serialize, Deserialize)]
+struct Credentials {
+    /// argon2 hash in PHC string format
+    password_hash: String,
+}
+
 #[derive(Debug)]
 pub struct Auth {
     printer_uuid: u128,
+    /// credentials file, rewritten when the password is reset
+    credentials_path: PathBuf,
+    /// password resets are serialized, so the file holds the last reset password
+    reset_lock: tokio::sync::Mutex<()>,
 }
 
 impl Auth {
-    pub fn acquire(printer_uuid: u128) -> Self {
-        Self { printer_uuid }
+    /// registers the printer with the password hash from the credentials file.
+    /// a missing file is created with the default password
+    pub async fn acquire(printer_uuid: u128, credentials_path: PathBuf) -> Self {
+        let auth = Self {
+            printer_uuid,
+            credentials_path,
+            reset_lock: tokio::sync::Mutex::new(()),
+        };
+
+        let password_hash = match auth.load_credentials().await {
+            Ok(hash) => hash,
+            Err(e) => {
+                log::error!(
+                    "failed to load credentials '{}', login is disabled until the file is fixed or removed: {}",
+                    auth.credentials_path.display(),
+                    e
+                );
+                // not a valid hash, every password is refused
+                String::new()
+            }
+        };
+
+        crate::global_auth::register(&auth.username(), password_hash);
+
+        return auth;
+    }
+
+    /// password hash from the credentials file, the file is created if missing
+    async fn load_credentials(&self) -> anyhow::Result<String> {
+        match tokio::fs::read(&self.credentials_path).await {
+            Ok(data) => {
+                let credentials: Credentials = serde_json::from_slice(&data)?;
+
+                Ok(credentials.password_hash)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let password_hash = crate::global_auth::default_password_hash();
+
+                if let Some(dir) = self.credentials_path.parent() {
+                    tokio::fs::create_dir_all(dir).await?;
+                }
+
+                write_credentials(&self.credentials_path, &password_hash).await?;
+
+                Ok(password_hash)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// printers are users of global auth, named by their uuid
+    fn username(&self) -> String {
+        itoa::Buffer::new().format(self.printer_uuid).to_string()
+    }
+
+    /// tokens of other printers are refused
+    fn is_own_token(&self, token: &str) -> bool {
+        crate::global_auth::token_subject(token).is_some_and(|s| s == self.username())
     }
 
     /// login printer, returns jwt token and refresh token
     pub fn login(&self, password: &str) -> Option<(String, String)> {
-        crate::global_auth::login(itoa::Buffer::new().format(self.printer_uuid), password)
+        crate::global_auth::login(&self.username(), password)
     }
 
     /// logout from printer, token would be invalidated
     pub fn logout(&self, token: &str) -> bool {
-        crate::global_auth::logout(token)
+        self.is_own_token(token) && crate::global_auth::logout(token)
     }
 
     /// returns (is_valid, is_timeout)
     pub fn validate_token(&self, token: &str) -> (bool, bool) {
+        if !self.is_own_token(token) {
+            return (false, false);
+        }
+
         crate::global_auth::validate_token(token)
     }
 
-    pub fn reset_password(&self, token: &str, password: &str) -> bool {
-        crate::global_auth::reset_password(token, password)
+    /// the new password is written to the credentials file before it is used
+    pub async fn reset_password(&self, token: &str, password: &str) -> bool {
+        if !self.validate_token(token).0 {
+            return false;
+        }
+
+        let _guard = self.reset_lock.lock().await;
+
+        let password_hash = crate::global_auth::hash_password(password);
+
+        if let Err(e) = write_credentials(&self.credentials_path, &password_hash).await {
+            log::error!(
+                "failed to write credentials '{}': {}",
+                self.credentials_path.display(),
+                e
+            );
+            return false;
+        }
+
+        crate::global_auth::reset_password(token, password_hash)
     }
 
     pub fn refresh_token(&self, refresh_token: &str) -> Option<(String, String)> {
+        if !self.is_own_token(refresh_token) {
+            return None;
+        }
+
         crate::global_auth::refresh_token(refresh_token)
     }
 }
+
+/// replace the credentials file, a crash never leaves it partially written
+async fn write_credentials(path: &Path, password_hash: &str) -> std::io::Result<()> {
+    let credentials = Credentials {
+        password_hash: password_hash.to_string(),
+    };
+
+    crate::files::write_atomic(path, &serde_json::to_vec_pretty(&credentials)?).await
+}
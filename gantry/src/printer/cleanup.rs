@@ -0,0 +1,94 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use gantry_api::{JobHistoryEntry, PrinterGcodeFile};
+
+use crate::config::FileCleanupConfig;
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// files to remove by the cleanup policy, least recently used first.
+/// 'in_use' are the files of queued and running jobs, 'free_space' is in bytes
+/// and the free space threshold is ignored if it is not known
+pub fn select_files(
+    config: &FileCleanupConfig,
+    files: Vec<PrinterGcodeFile>,
+    in_use: &HashSet<String>,
+    history: &[JobHistoryEntry],
+    free_space: Option<u64>,
+    now: u64,
+) -> Vec<String> {
+    let days_ago = |days: f64| now.saturating_sub((days * SECONDS_PER_DAY) as u64);
+
+    // end of the last job of each file
+    let mut last_printed: HashMap<&str, u64> = HashMap::new();
+
+    for job in history {
+        let end = last_printed.entry(job.filename.as_str()).or_default();
+        *end = (*end).max(job.end_time);
+    }
+
+    let keep_after = days_ago(config.keep_recent_days);
+
+    // (last used, file)
+    let mut candidates: Vec<(u64, PrinterGcodeFile)> = files
+        .into_iter()
+        .filter(|f| !in_use.contains(&f.path))
+        .filter_map(|f| {
+            let printed = last_printed.get(f.path.as_str()).copied();
+
+            if printed.is_some_and(|t| t >= keep_after) {
+                return None;
+            }
+
+            Some((f.modified.max(printed.unwrap_or_default()), f))
+        })
+        .collect();
+
+    candidates.sort_by_key(|(last_used, _)| *last_used);
+
+    let mut selected = Vec::new();
+    let mut freed = 0;
+
+    for (last_used, file) in candidates {
+        let expired = config
+            .max_age_days
+            .is_some_and(|days| last_used < days_ago(days));
+
+        let low_space = match (config.min_free_space, free_space) {
+            (Some(min), Some(free)) => free + freed < min,
+            _ => false,
+        };
+
+        // candidates are sorted, later files are neither expired nor needed for space
+        if !expired && !low_space {
+            break;
+        }
+
+        freed += file.size;
+        selected.push(file.path);
+    }
+
+    return selected;
+}
+
+/// available space in bytes of the filesystem holding 'path'
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    return Some(stat.f_bavail as u64 * stat.f_frsize as u64);
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
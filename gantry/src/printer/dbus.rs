@@ -4,6 +4,7 @@ use std::sync::Arc;
 use gantry_api::*;
 
 use super::Instance;
+use super::printer::JobMetadata;
 
 pub struct DBusInstance {
     pub inner: Arc<Instance>,
@@ -84,6 +85,26 @@ impl DBusInstance {
         return PrinterResult::ok(());
     }
 
+    /// reload printer config and reset motion state, clears error state
+    pub async fn firmware_restart(&self, token: &str) -> PrinterResult<()> {
+        // check for token only, error state is cleared by firmware restart
+        if let Err(err) = self.inner.validate_token(token) {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.firmware_restart().await;
+    }
+
+    /// acknowledge a recoverable error and return to ready state
+    pub async fn clear_error(&self, token: &str) -> PrinterResult<()> {
+        // check for token only, printer is expected to be in error state
+        if let Err(err) = self.inner.validate_token(token) {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.clear_error().await;
+    }
+
     /// list objects loaded
     pub async fn list_objects(&self, token: &str) -> PrinterResult<HashMap<String, String>> {
         if let Some(err) = self.inner.validate_token_state(token).await {
@@ -93,6 +114,15 @@ impl DBusInstance {
         return self.inner.list_objects().await;
     }
 
+    /// cancel an object of the running gcode
+    pub async fn exclude_object(&self, token: &str, name: &str) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.exclude_object(name).await;
+    }
+
     /// query endstop status
     pub async fn query_endstops(&self, token: &str) -> PrinterResult<PrinterEndstopStatus> {
         if let Some(err) = self.inner.validate_token_state(token).await {
@@ -102,6 +132,92 @@ impl DBusInstance {
         return self.inner.query_endstops().await;
     }
 
+    /// check endstops, heaters and motion, refused while a job is running
+    pub async fn run_diagnostics(&self, token: &str) -> PrinterResult<Vec<PrinterDiagnostic>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.run_diagnostics().await;
+    }
+
+    /// current toolhead position, interpolated while a move is executing
+    pub async fn get_position(&self, token: &str) -> PrinterResult<PrinterPosition> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.get_position().await;
+    }
+    /// motion limits in effect
+    pub async fn get_motion_limits(&self, token: &str) -> PrinterResult<PrinterMotionLimits> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.get_motion_limits().await;
+    }
+    /// build volume from the configured stepper ranges
+    pub async fn get_build_volume(&self, token: &str) -> PrinterResult<PrinterBuildVolume> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.get_build_volume().await;
+    }
+    /// list filament switch sensors
+    pub async fn list_filament_sensors(
+        &self,
+        token: &str,
+    ) -> PrinterResult<Vec<PrinterFilamentSensor>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.list_filament_sensors().await;
+    }
+    /// move the toolhead by a distance in mm at 'speed' mm/s, moved axes must be homed
+    pub async fn jog(&self, token: &str, x: f32, y: f32, z: f32, speed: f32) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.jog(x, y, z, speed).await
+    }
+    /// home the given axes, all axes if none is given
+    pub async fn home(&self, token: &str, x: bool, y: bool, z: bool) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.home(x, y, z).await
+    }
+    /// set the state of a filament switch sensor, pauses the running print on runout
+    pub async fn set_filament_sensor(
+        &self,
+        token: &str,
+        name: &str,
+        filament_detected: bool,
+    ) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self
+            .inner
+            .set_filament_sensor(name, filament_detected)
+            .await;
+    }
+
+    /// request a snapshot from an external camera service, returns immediately
+    pub async fn snapshot(&self, token: &str) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.snapshot();
+    }
+
     /////////////////////////////////////////////
     ///////////       Extensions      ///////////
     /////////////////////////////////////////////
@@ -178,6 +294,15 @@ impl DBusInstance {
         return self.inner.run_gcode(script).await;
     }
 
+    /// execute a gcode script, returns the responses of the commands, e.g. the position for M114
+    pub async fn run_gcode_response(&self, token: &str, script: String) -> PrinterResult<String> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.run_gcode_response(script).await;
+    }
+
     /// Retrieves a list of registered GCode Command Descriptions.
     pub async fn get_gcode_help(&self, token: &str) -> PrinterResult<HashMap<String, String>> {
         // check token
@@ -188,6 +313,18 @@ impl DBusInstance {
         return self.inner.get_gcode_help().await;
     }
 
+    /// Retrieves execution timing statistics of gcode commands.
+    pub async fn get_gcode_timings(
+        &self,
+        token: &str,
+    ) -> PrinterResult<HashMap<String, PrinterGcodeTiming>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.get_gcode_timings().await;
+    }
+
     /////////////////////////////////////////////
     ///////////       Print job       ///////////
     /////////////////////////////////////////////
@@ -203,7 +340,10 @@ impl DBusInstance {
             return PrinterResult::err(err);
         }
 
-        return self.inner.start_print_job(filename, exclude_objects).await;
+        return self
+            .inner
+            .start_print_job(filename, exclude_objects, None)
+            .await;
     }
     /// pause the print job
     pub async fn pause_print_job(&self, token: &str) -> PrinterResult<()> {
@@ -222,6 +362,14 @@ impl DBusInstance {
         return self.inner.resume_print_job().await;
     }
     /// cancel the print job
+    pub async fn resume_interrupted(&self, token: &str) -> PrinterResult<StartPrintJobResult> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        return self.inner.resume_interrupted().await;
+    }
+    /// cancel the print job
     pub async fn cancel_print_job(&self, token: &str) -> PrinterResult<()> {
         if let Some(err) = self.inner.validate_token_state(token).await {
             return PrinterResult::err(err);
@@ -239,17 +387,25 @@ impl DBusInstance {
         return self.inner.get_print_job_status().await;
     }
 
-    /// queue print job to run after current print job is finished
+    /// queue print job to run after current print job is finished.
+    /// empty operator or notes are not recorded
     pub async fn queue_print_job(
         &self,
         token: &str,
         filename: &str,
+        operator: &str,
+        notes: &str,
     ) -> PrinterResult<PrinterQueuePrintJob> {
         if let Some(err) = self.inner.validate_token_state(token).await {
             return PrinterResult::err(err);
         }
 
-        self.inner.queue_print_job(filename).await
+        let metadata = JobMetadata {
+            operator: Some(operator.to_string()).filter(|s| !s.is_empty()),
+            notes: Some(notes.to_string()).filter(|s| !s.is_empty()),
+        };
+
+        self.inner.queue_print_job(filename, metadata, None).await
     }
 
     //// delete a print job in queue
@@ -286,6 +442,15 @@ impl DBusInstance {
         self.inner.list_job_queue().await
     }
 
+    /// get finished jobs, oldest first
+    pub async fn list_history(&self, token: &str) -> PrinterResult<Vec<JobHistoryEntry>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.list_history().await
+    }
+
     /////////////////////////////////////////////
     ///////////      Gcode files      ///////////
     /////////////////////////////////////////////
@@ -298,6 +463,32 @@ impl DBusInstance {
 
         self.inner.list_files().await
     }
+    /// replace the tags of a gcode file
+    pub async fn tag_file(
+        &self,
+        token: &str,
+        filename: &str,
+        tags: Vec<String>,
+    ) -> PrinterResult<Vec<String>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.tag_file(filename, &tags).await
+    }
+    /// list gcode files carrying the tag whose path contains the query
+    pub async fn search_files(
+        &self,
+        token: &str,
+        tag: &str,
+        query: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeFile>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.search_files(tag, query).await
+    }
     /// get metadata for a specified gcode file
     pub async fn get_file_metadata(
         &self,
@@ -318,7 +509,59 @@ impl DBusInstance {
 
         self.inner.scan_file_metadata(filename).await
     }
-    /// upload a gcode file
+    /// check a gcode file for common slicer mistakes
+    pub async fn lint_gcode_file(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeLintWarning>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.lint_gcode_file(filename).await
+    }
+    /// list the excludable objects defined in a gcode file
+    pub async fn list_file_objects(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeObject>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.list_file_objects(filename).await
+    }
+    /// first 'head' and last 'tail' lines of a gcode file
+    pub async fn get_file_preview(
+        &self,
+        token: &str,
+        filename: &str,
+        head: u32,
+        tail: u32,
+    ) -> PrinterResult<PrinterGcodeFilePreview> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner
+            .get_file_preview(filename, head as usize, tail as usize)
+            .await
+    }
+    /// run a gcode file through the planner in simulation, the printer does not move
+    pub async fn get_move_plan(
+        &self,
+        token: &str,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterPlannedMove>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.get_move_plan(filename).await
+    }
+    /// upload a gcode file, 'filedata' is the base64 encoded file
     pub async fn upload_file(
         &self,
         token: &str,
@@ -331,7 +574,58 @@ impl DBusInstance {
 
         self.inner.upload_file(filename, filedata).await
     }
-    /// download a gcode file
+    /// start a chunked upload of a gcode file, returns the upload id.
+    /// 'sha256' is the hex checksum of the file, empty to skip the check
+    pub async fn upload_init(
+        &self,
+        token: &str,
+        filename: &str,
+        size: u64,
+        sha256: &str,
+    ) -> PrinterResult<String> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        let sha256 = (!sha256.is_empty()).then(|| sha256.to_string());
+
+        self.inner.upload_init(filename, size, sha256).await
+    }
+    /// write a chunk of a chunked upload, returns the number of bytes received
+    pub async fn upload_chunk(
+        &self,
+        token: &str,
+        upload_id: &str,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> PrinterResult<u64> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.upload_chunk(upload_id, offset, &data).await
+    }
+    /// check and store a chunked upload once all chunks are received
+    pub async fn upload_finalize(&self, token: &str, upload_id: &str) -> PrinterResult<()> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.upload_finalize(upload_id).await
+    }
+    /// delete gcode files, the file being printed is not deleted
+    pub async fn delete_files(
+        &self,
+        token: &str,
+        filenames: Vec<String>,
+    ) -> PrinterResult<Vec<PrinterDeleteFileResult>> {
+        if let Some(err) = self.inner.validate_token_state(token).await {
+            return PrinterResult::err(err);
+        }
+
+        self.inner.delete_files(&filenames).await
+    }
+    /// download a gcode file, the file is returned base64 encoded
     pub async fn download_file(&self, token: &str, filename: &str) -> PrinterResult<String> {
         if let Some(err) = self.inner.validate_token_state(token).await {
             return PrinterResult::err(err);
@@ -356,3 +650,47 @@ impl DBusInstance {
         self.inner.upload_printer_config(config).await
     }
 }
+
+#[tokio::test]
+async fn test_run_gcode_response_token() {
+    use std::time::Duration;
+
+    use crate::config::InstanceConfig;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: uuid::Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let dbus = Arc::new(inst).create_dbus_service();
+
+    // rejected before the script runs
+    let re = dbus
+        .run_gcode_response("invalid", "M114\n".to_string())
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::AuthTokenInvalid));
+    assert!(re.result.is_none());
+
+    let login = dbus
+        .inner
+        .login(crate::global_auth::DEFAULT_PASSWORD)
+        .await
+        .result
+        .unwrap();
+
+    let re = dbus
+        .run_gcode_response(&login.token, "M114\n".to_string())
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+    assert_eq!(re.result.unwrap(), "E:0.000");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
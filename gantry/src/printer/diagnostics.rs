@@ -0,0 +1,158 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use gantry_api::{PrinterDiagnostic, PrinterDiagnosticStatus};
+
+use super::action::{Action, ActionQueue, Move};
+use super::mcu::SimulatedMcu;
+
+/// heater target in celsius during the check, below any extrusion temperature
+const HEATER_TEST_TEMP: f32 = 40.0;
+/// distance in mm the z axis is lifted and lowered back
+const MOTION_TEST_DISTANCE: f32 = 1.0;
+/// speed in mm/s of the test moves
+const MOTION_TEST_SPEED: f32 = 5.0;
+/// time allowed for the mcu to consume the actions of a check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// check endstops, heaters and motion, one entry per subsystem.
+/// heaters are turned off after their check, no gcode may be running
+pub async fn run(
+    queue: &ActionQueue,
+    mcu: &SimulatedMcu,
+    heater_bed: bool,
+) -> Vec<PrinterDiagnostic> {
+    let mut report = vec![check_endstops(mcu)];
+
+    let extruder_count = queue.state.extruder_count.load(Ordering::SeqCst);
+
+    for index in 0..extruder_count {
+        let subsystem = match index {
+            0 => "heater extruder".to_string(),
+            n => format!("heater extruder{}", n),
+        };
+
+        let actions = [
+            Action::SetExtruderTemp {
+                index,
+                temp: HEATER_TEST_TEMP,
+            },
+            Action::SetExtruderTemp { index, temp: 0.0 },
+        ];
+
+        report.push(check_actions(queue, subsystem, actions).await);
+    }
+
+    if heater_bed {
+        let actions = [
+            Action::SetBedTemp(HEATER_TEST_TEMP),
+            Action::SetBedTemp(0.0),
+        ];
+
+        report.push(check_actions(queue, "heater heater_bed".to_string(), actions).await);
+    }
+
+    report.push(check_motion(queue).await);
+
+    return report;
+}
+
+fn entry(subsystem: &str, status: PrinterDiagnosticStatus, message: String) -> PrinterDiagnostic {
+    PrinterDiagnostic {
+        subsystem: subsystem.to_string(),
+        status,
+        message,
+    }
+}
+
+/// endstops must be released while the toolhead is not homing
+fn check_endstops(mcu: &SimulatedMcu) -> PrinterDiagnostic {
+    let triggered: Vec<&str> = ["x", "y", "z"]
+        .into_iter()
+        .zip(mcu.endstop_status())
+        .filter_map(|(axis, triggered)| triggered.then_some(axis))
+        .collect();
+
+    if !triggered.is_empty() {
+        return entry(
+            "endstops",
+            PrinterDiagnosticStatus::Failed,
+            format!("endstop {} triggered at rest", triggered.join(", ")),
+        );
+    }
+
+    entry("endstops", PrinterDiagnosticStatus::Passed, String::new())
+}
+
+/// send the actions and wait for the mcu to consume them
+async fn check_actions(
+    queue: &ActionQueue,
+    subsystem: String,
+    actions: impl IntoIterator<Item = Action>,
+) -> PrinterDiagnostic {
+    for action in actions {
+        if let Err(e) = queue.push(action).await {
+            return entry(&subsystem, PrinterDiagnosticStatus::Failed, e.to_string());
+        }
+    }
+
+    if tokio::time::timeout(CHECK_TIMEOUT, queue.wait_drained())
+        .await
+        .is_err()
+    {
+        return entry(
+            &subsystem,
+            PrinterDiagnosticStatus::Failed,
+            "mcu did not respond".to_string(),
+        );
+    }
+
+    entry(&subsystem, PrinterDiagnosticStatus::Passed, String::new())
+}
+
+/// lift z and lower it back, the toolhead must return to where it started
+async fn check_motion(queue: &ActionQueue) -> PrinterDiagnostic {
+    let state = &queue.state;
+
+    let [x, y, z] =
+        [&state.x_position, &state.y_position, &state.z_position].map(|p| p.load(Ordering::SeqCst));
+
+    if x.is_nan() || y.is_nan() || z.is_nan() {
+        return entry(
+            "motion",
+            PrinterDiagnosticStatus::Skipped,
+            "axes are not homed".to_string(),
+        );
+    }
+
+    // the test moves are relative and must not change the commanded feedrate
+    let absolute_position = state.absolute_position.swap(false, Ordering::SeqCst);
+    let feedrate = state.feedrate.load(Ordering::SeqCst);
+
+    let moves = [MOTION_TEST_DISTANCE, -MOTION_TEST_DISTANCE].map(|distance| {
+        Action::Move(Move {
+            start_velocity: f32::NAN,
+            target_velocity: MOTION_TEST_SPEED,
+            x: f32::NAN,
+            y: f32::NAN,
+            z: distance,
+            e: f32::NAN,
+        })
+    });
+
+    let mut report = check_actions(queue, "motion".to_string(), moves).await;
+
+    state
+        .absolute_position
+        .store(absolute_position, Ordering::SeqCst);
+    state.feedrate.store(feedrate, Ordering::SeqCst);
+
+    let end = state.z_position.load(Ordering::SeqCst);
+
+    if report.status == PrinterDiagnosticStatus::Passed && (end - z).abs() > 1e-4 {
+        report.status = PrinterDiagnosticStatus::Failed;
+        report.message = format!("toolhead ended at z {} instead of {}", end, z);
+    }
+
+    return report;
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use super::action::ActionState;
+use super::mcu::SimulatedMcu;
+
+/// distance in mm from the homed position an axis still triggers its endstop
+const TRIGGER_DISTANCE: f32 = 0.001;
+
+/// endstop switches of the x, y and z axes
+pub trait Endstops: Send + Sync {
+    /// triggered state (x, y, z)
+    fn triggered(&self) -> (bool, bool, bool);
+}
+
+/// endstops of a printer without hardware. an axis is triggered while the toolhead
+/// rests at its homed position, or while the mcu reports its switch stuck
+pub struct SimulatedEndstops {
+    state: Arc<ActionState>,
+    mcu: Arc<SimulatedMcu>,
+}
+
+impl SimulatedEndstops {
+    pub fn new(state: Arc<ActionState>, mcu: Arc<SimulatedMcu>) -> Self {
+        Self { state, mcu }
+    }
+}
+
+impl Endstops for SimulatedEndstops {
+    fn triggered(&self) -> (bool, bool, bool) {
+        let position = self.state.current_position();
+        let homed = [
+            &self.state.x_endstop,
+            &self.state.y_endstop,
+            &self.state.z_endstop,
+        ];
+        let switches = self.mcu.endstop_status();
+
+        // unhomed axes are at NaN and never at their endstop
+        let [x, y, z] = [0, 1, 2].map(|i| {
+            let at_endstop =
+                (position[i] - homed[i].load(Ordering::SeqCst)).abs() < TRIGGER_DISTANCE;
+
+            at_endstop || switches[i]
+        });
+
+        return (x, y, z);
+    }
+}
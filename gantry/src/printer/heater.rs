@@ -0,0 +1,104 @@
+use tokio::time::Instant;
+
+/// temperature in celsius heaters start at and cool down to
+pub const AMBIENT_TEMPERATURE: f32 = 25.0;
+/// modeled temperature rise in celsius per second at full power
+const HEATING_RATE: f32 = 2.0;
+/// modeled temperature drop in celsius per second with the heater off
+const COOLING_RATE: f32 = 1.0;
+/// rise above ambient that needs full power to hold
+const FULL_POWER_RISE: f32 = 400.0;
+
+/// config section of an extruder, [extruder] is extruder 0, [extruder1] is extruder 1
+pub fn extruder_name(index: usize) -> String {
+    match index {
+        0 => "extruder".to_string(),
+        i => format!("extruder{}", i),
+    }
+}
+
+/// a heater or temperature sensor. temperatures are not measured yet,
+/// they are modeled from the last commanded target on the tokio clock,
+/// so tests can pause time instead of waiting for a heater
+#[derive(Debug, Clone)]
+pub struct Heater {
+    /// config section name, e.g. 'extruder1', 'heater_bed' or 'chamber'
+    pub name: String,
+    /// commanded target in celsius, 0 is off
+    pub target: f32,
+    /// modeled temperature when the target was set
+    start: f32,
+    /// when the target was set
+    since: Instant,
+}
+
+impl Heater {
+    /// heater that is off and at ambient temperature
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            target: 0.0,
+            start: AMBIENT_TEMPERATURE,
+            since: Instant::now(),
+        }
+    }
+
+    /// set a new target, the temperature continues from its current value
+    pub fn set_target(&mut self, target: f32, now: Instant) {
+        self.start = self.temperature(now);
+        self.since = now;
+        self.target = target;
+    }
+
+    /// temperature approaching the target at a fixed rate, or ambient if off
+    pub fn temperature(&self, now: Instant) -> f32 {
+        let goal = self.target.max(AMBIENT_TEMPERATURE);
+        let elapsed = now.saturating_duration_since(self.since).as_secs_f32();
+
+        if self.start < goal {
+            return (self.start + HEATING_RATE * elapsed).min(goal);
+        }
+
+        return (self.start - COOLING_RATE * elapsed).max(goal);
+    }
+
+    /// duty from 0 to 1, full power while heating and a holding power at target
+    pub fn power(&self, now: Instant) -> f32 {
+        if self.target <= AMBIENT_TEMPERATURE {
+            return 0.0;
+        }
+
+        if self.temperature(now) < self.target {
+            return 1.0;
+        }
+
+        return ((self.target - AMBIENT_TEMPERATURE) / FULL_POWER_RISE).clamp(0.0, 1.0);
+    }
+}
+
+#[test]
+fn test_heater_model() {
+    use std::time::Duration;
+
+    let start = Instant::now();
+
+    let mut heater = Heater::new("extruder");
+    assert_eq!(heater.temperature(start), AMBIENT_TEMPERATURE);
+    assert_eq!(heater.power(start), 0.0);
+
+    // heats at full power, then holds the target
+    heater.set_target(45.0, start);
+    assert_eq!(heater.temperature(start + Duration::from_secs(5)), 35.0);
+    assert_eq!(heater.power(start + Duration::from_secs(5)), 1.0);
+    assert_eq!(heater.temperature(start + Duration::from_secs(60)), 45.0);
+    assert_eq!(heater.power(start + Duration::from_secs(60)), 0.05);
+
+    // cools down to ambient from where it was when turned off
+    heater.set_target(0.0, start + Duration::from_secs(5));
+    assert_eq!(heater.temperature(start + Duration::from_secs(10)), 30.0);
+    assert_eq!(heater.temperature(start + Duration::from_secs(60)), 25.0);
+    assert_eq!(heater.power(start + Duration::from_secs(10)), 0.0);
+
+    assert_eq!(extruder_name(0), "extruder");
+    assert_eq!(extruder_name(2), "extruder2");
+}
@@ -1,15 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use axum::extract::{Query, Request};
-use axum::http::StatusCode;
+use axum::extract::{FromRequestParts, Path, Query, Request};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Extension, Json};
 use axum_auth::AuthBearer;
+use base64::prelude::{BASE64_STANDARD, Engine};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use tokio::fs::File;
 use tokio::sync::RwLock;
@@ -18,10 +23,18 @@ use tokio::task::JoinHandle;
 use gantry_api::*;
 use uuid::Uuid;
 
+use super::action::ValidationError;
 use super::auth::Auth;
 use super::dbus::DBusInstance;
-use crate::config::InstanceConfig;
+use super::mcu::SimulatedMcuState;
+use super::notify::{Notification, Telemetry};
+use super::printer::JobMetadata;
+use super::tags::FileTags;
+use super::upload::ChunkedUploads;
+use crate::config::{FileCleanupConfig, InstanceConfig, InstanceMetadata, PrinterConfig};
 use crate::gcode::GcodeFile;
+use crate::gcode::lint::{self, LintLimits};
+use crate::gcode::objects;
 
 pub struct PrintJob {
     pub uuid: Uuid,
@@ -36,15 +49,69 @@ pub struct Instance {
     pub name: String,
     /// uuid of instance
     pub uuid: u128,
+    /// display name, location and model for dashboards
+    pub metadata: InstanceMetadata,
     /// path where printer data is stored
     printer_path: PathBuf,
+    /// path where gcode files are stored, may be outside printer path
+    gcodes_path: PathBuf,
     /// used to authenticate and store temporary tokens
     auth: Auth,
+    /// gcode files being uploaded in chunks
+    uploads: ChunkedUploads,
+    /// user tags of gcode files
+    file_tags: FileTags,
     /// the printer object, will be none unless state is ready
     printer: Arc<RwLock<super::Printer>>,
     print_jobs: RwLock<Vec<(Uuid, String)>>,
+    /// job ids created by requests carrying an idempotency key
+    idempotency_keys: tokio::sync::Mutex<HashMap<String, (String, Instant)>>,
+    /// snapshot requests for external camera services
+    snapshots: tokio::sync::broadcast::Sender<PrinterSnapshotRequest>,
+    /// bumped whenever the printer status changes, long-poll requests wait on it
+    status_version: tokio::sync::watch::Sender<u64>,
 }
 
+/// debug routes are only served if gantry is started with --debug
+static DEBUG_ROUTES: AtomicBool = AtomicBool::new(false);
+
+/// serve routes exposing internal state for tests and ui development.
+/// must never be enabled in production
+pub fn enable_debug_routes(enabled: bool) {
+    DEBUG_ROUTES.store(enabled, Ordering::SeqCst);
+}
+
+/// error of a gcode command, refused commands report the code of their validation
+fn gcode_error(e: anyhow::Error) -> PrinterError {
+    let code = match e.downcast_ref::<ValidationError>() {
+        Some(v) => v.code,
+        None => PrinterErrorCode::GcodeError,
+    };
+
+    return PrinterError {
+        code,
+        message: e.to_string(),
+    };
+}
+
+/// idempotency keys are forgotten after this duration
+const IDEMPOTENCY_KEY_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// interval to sample temperatures for status changes
+const STATUS_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// temperature change in celsius that bumps the status version
+const STATUS_TEMPERATURE_DELTA: f32 = 0.5;
+
+/// long-poll requests may not wait longer than this
+const MAX_LONGPOLL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// lines returned from either end of a file by get_file_preview
+const MAX_PREVIEW_LINES: usize = 1000;
+
+/// directory in the gcodes path holding chunked uploads in progress
+const UPLOADS_DIR: &str = ".uploads";
+
 impl Instance {
     pub async fn create(
         index: usize,
@@ -55,49 +122,161 @@ impl Instance {
         // printer path
         let printer_path = gantry_path.join(&name);
 
-        if !printer_path.exists() {
-            tokio::fs::create_dir(&printer_path)
-                .await
-                .expect("failed to create printer directory");
+        // gcodes are stored in the data directory if configured, relative to gantry path
+        let gcodes_path = config.gcodes_path(&name, &gantry_path);
 
-            let gcodes = printer_path.join("gcodes");
-            tokio::fs::create_dir_all(gcodes.join("build"))
-                .await
-                .expect("failed to create directory");
-            tokio::fs::create_dir(gcodes.join("thumbnails"))
-                .await
-                .expect("failed to create directory");
+        // printer log is optional, printer still runs without it
+        let printer = super::Printer::new();
+
+        if let Err(e) = printer.open_log(printer_path.join("logs").join("printer.log")) {
+            log::warn!("failed to open log for printer '{}': {}", name, e);
+        }
 
-            tokio::fs::create_dir(printer_path.join("extensions"))
-                .await
-                .expect("failed to create directory");
+        // subscribe before the first restart so startup is reported
+        if !config.webhooks.is_empty() {
+            super::webhook::spawn_webhooks(name.clone(), config.webhooks, printer.subscribe());
         }
 
+        let status_version = tokio::sync::watch::Sender::new(0);
+        let notifications = printer.subscribe();
+
         // create instance
         let inst = Self {
             index,
             name,
             uuid: config.uuid,
-            auth: Auth::acquire(config.uuid),
+            metadata: config.metadata,
+            auth: Auth::acquire(config.uuid, printer_path.join("auth.json")).await,
+            uploads: ChunkedUploads::new(gcodes_path.join(UPLOADS_DIR)),
+            file_tags: FileTags::load(printer_path.join("file_tags.json")).await,
             printer_path,
-            printer: Arc::new(RwLock::new(super::Printer::new())),
+            gcodes_path,
+            printer: Arc::new(RwLock::new(printer)),
             print_jobs: RwLock::new(Vec::new()),
+            idempotency_keys: tokio::sync::Mutex::new(HashMap::new()),
+            snapshots: tokio::sync::broadcast::channel(16).0,
+            status_version,
         };
 
+        Self::spawn_status_tracker(
+            Arc::downgrade(&inst.printer),
+            notifications,
+            inst.status_version.clone(),
+        );
+
         // start the printer
         inst.restart().await;
 
         return inst;
     }
 
+    /// create the directory tree of the instance if missing
+    async fn create_directories(&self) -> std::io::Result<()> {
+        // the printer directory may already hold the log and credentials
+        tokio::fs::create_dir_all(self.printer_path.join("extensions")).await?;
+
+        // data directory may be created or replaced independently
+        tokio::fs::create_dir_all(self.gcodes_path.join("build")).await?;
+        tokio::fs::create_dir_all(self.gcodes_path.join("thumbnails")).await?;
+
+        return Ok(());
+    }
+
     pub fn create_dbus_service(self: Arc<Self>) -> DBusInstance {
         DBusInstance { inner: self }
     }
 
+    /// configured display name, or the instance name
+    pub fn display_name(&self) -> &str {
+        self.metadata.display_name.as_deref().unwrap_or(&self.name)
+    }
+
     pub fn path(&self) -> &PathBuf {
         &self.printer_path
     }
 
+    /// root directory of gcode files
+    pub fn gcodes_path(&self) -> &PathBuf {
+        &self.gcodes_path
+    }
+
+    /// restart the printer whenever printer.cfg is changed externally.
+    /// if gcode is running, the reload is deferred until it finishes
+    pub async fn enable_config_hot_reload(self: &Arc<Self>) {
+        let (sender, mut recv) = tokio::sync::mpsc::unbounded_channel::<()>();
+
+        crate::files::watch(self.path().join("printer.cfg"), move |event| {
+            use notify::EventKind;
+            use notify::event::ModifyKind;
+
+            let changed = match event.kind {
+                EventKind::Create(_) => true,
+                EventKind::Modify(ModifyKind::Metadata(_)) => false,
+                EventKind::Modify(_) => true,
+                _ => false,
+            };
+
+            if changed {
+                let _ = sender.send(());
+            }
+
+            // handler is removed once the instance is dropped
+            let retain = !sender.is_closed();
+
+            Box::pin(async move { retain })
+        })
+        .await;
+
+        let inst = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            while recv.recv().await.is_some() {
+                // editors may write the file several times, wait for it to settle
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                while recv.try_recv().is_ok() {}
+
+                let Some(inst) = inst.upgrade() else {
+                    break;
+                };
+
+                if inst.printer.read().await.is_gcode_running() {
+                    log::warn!(
+                        "printer.cfg of '{}' changed while printing, reload deferred",
+                        inst.name
+                    );
+
+                    while inst.printer.read().await.is_gcode_running() {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+
+                log::info!("printer.cfg of '{}' changed, restarting", inst.name);
+
+                inst.restart().await;
+            }
+        });
+    }
+
+    /// periodically remove old gcode files until the instance is dropped
+    pub fn enable_file_cleanup(self: &Arc<Self>, config: FileCleanupConfig) {
+        let inst = Arc::downgrade(self);
+        let interval = Duration::from_secs_f64(config.interval.max(1.0));
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+
+            loop {
+                interval.tick().await;
+
+                let Some(inst) = inst.upgrade() else {
+                    break;
+                };
+
+                inst.cleanup_files(&config).await;
+            }
+        });
+    }
+
     /// get state of printer
     pub async fn state(&self) -> super::printer::State {
         self.printer.read().await.state()
@@ -112,7 +291,7 @@ impl Instance {
 
         // validate state
         match self.state().await {
-            super::printer::State::Error { code, message } => {
+            super::printer::State::Error { code, message, .. } => {
                 return Some(PrinterError { code, message });
             }
             super::printer::State::Shutdown => {
@@ -186,7 +365,7 @@ impl Instance {
     }
     /// reset password
     pub async fn reset_password(&self, token: &str, new_password: &str) -> PrinterResult<()> {
-        if !self.auth.reset_password(token, new_password) {
+        if !self.auth.reset_password(token, new_password).await {
             return PrinterResult::err(PrinterError {
                 code: PrinterErrorCode::AuthFailed,
                 message: String::new(),
@@ -220,12 +399,21 @@ impl Instance {
         let state: PrinterState;
         let mut error_state_code = PrinterErrorCode::None;
         let mut error_state_message = String::new();
+        let mut error_state_source = PrinterErrorSource::None;
+        let mut error_state_timestamp = 0;
 
         match printer_state {
-            super::printer::State::Error { code, message } => {
+            super::printer::State::Error {
+                code,
+                message,
+                source,
+                timestamp,
+            } => {
                 state = PrinterState::Error;
                 error_state_code = code;
                 error_state_message = message;
+                error_state_source = source;
+                error_state_timestamp = timestamp;
             }
             super::printer::State::Ready => {
                 state = PrinterState::Ready;
@@ -242,12 +430,19 @@ impl Instance {
             state,
             error_state_code,
             error_state_message,
+            error_state_source,
+            error_state_timestamp,
             printer_path: self.path().to_string_lossy().to_string(),
+            gcodes_path: self.gcodes_path().to_string_lossy().to_string(),
+            display_name: self.display_name().to_string(),
+            location: self.metadata.location.clone().unwrap_or_default(),
+            model: self.metadata.model.clone().unwrap_or_default(),
         });
     }
 
+    /// temperature of each extruder, the heated bed and temperature sensors
     pub async fn get_temperatures(&self) -> PrinterResult<Vec<PrinterTemperatureInfo>> {
-        todo!()
+        PrinterResult::ok(self.printer.read().await.temperatures())
     }
 
     /// emergency stop
@@ -269,6 +464,22 @@ impl Instance {
         // stop the printer
         printer.emergency_stop();
 
+        // the printer cannot run without its directories,
+        // failure only affects this instance
+        if let Err(e) = self.create_directories().await {
+            let code = match e.kind() {
+                std::io::ErrorKind::StorageFull => PrinterErrorCode::FileCapacityFull,
+                _ => PrinterErrorCode::FileNotFound,
+            };
+            let message = format!("failed to create printer directories: {}", e);
+
+            log::error!("printer '{}': {}", self.name, message);
+
+            printer.set_error(code, PrinterErrorSource::Config, message.clone());
+
+            return PrinterResult::err(PrinterError { code, message });
+        }
+
         let printer = self.printer.clone();
         let printer_config_path = self.path().join("printer.cfg");
 
@@ -279,9 +490,43 @@ impl Instance {
         return PrinterResult::ok(());
     }
 
-    /// list objects loaded
+    /// reload the printer config and reset the motion state without restarting gantry.
+    /// clears any error state, returns the new error if the config is still broken
+    pub async fn firmware_restart(&self) -> PrinterResult<()> {
+        let mut printer = self.printer.write().await;
+
+        printer
+            .firmware_restart(self.path().join("printer.cfg"))
+            .await;
+
+        if let super::printer::State::Error { code, message, .. } = printer.state() {
+            return PrinterResult::err(PrinterError { code, message });
+        }
+
+        return PrinterResult::ok(());
+    }
+
+    /// acknowledge a recoverable error and return to ready state without reloading config
+    pub async fn clear_error(&self) -> PrinterResult<()> {
+        if let Err(err) = self.printer.write().await.clear_error() {
+            return PrinterResult::err(err);
+        }
+
+        return PrinterResult::ok(());
+    }
+
+    /// objects of the running gcode and their status, 'printing' or 'excluded'
     pub async fn list_objects(&self) -> PrinterResult<HashMap<String, String>> {
-        todo!()
+        PrinterResult::ok(self.printer.read().await.objects().await)
+    }
+
+    /// cancel an object of the running gcode
+    pub async fn exclude_object(&self, name: &str) -> PrinterResult<()> {
+        if let Err(err) = self.printer.read().await.exclude_object(name).await {
+            return PrinterResult::err(err);
+        }
+
+        return PrinterResult::ok(());
     }
 
     /// returns endstop triggered xyz
@@ -297,6 +542,198 @@ impl Instance {
         });
     }
 
+    /// check endstops, heaters and motion, refused while a job is running
+    pub async fn run_diagnostics(&self) -> PrinterResult<Vec<PrinterDiagnostic>> {
+        match self.printer.read().await.run_diagnostics().await {
+            Ok(report) => PrinterResult::ok(report),
+            Err(err) => PrinterResult::err(err),
+        }
+    }
+
+    /// returns the current toolhead position, interpolated while a move is executing
+    pub async fn get_position(&self) -> PrinterResult<PrinterPosition> {
+        let [x, y, z, e] = self.printer.read().await.reported_position();
+
+        return PrinterResult::ok(PrinterPosition { x, y, z, e });
+    }
+
+    /// position, homed axes, feedrate and positioning modes
+    pub async fn get_motion_state(&self) -> PrinterResult<PrinterMotionState> {
+        PrinterResult::ok(self.printer.read().await.motion_state())
+    }
+
+    /// xyz bounds from position_min and position_max of the steppers
+    pub async fn get_build_volume(&self) -> PrinterResult<PrinterBuildVolume> {
+        PrinterResult::ok(self.printer.read().await.build_volume())
+    }
+
+    /// motion limits in effect, including changes by SET_VELOCITY_LIMIT
+    pub async fn get_motion_limits(&self) -> PrinterResult<PrinterMotionLimits> {
+        PrinterResult::ok(self.printer.read().await.motion_limits())
+    }
+
+    /// subscribe to state changes and job events
+    pub async fn subscribe(&self) -> tokio::sync::broadcast::Receiver<Notification> {
+        self.printer.read().await.subscribe()
+    }
+
+    /// sample the current status for integrations
+    pub async fn telemetry(&self) -> Telemetry {
+        self.printer.read().await.telemetry()
+    }
+
+    /// bumps the status version on notifications and temperature changes,
+    /// stops when the printer is dropped
+    fn spawn_status_tracker(
+        printer: std::sync::Weak<RwLock<super::Printer>>,
+        mut notifications: tokio::sync::broadcast::Receiver<Notification>,
+        version: tokio::sync::watch::Sender<u64>,
+    ) {
+        use tokio::sync::broadcast::error::RecvError;
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATUS_SAMPLE_INTERVAL);
+            let mut temperature = 0.0;
+
+            loop {
+                tokio::select! {
+                    re = notifications.recv() => {
+                        // missed notifications still mean the status changed
+                        if let Err(RecvError::Closed) = re {
+                            return;
+                        }
+                    }
+                    _ = interval.tick() => {
+                        let Some(printer) = printer.upgrade() else {
+                            return;
+                        };
+
+                        let t = printer.read().await.telemetry().extruder_temperature;
+
+                        if (t - temperature).abs() < STATUS_TEMPERATURE_DELTA {
+                            continue;
+                        }
+
+                        temperature = t;
+                    }
+                }
+
+                version.send_modify(|v| *v += 1);
+            }
+        });
+    }
+
+    /// compact status of the printer
+    pub async fn get_status(&self) -> PrinterStatus {
+        // read the version first, a change while sampling is reported on the next poll
+        let version = *self.status_version.borrow();
+        let info = self.get_info().await.result.unwrap_or_default();
+        let telemetry = self.telemetry().await;
+
+        return PrinterStatus {
+            version,
+            state: info.state,
+            state_message: info.error_state_message,
+            gcode_running: telemetry.gcode_running,
+            progress: telemetry.progress as f64,
+            extruder_temperature: telemetry.extruder_temperature as f64,
+        };
+    }
+
+    /// waits until the status version differs from 'since' or the timeout elapses,
+    /// then returns the current status
+    pub async fn status_longpoll(&self, since: u64, timeout: Duration) -> PrinterStatus {
+        let mut version = self.status_version.subscribe();
+        let timeout = timeout.min(MAX_LONGPOLL_TIMEOUT);
+
+        // versions restart from zero with gantry, any difference is a change
+        let _ = tokio::time::timeout(timeout, version.wait_for(|v| *v != since)).await;
+
+        return self.get_status().await;
+    }
+
+    /// list filament switch sensors
+    pub async fn list_filament_sensors(&self) -> PrinterResult<Vec<PrinterFilamentSensor>> {
+        let printer = self.printer.read().await;
+
+        let sensors = printer
+            .filament_sensors()
+            .iter()
+            .map(|s| PrinterFilamentSensor {
+                name: s.name.clone(),
+                filament_detected: s.filament_detected(),
+                pause_on_runout: s.pause_on_runout,
+            })
+            .collect();
+
+        return PrinterResult::ok(sensors);
+    }
+
+    /// set the state of a filament switch sensor.
+    /// there is no sensor hardware yet, this simulates toggling the switch
+    pub async fn set_filament_sensor(
+        &self,
+        name: &str,
+        filament_detected: bool,
+    ) -> PrinterResult<()> {
+        let printer = self.printer.read().await;
+
+        return match printer.set_filament_detected(name, filament_detected).await {
+            Ok(()) => PrinterResult::ok(()),
+            Err(e) => PrinterResult::err(e),
+        };
+    }
+
+    /// move the toolhead by a distance in mm, moved axes must be homed.
+    /// the jog is refused if it leaves the soft limits or exceeds [jog] max_distance
+    pub async fn jog(&self, x: f32, y: f32, z: f32, speed: f32) -> PrinterResult<()> {
+        return match self.printer.read().await.jog([x, y, z], speed).await {
+            Ok(()) => PrinterResult::ok(()),
+            Err(e) => PrinterResult::err(e),
+        };
+    }
+
+    /// home the given axes, all axes if none is given
+    pub async fn home(&self, x: bool, y: bool, z: bool) -> PrinterResult<()> {
+        return match self.printer.read().await.home(x, y, z).await {
+            Ok(()) => PrinterResult::ok(()),
+            Err(e) => PrinterResult::err(e),
+        };
+    }
+
+    /// internal state of the simulated mcu
+    pub async fn debug_mcu_state(&self) -> SimulatedMcuState {
+        self.printer.read().await.mcu_state()
+    }
+
+    /// set a simulated endstop switch, axis 0 is x
+    pub async fn debug_set_endstop(&self, axis: usize, triggered: bool) {
+        self.printer.read().await.mcu().set_endstop(axis, triggered);
+    }
+
+    /// inject a fault into the simulated mcu, the printer enters the error state
+    pub async fn debug_mcu_fault(&self, message: String) {
+        self.printer.read().await.mcu().report_fault(message);
+    }
+
+    /// request a snapshot from an external camera service.
+    /// returns immediately, the request is dropped if no service is listening
+    pub fn snapshot(&self) -> PrinterResult<()> {
+        let timestamp = crate::timestamp::unix_timestamp();
+
+        let _ = self.snapshots.send(PrinterSnapshotRequest {
+            printer: self.name.clone(),
+            timestamp,
+        });
+
+        return PrinterResult::ok(());
+    }
+
+    /// subscribe to snapshot requests
+    pub fn subscribe_snapshots(&self) -> tokio::sync::broadcast::Receiver<PrinterSnapshotRequest> {
+        self.snapshots.subscribe()
+    }
+
     /////////////////////////////////////////////
     ///////////       Extensions      ///////////
     /////////////////////////////////////////////
@@ -305,9 +742,34 @@ impl Instance {
     pub async fn list_extensions(&self) -> PrinterResult<HashMap<String, PrinterExtension>> {
         todo!()
     }
-    /// install an extension
+    /// download an extension from its repo url into the extensions directory
     pub async fn install_extension(&self, repo: String) -> PrinterResult<()> {
-        todo!()
+        let dir = self.printer_path.join("extensions");
+        let timeout = crate::outbound::timeouts().extension_install();
+
+        match crate::extensions::install(&repo, &dir, timeout).await {
+            Ok(extension) => {
+                log::info!(
+                    "installed extension '{}' {} from '{}'",
+                    extension.name,
+                    extension.version,
+                    repo
+                );
+                return PrinterResult::ok(());
+            }
+            Err(e) if e.is::<crate::outbound::TimeoutError>() => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::Timeout,
+                    message: e.to_string(),
+                });
+            }
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GenericError,
+                    message: format!("failed to install extension from '{}': {}", repo, e),
+                });
+            }
+        }
     }
     /// remove an extension
     pub async fn remove_extension(&self, name: String) -> PrinterResult<()> {
@@ -330,36 +792,141 @@ impl Instance {
         let printer = self.printer.read().await;
 
         if let Err(e) = printer.run_gcode_string(script).await {
-            return PrinterResult::err(PrinterError {
-                code: PrinterErrorCode::GcodeError,
-                message: e.to_string(),
-            });
+            return PrinterResult::err(gcode_error(e));
         }
 
         return PrinterResult::ok(());
     }
 
+    /// execute a gcode script, returns the responses of the commands separated by newlines
+    pub async fn run_gcode_response(&self, script: String) -> PrinterResult<String> {
+        let printer = self.printer.read().await;
+
+        let mut responses = Vec::new();
+
+        for line in script.lines() {
+            match printer.run_gcode_line(line).await {
+                Ok(r) if r.is_empty() => {}
+                Ok(r) => responses.push(r),
+                Err(e) => {
+                    printer.flush_gcode().await;
+
+                    return PrinterResult::err(gcode_error(e));
+                }
+            }
+        }
+
+        printer.flush_gcode().await;
+
+        return PrinterResult::ok(responses.join("\n"));
+    }
+
+    /// runs gcode lines as they are received, like a tethered print.
+    /// every line is answered with its response followed by 'ok',
+    /// the stream stops at the first error which is answered with '!! <error>'
+    pub fn stream_gcode(
+        self: &Arc<Self>,
+        mut lines: tokio::sync::mpsc::Receiver<String>,
+    ) -> tokio::sync::mpsc::Receiver<String> {
+        let (sender, responses) = tokio::sync::mpsc::channel(64);
+        let inst = self.clone();
+
+        tokio::spawn(async move {
+            // a stream cannot interleave with a print job
+            if inst.printer.read().await.is_gcode_running() {
+                let _ = sender.send("!! print job running".to_string()).await;
+                return;
+            }
+
+            while let Some(line) = lines.recv().await {
+                // lock is released between lines so emergency stop is not blocked
+                let re = inst.printer.read().await.run_gcode_line(&line).await;
+
+                let response = match re {
+                    Ok(r) if r.is_empty() => "ok".to_string(),
+                    Ok(r) => format!("{}\nok", r),
+                    Err(e) => {
+                        let _ = sender.send(format!("!! {}", e)).await;
+                        break;
+                    }
+                };
+
+                // client disconnected
+                if sender.send(response).await.is_err() {
+                    break;
+                }
+            }
+
+            inst.printer.read().await.flush_gcode().await;
+        });
+
+        return responses;
+    }
+
     pub async fn get_gcode_help(&self) -> PrinterResult<HashMap<String, String>> {
         todo!()
     }
 
+    /// execution timing statistics per command, empty unless enabled by [debug] gcode_timing
+    pub async fn get_gcode_timings(&self) -> PrinterResult<HashMap<String, PrinterGcodeTiming>> {
+        let timings = self.printer.read().await.gcode_timings();
+
+        let timings = timings
+            .into_iter()
+            .map(|(cmd, t)| {
+                (
+                    cmd,
+                    PrinterGcodeTiming {
+                        count: t.count,
+                        total_us: t.total.as_micros() as u64,
+                        max_us: t.max.as_micros() as u64,
+                        slow_count: t.slow_count,
+                    },
+                )
+            })
+            .collect();
+
+        return PrinterResult::ok(timings);
+    }
+
     /////////////////////////////////////////////
     ///////////       Print job       ///////////
     /////////////////////////////////////////////
 
-    /// start a print job
+    /// start a print job.
+    /// a repeated request with the same idempotency key returns the original job id
     pub async fn start_print_job(
         &self,
         filename: &str,
         exclude_objects: Vec<String>,
+        idempotency_key: Option<&str>,
     ) -> PrinterResult<StartPrintJobResult> {
-        // create path
-        let path = self.printer_path.join("gcodes").join(filename);
+        let re = self
+            .idempotent_job(idempotency_key, async || {
+                self.spawn_print_job(filename, exclude_objects, JobMetadata::default())
+                    .await
+            })
+            .await;
+
+        return match re {
+            Ok(job_id) => PrinterResult::ok(StartPrintJobResult { job_id }),
+            Err(e) => PrinterResult::err(e),
+        };
+    }
+
+    /// parse the gcode file and add it to the job queue, returns the job id
+    async fn spawn_print_job(
+        &self,
+        filename: &str,
+        exclude_objects: Vec<String>,
+        metadata: JobMetadata,
+    ) -> Result<String, PrinterError> {
+        let path = self.gcode_file_path(filename)?;
 
         let file = match crate::files::open_gcode_file(path).await {
             Ok(f) => f,
             Err(e) => {
-                return PrinterResult::err(PrinterError {
+                return Err(PrinterError {
                     code: PrinterErrorCode::GcodeParseError,
                     message: e.to_string(),
                 });
@@ -368,527 +935,3923 @@ impl Instance {
 
         let uuid = Uuid::new_v4();
 
-        let printer = self.printer.clone();
-
-        printer
+        self.printer
             .read()
             .await
-            .spawn_print_job(uuid, file, exclude_objects)
-            .await;
+            .spawn_print_job(uuid, filename.to_string(), file, exclude_objects, metadata)
+            .await?;
 
-        return PrinterResult::ok(StartPrintJobResult {
-            job_id: uuid.to_string(),
-        });
+        return Ok(uuid.to_string());
+    }
+
+    /// runs create unless a job was created with the same key before it expired
+    async fn idempotent_job(
+        &self,
+        key: Option<&str>,
+        create: impl AsyncFnOnce() -> Result<String, PrinterError>,
+    ) -> Result<String, PrinterError> {
+        let Some(key) = key else {
+            return create().await;
+        };
+
+        // lock is held while creating, concurrent retries wait for the first request
+        let mut keys = self.idempotency_keys.lock().await;
+
+        keys.retain(|_, (_, created)| created.elapsed() < IDEMPOTENCY_KEY_EXPIRY);
+
+        if let Some((job_id, _)) = keys.get(key) {
+            return Ok(job_id.clone());
+        }
+
+        let job_id = create().await?;
+
+        keys.insert(key.to_string(), (job_id.clone(), Instant::now()));
+
+        return Ok(job_id);
     }
-    /// pause the print job
+    /// pause the print job, elapsed time stops counting until resumed
     pub async fn pause_print_job(&self) -> PrinterResult<()> {
-        todo!()
+        let printer = self.printer.read().await;
+
+        if printer.current_job().is_none() {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrintJobNotRunning,
+                message: String::new(),
+            });
+        }
+
+        printer.pause_print_job();
+
+        return PrinterResult::ok(());
     }
     /// resume the print job
     pub async fn resume_print_job(&self) -> PrinterResult<()> {
-        todo!()
-    }
-    /// cancel the print job
-    pub async fn cancel_print_job(&self) -> PrinterResult<()> {
-        todo!()
-    }
+        let printer = self.printer.read().await;
 
-    pub async fn get_print_job_status(&self) -> PrinterResult<PrintJobStatus> {
-        todo!()
-    }
+        if printer.current_job().is_none() {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrintJobNotRunning,
+                message: String::new(),
+            });
+        }
 
-    /// queue print job to run after current print job is finished
-    pub async fn queue_print_job(&self, filename: &str) -> PrinterResult<PrinterQueuePrintJob> {
-        todo!()
-    }
-    //// delete a print job in queue
-    pub async fn delete_queue_print_job(&self, id: &str) -> PrinterResult<()> {
-        todo!()
-    }
+        printer.unpause_print_job();
 
-    /// pause the job queue, next job will not start when current job is finished
-    pub async fn pause_job_queue(&self) -> PrinterResult<()> {
-        todo!()
+        return PrinterResult::ok(());
     }
+    /// continue a print interrupted by power loss.
+    /// reheats, homes x and y, moves to the saved position and continues from the saved line
+    pub async fn resume_interrupted(&self) -> PrinterResult<StartPrintJobResult> {
+        let printer = self.printer.read().await;
 
-    /// resume the job queue
-    pub async fn resume_job_queue(&self) -> PrinterResult<()> {
-        todo!()
-    }
+        let Some(recovery) = printer.recovery() else {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::GenericError,
+                message: "power loss recovery is not enabled, see [power_loss_recovery]"
+                    .to_string(),
+            });
+        };
 
-    /// get a list of jobs in job queue
-    pub async fn list_job_queue(&self) -> PrinterResult<Vec<JobQueuePrintJob>> {
-        todo!()
+        if printer.is_gcode_running() {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: String::new(),
+            });
+        }
+
+        let Some(saved) = recovery.load().await else {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::FileNotFound,
+                message: "no interrupted print".to_string(),
+            });
+        };
+
+        let file = match crate::files::open_gcode_file(self.gcodes_path.join(&saved.filename)).await
+        {
+            Ok(f) => f,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GcodeParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let job_id = saved.job_id.clone();
+
+        printer.resume_print_job(saved, file).await;
+
+        return PrinterResult::ok(StartPrintJobResult { job_id });
     }
+    /// cancel the print job
+    pub async fn cancel_print_job(&self) -> PrinterResult<()> {
+        if !self.printer.read().await.cancel_print_job().await {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrintJobNotRunning,
+                message: String::new(),
+            });
+        }
 
-    /////////////////////////////////////////////
-    ///////////      Gcode files      ///////////
-    /////////////////////////////////////////////
+        return PrinterResult::ok(());
+    }
 
-    /// list avaliable gcode files
-    pub async fn list_files(&self) -> PrinterResult<Vec<PrinterGcodeFile>> {
-        todo!()
+    /// status of the running job, elapsed time excludes time paused
+    pub async fn get_print_job_status(&self) -> PrinterResult<PrintJobStatus> {
+        let printer = self.printer.read().await;
+
+        let Some(job) = printer.current_job() else {
+            return PrinterResult::ok(PrintJobStatus {
+                state: "idle".to_string(),
+                ..Default::default()
+            });
+        };
+
+        let state = match job.is_paused() {
+            true => "paused",
+            false => "printing",
+        };
+
+        let elapsed = job.elapsed().as_secs();
+
+        // time paused moves the eta back, the estimate does not include it
+        let (progress, eta) = match job.estimated_print_time {
+            Some(estimate) if estimate > 0 => {
+                let now = crate::timestamp::unix_timestamp();
+                let paused = now
+                    .saturating_sub(job.start_timestamp)
+                    .saturating_sub(elapsed);
+
+                (
+                    (elapsed as f64 / estimate as f64).min(1.0),
+                    job.start_timestamp + paused + estimate.max(elapsed),
+                )
+            }
+            _ => (0.0, 0),
+        };
+
+        return PrinterResult::ok(PrintJobStatus {
+            filename: job.filename.clone(),
+            state: state.to_string(),
+            estimate_duration: job.estimated_print_time.unwrap_or_default(),
+            elapsed,
+            line: printer.gcode_line() as u64,
+            start_timestamp: job.start_timestamp,
+            progress,
+            eta,
+            ..Default::default()
+        });
     }
-    /// get metadata for a specified gcode file
-    pub async fn get_file_metadata(
+
+    /// queue print job to run after current print job is finished.
+    /// operator and notes are kept in the job history.
+    /// a repeated request with the same idempotency key returns the original job id
+    pub async fn queue_print_job(
         &self,
         filename: &str,
-    ) -> PrinterResult<PrinterGcodeFileMetadata> {
-        todo!()
+        metadata: JobMetadata,
+        idempotency_key: Option<&str>,
+    ) -> PrinterResult<PrinterQueuePrintJob> {
+        let re = self
+            .idempotent_job(idempotency_key, async || {
+                self.spawn_print_job(filename, Vec::new(), metadata).await
+            })
+            .await;
+
+        return match re {
+            Ok(id) => PrinterResult::ok(PrinterQueuePrintJob { id }),
+            Err(e) => PrinterResult::err(e),
+        };
     }
-    /// Initiate a metadata scan for a selected file. If the file has already been scanned the endpoint will force a re-scan.
-    pub async fn scan_file_metadata(&self, filename: &str) -> PrinterResult<()> {
+    //// delete a print job in queue
+    pub async fn delete_queue_print_job(&self, id: &str) -> PrinterResult<()> {
         todo!()
     }
-    /// upload a gcode file
-    pub async fn upload_file(&self, filename: &str, filedata: String) -> PrinterResult<()> {
-        todo!()
+
+    /// pause the job queue, next job will not start when current job is finished
+    pub async fn pause_job_queue(&self) -> PrinterResult<()> {
+        self.printer.read().await.pause_job_queue();
+
+        return PrinterResult::ok(());
     }
-    /// download a gcode file
-    pub async fn download_file(&self, filename: &str) -> PrinterResult<String> {
-        todo!()
+
+    /// resume the job queue, the next job starts if none is running.
+    /// in manual queue mode this confirms the next job after each job
+    pub async fn resume_job_queue(&self) -> PrinterResult<()> {
+        self.printer.read().await.resume_job_queue();
+
+        return PrinterResult::ok(());
     }
-    /// download the printer config
-    pub async fn download_printer_config(&self) -> PrinterResult<String> {
-        todo!()
+
+    /// get a list of jobs in job queue
+    pub async fn list_job_queue(&self) -> PrinterResult<Vec<JobQueuePrintJob>> {
+        return PrinterResult::ok(self.printer.read().await.list_job_queue().await);
     }
-    /// upload the printer config
-    pub async fn upload_printer_config(&self, config: String) -> PrinterResult<()> {
-        todo!()
+
+    /// get a list of finished jobs, oldest first
+    pub async fn list_history(&self) -> PrinterResult<Vec<JobHistoryEntry>> {
+        return PrinterResult::ok(self.printer.read().await.job_history().await);
     }
-}
 
-/////////////////////////////////////////////
-///////////       REST API        ///////////
-/////////////////////////////////////////////
+    /////////////////////////////////////////////
+    ///////////      Gcode files      ///////////
+    /////////////////////////////////////////////
 
-/// create router for the printer interface
-pub fn create_service_router() -> axum::Router {
-    // login and refresh_token does not have bearer token
-    let without_bearer = axum::Router::new()
-        .route("/login", post(login))
-        .route("/refresh_token", post(refresh_token))
-        .layer(axum::middleware::from_fn(instance_extracter));
+    /// list avaliable gcode files, paths are relative to the gcodes directory.
+    /// an entry that cannot be read is logged and skipped
+    pub async fn list_files(&self) -> PrinterResult<Vec<PrinterGcodeFile>> {
+        let tags = self.file_tags.all().await;
+        let mut files = Vec::new();
+        let mut dirs = vec![self.gcodes_path.clone()];
 
-    // all other methods requires bearer token
-    let with_bearer = axum::Router::new()
-        .route("/logout", post(logout))
-        .route("/reset_password", post(reset_password))
-        .route("/info", get(get_info))
-        .route("/temperatures", get(get_temperatures))
-        .route("/emergency_stop", post(emergency_stop))
-        .route("/restart", post(restart))
-        .route("/list_objects", get(list_objects))
-        .route("/query_endstops", get(query_endstops))
-        .route("/list_extensions", get(list_extensions))
-        .route("/install_extension", post(install_extension))
-        .route("/remove_extension", post(remove_extension))
-        .route("/download_extension_config", get(download_extension_config))
-        .route("/upload_extension_config", post(upload_extension_config))
-        .route("/run_gcode", post(run_gcode))
-        .route("/gcode_help", get(get_gcode_help))
-        .route("/start_print_job", post(start_print_job))
-        .route("/pause_print_job", post(pause_print_job))
-        .route("/resume_print_job", post(resume_print_job))
-        .route("/cancel_print_job", post(cancel_print_job))
-        .route("/print_job_status", get(get_print_job_status))
-        .route("/queue_print_job", post(queue_print_job))
-        .route("/delete_queue_print_job", post(delete_queue_print_job))
-        .route("/pause_job_queue", post(pause_job_queue))
-        .route("/resume_job_queue", post(resume_job_queue))
-        .route("/list_job_queue", get(list_job_queue))
-        .route("/list_files", get(list_files))
-        .route("/file_metadata", get(get_file_metadata))
-        .route("/scan_file_metadata", post(scan_file_metadata))
-        .route("/download_file", get(download_file))
-        .route("/upload_file", post(upload_file))
-        .route("/download_printer_config", get(download_printer_config))
-        .route("/upload_printer_config", post(upload_printer_config))
-        .layer(axum::middleware::from_fn(instance_authenticator));
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(e) => e,
+                // only a missing gcodes directory fails the listing
+                Err(e) if dir == self.gcodes_path => {
+                    return PrinterResult::err(PrinterError {
+                        code: PrinterErrorCode::FileNotFound,
+                        message: e.to_string(),
+                    });
+                }
+                Err(e) => {
+                    log::warn!("failed to read directory '{}': {}", dir.display(), e);
+                    continue;
+                }
+            };
 
-    without_bearer.merge(with_bearer)
-}
+            loop {
+                let entry = match entries.next_entry().await {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => break,
+                    Err(e) => {
+                        log::warn!("failed to read directory '{}': {}", dir.display(), e);
+                        break;
+                    }
+                };
 
-/// find the instance by name
-async fn find_instance(name: &str) -> Option<Arc<Instance>> {
-    let instances = crate::INSTANCES.read().await;
+                let path = entry.path();
 
-    return instances.get(name).cloned();
-}
+                let stat = match entry.metadata().await {
+                    Ok(stat) => stat,
+                    Err(e) => {
+                        log::warn!("failed to read metadata of '{}': {}", path.display(), e);
+                        continue;
+                    }
+                };
 
-/// query printer name
-#[derive(Deserialize)]
-pub struct PrinterNameQuery {
-    /// 'name' in query
-    name: String,
-}
+                if stat.is_dir() {
+                    // thumbnails and partial uploads are not gcode files
+                    if path != self.gcodes_path.join("thumbnails")
+                        && path != self.gcodes_path.join(UPLOADS_DIR)
+                    {
+                        dirs.push(path);
+                    }
+                    continue;
+                }
 
-/// extracte instance and verify bearer token
-async fn instance_authenticator(
-    AuthBearer(bearer_token): AuthBearer,
-    query: Query<PrinterNameQuery>,
-    mut request: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    // get the instance request is refering to
-    let instance = match find_instance(&query.name).await {
-        Some(i) => i,
-        None => return Err(StatusCode::BAD_REQUEST),
-    };
+                // symlinks, sockets and the like
+                if !stat.is_file() {
+                    continue;
+                }
+
+                let Ok(relative) = path.strip_prefix(&self.gcodes_path) else {
+                    continue;
+                };
+
+                let modified = stat
+                    .modified()
+                    .map(crate::timestamp::to_unix_timestamp)
+                    .unwrap_or_default();
+
+                let relative = relative.to_string_lossy().replace('\\', "/");
+
+                files.push(PrinterGcodeFile {
+                    tags: tags
+                        .get(&relative)
+                        .map(|t| t.iter().cloned().collect())
+                        .unwrap_or_default(),
+                    path: relative,
+                    modified,
+                    size: stat.len(),
+                    permissions: crate::files::permissions_string(&stat),
+                });
+            }
+        }
 
-    if let Err(_) = instance.validate_token(&bearer_token) {
-        return Err(StatusCode::UNAUTHORIZED);
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        return PrinterResult::ok(files);
     }
+    /// list gcode files sorted by 'sort_by', skipping 'offset' files
+    /// and returning at most 'limit' files if given
+    pub async fn list_files_sorted(
+        &self,
+        sort_by: FileSortKey,
+        order: SortOrder,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> PrinterResult<Vec<PrinterGcodeFile>> {
+        let mut result = self.list_files().await;
 
-    request.extensions_mut().insert(instance);
+        if let Some(files) = &mut result.result {
+            // files are sorted by name already, ties keep that order
+            match sort_by {
+                FileSortKey::Name => {}
+                FileSortKey::Modified => files.sort_by_key(|f| f.modified),
+                FileSortKey::Size => files.sort_by_key(|f| f.size),
+            }
 
-    return Ok(next.run(request).await);
-}
+            if let SortOrder::Desc = order {
+                files.reverse();
+            }
 
-/// extract instance wothout verifying bearer
-async fn instance_extracter(
-    query: Query<PrinterNameQuery>,
-    mut request: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    // get the instance request is refering to
-    let instance = match find_instance(&query.name).await {
-        Some(i) => i,
-        None => return Err(StatusCode::BAD_REQUEST),
-    };
+            let end = match limit {
+                Some(limit) => offset.saturating_add(limit).min(files.len()),
+                None => files.len(),
+            };
 
-    request.extensions_mut().insert(instance);
+            *files = files.drain(offset.min(end)..end).collect();
+        }
 
-    return Ok(next.run(request).await);
-}
+        return result;
+    }
+    /// replace the tags of a gcode file, an empty list removes all tags.
+    /// returns the stored tags, trimmed and lowercased
+    pub async fn tag_file(&self, filename: &str, tags: &[String]) -> PrinterResult<Vec<String>> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
 
-/////////////////////////////////////////////
-///////////      Authentication    //////////
-/////////////////////////////////////////////
-#[derive(Deserialize)]
-pub struct LoginParams {
-    pub password: String,
-}
-/// login to the printer
-pub async fn login(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(login): Json<LoginParams>,
-) -> Json<PrinterResult<PrinterLogin>> {
-    Json(instance.login(&login.password).await)
-}
-/// logout from the printer
-pub async fn logout(
-    Extension(instance): Extension<Arc<Instance>>,
-    AuthBearer(bearer_token): AuthBearer,
-) -> Json<PrinterResult<()>> {
-    Json(instance.logout(&bearer_token).await)
-}
-#[derive(Deserialize)]
-pub struct ResetPasswordParams {
-    pub new_password: String,
-}
-/// reset password
-pub async fn reset_password(
-    Extension(instance): Extension<Arc<Instance>>,
-    AuthBearer(bearer_token): AuthBearer,
-    Json(reset): Json<ResetPasswordParams>,
-) -> Json<PrinterResult<()>> {
-    Json(
-        instance
-            .reset_password(&bearer_token, &reset.new_password)
-            .await,
-    )
-}
-#[derive(Deserialize)]
-pub struct RefreshTokenParams {
-    pub refresh_token: String,
-}
-/// refresh token
-pub async fn refresh_token(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(refresh): Json<RefreshTokenParams>,
-) -> Json<PrinterResult<PrinterLogin>> {
-    Json(instance.refresh_token(&refresh.refresh_token).await)
-}
+        if !tokio::fs::metadata(&path)
+            .await
+            .map(|m| m.is_file())
+            .unwrap_or(false)
+        {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::FileNotFound,
+                message: format!("file '{}' does not exist", filename),
+            });
+        }
 
-/////////////////////////////////////////////
-///////////         Status        ///////////
-/////////////////////////////////////////////
+        return match self.file_tags.set(filename, tags).await {
+            Ok(tags) => PrinterResult::ok(tags),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::GenericError,
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// list gcode files carrying the tag whose path contains the query,
+    /// both are case insensitive and match every file if empty
+    pub async fn search_files(
+        &self,
+        tag: &str,
+        query: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeFile>> {
+        let tag = tag.trim().to_lowercase();
+        let query = query.to_lowercase();
 
-/// get printer info
-pub async fn get_info(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<PrinterInfo>> {
-    Json(instance.get_info().await)
-}
-/// get printer temperatures
-pub async fn get_temperatures(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<Vec<PrinterTemperatureInfo>>> {
-    Json(instance.get_temperatures().await)
-}
+        let mut result = self.list_files().await;
+
+        if let Some(files) = &mut result.result {
+            files.retain(|f| {
+                (tag.is_empty() || f.tags.contains(&tag)) && f.path.to_lowercase().contains(&query)
+            });
+        }
+
+        return result;
+    }
+    /// get metadata for a specified gcode file
+    pub async fn get_file_metadata(
+        &self,
+        filename: &str,
+    ) -> PrinterResult<PrinterGcodeFileMetadata> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let stat = match tokio::fs::metadata(&path).await {
+            Ok(m) => m,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::FileNotFound,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let file = match crate::files::open_gcode_file(path).await {
+            Ok(f) => f,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GcodeParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let modified = stat
+            .modified()
+            .map(crate::timestamp::to_unix_timestamp)
+            .unwrap_or_default();
+
+        let estimate = crate::gcode::estimate::estimate(&file);
+        let config = &file.config;
+
+        // thumbnails are still reported if they cannot be stored, without a path
+        let thumbnails = match self.write_thumbnails(filename, &file, false).await {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("failed to store thumbnails of '{}': {}", filename, e);
+
+                file.thumbnails
+                    .iter()
+                    .map(|t| PrinterGcodeThumbnail {
+                        width: t.width,
+                        height: t.height,
+                        size: t.data.len() as u32,
+                        relative_path: String::new(),
+                    })
+                    .collect()
+            }
+        };
+
+        // stable for a path, so clients can cache by uuid until the file is modified
+        let hash: [u8; 32] = Sha256::digest(filename.as_bytes()).into();
+        let uuid = uuid::Builder::from_custom_bytes(hash[..16].try_into().unwrap()).into_uuid();
+
+        return PrinterResult::ok(PrinterGcodeFileMetadata {
+            size: stat.len(),
+            modified,
+            uuid: uuid.to_string(),
+            slicer: file.slicer.slicer.clone().unwrap_or_default(),
+            slicer_version: file.slicer.version.clone().unwrap_or_default(),
+            object_height: estimate.object_height(),
+            nozzle_diameter: config.nozzle_diameter().unwrap_or_default(),
+            layer_height: config.layer_height().unwrap_or_default(),
+            first_layer_height: config.first_layer_height().unwrap_or_default(),
+            first_layer_extr_temp: config.extruder_temperature().unwrap_or_default(),
+            first_layer_bed_temp: config.bed_temperature().unwrap_or_default(),
+            chamber_temp: config.chamber_temperature().unwrap_or_default(),
+            filament_name: config.filament_name().unwrap_or_default().to_string(),
+            filament_type: config.filament_type().unwrap_or_default().to_string(),
+            // prefer the slicer estimate, it accounts for acceleration
+            estimated_time: file
+                .meta
+                .estimated_print_time
+                .map(|t| t as f32)
+                .unwrap_or(estimate.time),
+            filament_total: file.meta.filament_length_used.unwrap_or_default(),
+            filament_weight_total: file.meta.filament_weight_used.unwrap_or_default(),
+            thumbnails,
+            filename: filename.to_string(),
+            ..Default::default()
+        });
+    }
+    /// list the excludable objects defined in a gcode file,
+    /// so a bed map can be drawn for cancelling objects mid print
+    pub async fn list_file_objects(
+        &self,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeObject>> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let file = match crate::files::open_gcode_file(path).await {
+            Ok(f) => f,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GcodeParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        return PrinterResult::ok(objects::defined_objects(&file));
+    }
+    /// run a gcode file through the planner with the limits in effect,
+    /// the printer does not move
+    pub async fn get_move_plan(&self, filename: &str) -> PrinterResult<Vec<PrinterPlannedMove>> {
+        use super::action::ActionQueue;
+        use crate::gcode::vm::GcodeVM;
+
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let file = match crate::files::open_gcode_file(path).await {
+            Ok(f) => f,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GcodeParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let state = Arc::new(self.printer.read().await.simulation_state());
+        let queue = Arc::new(ActionQueue::simulation(state.clone()));
+        let vm = GcodeVM::new(queue.clone());
+
+        if let Err(e) = vm.run_gcode_file(&file).await {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::GcodeError,
+                message: format!(
+                    "line {}: {}",
+                    state.gcode_line.load(Ordering::SeqCst) + 1,
+                    e
+                ),
+            });
+        }
+
+        vm.flush().await;
+
+        return PrinterResult::ok(queue.take_plan());
+    }
+    /// Initiate a metadata scan for a selected file. If the file has already been scanned the endpoint will force a re-scan.
+    pub async fn scan_file_metadata(&self, filename: &str) -> PrinterResult<()> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let file = match crate::files::open_gcode_file(path).await {
+            Ok(f) => f,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GcodeParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        if let Err(e) = self.write_thumbnails(filename, &file, true).await {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::GenericError,
+                message: format!("failed to store thumbnails of '{}': {}", filename, e),
+            });
+        }
+
+        return PrinterResult::ok(());
+    }
+    /// store the thumbnails of a gcode file as 'thumbnails/<file-stem>-<WxH>.png'.
+    /// a stored thumbnail of the same size is kept unless 'overwrite' is set
+    async fn write_thumbnails(
+        &self,
+        filename: &str,
+        file: &GcodeFile,
+        overwrite: bool,
+    ) -> std::io::Result<Vec<PrinterGcodeThumbnail>> {
+        let stem = std::path::Path::new(filename).with_extension("");
+
+        let mut thumbnails = Vec::new();
+
+        for t in &file.thumbnails {
+            let relative_path = format!(
+                "thumbnails/{}-{}x{}.png",
+                stem.to_string_lossy(),
+                t.width,
+                t.height
+            );
+            let path = self.gcodes_path.join(&relative_path);
+
+            let stored = match tokio::fs::metadata(&path).await {
+                Ok(m) => m.len() == t.data.len() as u64,
+                Err(_) => false,
+            };
+
+            if overwrite || !stored {
+                // thumbnails of files in subdirectories are kept in the same subdirectory
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+
+                tokio::fs::write(&path, &t.data).await?;
+            }
+
+            thumbnails.push(PrinterGcodeThumbnail {
+                width: t.width,
+                height: t.height,
+                size: t.data.len() as u32,
+                relative_path,
+            });
+        }
+
+        return Ok(thumbnails);
+    }
+    /// read a stored thumbnail, 'path' is relative to the thumbnails directory
+    pub async fn get_thumbnail(&self, path: &str) -> PrinterResult<Vec<u8>> {
+        let path = match self.gcode_file_path(&format!("thumbnails/{}", path)) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        return match tokio::fs::read(&path).await {
+            Ok(data) => PrinterResult::ok(data),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: match e.kind() {
+                    std::io::ErrorKind::NotFound => PrinterErrorCode::FileNotFound,
+                    _ => PrinterErrorCode::FileReadError,
+                },
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// check a gcode file for common slicer mistakes.
+    /// temperatures are checked against the limits in printer.cfg if it can be parsed
+    pub async fn lint_gcode_file(
+        &self,
+        filename: &str,
+    ) -> PrinterResult<Vec<PrinterGcodeLintWarning>> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let gcode = match tokio::fs::read_to_string(path).await {
+            Ok(g) => g,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::FileNotFound,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let limits = tokio::fs::read_to_string(self.path().join("printer.cfg"))
+            .await
+            .ok()
+            .and_then(|c| PrinterConfig::parse(&c).ok())
+            .map(|c| LintLimits::from_config(&c))
+            .unwrap_or_default();
+
+        return PrinterResult::ok(lint::lint(&gcode, &limits));
+    }
+    /// first 'head' and last 'tail' lines of a gcode file, at most MAX_PREVIEW_LINES each.
+    /// only the start and the end of the file are read
+    pub async fn get_file_preview(
+        &self,
+        filename: &str,
+        head: usize,
+        tail: usize,
+    ) -> PrinterResult<PrinterGcodeFilePreview> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let re = async {
+            let head = crate::files::read_head_lines(&path, head.min(MAX_PREVIEW_LINES)).await?;
+            let tail = crate::files::read_tail_lines(&path, tail.min(MAX_PREVIEW_LINES)).await?;
+
+            std::io::Result::Ok(PrinterGcodeFilePreview { head, tail })
+        }
+        .await;
+
+        return match re {
+            Ok(preview) => PrinterResult::ok(preview),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: match e.kind() {
+                    std::io::ErrorKind::NotFound => PrinterErrorCode::FileNotFound,
+                    _ => PrinterErrorCode::FileReadError,
+                },
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// upload a gcode file, 'filedata' is the base64 encoded file
+    pub async fn upload_file(&self, filename: &str, filedata: String) -> PrinterResult<()> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        // large files are decoded off the runtime threads
+        let data = tokio::task::spawn_blocking(move || BASE64_STANDARD.decode(filedata)).await;
+
+        let data = match data {
+            Ok(Ok(data)) => data,
+            Ok(Err(e)) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::InvalidParameter,
+                    message: format!("file data is not valid base64: {}", e),
+                });
+            }
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::GenericError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        return Self::write_gcode_file(&path, data).await;
+    }
+    /// store a gcode file received by other means than the api, e.g. multipart forms
+    pub async fn store_file(&self, filename: &str, data: Vec<u8>) -> PrinterResult<()> {
+        return match self.gcode_file_path(filename) {
+            Ok(path) => Self::write_gcode_file(&path, data).await,
+            Err(e) => PrinterResult::err(e),
+        };
+    }
+    /// write a gcode file atomically, parent directories are created
+    async fn write_gcode_file(path: &std::path::Path, data: Vec<u8>) -> PrinterResult<()> {
+        if let Err(e) = crate::files::write_upload(path, data).await {
+            let code = match e.kind() {
+                std::io::ErrorKind::StorageFull => PrinterErrorCode::FileCapacityFull,
+                _ => PrinterErrorCode::GenericError,
+            };
+
+            return PrinterResult::err(PrinterError {
+                code,
+                message: e.to_string(),
+            });
+        }
+
+        return PrinterResult::ok(());
+    }
+
+    /// start a chunked upload of a gcode file, returns the upload id
+    pub async fn upload_init(
+        &self,
+        filename: &str,
+        size: u64,
+        sha256: Option<String>,
+    ) -> PrinterResult<String> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        match self.uploads.init(path, size, sha256).await {
+            Ok(id) => PrinterResult::ok(id),
+            Err(e) => PrinterResult::err(e),
+        }
+    }
+    /// write a chunk of a chunked upload, returns the number of bytes received
+    pub async fn upload_chunk(
+        &self,
+        upload_id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> PrinterResult<u64> {
+        match self.uploads.write_chunk(upload_id, offset, data).await {
+            Ok(received) => PrinterResult::ok(received),
+            Err(e) => PrinterResult::err(e),
+        }
+    }
+    /// check the size and checksum of a chunked upload and store the file
+    pub async fn upload_finalize(&self, upload_id: &str) -> PrinterResult<()> {
+        match self.uploads.finalize(upload_id).await {
+            Ok(()) => PrinterResult::ok(()),
+            Err(e) => PrinterResult::err(e),
+        }
+    }
+
+    /// path of a gcode file, the filename must stay inside the gcodes directory
+    fn gcode_file_path(&self, filename: &str) -> Result<PathBuf, PrinterError> {
+        let relative = std::path::Path::new(filename);
+
+        if filename.is_empty() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::InvalidParameter,
+                message: "filename must not be empty".to_string(),
+            });
+        }
+
+        let is_valid = relative
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+
+        if !is_valid {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PathTraversal,
+                message: format!(
+                    "invalid filename '{}', must be a relative path without '..'",
+                    filename
+                ),
+            });
+        }
+
+        return Ok(self.gcodes_path.join(relative));
+    }
+    /// parse gcode files in the background, so their metadata and thumbnails are cached
+    /// before they are requested
+    pub async fn prewarm_files(&self, filenames: &[String]) -> PrinterResult<()> {
+        let mut paths = Vec::new();
+
+        for filename in filenames {
+            match self.gcode_file_path(filename) {
+                Ok(p) => paths.push(p),
+                Err(e) => return PrinterResult::err(e),
+            }
+        }
+
+        crate::files::prewarm(paths);
+
+        return PrinterResult::ok(());
+    }
+    /// delete gcode files, each file is deleted on its own and reports its own error.
+    /// the file of the running job is not deleted
+    pub async fn delete_files(
+        &self,
+        filenames: &[String],
+    ) -> PrinterResult<Vec<PrinterDeleteFileResult>> {
+        let printing = self
+            .printer
+            .read()
+            .await
+            .current_job()
+            .map(|job| self.gcodes_path.join(job.filename));
+
+        let mut results = Vec::new();
+
+        for filename in filenames {
+            let error = match self.delete_file(filename, printing.as_ref()).await {
+                Ok(()) => PrinterError::NONE,
+                Err(e) => e,
+            };
+
+            results.push(PrinterDeleteFileResult {
+                path: filename.clone(),
+                error,
+            });
+        }
+
+        return PrinterResult::ok(results);
+    }
+    /// 'printing' is the path of the running job
+    async fn delete_file(
+        &self,
+        filename: &str,
+        printing: Option<&PathBuf>,
+    ) -> Result<(), PrinterError> {
+        let path = self.gcode_file_path(filename)?;
+
+        if printing == Some(&path) {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: format!("file '{}' is being printed", filename),
+            });
+        }
+
+        match tokio::fs::metadata(&path).await {
+            Ok(m) if m.is_file() => {}
+            _ => {
+                return Err(PrinterError {
+                    code: PrinterErrorCode::FileNotFound,
+                    message: format!("file '{}' does not exist", filename),
+                });
+            }
+        }
+
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            return Err(PrinterError {
+                code: PrinterErrorCode::GenericError,
+                message: e.to_string(),
+            });
+        }
+
+        // a file uploaded later under the same name starts untagged
+        if let Err(e) = self.file_tags.set(filename, &[]).await {
+            log::warn!("failed to remove tags of '{}': {}", filename, e);
+        }
+
+        return Ok(());
+    }
+    /// remove the gcode files selected by the cleanup policy, returns the removed files.
+    /// files of queued and running jobs and recently printed files are kept
+    pub async fn cleanup_files(&self, config: &FileCleanupConfig) -> Vec<String> {
+        let Some(files) = self.list_files().await.result else {
+            return Vec::new();
+        };
+
+        let printer = self.printer.read().await;
+
+        let mut in_use: HashSet<String> = printer
+            .list_job_queue()
+            .await
+            .into_iter()
+            .map(|job| job.filename)
+            .collect();
+
+        let printing = printer.current_job().map(|job| job.filename);
+        in_use.extend(printing.clone());
+
+        let history = printer.job_history().await;
+
+        drop(printer);
+
+        let selected = super::cleanup::select_files(
+            config,
+            files,
+            &in_use,
+            &history,
+            super::cleanup::free_space(&self.gcodes_path),
+            crate::timestamp::unix_timestamp(),
+        );
+
+        let printing = printing.map(|f| self.gcodes_path.join(f));
+        let mut removed = Vec::new();
+
+        for filename in selected {
+            match self.delete_file(&filename, printing.as_ref()).await {
+                Ok(()) => {
+                    log::info!("removed old gcode file '{}' of '{}'", filename, self.name);
+                    removed.push(filename);
+                }
+                Err(e) => log::warn!("failed to remove '{}': {}", filename, e.message),
+            }
+        }
+
+        return removed;
+    }
+    /// download a gcode file, the file is returned base64 encoded
+    pub async fn download_file(&self, filename: &str) -> PrinterResult<String> {
+        let path = match self.gcode_file_path(filename) {
+            Ok(p) => p,
+            Err(e) => return PrinterResult::err(e),
+        };
+
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: match e.kind() {
+                        std::io::ErrorKind::NotFound => PrinterErrorCode::FileNotFound,
+                        _ => PrinterErrorCode::FileReadError,
+                    },
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        return match tokio::task::spawn_blocking(move || BASE64_STANDARD.encode(data)).await {
+            Ok(encoded) => PrinterResult::ok(encoded),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::FileReadError,
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// the printer config parsed into sections and typed values
+    pub async fn get_parsed_config(&self) -> PrinterResult<PrinterConfig> {
+        let config = match tokio::fs::read_to_string(self.path().join("printer.cfg")).await {
+            Ok(c) => c,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::FileReadError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        return match PrinterConfig::parse(&config) {
+            Ok(c) => PrinterResult::ok(c),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrinterConfigParseError,
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// change a single key of the printer config, other lines and comments are kept.
+    /// the key is removed if value is none. the patched config is validated before it is
+    /// written, and the printer is restarted if requested
+    pub async fn patch_config(
+        &self,
+        section: &str,
+        key: &str,
+        value: Option<&str>,
+        restart: bool,
+    ) -> PrinterResult<()> {
+        let path = self.path().join("printer.cfg");
+
+        let config = match tokio::fs::read_to_string(&path).await {
+            Ok(c) => c,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::FileReadError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        let patched = match crate::config::patch::patch(&config, section, key, value)
+            .and_then(|p| crate::config::patch::validate(&p).map(|_| p))
+        {
+            Ok(p) => p,
+            Err(e) => {
+                return PrinterResult::err(PrinterError {
+                    code: PrinterErrorCode::PrinterConfigParseError,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        if let Err(e) = crate::files::write_atomic(&path, patched.as_bytes()).await {
+            let code = match e.kind() {
+                std::io::ErrorKind::StorageFull => PrinterErrorCode::FileCapacityFull,
+                _ => PrinterErrorCode::GenericError,
+            };
+
+            return PrinterResult::err(PrinterError {
+                code,
+                message: e.to_string(),
+            });
+        }
+
+        if restart {
+            return self.restart().await;
+        }
+
+        return PrinterResult::ok(());
+    }
+    /// download the printer config
+    pub async fn download_printer_config(&self) -> PrinterResult<String> {
+        return match tokio::fs::read_to_string(self.path().join("printer.cfg")).await {
+            Ok(config) => PrinterResult::ok(config),
+            Err(e) => PrinterResult::err(PrinterError {
+                code: match e.kind() {
+                    std::io::ErrorKind::NotFound => PrinterErrorCode::FileNotFound,
+                    _ => PrinterErrorCode::FileReadError,
+                },
+                message: e.to_string(),
+            }),
+        };
+    }
+    /// upload the printer config, it must parse before it replaces the current config.
+    /// the printer is not restarted, the config takes effect on the next restart
+    pub async fn upload_printer_config(&self, config: String) -> PrinterResult<()> {
+        if let Err(e) = PrinterConfig::parse(&config) {
+            return PrinterResult::err(PrinterError {
+                code: PrinterErrorCode::PrinterConfigParseError,
+                message: e.to_string(),
+            });
+        }
+
+        let path = self.path().join("printer.cfg");
+
+        if let Err(e) = crate::files::write_atomic(&path, config.as_bytes()).await {
+            let code = match e.kind() {
+                std::io::ErrorKind::StorageFull => PrinterErrorCode::FileCapacityFull,
+                _ => PrinterErrorCode::GenericError,
+            };
+
+            return PrinterResult::err(PrinterError {
+                code,
+                message: e.to_string(),
+            });
+        }
+
+        return PrinterResult::ok(());
+    }
+}
+
+/////////////////////////////////////////////
+///////////       REST API        ///////////
+/////////////////////////////////////////////
+
+/// create router for the printer interface
+pub fn create_service_router() -> axum::Router {
+    // login and refresh_token does not have bearer token
+    let without_bearer = axum::Router::new()
+        .route("/login", post(login))
+        .route("/refresh_token", post(refresh_token))
+        .layer(axum::middleware::from_fn(instance_extracter));
+
+    // all other methods requires bearer token
+    let with_bearer = axum::Router::new()
+        .route("/logout", post(logout))
+        .route("/reset_password", post(reset_password))
+        .route("/info", get(get_info))
+        .route("/temperatures", get(get_temperatures))
+        .route("/emergency_stop", post(emergency_stop))
+        .route("/restart", post(restart))
+        .route("/firmware_restart", post(firmware_restart))
+        .route("/clear_error", post(clear_error))
+        .route("/list_objects", get(list_objects))
+        .route("/exclude_object", post(exclude_object))
+        .route("/query_endstops", get(query_endstops))
+        .route("/run_diagnostics", post(run_diagnostics))
+        .route("/position", get(get_position))
+        .route("/motion_limits", get(get_motion_limits))
+        .route("/build_volume", get(get_build_volume))
+        .route("/jog", post(jog))
+        .route("/home", post(home))
+        .route("/status_longpoll", get(status_longpoll))
+        .route("/filament_sensors", get(list_filament_sensors))
+        .route("/set_filament_sensor", post(set_filament_sensor))
+        .route("/snapshot", post(snapshot))
+        .route("/debug/mcu", get(get_debug_mcu))
+        .route("/debug/mcu/endstop", post(debug_set_endstop))
+        .route("/debug/mcu/fault", post(debug_mcu_fault))
+        .route("/list_extensions", get(list_extensions))
+        .route("/install_extension", post(install_extension))
+        .route("/remove_extension", post(remove_extension))
+        .route("/download_extension_config", get(download_extension_config))
+        .route("/upload_extension_config", post(upload_extension_config))
+        .route("/run_gcode", post(run_gcode))
+        .route("/stream_gcode", post(stream_gcode))
+        .route("/gcode_help", get(get_gcode_help))
+        .route("/gcode_timings", get(get_gcode_timings))
+        .route("/start_print_job", post(start_print_job))
+        .route("/pause_print_job", post(pause_print_job))
+        .route("/resume_print_job", post(resume_print_job))
+        .route("/resume_interrupted", post(resume_interrupted))
+        .route("/cancel_print_job", post(cancel_print_job))
+        .route("/print_job_status", get(get_print_job_status))
+        .route("/queue_print_job", post(queue_print_job))
+        .route("/delete_queue_print_job", post(delete_queue_print_job))
+        .route("/pause_job_queue", post(pause_job_queue))
+        .route("/resume_job_queue", post(resume_job_queue))
+        .route("/list_job_queue", get(list_job_queue))
+        .route("/list_history", get(list_history))
+        .route("/list_files", get(list_files))
+        .route("/tag_file", post(tag_file))
+        .route("/search_files", get(search_files))
+        .route("/file_metadata", get(get_file_metadata))
+        .route("/scan_file_metadata", post(scan_file_metadata))
+        .route("/lint_gcode_file", get(lint_gcode_file))
+        .route("/file_objects", get(list_file_objects))
+        .route("/file_preview", get(get_file_preview))
+        .route("/thumbnails/{*path}", get(get_thumbnail))
+        .route("/move_plan", get(get_move_plan))
+        .route("/delete_files", post(delete_files))
+        .route("/download_file", get(download_file))
+        .route("/upload_file", post(upload_file))
+        .route("/upload_init", post(upload_init))
+        .route("/upload_chunk", post(upload_chunk))
+        .route("/upload_finalize", post(upload_finalize))
+        .route("/prewarm", post(prewarm_files))
+        .route("/config_parsed", get(get_parsed_config))
+        .route("/config_patch", post(patch_config))
+        .route("/download_printer_config", get(download_printer_config))
+        .route("/upload_printer_config", post(upload_printer_config))
+        .layer(axum::middleware::from_fn(instance_authenticator));
+
+    nest_instance_routes(without_bearer.merge(with_bearer))
+}
+
+/// serve routes under both '/route?name=<name>' and '/<name>/route'
+fn nest_instance_routes(router: axum::Router) -> axum::Router {
+    router.clone().nest("/{name}", router)
+}
+
+/// find the instance by name
+async fn find_instance(name: &str) -> Option<Arc<Instance>> {
+    let instances = crate::INSTANCES.read().await;
+
+    return instances.get(name).cloned();
+}
+
+/// query printer name
+#[derive(Deserialize)]
+pub struct PrinterNameQuery {
+    /// 'name' in query
+    name: String,
+}
+
+/// printer name from the path prefix, falls back to the 'name' query
+pub struct PrinterName(String);
+
+impl<S: Send + Sync> FromRequestParts<S> for PrinterName {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Ok(Path(path)) = Path::<PrinterNameQuery>::from_request_parts(parts, state).await {
+            return Ok(Self(path.name));
+        }
+
+        match Query::<PrinterNameQuery>::from_request_parts(parts, state).await {
+            Ok(Query(query)) => Ok(Self(query.name)),
+            Err(_) => Err(StatusCode::BAD_REQUEST),
+        }
+    }
+}
+
+/// extracte instance and verify bearer token
+async fn instance_authenticator(
+    AuthBearer(bearer_token): AuthBearer,
+    PrinterName(name): PrinterName,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    // get the instance request is refering to
+    let instance = match find_instance(&name).await {
+        Some(i) => i,
+        None => return Err(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    if let Err(err) = instance.validate_token(&bearer_token) {
+        let body = Json(PrinterResult::<()>::err(err));
+
+        return Err((StatusCode::UNAUTHORIZED, body).into_response());
+    }
+
+    request.extensions_mut().insert(instance);
+
+    return Ok(next.run(request).await);
+}
+
+/// extract instance wothout verifying bearer
+async fn instance_extracter(
+    PrinterName(name): PrinterName,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    // get the instance request is refering to
+    let instance = match find_instance(&name).await {
+        Some(i) => i,
+        None => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    request.extensions_mut().insert(instance);
+
+    return Ok(next.run(request).await);
+}
+
+/////////////////////////////////////////////
+///////////      Authentication    //////////
+/////////////////////////////////////////////
+#[derive(Deserialize)]
+pub struct LoginParams {
+    pub password: String,
+}
+/// login to the printer
+pub async fn login(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(login): Json<LoginParams>,
+) -> Json<PrinterResult<PrinterLogin>> {
+    Json(instance.login(&login.password).await)
+}
+/// logout from the printer
+pub async fn logout(
+    Extension(instance): Extension<Arc<Instance>>,
+    AuthBearer(bearer_token): AuthBearer,
+) -> Json<PrinterResult<()>> {
+    Json(instance.logout(&bearer_token).await)
+}
+#[derive(Deserialize)]
+pub struct ResetPasswordParams {
+    pub new_password: String,
+}
+/// reset password
+pub async fn reset_password(
+    Extension(instance): Extension<Arc<Instance>>,
+    AuthBearer(bearer_token): AuthBearer,
+    Json(reset): Json<ResetPasswordParams>,
+) -> Json<PrinterResult<()>> {
+    Json(
+        instance
+            .reset_password(&bearer_token, &reset.new_password)
+            .await,
+    )
+}
+#[derive(Deserialize)]
+pub struct RefreshTokenParams {
+    pub refresh_token: String,
+}
+/// refresh token
+pub async fn refresh_token(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(refresh): Json<RefreshTokenParams>,
+) -> Json<PrinterResult<PrinterLogin>> {
+    Json(instance.refresh_token(&refresh.refresh_token).await)
+}
+
+/////////////////////////////////////////////
+///////////         Status        ///////////
+/////////////////////////////////////////////
+
+/// get printer info
+pub async fn get_info(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterInfo>> {
+    Json(instance.get_info().await)
+}
+/// get printer temperatures
+pub async fn get_temperatures(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<Vec<PrinterTemperatureInfo>>> {
+    Json(instance.get_temperatures().await)
+}
 /// emergency stop
 pub async fn emergency_stop(
     Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.emergency_stop().await)
+) -> Json<PrinterResult<()>> {
+    Json(instance.emergency_stop().await)
+}
+/// restart gantry
+pub async fn restart(Extension(instance): Extension<Arc<Instance>>) -> Json<PrinterResult<()>> {
+    Json(instance.restart().await)
+}
+/// reload printer config and reset motion state
+pub async fn firmware_restart(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.firmware_restart().await)
+}
+/// acknowledge a recoverable error
+pub async fn clear_error(Extension(instance): Extension<Arc<Instance>>) -> Json<PrinterResult<()>> {
+    Json(instance.clear_error().await)
+}
+/// list objects loaded
+pub async fn list_objects(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<HashMap<String, String>>> {
+    Json(instance.list_objects().await)
+}
+#[derive(Deserialize)]
+pub struct ExcludeObjectParams {
+    pub name: String,
+}
+/// cancel an object of the running gcode
+pub async fn exclude_object(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<ExcludeObjectParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.exclude_object(&params.name).await)
+}
+/// query endstop status
+pub async fn query_endstops(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterEndstopStatus>> {
+    Json(instance.query_endstops().await)
+}
+/// check endstops, heaters and motion
+pub async fn run_diagnostics(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<Vec<PrinterDiagnostic>>> {
+    Json(instance.run_diagnostics().await)
+}
+#[derive(Deserialize)]
+pub struct StatusLongpollParams {
+    /// last status version seen by the client
+    #[serde(default)]
+    pub since: u64,
+    /// seconds to wait for a change
+    #[serde(default = "StatusLongpollParams::default_timeout")]
+    pub timeout: f64,
+}
+
+impl StatusLongpollParams {
+    fn default_timeout() -> f64 {
+        30.0
+    }
+}
+
+/// wait for the printer status to change
+pub async fn status_longpoll(
+    Extension(instance): Extension<Arc<Instance>>,
+    Query(params): Query<StatusLongpollParams>,
+) -> Json<PrinterResult<PrinterStatus>> {
+    let timeout = Duration::try_from_secs_f64(params.timeout).unwrap_or(MAX_LONGPOLL_TIMEOUT);
+
+    Json(PrinterResult::ok(
+        instance.status_longpoll(params.since, timeout).await,
+    ))
+}
+/// get toolhead position
+pub async fn get_position(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterPosition>> {
+    Json(instance.get_position().await)
+}
+/// get the motion limits in effect
+pub async fn get_motion_limits(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterMotionLimits>> {
+    Json(instance.get_motion_limits().await)
+}
+/// get the xyz bounds of the printer
+pub async fn get_build_volume(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterBuildVolume>> {
+    Json(instance.get_build_volume().await)
+}
+/// list filament switch sensors
+pub async fn list_filament_sensors(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<Vec<PrinterFilamentSensor>>> {
+    Json(instance.list_filament_sensors().await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetFilamentSensorParams {
+    pub name: String,
+    pub filament_detected: bool,
+}
+/// set the state of a filament switch sensor
+pub async fn set_filament_sensor(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<SetFilamentSensorParams>,
+) -> Json<PrinterResult<()>> {
+    Json(
+        instance
+            .set_filament_sensor(&params.name, params.filament_detected)
+            .await,
+    )
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JogParams {
+    /// distances in mm, zero leaves the axis in place
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub y: f32,
+    #[serde(default)]
+    pub z: f32,
+    /// mm/s
+    #[serde(default = "JogParams::default_speed")]
+    pub speed: f32,
+}
+impl JogParams {
+    fn default_speed() -> f32 {
+        25.0
+    }
+}
+/// move the toolhead by a distance
+pub async fn jog(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<JogParams>,
+) -> Json<PrinterResult<()>> {
+    Json(
+        instance
+            .jog(params.x, params.y, params.z, params.speed)
+            .await,
+    )
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HomeParams {
+    pub x: bool,
+    pub y: bool,
+    pub z: bool,
+}
+/// home the given axes, all axes if none is given
+pub async fn home(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<HomeParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.home(params.x, params.y, params.z).await)
+}
+/// internal state of the simulated mcu, not found unless debug routes are enabled
+pub async fn get_debug_mcu(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Result<Json<SimulatedMcuState>, StatusCode> {
+    if !DEBUG_ROUTES.load(Ordering::SeqCst) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    return Ok(Json(instance.debug_mcu_state().await));
+}
+#[derive(Deserialize)]
+pub struct DebugEndstopParams {
+    /// 0 is x, 1 is y, 2 is z
+    pub axis: usize,
+    pub triggered: bool,
+}
+/// set a simulated endstop switch, not found unless debug routes are enabled
+pub async fn debug_set_endstop(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<DebugEndstopParams>,
+) -> StatusCode {
+    if !DEBUG_ROUTES.load(Ordering::SeqCst) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    if params.axis > 2 {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    instance
+        .debug_set_endstop(params.axis, params.triggered)
+        .await;
+
+    return StatusCode::NO_CONTENT;
+}
+#[derive(Deserialize)]
+pub struct DebugFaultParams {
+    pub message: String,
+}
+/// inject a simulated mcu fault, not found unless debug routes are enabled
+pub async fn debug_mcu_fault(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<DebugFaultParams>,
+) -> StatusCode {
+    if !DEBUG_ROUTES.load(Ordering::SeqCst) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    instance.debug_mcu_fault(params.message).await;
+
+    return StatusCode::NO_CONTENT;
+}
+/// request a snapshot from an external camera service, returns immediately
+pub async fn snapshot(Extension(instance): Extension<Arc<Instance>>) -> Json<PrinterResult<()>> {
+    Json(instance.snapshot())
+}
+
+/////////////////////////////////////////////
+///////////       Extensions      ///////////
+/////////////////////////////////////////////
+
+/// list extensions loaded
+pub async fn list_extensions(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<HashMap<String, PrinterExtension>>> {
+    Json(instance.list_extensions().await)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstallExtensionParams {
+    pub repo: String,
+}
+/// install an extension
+pub async fn install_extension(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(install): Json<InstallExtensionParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.install_extension(install.repo).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveExtensionParams {
+    pub name: String,
+}
+/// remove an extension
+pub async fn remove_extension(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(remove): Json<RemoveExtensionParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.remove_extension(remove.name).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadExtensionConfigParams {
+    pub name: String,
+}
+/// download extension config
+pub async fn download_extension_config(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(download): Json<DownloadExtensionConfigParams>,
+) -> Json<PrinterResult<String>> {
+    Json(instance.download_extension_config(&download.name).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadExtensionConfigParams {
+    pub name: String,
+    pub config: String,
+}
+/// upload extension config
+pub async fn upload_extension_config(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(upload): Json<UploadExtensionConfigParams>,
+) -> Json<PrinterResult<()>> {
+    Json(
+        instance
+            .upload_extension_config(&upload.name, upload.config)
+            .await,
+    )
+}
+
+/////////////////////////////////////////////
+///////////       Gcode API       ///////////
+/////////////////////////////////////////////
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunGcodeParams {
+    pub script: String,
+}
+
+/// execute a gcode script
+pub async fn run_gcode(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<RunGcodeParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.run_gcode(params.script).await)
+}
+/// run gcode lines from the request body as they arrive, responses are streamed back
+pub async fn stream_gcode(
+    Extension(instance): Extension<Arc<Instance>>,
+    body: axum::body::Body,
+) -> Response {
+    use futures::StreamExt;
+
+    let (line_sender, lines) = tokio::sync::mpsc::channel::<String>(64);
+    let mut responses = instance.stream_gcode(lines);
+
+    // split the body into lines, a line may span several chunks
+    tokio::spawn(async move {
+        let mut body = body.into_data_stream();
+        let mut buffer = Vec::new();
+
+        while let Some(Ok(chunk)) = body.next().await {
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(i) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=i).collect();
+
+                if line_sender
+                    .send(String::from_utf8_lossy(&line).into_owned())
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+
+        // last line without newline
+        if !buffer.is_empty() {
+            let _ = line_sender
+                .send(String::from_utf8_lossy(&buffer).into_owned())
+                .await;
+        }
+    });
+
+    let stream = async_stream::stream! {
+        while let Some(response) = responses.recv().await {
+            yield Ok::<_, std::convert::Infallible>(format!("{}\n", response));
+        }
+    };
+
+    return Response::builder()
+        .header(axum::http::header::CONTENT_TYPE, "text/plain")
+        .body(axum::body::Body::from_stream(stream))
+        .unwrap();
+}
+/// Retrieves a list of registered GCode Command Descriptions.
+pub async fn get_gcode_help(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<HashMap<String, String>>> {
+    Json(instance.get_gcode_help().await)
+}
+/// Retrieves execution timing statistics of gcode commands.
+pub async fn get_gcode_timings(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<HashMap<String, PrinterGcodeTiming>>> {
+    Json(instance.get_gcode_timings().await)
+}
+
+/////////////////////////////////////////////
+///////////       Print job       ///////////
+/////////////////////////////////////////////
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartPrintJobParams {
+    pub filename: String,
+    pub exclude_objects: Vec<String>,
+}
+/// optional 'Idempotency-Key' header
+fn idempotency_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get("idempotency-key")?.to_str().ok()
+}
+/// start a print job
+pub async fn start_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+    headers: HeaderMap,
+    Json(params): Json<StartPrintJobParams>,
+) -> Json<PrinterResult<StartPrintJobResult>> {
+    Json(
+        instance
+            .start_print_job(
+                &params.filename,
+                params.exclude_objects,
+                idempotency_key(&headers),
+            )
+            .await,
+    )
+}
+/// pause the print job
+pub async fn pause_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.pause_print_job().await)
+}
+/// resume the print job
+pub async fn resume_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.resume_print_job().await)
+}
+/// continue a print interrupted by power loss
+pub async fn resume_interrupted(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<StartPrintJobResult>> {
+    Json(instance.resume_interrupted().await)
+}
+/// cancel the print job
+pub async fn cancel_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.cancel_print_job().await)
+}
+/// get print job status
+pub async fn get_print_job_status(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrintJobStatus>> {
+    Json(instance.get_print_job_status().await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuePrintJobParams {
+    pub filename: String,
+    /// who queued the job
+    #[serde(default)]
+    pub operator: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+/// queue print job to run after current print job is finished
+pub async fn queue_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+    headers: HeaderMap,
+    Json(params): Json<QueuePrintJobParams>,
+) -> Json<PrinterResult<PrinterQueuePrintJob>> {
+    let metadata = JobMetadata {
+        operator: params.operator,
+        notes: params.notes,
+    };
+
+    Json(
+        instance
+            .queue_print_job(&params.filename, metadata, idempotency_key(&headers))
+            .await,
+    )
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteQueuePrintJobParams {
+    pub id: String,
+}
+//// delete a print job in queue
+pub async fn delete_queue_print_job(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<DeleteQueuePrintJobParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.delete_queue_print_job(&params.id).await)
+}
+
+/// pause the job queue, next job will not start when current job is finished
+pub async fn pause_job_queue(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.pause_job_queue().await)
+}
+
+/// resume the job queue
+pub async fn resume_job_queue(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.resume_job_queue().await)
+}
+
+/// get a list of jobs in job queue
+pub async fn list_job_queue(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<Vec<JobQueuePrintJob>>> {
+    Json(instance.list_job_queue().await)
+}
+
+/// get a list of finished jobs, oldest first
+pub async fn list_history(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<Vec<JobHistoryEntry>>> {
+    Json(instance.list_history().await)
+}
+
+/////////////////////////////////////////////
+///////////      Gcode files      ///////////
+/////////////////////////////////////////////
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSortKey {
+    #[default]
+    Name,
+    Modified,
+    Size,
+}
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListFilesParams {
+    #[serde(default)]
+    pub sort_by: FileSortKey,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// every file from offset if not given
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+/// list avaliable gcode files
+pub async fn list_files(
+    Extension(instance): Extension<Arc<Instance>>,
+    Query(params): Query<ListFilesParams>,
+) -> Json<PrinterResult<Vec<PrinterGcodeFile>>> {
+    Json(
+        instance
+            .list_files_sorted(params.sort_by, params.order, params.offset, params.limit)
+            .await,
+    )
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagFileParams {
+    pub filename: String,
+    pub tags: Vec<String>,
+}
+/// replace the tags of a gcode file
+pub async fn tag_file(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<TagFileParams>,
+) -> Json<PrinterResult<Vec<String>>> {
+    Json(instance.tag_file(&params.filename, &params.tags).await)
 }
-/// restart gantry
-pub async fn restart(Extension(instance): Extension<Arc<Instance>>) -> Json<PrinterResult<()>> {
-    Json(instance.restart().await)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchFilesParams {
+    #[serde(default)]
+    pub tag: String,
+    /// substring of the file path
+    #[serde(default)]
+    pub query: String,
 }
-/// list objects loaded
-pub async fn list_objects(
+/// list gcode files by tag and path
+pub async fn search_files(
     Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<HashMap<String, String>>> {
-    Json(instance.list_objects().await)
+    Query(params): Query<SearchFilesParams>,
+) -> Json<PrinterResult<Vec<PrinterGcodeFile>>> {
+    Json(instance.search_files(&params.tag, &params.query).await)
 }
-/// query endstop status
-pub async fn query_endstops(
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetFileMetaParams {
+    pub filename: String,
+}
+/// get metadata for a specified gcode file
+pub async fn get_file_metadata(
     Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<PrinterEndstopStatus>> {
-    Json(instance.query_endstops().await)
+    Json(params): Json<GetFileMetaParams>,
+) -> Json<PrinterResult<PrinterGcodeFileMetadata>> {
+    Json(instance.get_file_metadata(&params.filename).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScanFileParams {
+    pub filename: String,
+}
+/// Initiate a metadata scan for a selected file. If the file has already been scanned the endpoint will force a re-scan.
+pub async fn scan_file_metadata(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<ScanFileParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.scan_file_metadata(&params.filename).await)
+}
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailPath {
+    /// relative to the thumbnails directory
+    pub path: String,
 }
+/// serve a stored thumbnail, 'relative_path' of the file metadata without the 'thumbnails/' prefix
+pub async fn get_thumbnail(
+    Extension(instance): Extension<Arc<Instance>>,
+    Path(params): Path<ThumbnailPath>,
+) -> Response {
+    let re = instance.get_thumbnail(&params.path).await;
 
-/////////////////////////////////////////////
-///////////       Extensions      ///////////
-/////////////////////////////////////////////
+    let Some(data) = re.result else {
+        let status = match re.error.code {
+            PrinterErrorCode::FileNotFound => StatusCode::NOT_FOUND,
+            PrinterErrorCode::InvalidParameter | PrinterErrorCode::PathTraversal => {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
 
-/// list extensions loaded
-pub async fn list_extensions(
+        return (status, Json(PrinterResult::<()>::err(re.error))).into_response();
+    };
+
+    let content_type = match std::path::Path::new(&params.path)
+        .extension()
+        .and_then(|e| e.to_str())
+    {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("qoi") => "image/qoi",
+        _ => "application/octet-stream",
+    };
+
+    return ([(axum::http::header::CONTENT_TYPE, content_type)], data).into_response();
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LintGcodeFileParams {
+    pub filename: String,
+}
+/// check a gcode file for common slicer mistakes
+pub async fn lint_gcode_file(
     Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<HashMap<String, PrinterExtension>>> {
-    Json(instance.list_extensions().await)
+    Json(params): Json<LintGcodeFileParams>,
+) -> Json<PrinterResult<Vec<PrinterGcodeLintWarning>>> {
+    Json(instance.lint_gcode_file(&params.filename).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListFileObjectsParams {
+    pub filename: String,
+}
+/// list the excludable objects defined in a gcode file
+pub async fn list_file_objects(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<ListFileObjectsParams>,
+) -> Json<PrinterResult<Vec<PrinterGcodeObject>>> {
+    Json(instance.list_file_objects(&params.filename).await)
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MovePlanFormat {
+    #[default]
+    Json,
+    Csv,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MovePlanParams {
+    pub filename: String,
+    #[serde(default)]
+    pub format: MovePlanFormat,
+}
+/// planned moves of a gcode file as json, or as csv for plotting
+pub async fn get_move_plan(
+    Extension(instance): Extension<Arc<Instance>>,
+    Query(params): Query<MovePlanParams>,
+) -> Response {
+    let re = instance.get_move_plan(&params.filename).await;
+
+    // errors are returned as json in either format
+    if let (MovePlanFormat::Csv, Some(moves)) = (&params.format, &re.result) {
+        return (
+            [(axum::http::header::CONTENT_TYPE, "text/csv")],
+            move_plan_csv(moves),
+        )
+            .into_response();
+    }
+
+    return Json(re).into_response();
 }
+/// one row per move, positions are split into columns
+fn move_plan_csv(moves: &[PrinterPlannedMove]) -> String {
+    use std::fmt::Write;
 
+    let mut csv = String::from(
+        "start_x,start_y,start_z,start_e,end_x,end_y,end_z,end_e,\
+         distance,start_velocity,cruise_velocity,end_velocity,acceleration,duration\n",
+    );
+
+    for m in moves {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            m.start.x,
+            m.start.y,
+            m.start.z,
+            m.start.e,
+            m.end.x,
+            m.end.y,
+            m.end.z,
+            m.end.e,
+            m.distance,
+            m.start_velocity,
+            m.cruise_velocity,
+            m.end_velocity,
+            m.acceleration,
+            m.duration
+        );
+    }
+
+    return csv;
+}
 #[derive(Debug, Serialize, Deserialize)]
-pub struct InstallExtensionParams {
-    pub repo: String,
+pub struct FilePreviewParams {
+    pub filename: String,
+    /// number of lines from the start
+    #[serde(default = "FilePreviewParams::default_lines")]
+    pub head: usize,
+    /// number of lines from the end
+    #[serde(default = "FilePreviewParams::default_lines")]
+    pub tail: usize,
 }
-/// install an extension
-pub async fn install_extension(
+impl FilePreviewParams {
+    fn default_lines() -> usize {
+        10
+    }
+}
+/// first and last lines of a gcode file
+pub async fn get_file_preview(
     Extension(instance): Extension<Arc<Instance>>,
-    Json(install): Json<InstallExtensionParams>,
+    Query(params): Query<FilePreviewParams>,
+) -> Json<PrinterResult<PrinterGcodeFilePreview>> {
+    Json(
+        instance
+            .get_file_preview(&params.filename, params.head, params.tail)
+            .await,
+    )
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadFileParams {
+    pub filename: String,
+    /// base64 encoded file
+    pub data: String,
+}
+/// upload a gcode file
+pub async fn upload_file(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<UploadFileParams>,
 ) -> Json<PrinterResult<()>> {
-    Json(instance.install_extension(install.repo).await)
+    Json(instance.upload_file(&params.filename, params.data).await)
 }
 #[derive(Debug, Serialize, Deserialize)]
-pub struct RemoveExtensionParams {
-    pub name: String,
+pub struct PrewarmFilesParams {
+    pub filenames: Vec<String>,
 }
-/// remove an extension
-pub async fn remove_extension(
+/// parse and cache gcode files in the background
+pub async fn prewarm_files(
     Extension(instance): Extension<Arc<Instance>>,
-    Json(remove): Json<RemoveExtensionParams>,
+    Json(params): Json<PrewarmFilesParams>,
 ) -> Json<PrinterResult<()>> {
-    Json(instance.remove_extension(remove.name).await)
+    Json(instance.prewarm_files(&params.filenames).await)
 }
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DownloadExtensionConfigParams {
-    pub name: String,
+pub struct DeleteFilesParams {
+    pub filenames: Vec<String>,
 }
-/// download extension config
-pub async fn download_extension_config(
+/// delete gcode files, returns the result of each file
+pub async fn delete_files(
     Extension(instance): Extension<Arc<Instance>>,
-    Json(download): Json<DownloadExtensionConfigParams>,
+    Json(params): Json<DeleteFilesParams>,
+) -> Json<PrinterResult<Vec<PrinterDeleteFileResult>>> {
+    Json(instance.delete_files(&params.filenames).await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadFileParams {
+    pub filename: String,
+}
+/// download a gcode file
+pub async fn download_file(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<DownloadFileParams>,
 ) -> Json<PrinterResult<String>> {
-    Json(instance.download_extension_config(&download.name).await)
+    Json(instance.download_file(&params.filename).await)
+}
+/// get the printer config parsed into sections and typed values
+pub async fn get_parsed_config(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<PrinterConfig>> {
+    Json(instance.get_parsed_config().await)
 }
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UploadExtensionConfigParams {
-    pub name: String,
-    pub config: String,
+pub struct PatchConfigParams {
+    /// section name as written in brackets, e.g. 'printer' or 'stepper x'
+    pub section: String,
+    pub key: String,
+    /// new value, the key is removed if none
+    #[serde(default)]
+    pub value: Option<String>,
+    /// restart the printer after the config is written
+    #[serde(default)]
+    pub restart: bool,
 }
-/// upload extension config
-pub async fn upload_extension_config(
+/// change a single key of the printer config
+pub async fn patch_config(
     Extension(instance): Extension<Arc<Instance>>,
-    Json(upload): Json<UploadExtensionConfigParams>,
+    Json(params): Json<PatchConfigParams>,
 ) -> Json<PrinterResult<()>> {
     Json(
         instance
-            .upload_extension_config(&upload.name, upload.config)
+            .patch_config(
+                &params.section,
+                &params.key,
+                params.value.as_deref(),
+                params.restart,
+            )
+            .await,
+    )
+}
+/// download the printer config
+pub async fn download_printer_config(
+    Extension(instance): Extension<Arc<Instance>>,
+) -> Json<PrinterResult<String>> {
+    Json(instance.download_printer_config().await)
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadPrinterConfigParams {
+    pub config: String,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadInitParams {
+    pub filename: String,
+    /// size in bytes of the complete file
+    pub size: u64,
+    /// hex sha256 of the complete file, checked when finalized if set
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+/// start a chunked upload, returns the upload id
+pub async fn upload_init(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<UploadInitParams>,
+) -> Json<PrinterResult<String>> {
+    Json(
+        instance
+            .upload_init(&params.filename, params.size, params.sha256)
+            .await,
+    )
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadChunkParams {
+    pub upload_id: String,
+    /// position of the chunk in the file
+    pub offset: u64,
+}
+/// write the request body as a chunk of an upload
+pub async fn upload_chunk(
+    Extension(instance): Extension<Arc<Instance>>,
+    Query(params): Query<UploadChunkParams>,
+    data: axum::body::Bytes,
+) -> Json<PrinterResult<u64>> {
+    Json(
+        instance
+            .upload_chunk(&params.upload_id, params.offset, &data)
             .await,
     )
 }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadFinalizeParams {
+    pub upload_id: String,
+}
+/// assemble a chunked upload once all chunks are received
+pub async fn upload_finalize(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<UploadFinalizeParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.upload_finalize(&params.upload_id).await)
+}
+/// upload the printer config
+pub async fn upload_printer_config(
+    Extension(instance): Extension<Arc<Instance>>,
+    Json(params): Json<UploadPrinterConfigParams>,
+) -> Json<PrinterResult<()>> {
+    Json(instance.upload_printer_config(params.config).await)
+}
+
+#[tokio::test]
+async fn test_data_path() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    let data_path = std::env::temp_dir().join(format!("gantry-data-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let inst = Instance::create(
+        0,
+        "printer".to_string(),
+        InstanceConfig {
+            uuid: Uuid::new_v4().as_u128(),
+            data_path: Some(data_path.to_string_lossy().to_string()),
+            ..Default::default()
+        },
+        gantry_path.clone(),
+    )
+    .await;
+
+    // gcodes tree is created in the data directory
+    assert_eq!(inst.gcodes_path(), &data_path.join("gcodes"));
+    assert!(data_path.join("gcodes").join("thumbnails").is_dir());
+    assert!(!gantry_path.join("printer").join("gcodes").exists());
+
+    // uploads are written to the data directory
+    let re = inst
+        .upload_file("parts/cube.gcode", BASE64_STANDARD.encode("G28\n"))
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let uploaded = data_path.join("gcodes").join("parts").join("cube.gcode");
+    assert_eq!(std::fs::read_to_string(uploaded).unwrap(), "G28\n");
+    assert!(!gantry_path.join("printer").join("gcodes").exists());
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+    let _ = std::fs::remove_dir_all(data_path);
+}
+
+#[tokio::test]
+async fn test_config_hot_reload() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let inst = Arc::new(
+        Instance::create(
+            0,
+            "printer".to_string(),
+            InstanceConfig {
+                uuid: Uuid::new_v4().as_u128(),
+                hot_reload: true,
+                ..Default::default()
+            },
+            gantry_path.clone(),
+        )
+        .await,
+    );
+
+    // wait for initial startup
+    for _ in 0..50 {
+        if matches!(inst.state().await, super::printer::State::Ready) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    inst.enable_config_hot_reload().await;
+    // give the watcher time to register
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // externally write a broken config
+    crate::files::write_atomic(&inst.path().join("printer.cfg"), b"[printer\n")
+        .await
+        .unwrap();
+
+    let mut reloaded = false;
+
+    for _ in 0..100 {
+        if matches!(inst.state().await, super::printer::State::Error { .. }) {
+            reloaded = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert!(reloaded);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_broken_instance_isolated() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("broken_config")).unwrap();
+    std::fs::write(
+        gantry_path.join("broken_config").join("printer.cfg"),
+        "[printer\n",
+    )
+    .unwrap();
+    // printer directory cannot be created
+    std::fs::write(gantry_path.join("broken_dir"), "").unwrap();
+
+    let mut instances = Vec::new();
+
+    for (i, name) in ["broken_config", "broken_dir", "working"]
+        .iter()
+        .enumerate()
+    {
+        let config = InstanceConfig {
+            uuid: Uuid::new_v4().as_u128(),
+            ..Default::default()
+        };
+
+        instances.push(Instance::create(i, name.to_string(), config, gantry_path.clone()).await);
+    }
+
+    // wait for restarts to finish
+    for _ in 0..50 {
+        if matches!(instances[2].state().await, super::printer::State::Ready) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    assert!(matches!(
+        instances[0].state().await,
+        super::printer::State::Error { .. }
+    ));
+    assert!(matches!(
+        instances[1].state().await,
+        super::printer::State::Error { .. }
+    ));
+    assert!(matches!(
+        instances[2].state().await,
+        super::printer::State::Ready
+    ));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_path_prefix_routing() {
+    use tower::ServiceExt;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    for name in ["myprinter", "otherprinter"] {
+        let config = InstanceConfig {
+            uuid: Uuid::new_v4().as_u128(),
+            ..Default::default()
+        };
+        let inst = Instance::create(0, name.to_string(), config, gantry_path.clone()).await;
+
+        crate::INSTANCES
+            .write()
+            .await
+            .insert(name.to_string(), Arc::new(inst));
+    }
+
+    // responds with the name of the resolved instance
+    let router = nest_instance_routes(
+        axum::Router::new()
+            .route(
+                "/info",
+                get(|Extension(instance): Extension<Arc<Instance>>| async move {
+                    instance.name.clone()
+                }),
+            )
+            .layer(axum::middleware::from_fn(instance_extracter)),
+    );
+
+    for uri in ["/myprinter/info", "/info?name=myprinter"] {
+        let response = router
+            .clone()
+            .oneshot(
+                axum::http::Request::get(uri)
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"myprinter");
+    }
+
+    // unknown printer
+    let response = router
+        .oneshot(
+            axum::http::Request::get("/unknown/info")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let mut instances = crate::INSTANCES.write().await;
+    instances.remove("myprinter");
+    instances.remove("otherprinter");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_idempotency_key() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    std::fs::write(inst.gcodes_path().join("cube.gcode"), "G28\nG1 X10 Y10\n").unwrap();
+
+    let start = async |key| {
+        inst.start_print_job("cube.gcode", Vec::new(), key)
+            .await
+            .result
+            .unwrap()
+            .job_id
+    };
+
+    // retried request returns the original job
+    let first = start(Some("retry-1")).await;
+    let retry = start(Some("retry-1")).await;
+    assert_eq!(first, retry);
+
+    // a new key creates a new job
+    let other = start(Some("retry-2")).await;
+    assert_ne!(first, other);
+
+    // requests without a key are never deduplicated
+    assert_ne!(start(None).await, start(None).await);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_status_longpoll() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+
+    // wait for startup to settle
+    while let super::printer::State::Startup = inst.state().await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let since = inst.get_status().await.version;
+
+    // no change, returns with the same version after the timeout
+    let status = inst.status_longpoll(since, Duration::from_millis(50)).await;
+    assert_eq!(status.version, since);
+
+    let poll = tokio::spawn({
+        let inst = inst.clone();
+        async move { inst.status_longpoll(since, Duration::from_secs(30)).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!poll.is_finished());
+
+    let start = Instant::now();
+    inst.emergency_stop().await;
+
+    let status = tokio::time::timeout(Duration::from_secs(5), poll)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(status.version > since);
+    assert!(matches!(status.state, PrinterState::Shutdown));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_status_longpoll_temperature() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(inst.get_status().await.extruder_temperature, 25.0);
+
+    let re = inst.run_gcode("M104 S200".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let since = inst.get_status().await;
+
+    // the heating extruder changes the status
+    let status = inst
+        .status_longpoll(since.version, Duration::from_secs(5))
+        .await;
+    assert!(status.version > since.version);
+    assert!(status.extruder_temperature > since.extruder_temperature);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
+
+#[tokio::test]
+async fn test_stream_gcode() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+
+    while let super::printer::State::Startup = inst.state().await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let response = stream_gcode(
+        Extension(inst.clone()),
+        axum::body::Body::from("G1 X10 F3000\n; comment\nM302\nG1 Y10\nG999\nG1 X0\n"),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+
+    assert_eq!(
+        lines[..3],
+        ["ok", "ok", "Cold extrudes are disallowed (min temp 170)"]
+    );
+    assert_eq!(lines[3..5], ["ok", "ok"]);
+    // execution stops at the unknown command
+    assert!(lines[5].starts_with("!! Unknown command"));
+    assert_eq!(lines.len(), 6);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
 
-/////////////////////////////////////////////
-///////////       Gcode API       ///////////
-/////////////////////////////////////////////
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RunGcodeParams {
-    pub script: String,
+#[tokio::test]
+async fn test_run_gcode_response() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // instance restarts the printer after creation
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let re = inst.run_gcode_response("M302 P1\nM114\n".to_string()).await;
+
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let result = re.result.unwrap();
+    let lines: Vec<&str> = result.lines().collect();
+
+    // responses of both commands, unhomed axes are not reported
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("Cold extrudes are allowed"));
+    assert_eq!(lines[1], "E:0.000");
+
+    let re = inst.run_gcode_response("G28 X\nM114\n".to_string()).await;
+    assert_eq!(re.result.unwrap(), "X:0.000 E:0.000");
+
+    let re = inst.run_gcode_response("G999\n".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::GcodeError));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
 
-/// execute a gcode script
-pub async fn run_gcode(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<RunGcodeParams>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.run_gcode(params.script).await)
+#[tokio::test]
+async fn test_password_persists() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    // the same printer is created twice
+    let uuid = Uuid::new_v4().as_u128();
+    let config = || InstanceConfig {
+        uuid,
+        ..Default::default()
+    };
+
+    let inst = Instance::create(0, "printer".to_string(), config(), gantry_path.clone()).await;
+
+    // credentials file is created with the default password
+    assert!(gantry_path.join("printer").join("auth.json").is_file());
+    assert!(gantry_path.join("printer").join("extensions").is_dir());
+
+    let login = inst
+        .login(crate::global_auth::DEFAULT_PASSWORD)
+        .await
+        .result
+        .unwrap();
+    let re = inst.reset_password(&login.token, "new password").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    drop(inst);
+
+    // the reset password is loaded from disk
+    let inst = Instance::create(0, "printer".to_string(), config(), gantry_path.clone()).await;
+
+    let re = inst.login(crate::global_auth::DEFAULT_PASSWORD).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::AuthFailed));
+
+    let re = inst.login("new password").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// Retrieves a list of registered GCode Command Descriptions.
-pub async fn get_gcode_help(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<HashMap<String, String>>> {
-    Json(instance.get_gcode_help().await)
+
+#[tokio::test]
+async fn test_upload_does_not_block_status() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let data = "G1 X10 Y10\n".repeat(4 * 1024 * 1024);
+    let len = data.len() as u64;
+    let data = BASE64_STANDARD.encode(data);
+
+    let upload = tokio::spawn({
+        let inst = inst.clone();
+        async move { inst.upload_file("large.gcode", data).await }
+    });
+
+    // let the upload start writing
+    tokio::task::yield_now().await;
+
+    let start = std::time::Instant::now();
+    inst.get_status().await;
+    assert!(start.elapsed() < Duration::from_millis(200));
+
+    let re = upload.await.unwrap();
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let path = inst.gcodes_path().join("large.gcode");
+    assert_eq!(std::fs::metadata(path).unwrap().len(), len);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
 
-/////////////////////////////////////////////
-///////////       Print job       ///////////
-/////////////////////////////////////////////
-#[derive(Debug, Serialize, Deserialize)]
-pub struct StartPrintJobParams {
-    pub filename: String,
-    pub exclude_objects: Vec<String>,
+#[tokio::test]
+async fn test_chunked_upload() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let data = "G1 X10 Y10\nG1 X20 Y20\nG1 X30 Y30\n".repeat(100);
+    let sha256: String = Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let id = inst
+        .upload_init("parts/cube.gcode", data.len() as u64, Some(sha256))
+        .await
+        .result
+        .unwrap();
+
+    let chunks = [0, 1000, 2000, data.len()];
+
+    // a chunk after a gap is refused
+    let re = inst
+        .upload_chunk(&id, 1000, &data.as_bytes()[1000..2000])
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::UploadInvalid));
+
+    for range in chunks.windows(2) {
+        let re = inst
+            .upload_chunk(&id, range[0] as u64, &data.as_bytes()[range[0]..range[1]])
+            .await;
+        assert_eq!(re.result, Some(range[1] as u64));
+
+        // incomplete uploads are not stored
+        if range[1] < data.len() {
+            let re = inst.upload_finalize(&id).await;
+            assert!(matches!(re.error.code, PrinterErrorCode::UploadInvalid));
+        }
+    }
+
+    // a resent chunk does not change the file
+    let re = inst.upload_chunk(&id, 0, &data.as_bytes()[..1000]).await;
+    assert_eq!(re.result, Some(data.len() as u64));
+
+    let re = inst.upload_finalize(&id).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let path = inst.gcodes_path().join("parts").join("cube.gcode");
+    assert_eq!(std::fs::read_to_string(path).unwrap(), data);
+
+    // the upload is removed once finalized
+    let re = inst.upload_finalize(&id).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::UploadNotFound));
+
+    let files = inst.list_files().await.result.unwrap();
+    assert_eq!(files.len(), 1);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// start a print job
-pub async fn start_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<StartPrintJobParams>,
-) -> Json<PrinterResult<StartPrintJobResult>> {
-    Json(
-        instance
-            .start_print_job(&params.filename, params.exclude_objects)
-            .await,
+
+#[tokio::test]
+async fn test_motion_limits() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\nmax_accel: 3000\n",
     )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let limits = get_motion_limits(Extension(inst.clone()))
+        .await
+        .0
+        .result
+        .unwrap();
+    assert_eq!((limits.max_velocity, limits.max_accel), (300.0, 3000.0));
+
+    let re = inst
+        .run_gcode_response(
+            "SET_VELOCITY_LIMIT VELOCITY=150 ACCEL=1500 SQUARE_CORNER_VELOCITY=8 MINIMUM_CRUISE_RATIO=0.25\n"
+                .to_string(),
+        )
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let limits = get_motion_limits(Extension(inst.clone()))
+        .await
+        .0
+        .result
+        .unwrap();
+    assert_eq!(limits.max_velocity, 150.0);
+    assert_eq!(limits.max_accel, 1500.0);
+    assert_eq!(limits.square_corner_velocity, 8.0);
+    assert_eq!(limits.minimum_cruise_ratio, 0.25);
+
+    // an invalid value changes nothing
+    let re = inst
+        .run_gcode_response("SET_VELOCITY_LIMIT VELOCITY=100 ACCEL=0\n".to_string())
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::GcodeError));
+
+    let limits = get_motion_limits(Extension(inst)).await.0.result.unwrap();
+    assert_eq!(limits.max_velocity, 150.0);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// pause the print job
-pub async fn pause_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.pause_print_job().await)
+
+#[tokio::test]
+async fn test_display_metadata() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config: InstanceConfig = toml::from_str(&format!(
+        "uuid = \"{}\"\ndisplay_name = \"Workshop Voron\"\nlocation = \"lab 2\"\n",
+        Uuid::new_v4()
+    ))
+    .unwrap();
+    let inst = Instance::create(0, "voron".to_string(), config, gantry_path.clone()).await;
+
+    let info = inst.get_info().await.result.unwrap();
+
+    assert_eq!(info.display_name, "Workshop Voron");
+    assert_eq!(info.location, "lab 2");
+    // not configured
+    assert_eq!(info.model, "");
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(1, "other".to_string(), config, gantry_path.clone()).await;
+
+    // falls back to the instance name
+    assert_eq!(inst.get_info().await.result.unwrap().display_name, "other");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// resume the print job
-pub async fn resume_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.resume_print_job().await)
+
+#[tokio::test]
+async fn test_upload_download_file() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // files are not required to be utf8
+    let data = b"G28\n; \xff\xfe\nG1 X10\n".to_vec();
+
+    let re = inst
+        .upload_file("parts/cube.gcode", BASE64_STANDARD.encode(&data))
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let path = inst.gcodes_path().join("parts").join("cube.gcode");
+    assert_eq!(std::fs::read(path).unwrap(), data);
+
+    let re = inst.download_file("parts/cube.gcode").await;
+    assert_eq!(BASE64_STANDARD.decode(re.result.unwrap()).unwrap(), data);
+
+    let re = inst.download_file("missing.gcode").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let re = inst
+        .upload_file("cube.gcode", "not base64!".to_string())
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::InvalidParameter));
+
+    // paths leaving the gcodes directory are refused
+    for filename in [
+        "../escape.gcode",
+        "parts/../../escape.gcode",
+        "/tmp/escape.gcode",
+    ] {
+        let re = inst
+            .upload_file(filename, BASE64_STANDARD.encode("G28\n"))
+            .await;
+        assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+        assert!(re.error.message.contains(".."));
+
+        let re = inst.download_file(filename).await;
+        assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+    }
+    assert!(!inst.path().join("escape.gcode").exists());
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// cancel the print job
-pub async fn cancel_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.cancel_print_job().await)
+
+#[tokio::test]
+async fn test_file_preview() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // the body spans several blocks read from the end
+    let gcode = format!(
+        "; generated by slicer\r\nG28\nG1 Z5\n{}M104 S0\nM140 S0\nM84\n",
+        "G1 X10 Y10 E1\n".repeat(2000)
+    );
+    std::fs::write(inst.gcodes_path().join("cube.gcode"), gcode).unwrap();
+
+    let preview = inst
+        .get_file_preview("cube.gcode", 2, 3)
+        .await
+        .result
+        .unwrap();
+    assert_eq!(preview.head, vec!["; generated by slicer", "G28"]);
+    assert_eq!(preview.tail, vec!["M104 S0", "M140 S0", "M84"]);
+
+    // a small file is returned whole from either end
+    std::fs::write(inst.gcodes_path().join("line.gcode"), "G28\nG1 X1").unwrap();
+
+    let preview = inst
+        .get_file_preview("line.gcode", 5, 5)
+        .await
+        .result
+        .unwrap();
+    assert_eq!(preview.head, vec!["G28", "G1 X1"]);
+    assert_eq!(preview.tail, vec!["G28", "G1 X1"]);
+
+    let re = inst.get_file_preview("missing.gcode", 5, 5).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let re = inst.get_file_preview("../printer.cfg", 5, 5).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// get print job status
-pub async fn get_print_job_status(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<PrintJobStatus>> {
-    Json(instance.get_print_job_status().await)
+
+#[tokio::test]
+async fn test_file_metadata() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let data = include_str!("../../tests/OrcaBenchy.gcode");
+    let filename = "3dbenchy_ABS_45m43s.gcode";
+    std::fs::write(inst.gcodes_path().join(filename), data).unwrap();
+
+    let meta = inst.get_file_metadata(filename).await.result.unwrap();
+
+    assert_eq!(meta.filename, filename);
+    assert_eq!(meta.size, data.len() as u64);
+    assert!(meta.modified > 0);
+    assert_eq!(meta.slicer, "OrcaSlicer");
+    assert_eq!(meta.slicer_version, "2.2.0");
+    assert_eq!(meta.estimated_time, 2743.0);
+    assert!(meta.filament_total > 0.0);
+    assert_eq!(meta.filament_type, "ABS");
+    assert_eq!(meta.nozzle_diameter, 0.4);
+    assert_eq!(meta.layer_height, 0.2);
+    assert_eq!(meta.first_layer_height, 0.2);
+    assert_eq!(meta.first_layer_bed_temp, 105.0);
+    assert!(!meta.thumbnails.is_empty());
+
+    // the uuid only depends on the path
+    let again = inst.get_file_metadata(filename).await.result.unwrap();
+    assert_eq!(meta.uuid, again.uuid);
+    assert!(Uuid::parse_str(&meta.uuid).is_ok());
+
+    let re = inst.get_file_metadata("missing.gcode").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let re = inst.get_file_metadata("../printer.cfg").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct QueuePrintJobParams {
-    pub filename: String,
+
+#[tokio::test]
+async fn test_file_tags() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    for filename in ["benchy.gcode", "parts/Bracket.gcode", "parts/hinge.gcode"] {
+        let re = inst
+            .upload_file(filename, BASE64_STANDARD.encode("G28\n"))
+            .await;
+        assert!(matches!(re.error.code, PrinterErrorCode::None));
+    }
+
+    let re = inst
+        .tag_file(
+            "benchy.gcode",
+            &["PETG ".to_string(), "calibration".to_string()],
+        )
+        .await;
+    assert_eq!(
+        re.result.unwrap(),
+        vec!["calibration".to_string(), "petg".to_string()]
+    );
+
+    let re = inst
+        .tag_file("parts/Bracket.gcode", &["petg".to_string()])
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    // missing files cannot be tagged
+    let re = inst.tag_file("missing.gcode", &["petg".to_string()]).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let paths = |files: Vec<PrinterGcodeFile>| -> Vec<String> {
+        files.into_iter().map(|f| f.path).collect()
+    };
+
+    let files = inst.search_files("Petg", "").await.result.unwrap();
+    assert_eq!(paths(files), vec!["benchy.gcode", "parts/Bracket.gcode"]);
+
+    let files = inst.search_files("petg", "bracket").await.result.unwrap();
+    assert_eq!(paths(files), vec!["parts/Bracket.gcode"]);
+
+    // untagged files are matched by path alone
+    let files = inst.search_files("", "parts/").await.result.unwrap();
+    assert_eq!(
+        paths(files),
+        vec!["parts/Bracket.gcode", "parts/hinge.gcode"]
+    );
+
+    // tags are reloaded with the instance
+    drop(inst);
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let files = inst.search_files("calibration", "").await.result.unwrap();
+    assert_eq!(paths(files), vec!["benchy.gcode"]);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// queue print job to run after current print job is finished
-pub async fn queue_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<QueuePrintJobParams>,
-) -> Json<PrinterResult<PrinterQueuePrintJob>> {
-    Json(instance.queue_print_job(&params.filename).await)
+
+#[tokio::test]
+async fn test_list_files() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let gcodes = inst.gcodes_path();
+    std::fs::create_dir_all(gcodes.join("build")).unwrap();
+    std::fs::create_dir_all(gcodes.join("thumbnails")).unwrap();
+
+    std::fs::write(gcodes.join("cube.gcode"), "G28\n").unwrap();
+    std::fs::write(gcodes.join("build").join("benchy.gcode"), "G28\nG1 X10\n").unwrap();
+    std::fs::write(gcodes.join("thumbnails").join("cube.png"), "png").unwrap();
+
+    let files = inst.list_files().await.result.unwrap();
+    assert_eq!(files.len(), 2);
+
+    assert_eq!(files[0].path, "build/benchy.gcode");
+    assert_eq!(files[0].size, 11);
+    assert_eq!(files[1].path, "cube.gcode");
+    assert_eq!(files[1].size, 4);
+
+    for file in &files {
+        assert_eq!(file.permissions.len(), 9);
+        assert!(file.permissions.starts_with("rw"));
+        assert!(file.modified > 0);
+    }
+
+    // a missing gcodes directory is reported
+    std::fs::remove_dir_all(gcodes).unwrap();
+    let re = inst.list_files().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DeleteQueuePrintJobParams {
-    pub id: String,
+
+#[tokio::test]
+async fn test_file_cleanup() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let old = std::time::SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60);
+
+    for filename in ["old.gcode", "queued.gcode", "new.gcode"] {
+        let path = inst.gcodes_path().join(filename);
+        std::fs::write(&path, "G28\n").unwrap();
+
+        if filename != "new.gcode" {
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(old).unwrap();
+        }
+    }
+
+    // the job waits in the queue
+    inst.pause_job_queue().await;
+    let re = inst
+        .queue_print_job("queued.gcode", JobMetadata::default(), None)
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let config = FileCleanupConfig {
+        max_age_days: Some(1.0),
+        ..Default::default()
+    };
+    assert_eq!(inst.cleanup_files(&config).await, vec!["old.gcode"]);
+
+    // low free space removes recent files too, the queued file is kept
+    let config = FileCleanupConfig {
+        min_free_space: Some(u64::MAX),
+        ..Default::default()
+    };
+    assert_eq!(inst.cleanup_files(&config).await, vec!["new.gcode"]);
+
+    let files = inst.list_files().await.result.unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "queued.gcode");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-//// delete a print job in queue
-pub async fn delete_queue_print_job(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<DeleteQueuePrintJobParams>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.delete_queue_print_job(&params.id).await)
+
+#[tokio::test]
+async fn test_list_files_sorted() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    let now = std::time::SystemTime::now();
+
+    // oldest first, sizes do not follow the modified order
+    for (i, (filename, data)) in [
+        ("a.gcode", "G28\n"),
+        ("c.gcode", "G28\nG1 X1\nG1 X2\n"),
+        ("b.gcode", "G28\nG1 X1\n"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let path = inst.gcodes_path().join(filename);
+        std::fs::write(&path, data).unwrap();
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(now - Duration::from_secs(3600 * (3 - i as u64)))
+            .unwrap();
+    }
+
+    let paths = |files: Vec<PrinterGcodeFile>| -> Vec<String> {
+        files.into_iter().map(|f| f.path).collect()
+    };
+
+    // every file by name by default
+    let files = inst
+        .list_files_sorted(FileSortKey::Name, SortOrder::Asc, 0, None)
+        .await;
+    assert_eq!(
+        paths(files.result.unwrap()),
+        vec!["a.gcode", "b.gcode", "c.gcode"]
+    );
+
+    // the two newest files
+    let files = inst
+        .list_files_sorted(FileSortKey::Modified, SortOrder::Desc, 0, Some(2))
+        .await;
+    assert_eq!(paths(files.result.unwrap()), vec!["b.gcode", "c.gcode"]);
+
+    let files = inst
+        .list_files_sorted(FileSortKey::Size, SortOrder::Asc, 1, Some(5))
+        .await;
+    assert_eq!(paths(files.result.unwrap()), vec!["b.gcode", "c.gcode"]);
+
+    // offset past the end is empty
+    let files = inst
+        .list_files_sorted(FileSortKey::Name, SortOrder::Asc, 10, None)
+        .await;
+    assert!(files.result.unwrap().is_empty());
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
 
-/// pause the job queue, next job will not start when current job is finished
-pub async fn pause_job_queue(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.pause_job_queue().await)
-}
+#[tokio::test]
+async fn test_delete_files() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // every move is waited for, so the job is still running when files are deleted
+    let gcode = format!("G28\n{}", "G1 X0.2\nM400\nG1 X-0.2\nM400\n".repeat(50));
+
+    for filename in ["benchy.gcode", "parts/bracket.gcode", "parts/hinge.gcode"] {
+        let re = inst
+            .upload_file(filename, BASE64_STANDARD.encode(&gcode))
+            .await;
+        assert!(matches!(re.error.code, PrinterErrorCode::None));
+    }
+
+    let re = inst
+        .tag_file("parts/hinge.gcode", &["petg".to_string()])
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    inst.start_print_job("parts/bracket.gcode", Vec::new(), None)
+        .await;
+
+    while inst.printer.read().await.current_job().is_none() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    let filenames = [
+        "benchy.gcode",
+        "parts/bracket.gcode",
+        "parts/hinge.gcode",
+        "../printer.cfg",
+    ]
+    .map(String::from);
 
-/// resume the job queue
-pub async fn resume_job_queue(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.resume_job_queue().await)
-}
+    let results = inst.delete_files(&filenames).await.result.unwrap();
+    assert_eq!(results.len(), 4);
 
-/// get a list of jobs in job queue
-pub async fn list_job_queue(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<Vec<JobQueuePrintJob>>> {
-    Json(instance.list_job_queue().await)
-}
+    assert!(matches!(results[0].error.code, PrinterErrorCode::None));
+    assert!(matches!(
+        results[1].error.code,
+        PrinterErrorCode::PrintJobRunning
+    ));
+    assert!(matches!(results[2].error.code, PrinterErrorCode::None));
+    // paths outside the gcodes directory are refused
+    assert!(matches!(
+        results[3].error.code,
+        PrinterErrorCode::PathTraversal
+    ));
 
-/////////////////////////////////////////////
-///////////      Gcode files      ///////////
-/////////////////////////////////////////////
+    let files = inst.list_files().await.result.unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "parts/bracket.gcode");
 
-/// list avaliable gcode files
-pub async fn list_files(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<Vec<PrinterGcodeFile>>> {
-    Json(instance.list_files().await)
-}
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetFileMetaParams {
-    pub filename: String,
+    // deleted files are untagged
+    assert!(
+        inst.search_files("petg", "")
+            .await
+            .result
+            .unwrap()
+            .is_empty()
+    );
+
+    // missing files are reported
+    let filenames = ["benchy.gcode".to_string()];
+    let results = inst.delete_files(&filenames).await.result.unwrap();
+    assert!(matches!(
+        results[0].error.code,
+        PrinterErrorCode::FileNotFound
+    ));
+
+    let re = inst.cancel_print_job().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// get metadata for a specified gcode file
-pub async fn get_file_metadata(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<GetFileMetaParams>,
-) -> Json<PrinterResult<PrinterGcodeFileMetadata>> {
-    Json(instance.get_file_metadata(&params.filename).await)
+
+#[tokio::test]
+async fn test_pause_resume_cancel_job() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // every move is waited for, so the job takes a while
+    let gcode = format!(
+        ";TIME:3600\nG28\n{}",
+        "G1 X0.2\nM400\nG1 X-0.2\nM400\n".repeat(50)
+    );
+    std::fs::write(inst.gcodes_path().join("cube.gcode"), gcode).unwrap();
+
+    // nothing to pause or cancel
+    let re = inst.pause_print_job().await;
+    assert!(matches!(
+        re.error.code,
+        PrinterErrorCode::PrintJobNotRunning
+    ));
+    let re = inst.cancel_print_job().await;
+    assert!(matches!(
+        re.error.code,
+        PrinterErrorCode::PrintJobNotRunning
+    ));
+
+    let status = async || inst.get_print_job_status().await.result.unwrap();
+
+    let history = async || inst.list_history().await.result.unwrap();
+
+    let wait_started = async || {
+        while status().await.line == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+
+    let wait_finished = async |count: usize| {
+        while history().await.len() < count {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+
+    inst.start_print_job("cube.gcode", Vec::new(), None).await;
+    wait_started().await;
+
+    // the job has just started, an hour is estimated
+    let started = status().await;
+    let now = crate::timestamp::unix_timestamp();
+    assert!(started.start_timestamp <= now && now - started.start_timestamp <= 1);
+    assert!(started.progress < 0.01);
+    assert!(started.eta >= started.start_timestamp + 3600);
+    assert!(started.eta <= now + 3600);
+
+    let re = inst.pause_print_job().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    // the running command completes before the job holds
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let paused = status().await;
+    assert_eq!(paused.state, "paused");
+    assert!(paused.line < 201);
+
+    // job does not advance while paused
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(status().await.line, paused.line);
+
+    let re = inst.resume_print_job().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    // job continues from the recorded line
+    let mut line = paused.line;
+    while history().await.is_empty() {
+        let current = status().await;
+        if current.state == "printing" {
+            assert!(current.line >= line);
+            line = current.line;
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert_eq!(history().await[0].status, "completed");
+
+    // cancel discards the rest of the job
+    inst.start_print_job("cube.gcode", Vec::new(), None).await;
+    wait_started().await;
+
+    let re = inst.cancel_print_job().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    assert_eq!(status().await.state, "idle");
+    assert_eq!(history().await[1].status, "cancelled");
+    assert!(matches!(
+        inst.printer.read().await.state(),
+        super::printer::State::Ready
+    ));
+
+    // the printer accepts the next job
+    std::fs::write(inst.gcodes_path().join("line.gcode"), "G28\nG1 X1\n").unwrap();
+    inst.start_print_job("line.gcode", Vec::new(), None).await;
+    wait_finished(3).await;
+    assert_eq!(history().await[2].status, "completed");
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ScanFileParams {
-    pub filename: String,
+
+#[tokio::test]
+async fn test_move_plan() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    std::fs::write(
+        inst.gcodes_path().join("square.gcode"),
+        "G28\nG90\nG1 X10 F3000\nG1 X10 Y20\n",
+    )
+    .unwrap();
+
+    let moves = inst.get_move_plan("square.gcode").await.result.unwrap();
+    assert_eq!(moves.len(), 2);
+
+    assert!((moves[0].distance - 10.0).abs() < 1e-3);
+    assert!((moves[1].distance - 20.0).abs() < 1e-3);
+    assert!((moves[0].end.x - 10.0).abs() < 1e-3);
+    assert!((moves[1].end.y - 20.0).abs() < 1e-3);
+
+    // the toolhead starts and ends at rest, slowing down at the corner
+    assert_eq!(moves[0].start_velocity, 0.0);
+    assert_eq!(moves[1].end_velocity, 0.0);
+    assert!(moves[0].end_velocity < moves[0].cruise_velocity);
+    assert!(moves.iter().all(|m| m.duration > 0.0));
+
+    // the printer itself did not move
+    assert!(inst.get_position().await.result.unwrap().x.is_nan());
+
+    let csv = move_plan_csv(&moves);
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.starts_with("start_x,"));
+
+    let re = inst.get_move_plan("../escape.gcode").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// Initiate a metadata scan for a selected file. If the file has already been scanned the endpoint will force a re-scan.
-pub async fn scan_file_metadata(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<ScanFileParams>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.scan_file_metadata(&params.filename).await)
+
+#[tokio::test]
+async fn test_thumbnails() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    std::fs::create_dir_all(inst.gcodes_path().join("parts")).unwrap();
+    std::fs::write(
+        inst.gcodes_path().join("parts").join("benchy.gcode"),
+        include_str!("../../tests/OrcaBenchy.gcode"),
+    )
+    .unwrap();
+
+    let re = inst.scan_file_metadata("parts/benchy.gcode").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let stored: Vec<_> = std::fs::read_dir(inst.gcodes_path().join("thumbnails").join("parts"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert!(!stored.is_empty());
+    assert!(stored.iter().all(|name| name.starts_with("benchy-")));
+
+    let meta = inst
+        .get_file_metadata("parts/benchy.gcode")
+        .await
+        .result
+        .unwrap();
+
+    for t in &meta.thumbnails {
+        assert_eq!(
+            t.relative_path,
+            format!("thumbnails/parts/benchy-{}x{}.png", t.width, t.height)
+        );
+
+        let path = inst.gcodes_path().join(&t.relative_path);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), t.size as u64);
+
+        let data = inst
+            .get_thumbnail(t.relative_path.strip_prefix("thumbnails/").unwrap())
+            .await
+            .result
+            .unwrap();
+        assert!(data.starts_with(b"\x89PNG"));
+    }
+
+    // thumbnails are not listed as gcode files
+    let files = inst.list_files().await.result.unwrap();
+    assert_eq!(files.len(), 1);
+
+    let re = inst.get_thumbnail("missing.png").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let re = inst.get_thumbnail("../parts/benchy.gcode").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::PathTraversal));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UploadFileParams {
-    pub filename: String,
-    pub data: String,
+
+#[tokio::test]
+async fn test_jog_limits() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n\n[stepper_x]\nposition_max: 15\n\n[jog]\nmax_distance: 20\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // axes are not homed yet
+    let re = inst.jog(5.0, 0.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::NotHomed));
+    assert!(re.error.message.contains("homed"));
+
+    let re = inst.home(false, false, false).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    // longer than the configured max single distance
+    let re = inst.jog(0.0, 25.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::InvalidParameter));
+    assert!(re.error.message.contains("max_distance"));
+
+    // below position_min and above position_max
+    let re = inst.jog(-1.0, 0.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::InvalidParameter));
+
+    let re = inst.jog(10.0, 0.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let re = inst.jog(10.0, 0.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::InvalidParameter));
+    assert!(re.error.message.contains("limits"));
+
+    let re = inst.jog(0.0, 20.0, 0.0, 100.0).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let re = inst.run_gcode("M400".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let position = inst.get_position().await.result.unwrap();
+    assert_eq!((position.x, position.y, position.z), (10.0, 20.0, 0.0));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// upload a gcode file
-pub async fn upload_file(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<UploadFileParams>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.upload_file(&params.filename, params.data).await)
+
+#[tokio::test]
+async fn test_debug_mcu_route() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[printer]\nmax_velocity: 300\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // hidden unless enabled
+    let re = get_debug_mcu(Extension(inst.clone())).await;
+    assert_eq!(re.err(), Some(StatusCode::NOT_FOUND));
+
+    let re = inst
+        .run_gcode("G28\nG1 X10 Y5 F6000\nM104 S200\nM400\n".to_string())
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    enable_debug_routes(true);
+    let re = get_debug_mcu(Extension(inst.clone())).await;
+    enable_debug_routes(false);
+
+    let Json(state) = re.unwrap();
+    assert_eq!(state.name, "mcu");
+    assert!(state.executed > 0);
+    assert!((state.motor_position[0] - 10.0).abs() < 1e-3);
+    assert!((state.motor_position[1] - 5.0).abs() < 1e-3);
+    assert!((state.toolhead_position[0] - 10.0).abs() < 1e-3);
+    assert!((state.toolhead_position[1] - 5.0).abs() < 1e-3);
+    // 80 steps per mm with the default rotation_distance and microsteps
+    assert_eq!(state.motor_steps[..2], [800, 400]);
+    assert_eq!(state.heater_targets.get("extruder"), Some(&200.0));
+    // the extruder heats at full power
+    assert_eq!(state.heater_duty.get("extruder"), Some(&1.0));
+
+    let endstop = |axis, triggered| Json(DebugEndstopParams { axis, triggered });
+    let fault = || {
+        Json(DebugFaultParams {
+            message: "step underrun".to_string(),
+        })
+    };
+
+    let re = debug_set_endstop(Extension(inst.clone()), endstop(2, true)).await;
+    assert_eq!(re, StatusCode::NOT_FOUND);
+    let re = debug_mcu_fault(Extension(inst.clone()), fault()).await;
+    assert_eq!(re, StatusCode::NOT_FOUND);
+
+    enable_debug_routes(true);
+    let invalid = debug_set_endstop(Extension(inst.clone()), endstop(3, true)).await;
+    let set = debug_set_endstop(Extension(inst.clone()), endstop(2, true)).await;
+    enable_debug_routes(false);
+
+    assert_eq!(invalid, StatusCode::BAD_REQUEST);
+    assert_eq!(set, StatusCode::NO_CONTENT);
+
+    // the stuck switch is reported although z is away from its endstop
+    let re = inst.run_gcode("G1 Z5\nM400\n".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+    let status = inst.query_endstops().await.result.unwrap();
+    assert!(status.z_triggered);
+    assert!(!status.x_triggered);
+
+    enable_debug_routes(true);
+    let re = debug_mcu_fault(Extension(inst.clone()), fault()).await;
+    enable_debug_routes(false);
+    assert_eq!(re, StatusCode::NO_CONTENT);
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !matches!(inst.state().await, super::printer::State::Error { .. }) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    match inst.state().await {
+        super::printer::State::Error { code, message, .. } => {
+            assert!(matches!(code, PrinterErrorCode::McuError));
+            assert!(message.contains("step underrun"));
+        }
+        s => panic!("expected error state, got {:?}", s),
+    }
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DownloadFileParams {
-    pub filename: String,
+
+#[tokio::test]
+async fn test_printer_config_round_trip() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    // the printer creates an empty config when it starts
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    std::fs::remove_file(inst.path().join("printer.cfg")).unwrap();
+
+    let re = inst.download_printer_config().await;
+    assert!(matches!(re.error.code, PrinterErrorCode::FileNotFound));
+
+    let cfg =
+        "# comments are kept\n[printer]\nmax_velocity: 250\n\n[stepper_x]\nposition_endstop: 0\n";
+
+    let re = inst.upload_printer_config(cfg.to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let re = inst.download_printer_config().await;
+    assert_eq!(re.result.unwrap(), cfg);
+
+    // not restarted by the upload
+    assert_eq!(
+        inst.get_motion_limits().await.result.unwrap().max_velocity,
+        100.0
+    );
+
+    // an invalid config is rejected and the previous config is kept
+    let re = inst
+        .upload_printer_config("[printer\nmax_velocity: 300\n".to_string())
+        .await;
+    assert!(matches!(
+        re.error.code,
+        PrinterErrorCode::PrinterConfigParseError
+    ));
+    assert!(!re.error.message.is_empty());
+
+    let re = inst.download_printer_config().await;
+    assert_eq!(re.result.unwrap(), cfg);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// download a gcode file
-pub async fn download_file(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<DownloadFileParams>,
-) -> Json<PrinterResult<String>> {
-    Json(instance.download_file(&params.filename).await)
+
+#[tokio::test]
+async fn test_validation_error_codes() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[extruder]\nmax_temp: 250\n\n[heater_bed]\nmax_temp: 100\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let code = |re: PrinterError| re.code;
+
+    // filenames
+    let re = inst.get_file_metadata("").await;
+    assert!(matches!(code(re.error), PrinterErrorCode::InvalidParameter));
+
+    let re = inst.download_file("../printer.cfg").await;
+    assert!(matches!(code(re.error), PrinterErrorCode::PathTraversal));
+
+    let re = inst.lint_gcode_file("../printer.cfg").await;
+    assert!(matches!(code(re.error), PrinterErrorCode::PathTraversal));
+
+    let re = inst.list_file_objects("/etc/passwd").await;
+    assert!(matches!(code(re.error), PrinterErrorCode::PathTraversal));
+
+    let re = inst.upload_file("cube.gcode", "%%%".to_string()).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::InvalidParameter));
+
+    // temperatures
+    for script in [
+        "M104 S300",
+        "M109 S251",
+        "M140 S150",
+        "M190 S101",
+        "M104 S-5",
+    ] {
+        let re = inst.run_gcode(script.to_string()).await;
+        assert!(
+            matches!(code(re.error), PrinterErrorCode::TemperatureOutOfRange),
+            "{}",
+            script
+        );
+    }
+
+    let re = inst.run_gcode_response("M140 S150\n".to_string()).await;
+    assert!(matches!(
+        code(re.error),
+        PrinterErrorCode::TemperatureOutOfRange
+    ));
+
+    let re = inst.run_gcode("M104 S200\nM140 S60".to_string()).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::None));
+
+    // other gcode errors keep the generic gcode code
+    let re = inst.run_gcode("G999".to_string()).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::GcodeError));
+
+    // moves
+    let re = inst.jog(1.0, 0.0, 0.0, 10.0).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::NotHomed));
+
+    let re = inst.jog(1.0, 0.0, 0.0, 0.0).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::InvalidParameter));
+
+    let re = inst.set_filament_sensor("missing", false).await;
+    assert!(matches!(code(re.error), PrinterErrorCode::InvalidParameter));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// download the printer config
-pub async fn download_printer_config(
-    Extension(instance): Extension<Arc<Instance>>,
-) -> Json<PrinterResult<String>> {
-    Json(instance.download_printer_config().await)
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_temperatures() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(gantry_path.join("printer")).unwrap();
+    std::fs::write(
+        gantry_path.join("printer").join("printer.cfg"),
+        "[extruder]\n\n[extruder1]\n\n[heater_bed]\n\n[temperature_sensor chamber]\n",
+    )
+    .unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let temperatures = inst.get_temperatures().await.result.unwrap();
+    let names: Vec<&str> = temperatures.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["extruder", "extruder1", "heater_bed", "chamber"]);
+
+    // all heaters are off at ambient temperature
+    for t in &temperatures {
+        assert_eq!(t.target, 0.0);
+        assert_eq!(t.power, 0.0);
+        assert_eq!(t.current, 25.0);
+    }
+
+    let re = inst.run_gcode("M104 S200\nM140 S60".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let temperatures = inst.get_temperatures().await.result.unwrap();
+    let find = |name: &str| temperatures.iter().find(|t| t.name == name).unwrap();
+
+    let extruder = find("extruder");
+    assert_eq!(extruder.target, 200.0);
+    assert_eq!(extruder.power, 1.0);
+    assert!(extruder.current >= 25.0 && extruder.current < 200.0);
+
+    assert_eq!(find("heater_bed").target, 60.0);
+    assert_eq!(find("extruder1").target, 0.0);
+    assert_eq!(find("chamber").target, 0.0);
+
+    // heaters are turned off by an emergency stop
+    inst.emergency_stop().await;
+
+    let temperatures = inst.get_temperatures().await.result.unwrap();
+    assert!(temperatures.iter().all(|t| t.target == 0.0));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UploadPrinterConfigParams {
-    pub config: String,
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_exclude_object() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert!(inst.list_objects().await.result.unwrap().is_empty());
+
+    let re = inst
+        .run_gcode(
+            "EXCLUDE_OBJECT_DEFINE NAME=cube CENTER=10,10\n\
+             EXCLUDE_OBJECT_DEFINE NAME=cylinder CENTER=50,50"
+                .to_string(),
+        )
+        .await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let re = inst.exclude_object("CYLINDER").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+    let objects = inst.list_objects().await.result.unwrap();
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects["cube"], "printing");
+    assert_eq!(objects["cylinder"], "excluded");
+
+    // moves are skipped between the markers of the excluded object
+    let x_after = async |script: &str| {
+        let re = inst.run_gcode(format!("{}\nM400", script)).await;
+        assert!(matches!(re.error.code, PrinterErrorCode::None));
+
+        inst.get_position().await.result.unwrap().x
+    };
+
+    assert_eq!(x_after("G28\nG90").await, 0.0);
+    assert_eq!(
+        x_after("EXCLUDE_OBJECT_START NAME=cube\nG1 X10 F6000").await,
+        10.0
+    );
+    assert_eq!(x_after("EXCLUDE_OBJECT_END").await, 10.0);
+    assert_eq!(
+        x_after("EXCLUDE_OBJECT_START NAME=cylinder\nG1 X50 F6000").await,
+        10.0
+    );
+
+    inst.run_gcode("EXCLUDE_OBJECT_END".to_string()).await;
+
+    // unknown objects cannot be excluded
+    let re = inst.exclude_object("sphere").await;
+    assert!(matches!(re.error.code, PrinterErrorCode::InvalidParameter));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
-/// upload the printer config
-pub async fn upload_printer_config(
-    Extension(instance): Extension<Arc<Instance>>,
-    Json(params): Json<UploadPrinterConfigParams>,
-) -> Json<PrinterResult<()>> {
-    Json(instance.upload_printer_config(params.config).await)
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_query_endstops() {
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst = Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await;
+
+    while !matches!(inst.state().await, super::printer::State::Ready) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let endstops = async || {
+        let status = inst.query_endstops().await.result.unwrap();
+
+        (status.x_triggered, status.y_triggered, status.z_triggered)
+    };
+
+    // nothing is homed
+    assert_eq!(endstops().await, (false, false, false));
+
+    // only x rests at its endstop
+    let re = inst.home(true, false, false).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+    assert_eq!(endstops().await, (true, false, false));
+
+    // released once x moves away
+    let re = inst.run_gcode("G91\nG1 X10 F6000\nM400".to_string()).await;
+    assert!(matches!(re.error.code, PrinterErrorCode::None));
+    assert_eq!(endstops().await, (false, false, false));
+
+    let _ = std::fs::remove_dir_all(gantry_path);
 }
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::logger::{RotatingFile, format_line};
+
+/// rotate printer log when it exceeds 1MiB
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// number of rotated printer logs to keep
+const MAX_LOG_FILES: usize = 3;
+
+/// Durable log of a single printer, capturing gcode commands,
+/// their responses, errors and state transitions.
+/// does nothing until a file is opened
+pub struct PrinterLog {
+    file: Mutex<Option<RotatingFile>>,
+}
+
+impl PrinterLog {
+    pub const fn new() -> Self {
+        Self {
+            file: Mutex::new(None),
+        }
+    }
+
+    /// open the log file, replacing any previously opened file
+    pub fn open(&self, path: PathBuf) -> std::io::Result<()> {
+        let file = RotatingFile::open(path, MAX_LOG_SIZE, MAX_LOG_FILES)?;
+
+        *self.file.lock().unwrap() = Some(file);
+
+        return Ok(());
+    }
+
+    fn write(&self, level: &str, target: &str, message: std::fmt::Arguments) {
+        let mut file = self.file.lock().unwrap();
+
+        if let Some(file) = file.as_mut() {
+            let line = format_line(level, target, &message);
+
+            if let Err(e) = file.write_line(&line) {
+                log::warn!("Printer log '{}': {}", file.path().display(), e);
+            }
+        }
+    }
+
+    /// log a gcode command and its response
+    pub fn gcode(&self, command: &str, response: Result<&str, &anyhow::Error>) {
+        match response {
+            Ok("") => self.write("INFO", "gcode", format_args!("{}", command)),
+            Ok(r) => self.write("INFO", "gcode", format_args!("{} -> {}", command, r)),
+            Err(e) => self.write("ERROR", "gcode", format_args!("{} -> {}", command, e)),
+        }
+    }
+
+    /// log a state transition
+    pub fn state(&self, from: &super::State, to: &super::State) {
+        self.write("INFO", "state", format_args!("{:?} -> {:?}", from, to));
+    }
+}
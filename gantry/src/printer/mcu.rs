@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use portable_atomic::AtomicF32;
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::kinematics::{Kinematics, MotorMove, MotorSteps};
+
+use super::action::PrinterAction;
+use super::heater::{Heater, extruder_name};
+
+/// steps per mm of a motor with the default rotation_distance and microsteps
+pub const DEFAULT_STEPS_PER_MM: f32 = 80.0;
+
+/// fault reported by an mcu, the printer must be restarted
+#[derive(Debug, Clone)]
+pub struct McuFault {
+    /// name of the mcu reporting the fault
+    pub mcu: String,
+    pub message: String,
+}
+
+/// internal state of a simulated mcu, exposed for tests and ui development
+#[derive(Debug, Serialize)]
+pub struct SimulatedMcuState {
+    pub name: String,
+    /// number of actions executed since startup
+    pub executed: u64,
+    /// travel of the a, b and c motors in mm
+    pub motor_position: [f32; 3],
+    /// position of the a, b and c motors in steps
+    pub motor_steps: [i64; 3],
+    /// toolhead position [x, y, z] of the motor positions
+    pub toolhead_position: [f32; 3],
+    /// extruder travel in mm since startup
+    pub extruder_position: f32,
+    /// endstop switches [x, y, z], true if triggered
+    pub endstops: [bool; 3],
+    /// target temperature in celsius of each heater that was set
+    pub heater_targets: BTreeMap<String, f32>,
+    /// duty cycle from 0 to 1 of each heater and sensor
+    pub heater_duty: BTreeMap<String, f32>,
+}
+
+/// Simulated micro controller.
+/// Actions are accepted without driving any hardware,
+/// faults can be injected to exercise the error path
+pub struct SimulatedMcu {
+    pub name: String,
+    /// number of actions executed since startup
+    executed: AtomicU64,
+    /// endstop switches [x, y, z], true if triggered
+    endstops: [AtomicBool; 3],
+    /// motor positions in mm, relative to startup until homed
+    motor_position: std::sync::Mutex<MotorSteps>,
+    /// steps per mm of the a, b and c motors, set from config on restart
+    steps_per_mm: [AtomicF32; 3],
+    /// extruder travel in mm since startup
+    extruder_position: AtomicF32,
+    /// target temperatures set by heater actions, keyed by heater name
+    heater_targets: std::sync::Mutex<BTreeMap<String, f32>>,
+    /// channel to report faults back to the event loop
+    fault_sender: UnboundedSender<McuFault>,
+}
+
+impl SimulatedMcu {
+    pub fn new(name: String, fault_sender: UnboundedSender<McuFault>) -> Self {
+        Self {
+            name,
+            executed: AtomicU64::new(0),
+            endstops: [const { AtomicBool::new(false) }; 3],
+            motor_position: std::sync::Mutex::new(MotorSteps::default()),
+            steps_per_mm: [const { AtomicF32::new(DEFAULT_STEPS_PER_MM) }; 3],
+            extruder_position: AtomicF32::new(0.0),
+            heater_targets: std::sync::Mutex::new(BTreeMap::new()),
+            fault_sender,
+        }
+    }
+
+    /// execute an action
+    pub fn execute(&self, action: &PrinterAction) {
+        let heater = match action {
+            PrinterAction::SetBedTemp(temp) | PrinterAction::SetBedTempWait(temp) => {
+                Some(("heater_bed".to_string(), *temp))
+            }
+            PrinterAction::SetExtruderTemp { index, temp }
+            | PrinterAction::SetExtruderTempWait { index, temp } => {
+                Some((extruder_name(*index), *temp))
+            }
+            _ => None,
+        };
+
+        if let Some((name, temp)) = heater {
+            self.heater_targets.lock().unwrap().insert(name, temp);
+        }
+
+        self.executed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// execute a kinematic move translated to motor coordinates
+    pub fn execute_move(&self, m: &MotorMove) {
+        let mut position = self.motor_position.lock().unwrap();
+        position.a += m.motors.a;
+        position.b += m.motors.b;
+        position.c += m.motors.c;
+        self.extruder_position.fetch_add(m.e, Ordering::SeqCst);
+
+        self.executed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// set the motor positions, e.g. after homing
+    pub fn set_motor_position(&self, position: MotorSteps) {
+        *self.motor_position.lock().unwrap() = position;
+    }
+
+    pub fn motor_position(&self) -> MotorSteps {
+        *self.motor_position.lock().unwrap()
+    }
+
+    /// set the steps per mm of the a, b and c motors
+    pub fn set_steps_per_mm(&self, steps_per_mm: [f32; 3]) {
+        for (value, steps) in self.steps_per_mm.iter().zip(steps_per_mm) {
+            value.store(steps, Ordering::SeqCst);
+        }
+    }
+
+    pub fn executed_count(&self) -> u64 {
+        self.executed.load(Ordering::SeqCst)
+    }
+
+    /// triggered state of the endstops [x, y, z]
+    pub fn endstop_status(&self) -> [bool; 3] {
+        self.endstops.each_ref().map(|e| e.load(Ordering::SeqCst))
+    }
+
+    /// set the state of a simulated endstop switch, axis 0 is x
+    pub fn set_endstop(&self, axis: usize, triggered: bool) {
+        self.endstops[axis].store(triggered, Ordering::SeqCst);
+    }
+
+    /// snapshot of the simulated state, the toolhead position is
+    /// translated from the motor positions by 'kinematics'.
+    /// the duty of 'heaters' is sampled now
+    pub fn state(&self, kinematics: &dyn Kinematics, heaters: &[Heater]) -> SimulatedMcuState {
+        let motors = self.motor_position();
+        let motor_position = [motors.a, motors.b, motors.c];
+        let now = tokio::time::Instant::now();
+
+        SimulatedMcuState {
+            name: self.name.clone(),
+            executed: self.executed_count(),
+            motor_position,
+            motor_steps: [0, 1, 2].map(|i| {
+                (motor_position[i] * self.steps_per_mm[i].load(Ordering::SeqCst)).round() as i64
+            }),
+            toolhead_position: kinematics.motor_to_cartesian(motors),
+            extruder_position: self.extruder_position.load(Ordering::SeqCst),
+            endstops: self.endstop_status(),
+            heater_targets: self.heater_targets.lock().unwrap().clone(),
+            heater_duty: heaters
+                .iter()
+                .map(|h| (h.name.clone(), h.power(now)))
+                .collect(),
+        }
+    }
+
+    /// report a fault, e.g. a step underrun
+    pub fn report_fault(&self, message: String) {
+        let _ = self.fault_sender.send(McuFault {
+            mcu: self.name.clone(),
+            message,
+        });
+    }
+}
@@ -1,10 +1,24 @@
 pub mod action;
 mod auth;
+mod cleanup;
 mod dbus;
+mod diagnostics;
+mod endstops;
+pub mod heater;
 mod instance;
+mod log;
+mod mcu;
+mod motion_thread;
+pub mod notify;
+mod print_start;
 mod printer;
+mod recovery;
+mod sensor;
+mod tags;
+mod upload;
+mod webhook;
 
 use printer::Printer;
 
-pub use instance::{Instance, create_service_router};
+pub use instance::{Instance, create_service_router, enable_debug_routes};
 pub use printer::State;
@@ -0,0 +1,88 @@
+use std::future::Future;
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// scheduling priority of the motion thread, low among realtime priorities
+#[cfg(unix)]
+const MOTION_THREAD_PRIORITY: i32 = 10;
+
+/// a runtime on a dedicated OS thread, so the event loop of a printer
+/// does not share worker threads with the rest of gantry.
+/// the thread exits when this is dropped
+pub struct MotionThread {
+    handle: tokio::runtime::Handle,
+    /// dropping the sender stops the runtime
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MotionThread {
+    /// spawn the thread and raise its priority, a failure to raise is only logged
+    pub fn spawn(name: String) -> std::io::Result<Self> {
+        let (shutdown, shutdown_recv) = oneshot::channel::<()>();
+        let (started, started_recv) = std::sync::mpsc::channel();
+
+        std::thread::Builder::new()
+            .name(name.clone())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(r) => r,
+                    Err(e) => {
+                        let _ = started.send(Err(e));
+                        return;
+                    }
+                };
+
+                if let Err(e) = raise_priority() {
+                    log::warn!("{}: running at normal priority: {}", name, e);
+                }
+
+                let _ = started.send(Ok(runtime.handle().clone()));
+
+                // tasks are driven until the motion thread is dropped
+                let _ = runtime.block_on(shutdown_recv);
+            })?;
+
+        let handle = started_recv
+            .recv()
+            .map_err(|_| std::io::Error::other("motion thread exited on startup"))??;
+
+        return Ok(Self {
+            handle,
+            _shutdown: shutdown,
+        });
+    }
+
+    /// run a future on the motion thread
+    pub fn spawn_task<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle.spawn(future)
+    }
+}
+
+/// realtime round robin scheduling, usually requires root or CAP_SYS_NICE
+#[cfg(unix)]
+fn raise_priority() -> std::io::Result<()> {
+    // other fields of sched_param are platform specific
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = MOTION_THREAD_PRIORITY;
+
+    let re = unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_RR, &param) };
+
+    if re != 0 {
+        return Err(std::io::Error::from_raw_os_error(re));
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn raise_priority() -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,40 @@
+use gantry_api::PrinterState;
+use serde::Serialize;
+
+/// sampled printer status published periodically to integrations
+#[derive(Debug, Clone, Serialize)]
+pub struct Telemetry {
+    /// temperature of the active extruder in celsius
+    pub extruder_temperature: f32,
+    pub gcode_running: bool,
+    /// progress of the running gcode file from 0 to 1
+    pub progress: f32,
+}
+
+/// printer event pushed to integrations such as webhooks
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    StateChanged {
+        state: PrinterState,
+        /// error message, empty unless state is error
+        message: String,
+    },
+    JobStarted {
+        job_id: String,
+        filename: String,
+    },
+    JobFinished {
+        job_id: String,
+        filename: String,
+    },
+    JobFailed {
+        job_id: String,
+        filename: String,
+        message: String,
+    },
+    JobCancelled {
+        job_id: String,
+        filename: String,
+    },
+}
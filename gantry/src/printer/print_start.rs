@@ -0,0 +1,73 @@
+use crate::config::PrinterConfig;
+use crate::gcode::GcodeFile;
+
+/// gcode run before every print job, configured by [print_start]
+#[derive(Debug, Clone)]
+pub struct PrintStart {
+    /// may contain {BED_TEMP} and {EXTRUDER_TEMP}, replaced by the temperatures of the job
+    pub gcode: String,
+}
+
+impl PrintStart {
+    /// none if no print start gcode is configured
+    pub fn from_config(config: &PrinterConfig) -> Option<Self> {
+        let gcode = config
+            .get_section("print_start", None)?
+            .get_string("gcode")?;
+
+        if gcode.trim().is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            gcode: gcode.to_string(),
+        })
+    }
+
+    /// the gcode with temperatures from the slicer config of the job filled in
+    pub fn render(&self, file: &GcodeFile) -> anyhow::Result<String> {
+        let params = [
+            ("{BED_TEMP}", file.config.bed_temperature()),
+            ("{EXTRUDER_TEMP}", file.config.extruder_temperature()),
+        ];
+
+        let mut gcode = self.gcode.clone();
+
+        for (name, value) in params {
+            if !gcode.contains(name) {
+                continue;
+            }
+
+            let Some(value) = value else {
+                anyhow::bail!(
+                    "print_start gcode uses {} but the job does not specify it",
+                    name
+                );
+            };
+
+            gcode = gcode.replace(name, &value.to_string());
+        }
+
+        return Ok(gcode);
+    }
+}
+
+#[test]
+fn test_render_temperatures() {
+    let config = PrinterConfig::parse(
+        "[print_start]\ngcode:\n  M190 S{BED_TEMP}\n  M109 S{EXTRUDER_TEMP}\n",
+    )
+    .unwrap();
+    let print_start = PrintStart::from_config(&config).unwrap();
+
+    let file = GcodeFile::blocking_parse(
+        "G1 X10\n; first_layer_bed_temperature = 60\n; first_layer_temperature = 215,200\n",
+    )
+    .unwrap();
+
+    assert_eq!(print_start.render(&file).unwrap(), "M190 S60\nM109 S215");
+
+    // a missing temperature fails instead of heating to a wrong target
+    let file = GcodeFile::blocking_parse("G1 X10\n").unwrap();
+    assert!(print_start.render(&file).is_err());
+}
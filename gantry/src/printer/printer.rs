@@ -1,19 +1,49 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use gantry_api::PrinterErrorCode;
+use gantry_api::{
+    JobHistoryEntry, JobQueuePrintJob, PrinterAxisRange, PrinterBuildVolume, PrinterDiagnostic,
+    PrinterError, PrinterErrorCode, PrinterErrorSource, PrinterMotionLimits, PrinterMotionState,
+    PrinterPosition, PrinterState, PrinterTemperatureInfo,
+};
 use tokio::io::AsyncReadExt;
 use tokio::sync::RwLock;
-use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 use crate::config::PrinterConfig;
 use crate::gcode::GcodeFile;
-use crate::gcode::vm::GcodeVM;
+use crate::gcode::vm::{GcodeTiming, GcodeVM};
+use crate::kinematics::{self, Cartesian, Kinematics};
 
-use super::action::{ActionQueue, ActionState, PrinterAction};
+use super::action::{Action, ActionQueue, ActionState, Cornering, Move, PrinterAction};
+use super::diagnostics;
+use super::endstops::{Endstops, SimulatedEndstops};
+use super::heater::{Heater, extruder_name};
+use super::log::PrinterLog;
+use super::mcu::{DEFAULT_STEPS_PER_MM, McuFault, SimulatedMcu, SimulatedMcuState};
+use super::motion_thread::MotionThread;
+use super::notify::{Notification, Telemetry};
+use super::print_start::PrintStart;
+use super::recovery::{PowerLossRecovery, SavedPrintState};
+use super::sensor::FilamentSensor;
+
+/// jobs waiting in queue unless configured in [job_queue]
+const DEFAULT_MAX_QUEUED_JOBS: usize = 32;
+
+/// longest single jog in mm if not set in [jog]
+const DEFAULT_MAX_JOG_DISTANCE: f32 = 50.0;
+
+/// finished jobs kept in history, older jobs are forgotten
+const MAX_JOB_HISTORY: usize = 100;
+
+/// full steps per rotation of the motors, 1.8 degree steppers
+const FULL_STEPS_PER_ROTATION: f64 = 200.0;
 
 #[derive(Debug, Clone)]
 pub enum State {
@@ -22,42 +52,174 @@ pub enum State {
     Error {
         code: PrinterErrorCode,
         message: String,
+        /// operation the error originated from
+        source: PrinterErrorSource,
+        /// unix timestamp in seconds when the error occoured
+        timestamp: u64,
     },
     Shutdown,
 }
 
+impl State {
+    /// creates an error state stamped with the current time
+    pub fn error(code: PrinterErrorCode, source: PrinterErrorSource, message: String) -> Self {
+        let timestamp = crate::timestamp::unix_timestamp();
+
+        State::Error {
+            code,
+            message,
+            source,
+            timestamp,
+        }
+    }
+
+    /// state reported over the api
+    pub fn api_state(&self) -> PrinterState {
+        match self {
+            State::Startup => PrinterState::Startup,
+            State::Ready => PrinterState::Ready,
+            State::Error { .. } => PrinterState::Error,
+            State::Shutdown => PrinterState::Shutdown,
+        }
+    }
+
+    /// returns true if the error can be cleared without reloading the config.
+    /// errors raised while loading the config always require a restart
+    pub fn is_recoverable_error(&self) -> bool {
+        match self {
+            State::Error { code, .. } => matches!(
+                code,
+                PrinterErrorCode::GenericError
+                    | PrinterErrorCode::GcodeParseError
+                    | PrinterErrorCode::GcodeError
+                    | PrinterErrorCode::PrintJobRunning
+                    | PrinterErrorCode::PrintJobNotRunning
+            ),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum PrinterEvent {
     Action(PrinterAction),
     RunNextPrintJob,
 }
 
+/// who queued a job and why, carried through to the job history
+#[derive(Debug, Clone, Default)]
+pub struct JobMetadata {
+    pub operator: Option<String>,
+    pub notes: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct PrintJob {
     pub id: Uuid,
-    /// filename
+    /// gcode filename
+    pub filename: String,
+    /// parsed gcode file
     pub file: Arc<GcodeFile>,
-    /// linux timestamp
+    /// unix timestamp in seconds, set when the job starts
     pub start_timestamp: Option<u64>,
     /// exluded objects
     pub exlude_objects: Vec<String>,
+    /// state of an interrupted job to resume from
+    pub resume: Option<SavedPrintState>,
+    pub metadata: JobMetadata,
+}
+
+/// the job being printed
+#[derive(Debug, Clone)]
+pub struct CurrentJob {
+    pub id: Uuid,
+    pub filename: String,
+    /// unix timestamp in seconds when the job started
+    pub start_timestamp: u64,
+    /// estimated print time in seconds given by the slicer
+    pub estimated_print_time: Option<u64>,
+    /// time printed before the last pause
+    active: Duration,
+    /// when the job last started or resumed, none while paused
+    resumed_at: Option<Instant>,
+    /// set by cancel_print_job, the job is recorded as cancelled
+    cancelled: bool,
+}
+
+impl CurrentJob {
+    /// time spent printing, time paused is not counted
+    pub fn elapsed(&self) -> Duration {
+        self.active + self.resumed_at.map(|t| t.elapsed()).unwrap_or_default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.resumed_at.is_none()
+    }
+
+    fn pause(&mut self) {
+        if let Some(t) = self.resumed_at.take() {
+            self.active += t.elapsed();
+        }
+    }
+
+    fn unpause(&mut self) {
+        self.resumed_at.get_or_insert_with(Instant::now);
+    }
 }
 
 pub struct Printer {
-    /// generic status of printer
-    state: State,
+    /// generic status of printer, shared with the event loop
+    state: Arc<std::sync::Mutex<State>>,
     /// status of physical printer
     action_state: Arc<ActionState>,
     /// queue for kinematic actions, trapezoid generator
     action_queue: Arc<ActionQueue>,
     /// gcode virtual machine
     vm: Arc<GcodeVM>,
-    /// job queue
-    print_job_queue: RwLock<VecDeque<PrintJob>>,
+    /// job queue, jobs are started by the event loop
+    print_job_queue: Arc<RwLock<VecDeque<PrintJob>>>,
+    /// maximum number of waiting jobs, the running job is not in the queue
+    max_queued_jobs: usize,
+    /// the running job, none if no job is running
+    current_job: Arc<std::sync::Mutex<Option<CurrentJob>>>,
+    /// finished jobs, oldest first
+    job_history: Arc<RwLock<VecDeque<JobHistoryEntry>>>,
+    /// no job is started while the queue is paused
+    job_queue_paused: Arc<AtomicBool>,
+    /// pause the queue after each job, so the bed can be cleared
+    manual_job_queue: bool,
     /// sender to send events to event loop
     event_sender: UnboundedSender<PrinterEvent>,
+    /// events recieved by the event loop, kept across restarts
+    event_reciever: Arc<tokio::sync::Mutex<UnboundedReceiver<PrinterEvent>>>,
     /// join handle for event loop
     event_loop_handle: Option<JoinHandle<()>>,
+    /// dedicated thread running the event loop, if enabled in config
+    motion_thread: Option<MotionThread>,
+    /// the micro controller actions are sent to
+    mcu: Arc<SimulatedMcu>,
+    /// translates moves to motor coordinates, from [printer] kinematics
+    kinematics: Arc<dyn Kinematics>,
+    /// endstop switches read by query_endstops
+    endstops: Arc<dyn Endstops>,
+    /// faults reported by the mcu, handled by the event loop
+    mcu_faults: Arc<tokio::sync::Mutex<UnboundedReceiver<McuFault>>>,
+    /// durable log of commands, errors and state transitions
+    log: Arc<PrinterLog>,
+    /// filament runout sensors
+    filament_sensors: Vec<FilamentSensor>,
+    /// state changes and job events for integrations
+    notifications: broadcast::Sender<Notification>,
+    /// saves print progress if enabled in config
+    recovery: Option<PowerLossRecovery>,
+    /// gcode run before every job
+    print_start: Option<PrintStart>,
+    /// a [heater_bed] section is configured
+    heater_bed: bool,
+    /// (min, max) position of x, y and z jogs may move to
+    soft_limits: [(f32, f32); 3],
+    /// longest single jog in mm on any axis
+    max_jog_distance: f32,
 }
 
 impl Printer {
@@ -68,60 +230,169 @@ impl Printer {
         let action_queue = Arc::new(ActionQueue::new(action_state.clone(), event_sender.clone()));
         let vm = Arc::new(GcodeVM::new(action_queue.clone()));
 
+        let (fault_sender, fault_reciever) = unbounded_channel();
+        let mcu = Arc::new(SimulatedMcu::new("mcu".to_string(), fault_sender));
+        let endstops = Arc::new(SimulatedEndstops::new(action_state.clone(), mcu.clone()));
+
         Self {
-            state: State::Startup,
+            state: Arc::new(std::sync::Mutex::new(State::Startup)),
             action_state,
             action_queue,
             vm,
-            print_job_queue: RwLock::const_new(VecDeque::new()),
+            print_job_queue: Arc::new(RwLock::const_new(VecDeque::new())),
+            max_queued_jobs: DEFAULT_MAX_QUEUED_JOBS,
+            current_job: Arc::new(std::sync::Mutex::new(None)),
+            job_history: Arc::new(RwLock::const_new(VecDeque::new())),
+            job_queue_paused: Arc::new(AtomicBool::new(false)),
+            manual_job_queue: false,
             event_sender,
+            event_reciever: Arc::new(tokio::sync::Mutex::new(event_reciever)),
             event_loop_handle: None,
+            motion_thread: None,
+            mcu,
+            kinematics: Arc::new(Cartesian),
+            endstops,
+            mcu_faults: Arc::new(tokio::sync::Mutex::new(fault_reciever)),
+            log: Arc::new(PrinterLog::new()),
+            filament_sensors: Vec::new(),
+            notifications: broadcast::channel(64).0,
+            recovery: None,
+            print_start: None,
+            heater_bed: false,
+            soft_limits: [(0.0, f32::INFINITY); 3],
+            max_jog_distance: DEFAULT_MAX_JOG_DISTANCE,
         }
     }
 
     pub fn state(&self) -> State {
-        return self.state.clone();
+        return self.state.lock().unwrap().clone();
+    }
+
+    pub fn mcu(&self) -> &Arc<SimulatedMcu> {
+        &self.mcu
+    }
+
+    /// internal state of the simulated mcu
+    pub fn mcu_state(&self) -> SimulatedMcuState {
+        self.mcu.state(
+            self.kinematics.as_ref(),
+            &self.action_state.heaters.lock().unwrap(),
+        )
+    }
+
+    /// open the per printer log file
+    pub fn open_log(&self, path: PathBuf) -> std::io::Result<()> {
+        self.log.open(path)
+    }
+
+    /// subscribe to state changes and job events
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.subscribe()
+    }
+
+    /// transition to a new state, the transition is logged
+    fn set_state(&mut self, state: State) {
+        transition(&self.state, &self.log, &self.notifications, state);
+    }
+
+    /// enter error state for a failure outside the printer, e.g. setting up its files
+    pub fn set_error(
+        &mut self,
+        code: PrinterErrorCode,
+        source: PrinterErrorSource,
+        message: String,
+    ) {
+        self.set_state(State::error(code, source, message));
     }
 
     /// stops the printer immediately
     pub fn emergency_stop(&mut self) {
-        // abort the event loop
-        if let Some(handle) = self.event_loop_handle.take() {
-            handle.abort();
-        }
+        self.stop_event_loop();
 
         // abort the action queue
         self.action_queue.suspend();
         // abort the vm
         self.vm.suspend();
+        // heaters are off while shut down
+        self.action_state.heaters_off();
         // set state to shutdown
-        self.state = State::Shutdown;
+        self.set_state(State::Shutdown);
+    }
+
+    fn stop_event_loop(&mut self) {
+        if let Some(handle) = self.event_loop_handle.take() {
+            handle.abort();
+        }
+    }
+
+    /// spawns the event loop, replacing the running one
+    fn start_event_loop(&mut self) {
+        self.stop_event_loop();
+
+        let event_loop = EventLoop {
+            state: self.state.clone(),
+            log: self.log.clone(),
+            notifications: self.notifications.clone(),
+            action_state: self.action_state.clone(),
+            action_queue: self.action_queue.clone(),
+            vm: self.vm.clone(),
+            mcu: self.mcu.clone(),
+            kinematics: self.kinematics.clone(),
+            print_job_queue: self.print_job_queue.clone(),
+            current_job: self.current_job.clone(),
+            job_history: self.job_history.clone(),
+            job_queue_paused: self.job_queue_paused.clone(),
+            manual_job_queue: self.manual_job_queue,
+            recovery: self.recovery.clone(),
+            print_start: self.print_start.clone(),
+            events: self.event_reciever.clone(),
+            faults: self.mcu_faults.clone(),
+        };
+
+        self.event_loop_handle = Some(match &self.motion_thread {
+            Some(thread) => thread.spawn_task(event_loop.run()),
+            None => tokio::spawn(event_loop.run()),
+        });
     }
 
     /// restart the printer
     pub async fn restart(&mut self, config_path: PathBuf) {
         // set state to startup
-        self.state = State::Startup;
+        self.set_state(State::Startup);
+
+        // faults from before the restart no longer apply
+        if let Ok(mut faults) = self.mcu_faults.try_lock() {
+            while faults.try_recv().is_ok() {}
+        }
 
         // buffer for printer config
         let mut printer_config = String::new();
 
-        // open config file
-        let file = tokio::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&config_path)
-            .await;
+        // create an empty config if not exist
+        if !config_path.exists()
+            && let Err(e) = crate::files::write_atomic(&config_path, b"").await
+        {
+            self.set_state(State::error(
+                PrinterErrorCode::FileNotFound,
+                PrinterErrorSource::Config,
+                e.to_string(),
+            ));
+
+            return;
+        }
+
+        // open config file, config is only ever written through write_atomic
+        let file = tokio::fs::File::open(&config_path).await;
 
         // error state if failed to open file
         let mut file = match file {
             Ok(f) => f,
             Err(e) => {
-                self.state = State::Error {
-                    code: PrinterErrorCode::FileNotFound,
-                    message: e.to_string(),
-                };
+                self.set_state(State::error(
+                    PrinterErrorCode::FileNotFound,
+                    PrinterErrorSource::Config,
+                    e.to_string(),
+                ));
 
                 return;
             }
@@ -132,10 +403,11 @@ impl Printer {
 
         // error state if failed to read file
         if let Err(e) = re {
-            self.state = State::Error {
-                code: PrinterErrorCode::FileReadError,
-                message: e.to_string(),
-            };
+            self.set_state(State::error(
+                PrinterErrorCode::FileReadError,
+                PrinterErrorSource::Config,
+                e.to_string(),
+            ));
 
             return;
         }
@@ -144,28 +416,490 @@ impl Printer {
         let config = match PrinterConfig::parse(&printer_config) {
             Ok(c) => c,
             Err(e) => {
-                self.state = State::Error {
-                    code: PrinterErrorCode::FileReadError,
-                    message: e.to_string(),
-                };
+                self.set_state(State::error(
+                    PrinterErrorCode::PrinterConfigParseError,
+                    PrinterErrorSource::Config,
+                    e.to_string(),
+                ));
+
+                return;
+            }
+        };
+
+        self.kinematics = match kinematics::from_config(&config) {
+            Ok(k) => k,
+            Err(e) => {
+                self.set_state(State::error(
+                    PrinterErrorCode::PrinterConfigParseError,
+                    PrinterErrorSource::Config,
+                    e,
+                ));
 
                 return;
             }
         };
 
+        // motor travel is converted to steps by the mcu
+        let mut steps_per_mm = [DEFAULT_STEPS_PER_MM; 3];
+
+        for (steps, name) in steps_per_mm
+            .iter_mut()
+            .zip(self.kinematics.stepper_sections())
+        {
+            let section = config.get_section(name, None);
+            let rotation_distance = section
+                .and_then(|s| s.get_number("rotation_distance"))
+                .unwrap_or(40.0);
+            let microsteps = section
+                .and_then(|s| s.get_number("microsteps"))
+                .unwrap_or(16.0);
+
+            if rotation_distance <= 0.0 || microsteps <= 0.0 {
+                self.set_state(State::error(
+                    PrinterErrorCode::PrinterConfigParseError,
+                    PrinterErrorSource::Config,
+                    format!(
+                        "[{}] rotation_distance and microsteps must be positive",
+                        name
+                    ),
+                ));
+
+                return;
+            }
+
+            *steps = (FULL_STEPS_PER_ROTATION * microsteps / rotation_distance) as f32;
+        }
+
+        self.mcu.set_steps_per_mm(steps_per_mm);
+
+        // kinematic limits, keys are listed in config::schema
+        if let Some(section) = config.get_section("printer", None) {
+            let limits = [
+                ("max_velocity", &self.action_state.max_velocity),
+                ("max_accel", &self.action_state.max_accel),
+                (
+                    "square_corner_velocity",
+                    &self.action_state.square_corner_velocity,
+                ),
+                (
+                    "minimum_cruise_ratio",
+                    &self.action_state.minimum_cruise_ratio,
+                ),
+                ("max_jerk", &self.action_state.max_jerk),
+            ];
+
+            for (key, value) in limits {
+                if let Some(n) = section.get_number(key) {
+                    value.store(n as f32, std::sync::atomic::Ordering::SeqCst);
+                }
+            }
+
+            let cornering = match section.get_string("cornering") {
+                None | Some("square_corner_velocity") => Cornering::SquareCornerVelocity,
+                Some("jerk") => Cornering::Jerk,
+                Some(cornering) => {
+                    log::warn!(
+                        "unknown cornering '{}', using square_corner_velocity",
+                        cornering
+                    );
+                    Cornering::SquareCornerVelocity
+                }
+            };
+
+            *self.action_state.cornering.lock().unwrap() = cornering;
+        }
+
+        // position of each axis after homing
+        let endstops = [
+            ("stepper_x", &self.action_state.x_endstop),
+            ("stepper_y", &self.action_state.y_endstop),
+            ("stepper_z", &self.action_state.z_endstop),
+        ];
+
+        for (section, value) in endstops {
+            let n = config
+                .get_section(section, None)
+                .and_then(|s| s.get_number("position_endstop"))
+                .unwrap_or(0.0);
+
+            value.store(n as f32, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        // range of each axis jogs are kept in
+        for (limits, section) in
+            self.soft_limits
+                .iter_mut()
+                .zip(["stepper_x", "stepper_y", "stepper_z"])
+        {
+            let section = config.get_section(section, None);
+
+            *limits = (
+                section
+                    .and_then(|s| s.get_number("position_min"))
+                    .unwrap_or(0.0) as f32,
+                section
+                    .and_then(|s| s.get_number("position_max"))
+                    .map(|n| n as f32)
+                    .unwrap_or(f32::INFINITY),
+            );
+        }
+
+        self.max_jog_distance = config
+            .get_section("jog", None)
+            .and_then(|s| s.get_number("max_distance"))
+            .map(|n| n.max(0.0) as f32)
+            .unwrap_or(DEFAULT_MAX_JOG_DISTANCE);
+
+        // extrusion limits, reset to defaults if removed from config
+        let extruder = config.get_section("extruder", None);
+        let extrusion_limits = [
+            (
+                "min_extrude_temp",
+                170.0,
+                &self.action_state.min_extrude_temp,
+            ),
+            (
+                "max_extrude_only_distance",
+                50.0,
+                &self.action_state.max_extrude_only_distance,
+            ),
+            (
+                "max_extrude_cross_section",
+                0.64,
+                &self.action_state.max_extrude_cross_section,
+            ),
+            (
+                "filament_diameter",
+                1.75,
+                &self.action_state.filament_diameter,
+            ),
+        ];
+
+        for (key, default, value) in extrusion_limits {
+            let n = extruder.and_then(|s| s.get_number(key)).unwrap_or(default);
+
+            value.store(n as f32, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        // [extruder] is extruder 0, [extruder1] is extruder 1 and so on
+        let extruder_count = config
+            .sections
+            .iter()
+            .filter(|s| s.suffix_name.is_none())
+            .filter_map(|s| s.prefix_name.strip_prefix("extruder"))
+            .filter(|n| n.is_empty() || n.parse::<usize>().is_ok())
+            .count()
+            .max(1);
+
+        self.action_state
+            .extruder_count
+            .store(extruder_count, std::sync::atomic::Ordering::SeqCst);
+        self.action_state
+            .active_extruder
+            .store(0, std::sync::atomic::Ordering::SeqCst);
+
+        // heater limits, higher targets are refused
+        let max_temp = |name: &str| {
+            config
+                .get_section(name, None)
+                .and_then(|s| s.get_number("max_temp"))
+                .map(|n| n as f32)
+                .unwrap_or(f32::INFINITY)
+        };
+
+        *self.action_state.extruder_max_temp.lock().unwrap() = (0..extruder_count)
+            .map(|i| max_temp(&extruder_name(i)))
+            .collect();
+        self.action_state
+            .bed_max_temp
+            .store(max_temp("heater_bed"), std::sync::atomic::Ordering::SeqCst);
+
+        self.filament_sensors = FilamentSensor::from_config(&config);
+
+        self.recovery = PowerLossRecovery::from_config(&config, &config_path);
+
+        self.print_start = PrintStart::from_config(&config);
+
+        self.heater_bed = config.get_section("heater_bed", None).is_some();
+
+        // reported by get_temperatures, all heaters start off
+        let mut heaters: Vec<Heater> = (0..extruder_count)
+            .map(|i| Heater::new(extruder_name(i)))
+            .collect();
+
+        if self.heater_bed {
+            heaters.push(Heater::new("heater_bed"));
+        }
+
+        // [temperature_sensor chamber] is reported as 'chamber'
+        heaters.extend(
+            config
+                .sections
+                .iter()
+                .filter(|s| s.prefix_name == "temperature_sensor")
+                .filter_map(|s| s.suffix_name.clone())
+                .map(Heater::new),
+        );
+
+        *self.action_state.heaters.lock().unwrap() = heaters;
+
+        // lift when travelling over excluded objects, disabled by default
+        let z_hop = config
+            .get_section("exclude_object", None)
+            .and_then(|s| s.get_number("z_hop"))
+            .unwrap_or(0.0);
+
+        self.action_state
+            .exclude_z_hop
+            .store(z_hop.max(0.0) as f32, std::sync::atomic::Ordering::SeqCst);
+
+        let job_queue = config.get_section("job_queue", None);
+
+        self.max_queued_jobs = job_queue
+            .and_then(|s| s.get_number("max_queued_jobs"))
+            .map(|n| n.max(0.0) as usize)
+            .unwrap_or(DEFAULT_MAX_QUEUED_JOBS);
+
+        self.manual_job_queue = match job_queue.and_then(|s| s.get_string("mode")) {
+            None | Some("auto") => false,
+            Some("manual") => true,
+            Some(mode) => {
+                log::warn!("unknown job queue mode '{}', using auto", mode);
+                false
+            }
+        };
+
+        // unit and precision of reported positions and temperatures
+        let reporting = config.get_section("reporting", None);
+        let precision = reporting
+            .and_then(|s| s.get_number("precision"))
+            .unwrap_or(3.0);
+        let inches = match reporting.and_then(|s| s.get_string("unit")) {
+            None | Some("mm") => false,
+            Some("inch") => true,
+            Some(unit) => {
+                log::warn!("unknown reporting unit '{}', using mm", unit);
+                false
+            }
+        };
+
+        self.action_state.report_precision.store(
+            precision.clamp(0.0, 6.0) as usize,
+            std::sync::atomic::Ordering::SeqCst,
+        );
+        self.action_state
+            .report_inches
+            .store(inches, std::sync::atomic::Ordering::SeqCst);
+
+        // gcode execution timing, for debugging only
+        let debug = config.get_section("debug", None);
+        let gcode_timing = debug
+            .and_then(|s| s.get_bool("gcode_timing"))
+            .unwrap_or(false);
+        let slow_gcode_threshold = debug
+            .and_then(|s| s.get_number("slow_gcode_threshold"))
+            .unwrap_or(50.0);
+
+        self.vm.configure_timing(
+            gcode_timing,
+            Duration::from_secs_f64(slow_gcode_threshold.max(0.0) / 1000.0),
+        );
+
+        // run the event loop on its own thread, away from the shared workers
+        let motion_thread = config
+            .get_section("printer", None)
+            .and_then(|s| s.get_bool("motion_thread"))
+            .unwrap_or(false);
+
+        if !motion_thread {
+            self.motion_thread = None;
+        } else if self.motion_thread.is_none() {
+            match MotionThread::spawn("gantry-motion".to_string()) {
+                Ok(thread) => self.motion_thread = Some(thread),
+                Err(e) => {
+                    log::error!(
+                        "failed to start motion thread, using the shared runtime: {}",
+                        e
+                    )
+                }
+            }
+        }
+
         // clear the action queue
         self.action_queue.clear().await;
         // resume the action queue
         self.action_queue.resume();
         // resume the gcode vm
         self.vm.resume();
+        self.vm.unpause();
+
+        self.set_state(State::Ready);
+
+        self.start_event_loop();
+    }
+
+    /// stops the printer, forgets the toolhead position and reloads the config.
+    /// unlike restart, this always runs to completion before returning
+    pub async fn firmware_restart(&mut self, config_path: PathBuf) {
+        self.emergency_stop();
+        // axes must be homed again after firmware restart
+        self.action_state.reset_position();
+        self.restart(config_path).await;
+    }
+
+    /// status of each object of the running gcode, 'printing' or 'excluded'.
+    /// objects excluded before they are defined are listed as well
+    pub async fn objects(&self) -> HashMap<String, String> {
+        let excluded = self.action_state.exclude_objects.read().await;
+
+        let mut objects: HashMap<String, String> = self
+            .action_state
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|name| (name.clone(), "printing".to_string()))
+            .collect();
+
+        for name in excluded.iter() {
+            let defined = objects
+                .keys()
+                .find(|o| o.eq_ignore_ascii_case(name))
+                .cloned()
+                .unwrap_or_else(|| name.clone());
+
+            objects.insert(defined, "excluded".to_string());
+        }
+
+        return objects;
+    }
+
+    /// cancel a defined object, moves between its markers are skipped
+    pub async fn exclude_object(&self, name: &str) -> Result<(), PrinterError> {
+        let defined = self
+            .action_state
+            .objects
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|o| o.eq_ignore_ascii_case(name));
+
+        if !defined {
+            return Err(PrinterError {
+                code: PrinterErrorCode::InvalidParameter,
+                message: format!("object '{}' is not defined", name),
+            });
+        }
+
+        self.action_queue.exclude_object(name).await;
 
-        todo!()
+        return Ok(());
+    }
+
+    /// acknowledge a recoverable error and return to ready state.
+    /// does nothing if the printer is not in error state
+    pub fn clear_error(&mut self) -> Result<(), PrinterError> {
+        let state = self.state();
+
+        if !matches!(state, State::Error { .. }) {
+            return Ok(());
+        }
+
+        if !state.is_recoverable_error() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::ErrorState,
+                message: "error is not recoverable, printer must be restarted".to_string(),
+            });
+        }
+
+        if self.is_gcode_running() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: "cannot clear error while gcode is running".to_string(),
+            });
+        }
+
+        self.set_state(State::Ready);
+
+        return Ok(());
     }
 
     /// returns endstop triggered xyz
     pub async fn get_endstop_status(&self) -> (bool, bool, bool) {
-        todo!()
+        self.endstops.triggered()
+    }
+
+    /// check endstops, heaters and motion of an idle printer
+    pub async fn run_diagnostics(&self) -> Result<Vec<PrinterDiagnostic>, PrinterError> {
+        if self.is_gcode_running() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: "cannot run diagnostics while gcode is running".to_string(),
+            });
+        }
+
+        Ok(diagnostics::run(&self.action_queue, &self.mcu, self.heater_bed).await)
+    }
+
+    /// a copy of the limits in effect to simulate gcode with, homed at the endstops
+    pub fn simulation_state(&self) -> ActionState {
+        self.action_state.simulation()
+    }
+
+    /// returns the current toolhead position [x, y, z, e]
+    pub fn get_position(&self) -> [f32; 4] {
+        self.action_state.current_position()
+    }
+
+    /// the stepper ranges jogs are kept in
+    pub fn build_volume(&self) -> PrinterBuildVolume {
+        let [x, y, z] = self
+            .soft_limits
+            .map(|(min, max)| PrinterAxisRange { min, max });
+
+        PrinterBuildVolume { x, y, z }
+    }
+
+    pub fn motion_limits(&self) -> PrinterMotionLimits {
+        let state = &self.action_state;
+
+        PrinterMotionLimits {
+            max_velocity: state.max_velocity.load(Ordering::SeqCst),
+            max_accel: state.max_accel.load(Ordering::SeqCst),
+            square_corner_velocity: state.square_corner_velocity.load(Ordering::SeqCst),
+            minimum_cruise_ratio: state.minimum_cruise_ratio.load(Ordering::SeqCst),
+        }
+    }
+
+    /// position, homed axes, feedrate and positioning modes
+    pub fn motion_state(&self) -> PrinterMotionState {
+        let state = &self.action_state;
+        let [x, y, z, e] = self.reported_position();
+
+        // positions are unknown until homed
+        let homed_axes = [
+            ('x', &state.x_position),
+            ('y', &state.y_position),
+            ('z', &state.z_position),
+        ]
+        .into_iter()
+        .filter(|(_, p)| !p.load(Ordering::SeqCst).is_nan())
+        .map(|(axis, _)| axis)
+        .collect();
+
+        PrinterMotionState {
+            position: PrinterPosition { x, y, z, e },
+            homed_axes,
+            feedrate: state.feedrate.load(Ordering::SeqCst),
+            absolute_position: state.absolute_position.load(Ordering::SeqCst),
+            absolute_extrusion: state.absolute_extrution.load(Ordering::SeqCst),
+        }
+    }
+
+    /// the toolhead position in the unit and precision set in [reporting]
+    pub fn reported_position(&self) -> [f32; 4] {
+        self.get_position()
+            .map(|v| self.action_state.report_length(v))
     }
 
     pub fn is_gcode_running(&self) -> bool {
@@ -174,29 +908,1814 @@ impl Printer {
             .load(std::sync::atomic::Ordering::SeqCst)
     }
 
-    /// spawns a tokio task to run print jobs
+    /// modeled temperature, target and power of each heater and sensor
+    pub fn temperatures(&self) -> Vec<PrinterTemperatureInfo> {
+        let now = tokio::time::Instant::now();
+
+        self.action_state
+            .heaters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|h| PrinterTemperatureInfo {
+                name: h.name.clone(),
+                current: self.action_state.report_temperature(h.temperature(now)) as f64,
+                target: h.target as f64,
+                power: h.power(now) as f64,
+            })
+            .collect()
+    }
+
+    /// sample the current status for integrations
+    pub fn telemetry(&self) -> Telemetry {
+        use std::sync::atomic::Ordering;
+
+        let line = self.action_state.gcode_line.load(Ordering::SeqCst);
+        let lines = self.action_state.gcode_lines.load(Ordering::SeqCst);
+        let gcode_running = self.is_gcode_running();
+
+        Telemetry {
+            extruder_temperature: self
+                .action_state
+                .report_temperature(self.action_state.active_extruder_temperature()),
+            gcode_running,
+            progress: match gcode_running && lines > 0 {
+                true => line as f32 / lines as f32,
+                false => 0.0,
+            },
+        }
+    }
+
+    /// spawns a tokio task to run print jobs.
+    /// fails if the queue is full, the running job is not counted
     pub async fn spawn_print_job(
         &self,
         id: Uuid,
+        filename: String,
         file: Arc<GcodeFile>,
         exlude_objects: Vec<String>,
-    ) {
+        metadata: JobMetadata,
+    ) -> Result<(), PrinterError> {
         let mut job_queue = self.print_job_queue.write().await;
 
+        if job_queue.len() >= self.max_queued_jobs {
+            return Err(PrinterError {
+                code: PrinterErrorCode::JobQueueFull,
+                message: format!("job queue is full ({} jobs)", self.max_queued_jobs),
+            });
+        }
+
         job_queue.push_back(PrintJob {
             id,
+            filename,
             file,
             start_timestamp: None,
             exlude_objects,
+            resume: None,
+            metadata,
         });
 
         if !self.is_gcode_running() {
             let _ = self.event_sender.send(PrinterEvent::RunNextPrintJob);
         }
+
+        return Ok(());
     }
 
-    /// runs a gcode string immediately
-    pub async fn run_gcode_string(&self, script: String) -> anyhow::Result<()> {
-        return self.vm.run_gcode_string(&script).await;
+    /// jobs waiting in queue, in the order they will run
+    pub async fn list_job_queue(&self) -> Vec<JobQueuePrintJob> {
+        self.print_job_queue
+            .read()
+            .await
+            .iter()
+            .map(|job| JobQueuePrintJob {
+                id: job.id.to_string(),
+                filename: job.filename.clone(),
+                estimated_time: job
+                    .file
+                    .meta
+                    .estimated_print_time
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+                thumbnail_path: String::new(),
+                operator: job.metadata.operator.clone().unwrap_or_default(),
+                notes: job.metadata.notes.clone().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// the running job, none if no job is running
+    pub fn current_job(&self) -> Option<CurrentJob> {
+        self.current_job.lock().unwrap().clone()
+    }
+
+    /// hold the running job after the current command, elapsed time stops counting
+    pub fn pause_print_job(&self) {
+        self.vm.pause();
+
+        if let Some(job) = self.current_job.lock().unwrap().as_mut() {
+            job.pause();
+        }
+    }
+
+    /// continue a job held by pause_print_job
+    pub fn unpause_print_job(&self) {
+        if let Some(job) = self.current_job.lock().unwrap().as_mut() {
+            job.unpause();
+        }
+
+        self.vm.unpause();
+    }
+
+    /// abort the running job and discard the rest of its commands.
+    /// unlike emergency_stop the printer stays ready, returns false if no job is running
+    pub async fn cancel_print_job(&self) -> bool {
+        let mut notifications = self.notifications.subscribe();
+
+        let id = {
+            let mut current_job = self.current_job.lock().unwrap();
+
+            let Some(job) = current_job.as_mut() else {
+                return false;
+            };
+
+            job.cancelled = true;
+
+            // the job task resumes the vm and queue once the job has stopped
+            self.action_queue.suspend();
+            self.vm.suspend();
+
+            job.id.to_string()
+        };
+
+        self.action_queue.clear().await;
+
+        loop {
+            match notifications.recv().await {
+                Ok(Notification::JobCancelled { job_id, .. }) if job_id == id => break,
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    if self.current_job().is_none_or(|j| j.id.to_string() != id) {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+
+        return true;
+    }
+
+    /// number of commands the running gcode file has completed
+    pub fn gcode_line(&self) -> usize {
+        self.action_state
+            .gcode_line
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// finished jobs, oldest first
+    pub async fn job_history(&self) -> Vec<JobHistoryEntry> {
+        self.job_history.read().await.iter().cloned().collect()
+    }
+
+    /// the next job will not start when the running job is finished
+    pub fn pause_job_queue(&self) {
+        self.job_queue_paused.store(true, Ordering::SeqCst);
+    }
+
+    /// start the next job if none is running
+    pub fn resume_job_queue(&self) {
+        self.job_queue_paused.store(false, Ordering::SeqCst);
+
+        let _ = self.event_sender.send(PrinterEvent::RunNextPrintJob);
+    }
+
+    /// power loss recovery, none unless enabled in config
+    pub fn recovery(&self) -> Option<&PowerLossRecovery> {
+        self.recovery.as_ref()
+    }
+
+    /// queue an interrupted job to continue from its saved state.
+    /// the job runs before any other queued job
+    pub async fn resume_print_job(&self, saved: SavedPrintState, file: Arc<GcodeFile>) {
+        let id = Uuid::parse_str(&saved.job_id).unwrap_or_else(|_| Uuid::new_v4());
+
+        self.print_job_queue.write().await.push_front(PrintJob {
+            id,
+            filename: saved.filename.clone(),
+            file,
+            start_timestamp: None,
+            exlude_objects: Vec::new(),
+            resume: Some(saved),
+            metadata: JobMetadata::default(),
+        });
+
+        if !self.is_gcode_running() {
+            let _ = self.event_sender.send(PrinterEvent::RunNextPrintJob);
+        }
     }
+
+    /// returns gcode execution timing statistics keyed by command
+    pub fn gcode_timings(&self) -> ahash::AHashMap<String, GcodeTiming> {
+        self.vm.timings()
+    }
+
+    pub fn filament_sensors(&self) -> &[FilamentSensor] {
+        &self.filament_sensors
+    }
+
+    /// update the state of a filament sensor.
+    /// when filament runs out during a print, the print is paused and runout_gcode is run
+    pub async fn set_filament_detected(
+        &self,
+        name: &str,
+        detected: bool,
+    ) -> Result<(), PrinterError> {
+        let Some(sensor) = self.filament_sensors.iter().find(|s| s.name == name) else {
+            return Err(PrinterError {
+                code: PrinterErrorCode::InvalidParameter,
+                message: format!("unknown filament sensor '{}'", name),
+            });
+        };
+
+        // runout is only handled while printing
+        if !sensor.set_filament_detected(detected) || !self.is_gcode_running() {
+            return Ok(());
+        }
+
+        log::warn!("filament runout detected by sensor '{}'", name);
+
+        if sensor.pause_on_runout {
+            self.pause_print_job();
+        }
+
+        if let Err(e) = self.run_gcode_string(sensor.runout_gcode.clone()).await {
+            return Err(PrinterError {
+                code: PrinterErrorCode::GcodeError,
+                message: e.to_string(),
+            });
+        }
+
+        return Ok(());
+    }
+
+    /// runs a gcode string immediately
+    pub async fn run_gcode_string(&self, script: String) -> anyhow::Result<()> {
+        // every line is logged with its response, as in run_gcode_line
+        return self
+            .vm
+            .run_gcode_string_with(&script, |line, re| {
+                if !line.trim().is_empty() {
+                    self.log.gcode(line.trim(), re);
+                }
+            })
+            .await;
+    }
+
+    /// runs a single line of gcode, returns the response of the command.
+    /// moves are queued until flush_gcode is called
+    pub async fn run_gcode_line(&self, line: &str) -> anyhow::Result<String> {
+        let re = self.vm.run_gcode_line(line).await;
+
+        self.log.gcode(line.trim(), re.as_ref().map(|r| r.as_str()));
+
+        return re;
+    }
+
+    /// wait for moves queued by run_gcode_line to be sent
+    pub async fn flush_gcode(&self) {
+        self.vm.flush().await;
+    }
+
+    /// move the toolhead by 'distance' [x, y, z] in mm at 'speed' mm/s.
+    /// moved axes must be homed and stay within their soft limits,
+    /// a jog longer than [jog] max_distance on any axis is refused
+    pub async fn jog(&self, distance: [f32; 3], speed: f32) -> Result<(), PrinterError> {
+        if self.is_gcode_running() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: "cannot jog while gcode is running".to_string(),
+            });
+        }
+
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(PrinterError {
+                code: PrinterErrorCode::InvalidParameter,
+                message: format!("invalid jog speed {}", speed),
+            });
+        }
+
+        let state = &self.action_state;
+        let axes = [
+            ('x', &state.x_position, &state.x_origin),
+            ('y', &state.y_position, &state.y_origin),
+            ('z', &state.z_position, &state.z_origin),
+        ];
+
+        // nan leaves the axis in place
+        let mut target = [f32::NAN; 3];
+
+        for (i, (axis, position, origin)) in axes.into_iter().enumerate() {
+            let d = distance[i];
+
+            if d == 0.0 {
+                continue;
+            }
+
+            if !d.is_finite() || d.abs() > self.max_jog_distance {
+                return Err(PrinterError {
+                    code: PrinterErrorCode::InvalidParameter,
+                    message: format!(
+                        "jog of {}mm on {} exceeds the maximum of {}mm, see 'max_distance' in [jog]",
+                        d, axis, self.max_jog_distance
+                    ),
+                });
+            }
+
+            // the commanded position, including moves still queued
+            let position = position.load(Ordering::SeqCst);
+
+            if position.is_nan() {
+                return Err(PrinterError {
+                    code: PrinterErrorCode::NotHomed,
+                    message: format!("{} must be homed before jogging", axis),
+                });
+            }
+
+            let (min, max) = self.soft_limits[i];
+
+            if position + d < min || position + d > max {
+                return Err(PrinterError {
+                    code: PrinterErrorCode::InvalidParameter,
+                    message: format!(
+                        "jog to {}={} is outside the limits {} to {}",
+                        axis,
+                        position + d,
+                        min,
+                        max
+                    ),
+                });
+            }
+
+            // the queue takes gcode coordinates in the current positioning mode
+            target[i] = match state.absolute_position.load(Ordering::SeqCst) {
+                true => position + d - origin.load(Ordering::SeqCst),
+                false => d,
+            };
+        }
+
+        // the feedrate of the jog is not used by later gcode
+        let feedrate = state.feedrate.load(Ordering::SeqCst);
+
+        let re = self
+            .action_queue
+            .push(Action::Move(Move {
+                start_velocity: 0.0,
+                target_velocity: speed,
+                x: target[0],
+                y: target[1],
+                z: target[2],
+                e: f32::NAN,
+            }))
+            .await;
+
+        state.feedrate.store(feedrate, Ordering::SeqCst);
+
+        if let Err(e) = re {
+            return Err(PrinterError {
+                code: PrinterErrorCode::GcodeError,
+                message: e.to_string(),
+            });
+        }
+
+        self.action_queue.flush().await;
+
+        return Ok(());
+    }
+
+    /// home the given axes, all axes if none is given
+    pub async fn home(&self, x: bool, y: bool, z: bool) -> Result<(), PrinterError> {
+        if self.is_gcode_running() {
+            return Err(PrinterError {
+                code: PrinterErrorCode::PrintJobRunning,
+                message: "cannot home while gcode is running".to_string(),
+            });
+        }
+
+        let all = !x && !y && !z;
+
+        let re = self
+            .action_queue
+            .push(Action::Home {
+                x: x || all,
+                y: y || all,
+                z: z || all,
+            })
+            .await;
+
+        if let Err(e) = re {
+            return Err(PrinterError {
+                code: PrinterErrorCode::GcodeError,
+                message: e.to_string(),
+            });
+        }
+
+        return Ok(());
+    }
+}
+
+/// transition to a new state, the transition is logged
+fn transition(
+    state: &std::sync::Mutex<State>,
+    log: &PrinterLog,
+    notifications: &broadcast::Sender<Notification>,
+    new_state: State,
+) {
+    let mut state = state.lock().unwrap();
+
+    log.state(&state, &new_state);
+
+    let message = match &new_state {
+        State::Error { message, .. } => message.clone(),
+        _ => String::new(),
+    };
+
+    // no receiver is not an error
+    let _ = notifications.send(Notification::StateChanged {
+        state: new_state.api_state(),
+        message,
+    });
+
+    *state = new_state;
+}
+
+/// forwards actions to the mcu and handles mcu faults while the printer is ready
+struct EventLoop {
+    state: Arc<std::sync::Mutex<State>>,
+    log: Arc<PrinterLog>,
+    notifications: broadcast::Sender<Notification>,
+    action_state: Arc<ActionState>,
+    action_queue: Arc<ActionQueue>,
+    vm: Arc<GcodeVM>,
+    mcu: Arc<SimulatedMcu>,
+    kinematics: Arc<dyn Kinematics>,
+    print_job_queue: Arc<RwLock<VecDeque<PrintJob>>>,
+    current_job: Arc<std::sync::Mutex<Option<CurrentJob>>>,
+    job_history: Arc<RwLock<VecDeque<JobHistoryEntry>>>,
+    job_queue_paused: Arc<AtomicBool>,
+    manual_job_queue: bool,
+    recovery: Option<PowerLossRecovery>,
+    print_start: Option<PrintStart>,
+    events: Arc<tokio::sync::Mutex<UnboundedReceiver<PrinterEvent>>>,
+    faults: Arc<tokio::sync::Mutex<UnboundedReceiver<McuFault>>>,
+}
+
+impl EventLoop {
+    /// position after homing the given axes, the mcu motors are set to match
+    fn homed_position(&self, position: [f32; 3], homed: [bool; 3]) -> [f32; 3] {
+        let endstops = [
+            &self.action_state.x_endstop,
+            &self.action_state.y_endstop,
+            &self.action_state.z_endstop,
+        ];
+
+        let position = [0, 1, 2].map(|i| match homed[i] {
+            true => endstops[i].load(Ordering::SeqCst),
+            false => position[i],
+        });
+
+        self.mcu
+            .set_motor_position(self.kinematics.cartesian_to_motor(
+                position[0],
+                position[1],
+                position[2],
+            ));
+
+        return position;
+    }
+
+    async fn run(self) {
+        let mut events = self.events.lock().await;
+        let mut faults = self.faults.lock().await;
+
+        // the running print job
+        let mut job: Option<JoinHandle<()>> = None;
+        // toolhead position after the executed moves, taken as the origin until homed
+        let mut position = [0.0f32; 3];
+
+        loop {
+            tokio::select! {
+                // faults take priority over pending actions
+                biased;
+
+                Some(fault) = faults.recv() => {
+                    self.handle_fault(fault);
+                    return;
+                }
+                Some(event) = events.recv() => match event {
+                    PrinterEvent::Action(action) => {
+                        match &action {
+                            // moves are sent to the mcu in motor coordinates
+                            PrinterAction::KinematicMove(m) => {
+                                for motor_move in self.kinematics.motor_moves(position, m) {
+                                    self.mcu.execute_move(&motor_move);
+                                }
+                                position = [position[0] + m.x, position[1] + m.y, position[2] + m.z];
+                            }
+                            PrinterAction::Home { x, y, z } => {
+                                self.mcu.execute(&action);
+                                position = self.homed_position(position, [*x, *y, *z]);
+                            }
+                            _ => self.mcu.execute(&action),
+                        }
+                        self.action_queue.action_completed();
+                    }
+                    PrinterEvent::RunNextPrintJob => {
+                        if job.is_none() {
+                            job = self.run_next_print_job().await;
+                        }
+                    }
+                },
+                // start the next job in queue once the running job ends
+                _ = async {
+                    match job.as_mut() {
+                        Some(j) => { let _ = j.await; }
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    // wait for confirmation before the next job, e.g. to clear the bed
+                    if self.manual_job_queue {
+                        self.job_queue_paused.store(true, Ordering::SeqCst);
+                    }
+
+                    job = self.run_next_print_job().await;
+                }
+                else => return,
+            }
+        }
+    }
+
+    /// spawns the next queued job, returns none if the queue is empty
+    async fn run_next_print_job(&self) -> Option<JoinHandle<()>> {
+        if !matches!(*self.state.lock().unwrap(), State::Ready) {
+            return None;
+        }
+
+        if self.job_queue_paused.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let mut job = self.print_job_queue.write().await.pop_front()?;
+
+        let start_timestamp = crate::timestamp::unix_timestamp();
+        job.start_timestamp = Some(start_timestamp);
+
+        *self.current_job.lock().unwrap() = Some(CurrentJob {
+            id: job.id,
+            filename: job.filename.clone(),
+            start_timestamp,
+            estimated_print_time: job.file.meta.estimated_print_time,
+            active: Duration::ZERO,
+            resumed_at: Some(Instant::now()),
+            cancelled: false,
+        });
+
+        *self.action_state.objects.lock().unwrap() =
+            crate::gcode::objects::defined_objects(&job.file)
+                .into_iter()
+                .map(|o| o.name)
+                .collect();
+        *self.action_state.exclude_objects.write().await = job.exlude_objects.clone();
+        *self.action_state.current_object.lock().unwrap() = None;
+        self.action_state
+            .excluding
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+
+        let vm = self.vm.clone();
+        let notifications = self.notifications.clone();
+        let action_state = self.action_state.clone();
+        let action_queue = self.action_queue.clone();
+        let recovery = self.recovery.clone();
+        let print_start = self.print_start.clone();
+        let current_job = self.current_job.clone();
+        let job_history = self.job_history.clone();
+
+        let handle = tokio::spawn(async move {
+            let job_id = job.id.to_string();
+            let filename = job.filename.clone();
+
+            let _ = notifications.send(Notification::JobStarted {
+                job_id: job_id.clone(),
+                filename: filename.clone(),
+            });
+
+            let saver = recovery.as_ref().map(|r| {
+                r.spawn_saver(
+                    job_id.clone(),
+                    filename.clone(),
+                    action_state.clone(),
+                    action_queue.clone(),
+                )
+            });
+
+            let re = async {
+                let Some(resume) = &job.resume else {
+                    // a resumed job already ran its preamble
+                    if let Some(print_start) = &print_start {
+                        vm.run_gcode_string(&print_start.render(&job.file)?).await?;
+                    }
+
+                    return vm.run_gcode_file(&job.file).await;
+                };
+
+                resume.restore(&action_state, &action_queue).await?;
+
+                vm.run_gcode_file_from(&job.file, resume.line).await
+            }
+            .await;
+
+            if let Some(saver) = saver {
+                saver.abort();
+            }
+
+            // the job is no longer running, cancel_print_job can not mark it past this point
+            let cancelled = current_job
+                .lock()
+                .unwrap()
+                .take()
+                .is_some_and(|j| j.cancelled);
+
+            if cancelled {
+                // suspended by cancel_print_job to stop the job
+                action_queue.resume();
+                vm.resume();
+            }
+
+            // an aborted job keeps its saved state so it can be resumed
+            if let Some(recovery) = &recovery {
+                match &re {
+                    Ok(()) => recovery.remove().await,
+                    Err(_) if cancelled => recovery.remove().await,
+                    Err(_) => {
+                        recovery
+                            .save_aborted(&job_id, &filename, &action_state)
+                            .await
+                    }
+                }
+            }
+
+            let entry = JobHistoryEntry {
+                id: job_id.clone(),
+                filename: filename.clone(),
+                status: match (&re, cancelled) {
+                    (_, true) => "cancelled".to_string(),
+                    (Ok(()), false) => "completed".to_string(),
+                    (Err(_), false) => "failed".to_string(),
+                },
+                message: match cancelled {
+                    true => String::new(),
+                    false => re.as_ref().err().map(|e| e.to_string()).unwrap_or_default(),
+                },
+                end_time: crate::timestamp::unix_timestamp(),
+                operator: job.metadata.operator.clone().unwrap_or_default(),
+                notes: job.metadata.notes.clone().unwrap_or_default(),
+            };
+
+            {
+                let mut history = job_history.write().await;
+
+                if history.len() >= MAX_JOB_HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(entry);
+            }
+
+            let notification = match re {
+                _ if cancelled => Notification::JobCancelled { job_id, filename },
+                Ok(()) => Notification::JobFinished { job_id, filename },
+                Err(e) => Notification::JobFailed {
+                    job_id,
+                    filename,
+                    message: e.to_string(),
+                },
+            };
+
+            let _ = notifications.send(notification);
+        });
+
+        return Some(handle);
+    }
+
+    /// stops any running gcode and enters error state
+    fn handle_fault(&self, fault: McuFault) {
+        log::error!("mcu '{}' fault: {}", fault.mcu, fault.message);
+
+        self.action_queue.suspend();
+        self.vm.suspend();
+
+        transition(
+            &self.state,
+            &self.log,
+            &self.notifications,
+            State::error(
+                PrinterErrorCode::McuError,
+                PrinterErrorSource::Hardware,
+                format!("mcu '{}': {}", fault.mcu, fault.message),
+            ),
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_firmware_restart_clears_error() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+
+    // broken config, section is never closed
+    std::fs::write(&config_path, "[printer\nmax_velocity: 300\n").unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path.clone()).await;
+
+    assert!(matches!(printer.state(), State::Error { .. }));
+
+    // fix the config
+    std::fs::write(&config_path, "[printer]\nmax_velocity: 300\n").unwrap();
+
+    printer.firmware_restart(config_path).await;
+
+    assert!(matches!(printer.state(), State::Ready));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_clear_error() {
+    let mut printer = Printer::new();
+
+    // runtime error can be cleared
+    printer.set_state(State::error(
+        PrinterErrorCode::GcodeError,
+        PrinterErrorSource::Gcode,
+        "Unknown command: G999".to_string(),
+    ));
+
+    assert!(printer.clear_error().is_ok());
+    assert!(matches!(printer.state(), State::Ready));
+
+    // config error requires restart
+    printer.set_state(State::error(
+        PrinterErrorCode::PrinterConfigParseError,
+        PrinterErrorSource::Config,
+        String::new(),
+    ));
+
+    assert!(printer.clear_error().is_err());
+    assert!(matches!(printer.state(), State::Error { .. }));
+}
+
+#[tokio::test]
+async fn test_config_error_context() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer\n").unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    match printer.state() {
+        State::Error {
+            code,
+            source,
+            timestamp,
+            ..
+        } => {
+            assert!(matches!(code, PrinterErrorCode::PrinterConfigParseError));
+            assert!(matches!(source, PrinterErrorSource::Config));
+            assert!(timestamp <= now && now - timestamp < 5);
+        }
+        s => panic!("expected error state, got {:?}", s),
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_printer_log() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer]\nmax_velocity: 300\n").unwrap();
+
+    let mut printer = Printer::new();
+    printer
+        .open_log(dir.join("logs").join("printer.log"))
+        .unwrap();
+    printer.restart(config_path).await;
+
+    printer
+        .run_gcode_string("M302 P1\n\nG28\n".to_string())
+        .await
+        .unwrap();
+    assert!(printer.run_gcode_string("G999".to_string()).await.is_err());
+
+    let content = std::fs::read_to_string(dir.join("logs").join("printer.log")).unwrap();
+
+    // state transitions are logged
+    assert!(content.contains("Ready"));
+    // each line of a script is logged with its response
+    assert!(content.contains("M302 P1 -> Cold extrudes are allowed"));
+    assert!(content.lines().any(|l| l.ends_with("G28")));
+    // failing command is logged with its error
+    assert!(content.contains("G999 -> Unknown command"));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_filament_runout() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[filament_switch_sensor spool]\nrunout_gcode: G1 E-5 F1800\n",
+    )
+    .unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse(&"G1 E0.1\n".repeat(10000)).unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // extruder is hot enough to extrude
+    printer
+        .run_gcode_string("M109 S200".to_string())
+        .await
+        .unwrap();
+
+    // start the job
+    let vm = printer.vm.clone();
+    let job = tokio::spawn(async move { vm.run_gcode_file(&file).await });
+
+    let state = printer.action_state.clone();
+
+    while state.gcode_line.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    let e = state.e_position.load(Ordering::SeqCst);
+
+    printer.set_filament_detected("spool", false).await.unwrap();
+
+    assert!(printer.vm.is_paused());
+
+    // runout gcode retracted the filament
+    let retracted = state.e_position.load(Ordering::SeqCst) - e;
+    assert!((retracted + 5.0).abs() < 0.01);
+
+    let line = state.gcode_line.load(Ordering::SeqCst);
+    assert!(line < 10000);
+
+    // job does not advance while paused
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(printer.is_gcode_running());
+    assert_eq!(state.gcode_line.load(Ordering::SeqCst), line);
+
+    // job runs to completion once resumed
+    printer.vm.unpause();
+    job.await.unwrap().unwrap();
+    assert_eq!(state.gcode_line.load(Ordering::SeqCst), 10000);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_mcu_fault_halts_job() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer]\nmax_velocity: 300\n").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse(&"G1 E0.1\n".repeat(10000)).unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // extruder is hot enough to extrude
+    printer
+        .run_gcode_string("M109 S200".to_string())
+        .await
+        .unwrap();
+
+    // start the job
+    let vm = printer.vm.clone();
+    let job = tokio::spawn(async move { vm.run_gcode_file(&file).await });
+
+    let state = printer.action_state.clone();
+
+    while state.gcode_line.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    printer.mcu().report_fault("step underrun".to_string());
+
+    // job is aborted
+    assert!(job.await.unwrap().is_err());
+    assert!(state.gcode_line.load(Ordering::SeqCst) < 10000);
+
+    match printer.state() {
+        State::Error {
+            code,
+            source,
+            message,
+            ..
+        } => {
+            assert!(matches!(code, PrinterErrorCode::McuError));
+            assert!(matches!(source, PrinterErrorSource::Hardware));
+            assert!(message.contains("step underrun"));
+        }
+        s => panic!("expected error state, got {:?}", s),
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_resume_interrupted() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[printer]\nmax_velocity: 300\n\n[power_loss_recovery]\nsave_interval: 0.01\n\n[debug]\ngcode_timing: true\n",
+    )
+    .unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse(&"G1 X1 E0.01\n".repeat(10000)).unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path.clone()).await;
+
+    let state = printer.action_state.clone();
+
+    // homed and hot
+    state.x_position.store(0.0, Ordering::SeqCst);
+    state.y_position.store(0.0, Ordering::SeqCst);
+    state.z_position.store(0.2, Ordering::SeqCst);
+    printer
+        .run_gcode_string("M109 S200".to_string())
+        .await
+        .unwrap();
+
+    let mut notifications = printer.subscribe();
+
+    printer
+        .spawn_print_job(
+            Uuid::new_v4(),
+            "cube.gcode".to_string(),
+            file.clone(),
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    while state.gcode_line.load(Ordering::SeqCst) == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    // hold the job so the saved line is stable, then cut the power
+    printer.vm.pause();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    printer.emergency_stop();
+
+    loop {
+        if let Notification::JobFailed { .. } = notifications.recv().await.unwrap() {
+            break;
+        }
+    }
+
+    let recovery = printer.recovery().unwrap().clone();
+    let saved = recovery.load().await.unwrap();
+
+    assert!(saved.line > 0 && saved.line < 10000);
+    assert_eq!(saved.filename, "cube.gcode");
+    assert_eq!(saved.extruder_target, 200.0);
+    assert_eq!(saved.position[2], Some(0.2));
+
+    drop(printer);
+
+    // power is back
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    let mut notifications = printer.subscribe();
+
+    printer.resume_print_job(saved.clone(), file).await;
+
+    loop {
+        match notifications.recv().await.unwrap() {
+            Notification::JobFinished { job_id, .. } => {
+                assert_eq!(job_id, saved.job_id);
+                break;
+            }
+            Notification::JobFailed { message, .. } => panic!("resume failed: {}", message),
+            _ => {}
+        }
+    }
+
+    // the extruder was reheated before resuming
+    assert_eq!(printer.action_state.active_extruder_target(), 200.0);
+    assert_eq!(printer.action_state.active_extruder_temperature(), 200.0);
+
+    // only the remaining commands ran
+    let timings = printer.gcode_timings();
+    assert_eq!(timings["g1"].count as usize, 10000 - saved.line);
+    assert_eq!(
+        printer.action_state.gcode_line.load(Ordering::SeqCst),
+        10000
+    );
+
+    // finished jobs are forgotten
+    assert!(recovery.load().await.is_none());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_max_queued_jobs() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[job_queue]\nmax_queued_jobs: 2\n").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // the running job waits before its first command
+    printer.vm.pause();
+
+    let queue = async |printer: &Printer| {
+        printer
+            .spawn_print_job(
+                Uuid::new_v4(),
+                "job.gcode".to_string(),
+                file.clone(),
+                Vec::new(),
+                JobMetadata::default(),
+            )
+            .await
+    };
+
+    queue(&printer).await.unwrap();
+
+    while !printer.is_gcode_running() {
+        tokio::task::yield_now().await;
+    }
+
+    // running job does not count against the limit
+    queue(&printer).await.unwrap();
+    queue(&printer).await.unwrap();
+
+    let err = queue(&printer).await.unwrap_err();
+    assert!(matches!(err.code, PrinterErrorCode::JobQueueFull));
+    assert_eq!(printer.print_job_queue.read().await.len(), 2);
+
+    // queue drains once jobs run
+    printer.vm.unpause();
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !printer.print_job_queue.read().await.is_empty() || printer.is_gcode_running() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(
+        printer
+            .action_state
+            .allow_cold_extrude
+            .load(Ordering::SeqCst)
+    );
+    queue(&printer).await.unwrap();
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_manual_job_queue() {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[job_queue]\nmode: manual\n").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    let mut notifications = printer.subscribe();
+
+    for name in ["first.gcode", "second.gcode"] {
+        printer
+            .spawn_print_job(
+                Uuid::new_v4(),
+                name.to_string(),
+                file.clone(),
+                Vec::new(),
+                JobMetadata::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    // next job to start or finish
+    let mut next_event = async || loop {
+        match notifications.recv().await {
+            Ok(Notification::JobStarted { filename, .. }) => return format!("start {}", filename),
+            Ok(Notification::JobFinished { filename, .. }) => return format!("end {}", filename),
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => panic!(),
+        }
+    };
+
+    assert_eq!(next_event().await, "start first.gcode");
+    assert_eq!(next_event().await, "end first.gcode");
+
+    // second job waits for confirmation
+    let waited = tokio::time::timeout(Duration::from_millis(200), next_event()).await;
+    assert!(waited.is_err());
+    assert_eq!(printer.print_job_queue.read().await.len(), 1);
+
+    printer.resume_job_queue();
+
+    assert_eq!(next_event().await, "start second.gcode");
+    assert_eq!(next_event().await, "end second.gcode");
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_job_metadata_history() {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    let mut notifications = printer.subscribe();
+
+    // keep the job in queue until it is listed
+    printer.pause_job_queue();
+
+    let metadata = JobMetadata {
+        operator: Some("alice".to_string()),
+        notes: Some("PETG, check first layer".to_string()),
+    };
+
+    printer
+        .spawn_print_job(
+            Uuid::new_v4(),
+            "cube.gcode".to_string(),
+            file,
+            Vec::new(),
+            metadata,
+        )
+        .await
+        .unwrap();
+
+    let queue = printer.list_job_queue().await;
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].operator, "alice");
+    assert_eq!(queue[0].notes, "PETG, check first layer");
+
+    printer.resume_job_queue();
+
+    loop {
+        match notifications.recv().await {
+            Ok(Notification::JobFinished { .. }) => break,
+            Ok(Notification::JobFailed { message, .. }) => panic!("{}", message),
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => panic!(),
+        }
+    }
+
+    let history = printer.job_history().await;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].id, queue[0].id);
+    assert_eq!(history[0].status, "completed");
+    assert_eq!(history[0].operator, "alice");
+    assert_eq!(history[0].notes, "PETG, check first layer");
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_job_start_timestamp() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // hold the job so it is still running when checked
+    printer.vm.pause();
+
+    assert!(printer.current_job().is_none());
+
+    let id = Uuid::new_v4();
+
+    printer
+        .spawn_print_job(
+            id,
+            "cube.gcode".to_string(),
+            file,
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    let job = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(job) = printer.current_job() {
+                return job;
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let now = crate::timestamp::unix_timestamp();
+
+    assert_eq!(job.id, id);
+    assert!(job.start_timestamp <= now && now - job.start_timestamp <= 1);
+
+    printer.vm.unpause();
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_paused_elapsed() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // hold the job so it is still running when checked
+    printer.vm.pause();
+
+    printer
+        .spawn_print_job(
+            Uuid::new_v4(),
+            "cube.gcode".to_string(),
+            file,
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    while printer.current_job().is_none() {
+        tokio::task::yield_now().await;
+    }
+
+    printer.pause_print_job();
+
+    let paused = printer.current_job().unwrap();
+    assert!(paused.is_paused());
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(printer.current_job().unwrap().elapsed(), paused.elapsed());
+
+    printer.unpause_print_job();
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_extruder_count() {
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[extruder]\nmax_temp: 280\n\n[extruder1]\nmax_temp: 280\n",
+    )
+    .unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    assert_eq!(
+        printer.action_state.extruder_count.load(Ordering::SeqCst),
+        2
+    );
+
+    printer.run_gcode_line("T1").await.unwrap();
+    assert_eq!(
+        printer.action_state.active_extruder.load(Ordering::SeqCst),
+        1
+    );
+
+    let err = printer.run_gcode_line("T3").await.unwrap_err();
+    assert!(err.to_string().contains("extruder 3 does not exist"));
+    assert_eq!(
+        printer.action_state.active_extruder.load(Ordering::SeqCst),
+        1
+    );
+
+    // temperatures of missing extruders are rejected
+    let err = printer
+        .action_queue
+        .push(super::action::Action::SetExtruderTemp {
+            index: 2,
+            temp: 200.0,
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("extruder 2 does not exist"));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_print_start_gcode() {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[print_start]\ngcode:\n  M302 P1\n  SAVE_GCODE_STATE NAME=nozzle_{EXTRUDER_TEMP}\n",
+    )
+    .unwrap();
+
+    // the first command fails unless the print start gcode ran before it
+    let file = Arc::new(
+        GcodeFile::blocking_parse(
+            "RESTORE_GCODE_STATE NAME=nozzle_215\nM302 P0\n; first_layer_temperature = 215\n",
+        )
+        .unwrap(),
+    );
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    let mut notifications = printer.subscribe();
+
+    printer
+        .spawn_print_job(
+            Uuid::new_v4(),
+            "cube.gcode".to_string(),
+            file,
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    loop {
+        match notifications.recv().await {
+            Ok(Notification::JobFinished { .. }) => break,
+            Ok(Notification::JobFailed { message, .. }) => panic!("{}", message),
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => panic!(),
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_diagnostics_report() {
+    use gantry_api::PrinterDiagnosticStatus;
+    use std::sync::atomic::Ordering;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[extruder]\nmin_extrude_temp: 170\n\n[heater_bed]\nmax_temp: 120\n",
+    )
+    .unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // homed
+    for axis in [
+        &printer.action_state.x_position,
+        &printer.action_state.y_position,
+        &printer.action_state.z_position,
+    ] {
+        axis.store(10.0, Ordering::SeqCst);
+    }
+
+    let report = printer.run_diagnostics().await.unwrap();
+    let status = |subsystem: &str| {
+        report
+            .iter()
+            .find(|d| d.subsystem == subsystem)
+            .map(|d| d.status)
+    };
+
+    assert_eq!(status("endstops"), Some(PrinterDiagnosticStatus::Passed));
+    assert_eq!(
+        status("heater extruder"),
+        Some(PrinterDiagnosticStatus::Passed)
+    );
+    assert_eq!(
+        status("heater heater_bed"),
+        Some(PrinterDiagnosticStatus::Passed)
+    );
+    assert_eq!(status("motion"), Some(PrinterDiagnosticStatus::Passed));
+    assert_eq!(printer.action_state.z_position.load(Ordering::SeqCst), 10.0);
+
+    // a stuck endstop fails its check
+    printer.mcu.set_endstop(2, true);
+
+    let report = printer.run_diagnostics().await.unwrap();
+    let endstops = report.iter().find(|d| d.subsystem == "endstops").unwrap();
+
+    assert_eq!(endstops.status, PrinterDiagnosticStatus::Failed);
+    assert!(endstops.message.contains('z'));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_motion_thread() {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer]\nmotion_thread: true\n").unwrap();
+
+    let file = Arc::new(GcodeFile::blocking_parse("M302 P1\n").unwrap());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path.clone()).await;
+
+    // thread the motion runtime drives its tasks on
+    let motion_thread_id = async |printer: &Printer| match &printer.motion_thread {
+        Some(thread) => Some(
+            thread
+                .spawn_task(async { std::thread::current().id() })
+                .await
+                .unwrap(),
+        ),
+        None => None,
+    };
+
+    let thread_id = motion_thread_id(&printer).await.unwrap();
+    assert_ne!(thread_id, std::thread::current().id());
+
+    // jobs are started by the event loop on the motion thread
+    let mut notifications = printer.subscribe();
+
+    printer
+        .spawn_print_job(
+            Uuid::new_v4(),
+            "job.gcode".to_string(),
+            file,
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    let finished = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match notifications.recv().await {
+                Ok(Notification::JobFinished { .. }) => return,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!(),
+            }
+        }
+    })
+    .await;
+    assert!(finished.is_ok());
+
+    // the thread is kept across restarts
+    printer.restart(config_path.clone()).await;
+    assert_eq!(motion_thread_id(&printer).await, Some(thread_id));
+
+    // and stopped when disabled
+    std::fs::write(&config_path, "[printer]\nmotion_thread: false\n").unwrap();
+    printer.restart(config_path).await;
+    assert!(motion_thread_id(&printer).await.is_none());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_corexy_moves_reach_mcu() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[printer]\nkinematics: corexy\n\n[stepper_x]\nrotation_distance: 40\nmicrosteps: 32\n",
+    )
+    .unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path.clone()).await;
+
+    printer
+        .run_gcode_string("M302 P1\nG28\nG1 Y10 E2 F6000\n".to_string())
+        .await
+        .unwrap();
+    printer.action_queue.wait_drained().await;
+
+    let motors = printer.mcu().motor_position();
+    assert!((motors.a - 10.0).abs() < 1e-3);
+    assert!((motors.b + 10.0).abs() < 1e-3);
+
+    // translated back to the toolhead position
+    let state = printer.mcu_state();
+    assert!(state.toolhead_position[0].abs() < 1e-3);
+    assert!((state.toolhead_position[1] - 10.0).abs() < 1e-3);
+    assert_eq!(state.extruder_position, 2.0);
+    // the a motor is configured by [stepper_x], b uses the default 80 steps per mm
+    assert_eq!(state.motor_steps[..2], [1600, -800]);
+
+    // steps per mm must be positive
+    std::fs::write(
+        &config_path,
+        "[printer]\nkinematics: corexy\n\n[stepper_y]\nrotation_distance: 0\n",
+    )
+    .unwrap();
+    printer.restart(config_path.clone()).await;
+
+    assert!(matches!(printer.state(), State::Error { .. }));
+
+    // unknown kinematics is a config error
+    std::fs::write(&config_path, "[printer]\nkinematics: scara\n").unwrap();
+    printer.restart(config_path).await;
+
+    assert!(matches!(printer.state(), State::Error { .. }));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_delta_config_validation() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+
+    // arm_length is missing
+    std::fs::write(
+        &config_path,
+        "[printer]\nkinematics: delta\ndelta_radius: 120\n",
+    )
+    .unwrap();
+
+    let mut printer = Printer::new();
+    printer.restart(config_path.clone()).await;
+
+    match printer.state() {
+        State::Error { code, message, .. } => {
+            assert!(matches!(code, PrinterErrorCode::PrinterConfigParseError));
+            assert!(message.contains("arm_length"));
+        }
+        s => panic!("expected error state, got {:?}", s),
+    }
+
+    std::fs::write(
+        &config_path,
+        "[printer]\nkinematics: delta\ndelta_radius: 120\n\n[stepper_a]\narm_length: 250\n",
+    )
+    .unwrap();
+    printer.restart(config_path).await;
+
+    assert!(matches!(printer.state(), State::Ready));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_restart_starts_event_loop() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer]\nmax_velocity: 300\n").unwrap();
+
+    let mut printer = Printer::new();
+    assert!(matches!(printer.state(), State::Startup));
+    assert!(printer.event_loop_handle.is_none());
+
+    printer.restart(config_path).await;
+
+    assert!(matches!(printer.state(), State::Ready));
+    assert!(!printer.event_loop_handle.as_ref().unwrap().is_finished());
+
+    // actions are consumed by the event loop
+    printer.run_gcode_string("G28\n".to_string()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), printer.action_queue.wait_drained())
+        .await
+        .unwrap();
+    assert!(printer.mcu().executed_count() > 0);
+
+    printer.emergency_stop();
+
+    assert!(matches!(printer.state(), State::Shutdown));
+    assert!(printer.event_loop_handle.is_none());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_job_queue_runs_to_completion() {
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "").unwrap();
+
+    let file = Arc::new(
+        GcodeFile::blocking_parse(concat!(
+            "M302 P1\n",
+            "G28\n",
+            "EXCLUDE_OBJECT_START NAME=part_1\n",
+            "G1 X10 E1\n",
+            "EXCLUDE_OBJECT_END NAME=part_1\n",
+            "G1 Y10 E1\n",
+        ))
+        .unwrap(),
+    );
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // held until both jobs are queued
+    printer.vm.pause();
+
+    for name in ["first.gcode", "second.gcode"] {
+        printer
+            .spawn_print_job(
+                Uuid::new_v4(),
+                name.to_string(),
+                file.clone(),
+                vec!["part_1".to_string()],
+                JobMetadata::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    // the second job waits for the first
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while printer.current_job().is_none() {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(printer.current_job().unwrap().filename, "first.gcode");
+    assert_eq!(printer.print_job_queue.read().await.len(), 1);
+
+    printer.vm.unpause();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while printer.job_history().await.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let history = printer.job_history().await;
+    assert!(history.iter().all(|j| j.status == "completed"));
+    assert!(history.iter().any(|j| j.filename == "second.gcode"));
+    assert!(printer.print_job_queue.read().await.is_empty());
+    assert!(!printer.is_gcode_running());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn test_config_keys_in_schema() {
+    use crate::config::READ_KEYS;
+    use crate::config::schema::CONFIG_SCHEMA;
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // every section in the schema, delta kinematics reads the most keys
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(
+        &config_path,
+        "[printer]\nkinematics: delta\ndelta_radius: 120\n\n\
+        [stepper_a]\narm_length: 250\n\n[stepper_b]\nangle: 330\n\n[stepper_c]\nangle: 90\n\n\
+        [stepper_x]\nposition_max: 200\n\n[stepper_y]\nposition_max: 200\n\n\
+        [stepper_z]\nposition_max: 200\n\n[extruder]\nmax_temp: 250\n\n\
+        [heater_bed]\nmax_temp: 100\n\n[temperature_sensor chamber]\nmax_temp: 60\n\n\
+        [filament_switch_sensor runout]\npause_on_runout: false\n\n\
+        [exclude_object]\nz_hop: 1\n\n[job_queue]\nmode: auto\n\n[jog]\nmax_distance: 50\n\n\
+        [print_start]\ngcode: G28\n\n[power_loss_recovery]\nenabled: false\n\n\
+        [reporting]\nunit: mm\n\n[debug]\ngcode_timing: false\n",
+    )
+    .unwrap();
+
+    READ_KEYS.with_borrow_mut(|keys| keys.clear());
+
+    let mut printer = Printer::new();
+    printer.restart(config_path).await;
+
+    // restart returns early on errors, before all keys are read
+    assert!(matches!(printer.state(), State::Ready));
+
+    // keys read through a variable are recorded too
+    let read = READ_KEYS.with_borrow_mut(std::mem::take);
+    assert!(
+        read.iter()
+            .any(|(s, k, _)| s == "printer" && k == "max_velocity")
+    );
+    assert!(
+        read.iter()
+            .any(|(s, k, _)| s == "extruder" && k == "filament_diameter")
+    );
+
+    for (section, key, value_type) in read {
+        let schema = CONFIG_SCHEMA
+            .iter()
+            .find(|s| s.name == section)
+            .unwrap_or_else(|| panic!("[{}] is not in CONFIG_SCHEMA", section));
+
+        let key_schema = schema
+            .keys
+            .iter()
+            .find(|k| k.name == key)
+            .unwrap_or_else(|| panic!("[{}] {} is not in CONFIG_SCHEMA", section, key));
+
+        assert_eq!(key_schema.value_type, value_type, "[{}] {}", section, key);
+    }
+
+    let _ = std::fs::remove_dir_all(dir);
 }
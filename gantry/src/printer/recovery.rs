@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::config::PrinterConfig;
+
+use super::action::{Action, ActionQueue, ActionState, Move};
+
+/// progress of the running print job, persisted so it can be resumed after power loss
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPrintState {
+    pub job_id: String,
+    /// gcode filename relative to the gcodes directory
+    pub filename: String,
+    /// number of commands completed, the job resumes at this command
+    pub line: usize,
+    /// toolhead position [x, y, z, e], none if the axis was not homed
+    pub position: [Option<f32>; 4],
+    pub absolute_position: bool,
+    pub absolute_extrusion: bool,
+    /// target of the active extruder in celsius, 0 if it was off
+    #[serde(alias = "extruder_temperature")]
+    pub extruder_target: f32,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// power loss recovery, configured by [power_loss_recovery]
+#[derive(Debug, Clone)]
+pub struct PowerLossRecovery {
+    /// file the print state is saved to
+    pub path: PathBuf,
+    /// interval between saves
+    pub save_interval: Duration,
+}
+
+impl PowerLossRecovery {
+    /// disabled unless the section is present in config.
+    /// the state is saved next to the printer config
+    pub fn from_config(config: &PrinterConfig, config_path: &Path) -> Option<Self> {
+        let section = config.get_section("power_loss_recovery", None)?;
+
+        if !section.get_bool("enabled").unwrap_or(true) {
+            return None;
+        }
+
+        let save_interval = section.get_number("save_interval").unwrap_or(5.0);
+
+        Some(Self {
+            path: config_path.with_file_name("print_state.json"),
+            save_interval: Duration::from_secs_f64(save_interval.max(0.01)),
+        })
+    }
+
+    /// read the saved state, none if no print was interrupted
+    pub async fn load(&self) -> Option<SavedPrintState> {
+        let data = tokio::fs::read(&self.path).await.ok()?;
+
+        return serde_json::from_slice(&data).ok();
+    }
+
+    pub async fn save(&self, state: &SavedPrintState) -> std::io::Result<()> {
+        let data = serde_json::to_vec(state)?;
+
+        crate::files::write_atomic(&self.path, &data).await
+    }
+
+    /// forget the saved state, called when the job ends normally
+    pub async fn remove(&self) {
+        let _ = tokio::fs::remove_file(&self.path).await;
+    }
+
+    /// periodically save the progress of a job until aborted
+    pub fn spawn_saver(
+        &self,
+        job_id: String,
+        filename: String,
+        action_state: Arc<ActionState>,
+        action_queue: Arc<ActionQueue>,
+    ) -> JoinHandle<()> {
+        let recovery = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(recovery.save_interval);
+
+            loop {
+                interval.tick().await;
+
+                let state = SavedPrintState::sample(&job_id, &filename, &action_state);
+
+                // an emergency stop suspends the queue before turning the heaters off,
+                // the aborted job is saved by save_aborted once it ends
+                if action_queue.is_suspended() {
+                    continue;
+                }
+
+                recovery.save_logged(&state).await;
+            }
+        })
+    }
+
+    /// save a print state, failures are only logged
+    async fn save_logged(&self, state: &SavedPrintState) {
+        if let Err(e) = self.save(state).await {
+            log::warn!("failed to save print state: {}", e);
+        }
+    }
+
+    /// save the progress of an aborted job. heaters are turned off by the abort,
+    /// so the extruder target of the last save of the job is kept
+    pub async fn save_aborted(&self, job_id: &str, filename: &str, action_state: &ActionState) {
+        let mut state = SavedPrintState::sample(job_id, filename, action_state);
+
+        if let Some(last) = self.load().await
+            && last.job_id == job_id
+        {
+            state.extruder_target = last.extruder_target;
+        }
+
+        self.save_logged(&state).await;
+    }
+}
+
+impl SavedPrintState {
+    fn sample(job_id: &str, filename: &str, state: &ActionState) -> Self {
+        let axis = |v: f32| match v.is_nan() {
+            true => None,
+            false => Some(v),
+        };
+
+        Self {
+            job_id: job_id.to_string(),
+            filename: filename.to_string(),
+            line: state.gcode_line.load(Ordering::SeqCst),
+            position: [
+                axis(state.x_position.load(Ordering::SeqCst)),
+                axis(state.y_position.load(Ordering::SeqCst)),
+                axis(state.z_position.load(Ordering::SeqCst)),
+                axis(state.e_position.load(Ordering::SeqCst)),
+            ],
+            absolute_position: state.absolute_position.load(Ordering::SeqCst),
+            absolute_extrusion: state.absolute_extrution.load(Ordering::SeqCst),
+            extruder_target: state.active_extruder_target(),
+            timestamp: crate::timestamp::unix_timestamp(),
+        }
+    }
+
+    /// reheat, home x and y and move back to the saved position.
+    /// z cannot be homed with a print on the bed, the saved height is trusted
+    pub async fn restore(&self, state: &ActionState, queue: &ActionQueue) -> anyhow::Result<()> {
+        let [x, y, z, e] = self.position;
+
+        if self.extruder_target > 0.0 {
+            queue
+                .push(Action::SetExtruderTempWait {
+                    index: 0,
+                    temp: self.extruder_target,
+                })
+                .await?;
+        }
+
+        // home x and y
+        state.x_position.store(0.0, Ordering::SeqCst);
+        state.y_position.store(0.0, Ordering::SeqCst);
+        state
+            .z_position
+            .store(z.unwrap_or(f32::NAN), Ordering::SeqCst);
+        state.e_position.store(e.unwrap_or(0.0), Ordering::SeqCst);
+
+        // moves to the saved position are relative to the homed position
+        state.absolute_position.store(false, Ordering::SeqCst);
+        state.absolute_extrution.store(false, Ordering::SeqCst);
+
+        if x.is_some() || y.is_some() {
+            queue
+                .push(Action::Move(Move {
+                    start_velocity: f32::NAN,
+                    target_velocity: f32::NAN,
+                    x: x.unwrap_or(f32::NAN),
+                    y: y.unwrap_or(f32::NAN),
+                    z: f32::NAN,
+                    e: f32::NAN,
+                }))
+                .await?;
+        }
+
+        queue.flush().await;
+
+        state
+            .absolute_position
+            .store(self.absolute_position, Ordering::SeqCst);
+        state
+            .absolute_extrution
+            .store(self.absolute_extrusion, Ordering::SeqCst);
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::PrinterConfig;
+
+/// a filament switch sensor, configured by [filament_switch_sensor <name>]
+#[derive(Debug)]
+pub struct FilamentSensor {
+    pub name: String,
+    /// pause the running print when filament runs out
+    pub pause_on_runout: bool,
+    /// gcode to run when filament runs out
+    pub runout_gcode: String,
+    /// switch state, true if filament is present
+    filament_detected: AtomicBool,
+}
+
+impl FilamentSensor {
+    /// read all filament sensors from config
+    pub fn from_config(config: &PrinterConfig) -> Vec<Self> {
+        config
+            .sections
+            .iter()
+            .filter(|s| s.prefix_name == "filament_switch_sensor")
+            .map(|s| Self {
+                name: s
+                    .suffix_name
+                    .clone()
+                    .unwrap_or_else(|| s.prefix_name.clone()),
+                pause_on_runout: s.get_bool("pause_on_runout").unwrap_or(true),
+                runout_gcode: s.get_string("runout_gcode").unwrap_or_default().to_string(),
+                filament_detected: AtomicBool::new(true),
+            })
+            .collect()
+    }
+
+    pub fn filament_detected(&self) -> bool {
+        self.filament_detected.load(Ordering::SeqCst)
+    }
+
+    /// update the switch state, returns true if filament just ran out
+    pub fn set_filament_detected(&self, detected: bool) -> bool {
+        let was_detected = self.filament_detected.swap(detected, Ordering::SeqCst);
+
+        return was_detected && !detected;
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use tokio::sync::Mutex;
+
+/// user tags of gcode files, stored as an index in the printer directory
+/// so the gcodes directory only holds gcode files
+#[derive(Debug)]
+pub struct FileTags {
+    /// index file, rewritten whenever tags change
+    path: PathBuf,
+    /// tags by filename relative to the gcodes directory
+    tags: Mutex<BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl FileTags {
+    /// read the index, a missing or invalid index has no tags
+    pub async fn load(path: PathBuf) -> Self {
+        let tags = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::warn!("ignoring invalid file tags '{}': {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path,
+            tags: Mutex::new(tags),
+        }
+    }
+
+    /// replace the tags of a file, tags are trimmed and lowercased.
+    /// returns the stored tags
+    pub async fn set(&self, filename: &str, tags: &[String]) -> std::io::Result<Vec<String>> {
+        let tags: BTreeSet<String> = tags
+            .iter()
+            .map(|t| t.trim().to_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let mut index = self.tags.lock().await;
+
+        if tags.is_empty() {
+            index.remove(filename);
+        } else {
+            index.insert(filename.to_string(), tags.clone());
+        }
+
+        // lock is held while writing, so the file holds the last change
+        crate::files::write_atomic(&self.path, &serde_json::to_vec(&*index)?).await?;
+
+        return Ok(tags.into_iter().collect());
+    }
+
+    /// tags of every tagged file
+    pub async fn all(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.tags.lock().await.clone()
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use gantry_api::{PrinterError, PrinterErrorCode};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// uploads without a chunk for this duration are removed
+const UPLOAD_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// a file uploaded in chunks, assembled in a part file until finalized
+struct ChunkedUpload {
+    /// target of the upload
+    path: PathBuf,
+    /// partial file the chunks are written to
+    part_path: PathBuf,
+    /// size in bytes of the complete file
+    size: u64,
+    /// hex sha256 of the complete file, checked when finalized if set
+    sha256: Option<String>,
+    /// bytes received from the start of the file, chunks may not leave gaps
+    received: u64,
+    /// time of init or the last chunk
+    last_active: Instant,
+}
+
+/// uploads in progress of an instance, keyed by upload id
+pub struct ChunkedUploads {
+    /// directory of the part files, must be on the filesystem of the targets
+    dir: PathBuf,
+    uploads: Mutex<HashMap<String, Arc<Mutex<ChunkedUpload>>>>,
+}
+
+fn upload_error(code: PrinterErrorCode, message: impl Into<String>) -> PrinterError {
+    PrinterError {
+        code,
+        message: message.into(),
+    }
+}
+
+fn io_error(e: std::io::Error) -> PrinterError {
+    let code = match e.kind() {
+        std::io::ErrorKind::StorageFull => PrinterErrorCode::FileCapacityFull,
+        _ => PrinterErrorCode::GenericError,
+    };
+
+    upload_error(code, e.to_string())
+}
+
+impl ChunkedUploads {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// start an upload to 'path', returns the upload id
+    pub async fn init(
+        &self,
+        path: PathBuf,
+        size: u64,
+        sha256: Option<String>,
+    ) -> Result<String, PrinterError> {
+        self.remove_expired().await;
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let part_path = self.dir.join(format!("{}.part", id));
+
+        // held while the part file is untracked, so it is not removed as left over
+        let mut uploads = self.uploads.lock().await;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(io_error)?;
+        tokio::fs::File::create(&part_path)
+            .await
+            .map_err(io_error)?;
+
+        let upload = ChunkedUpload {
+            path,
+            part_path,
+            size,
+            sha256: sha256.map(|s| s.to_ascii_lowercase()),
+            received: 0,
+            last_active: Instant::now(),
+        };
+
+        uploads.insert(id.clone(), Arc::new(Mutex::new(upload)));
+
+        return Ok(id);
+    }
+
+    /// write a chunk at 'offset', returns the number of bytes received.
+    /// a chunk may be sent again, e.g. when its response was lost
+    pub async fn write_chunk(
+        &self,
+        id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<u64, PrinterError> {
+        let upload = self.get(id).await?;
+        let mut upload = upload.lock().await;
+
+        if offset > upload.received {
+            return Err(upload_error(
+                PrinterErrorCode::UploadInvalid,
+                format!(
+                    "chunk at offset {} leaves a gap, {} bytes received",
+                    offset, upload.received
+                ),
+            ));
+        }
+
+        let end = offset + data.len() as u64;
+
+        if end > upload.size {
+            return Err(upload_error(
+                PrinterErrorCode::UploadInvalid,
+                format!("chunk ends at {} beyond the file size {}", end, upload.size),
+            ));
+        }
+
+        let re: std::io::Result<()> = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&upload.part_path)
+                .await?;
+
+            file.seek(SeekFrom::Start(offset)).await?;
+            file.write_all(data).await?;
+            file.flush().await
+        }
+        .await;
+
+        re.map_err(io_error)?;
+
+        upload.received = upload.received.max(end);
+        upload.last_active = Instant::now();
+
+        return Ok(upload.received);
+    }
+
+    /// check the size and checksum and move the file to its target.
+    /// the upload is removed unless the file is incomplete, so missing chunks can be sent
+    pub async fn finalize(&self, id: &str) -> Result<(), PrinterError> {
+        let upload = self.get(id).await?;
+        let upload = upload.lock().await;
+
+        if upload.received != upload.size {
+            return Err(upload_error(
+                PrinterErrorCode::UploadInvalid,
+                format!(
+                    "upload is incomplete, {} of {} bytes received",
+                    upload.received, upload.size
+                ),
+            ));
+        }
+
+        let re = async {
+            if let Some(expected) = &upload.sha256 {
+                let actual = sha256_file(&upload.part_path).await.map_err(io_error)?;
+
+                if &actual != expected {
+                    return Err(upload_error(
+                        PrinterErrorCode::UploadInvalid,
+                        format!("checksum mismatch, expected {} got {}", expected, actual),
+                    ));
+                }
+            }
+
+            if let Some(parent) = upload.path.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+            }
+
+            // the part file is on the same filesystem, readers never see a partial file
+            tokio::fs::rename(&upload.part_path, &upload.path)
+                .await
+                .map_err(io_error)
+        }
+        .await;
+
+        if re.is_err() {
+            let _ = tokio::fs::remove_file(&upload.part_path).await;
+        }
+
+        // removed last, the part file is never mistaken for a left over
+        self.uploads.lock().await.remove(id);
+
+        return re;
+    }
+
+    async fn get(&self, id: &str) -> Result<Arc<Mutex<ChunkedUpload>>, PrinterError> {
+        self.uploads.lock().await.get(id).cloned().ok_or_else(|| {
+            upload_error(
+                PrinterErrorCode::UploadNotFound,
+                format!("upload '{}' does not exist or has expired", id),
+            )
+        })
+    }
+
+    /// remove expired uploads and part files left over from a previous run
+    async fn remove_expired(&self) {
+        let mut uploads = self.uploads.lock().await;
+
+        uploads.retain(|_, upload| match upload.try_lock() {
+            Ok(upload) => upload.last_active.elapsed() < UPLOAD_EXPIRY,
+            // a chunk is being written
+            Err(_) => true,
+        });
+
+        let Ok(mut entries) = tokio::fs::read_dir(&self.dir).await else {
+            return;
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+
+            let tracked = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|id| uploads.contains_key(id));
+
+            if !tracked {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        }
+    }
+}
+
+/// hex encoded sha256 of a file
+async fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = file.read(&mut buffer).await?;
+
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..n]);
+    }
+
+    return Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect());
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+
+use crate::outbound::{timeouts, with_timeout};
+
+use super::notify::Notification;
+
+/// payloads waiting for delivery per url, newer payloads are dropped when full
+const WEBHOOK_QUEUE_SIZE: usize = 64;
+/// delivery attempts before a payload is dropped
+const WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+/// delay before the first retry, doubled on each retry
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// json body posted to webhook urls
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    /// name of the printer
+    pub printer: String,
+    /// unix timestamp in seconds
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub notification: Notification,
+}
+
+/// post printer notifications to each url.
+/// every url has its own bounded queue, so a slow endpoint never blocks the printer
+/// or delays delivery to the other urls
+pub fn spawn_webhooks(
+    printer: String,
+    urls: Vec<String>,
+    mut notifications: broadcast::Receiver<Notification>,
+) {
+    let client = reqwest::Client::new();
+
+    let queues: Vec<mpsc::Sender<WebhookPayload>> = urls
+        .into_iter()
+        .map(|url| {
+            let (sender, reciever) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+            tokio::spawn(deliver(client.clone(), url, reciever));
+            sender
+        })
+        .collect();
+
+    tokio::spawn(async move {
+        loop {
+            let notification = match notifications.recv().await {
+                Ok(n) => n,
+                Err(RecvError::Lagged(n)) => {
+                    log::warn!("printer '{}': {} webhook events dropped", printer, n);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let payload = WebhookPayload {
+                printer: printer.clone(),
+                timestamp: crate::timestamp::unix_timestamp(),
+                notification,
+            };
+
+            for queue in &queues {
+                if queue.try_send(payload.clone()).is_err() {
+                    log::warn!("printer '{}': webhook queue full, event dropped", printer);
+                }
+            }
+        }
+    });
+}
+
+/// post payloads to the url in order, retrying with exponential backoff
+async fn deliver(
+    client: reqwest::Client,
+    url: String,
+    mut reciever: mpsc::Receiver<WebhookPayload>,
+) {
+    while let Some(payload) = reciever.recv().await {
+        let mut delay = WEBHOOK_RETRY_DELAY;
+
+        for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+            let result = with_timeout("webhook", timeouts().webhook(), async {
+                client
+                    .post(&url)
+                    .json(&payload)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+
+                Ok(())
+            })
+            .await;
+
+            match result {
+                Ok(_) => break,
+                Err(e) if attempt == WEBHOOK_MAX_ATTEMPTS => {
+                    log::error!("webhook '{}' failed, event dropped: {}", url, e);
+                }
+                Err(e) => {
+                    log::warn!("webhook '{}' failed, retrying in {:?}: {}", url, delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_webhook_job_finished() {
+    use axum::Json;
+    use axum::routing::post;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    use super::printer::JobMetadata;
+    use crate::gcode::GcodeFile;
+
+    // mock endpoint forwarding the received bodies
+    let (body_sender, mut bodies) = mpsc::unbounded_channel::<serde_json::Value>();
+
+    let app = axum::Router::new().route(
+        "/hook",
+        post(async move |Json(body): Json<serde_json::Value>| {
+            let _ = body_sender.send(body);
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let dir = std::env::temp_dir().join(format!("gantry-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("printer.cfg");
+    std::fs::write(&config_path, "[printer]\nmax_velocity: 300\n").unwrap();
+
+    let mut printer = super::Printer::new();
+    printer.restart(config_path).await;
+
+    spawn_webhooks("printer".to_string(), vec![url], printer.subscribe());
+
+    let id = Uuid::new_v4();
+    let file = Arc::new(GcodeFile::blocking_parse("G1 X10 F3000\n").unwrap());
+
+    printer
+        .spawn_print_job(
+            id,
+            "cube.gcode".to_string(),
+            file,
+            Vec::new(),
+            JobMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+    // wait for the job to finish
+    let body = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let body = bodies.recv().await.unwrap();
+
+            if body["event"] == "job_finished" {
+                return body;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(body["printer"], "printer");
+    assert_eq!(body["job_id"], id.to_string());
+    assert_eq!(body["filename"], "cube.gcode");
+    assert!(body["timestamp"].as_u64().unwrap() > 0);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
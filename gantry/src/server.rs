@@ -1,10 +1,295 @@
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::Json;
 use axum::Router;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
+use gantry_api::{PrinterError, PrinterErrorCode, PrinterResult, PrinterStatus};
+
+use crate::config::schema::{CONFIG_SCHEMA, SectionSchema};
+use crate::printer::Instance;
 
 pub fn create_service_router() -> Router {
-    Router::new().route("/server_info", get(get_server_info))
+    Router::new()
+        .route("/server_info", get(get_server_info))
+        .route("/config_schema", get(get_config_schema))
+        .route("/dashboard", get(get_dashboard))
 }
 
 pub async fn get_server_info() -> String {
     "hello world".to_string()
 }
+
+/// known printer config sections and their keys
+pub async fn get_config_schema() -> Json<&'static [SectionSchema]> {
+    Json(CONFIG_SCHEMA)
+}
+
+/// compact status of all printers, unchanged status returns 304 when 'If-None-Match' matches
+pub async fn get_dashboard(headers: HeaderMap) -> Response {
+    let instances: Vec<Arc<Instance>> = crate::INSTANCES.read().await.values().cloned().collect();
+
+    dashboard(&instances, &headers).await
+}
+
+async fn dashboard(instances: &[Arc<Instance>], headers: &HeaderMap) -> Response {
+    let mut printers = BTreeMap::new();
+
+    for inst in instances {
+        printers.insert(inst.name.clone(), inst.get_status().await);
+    }
+
+    let etag = dashboard_etag(&printers);
+
+    // 'If-None-Match' may list several tags or '*'
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"));
+
+    let etag = HeaderValue::from_str(&etag).unwrap();
+
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    return ([(header::ETAG, etag)], Json(printers)).into_response();
+}
+
+lazy_static::lazy_static! {
+    /// random per process. status versions restart from 0 with gantry,
+    /// tags from before a restart must not match
+    static ref ETAG_NONCE: u128 = uuid::Uuid::new_v4().as_u128();
+}
+
+/// derived from the printer names and status versions
+fn dashboard_etag(printers: &BTreeMap<String, PrinterStatus>) -> String {
+    let mut hasher = DefaultHasher::new();
+    ETAG_NONCE.hash(&mut hasher);
+
+    for (name, status) in printers {
+        name.hash(&mut hasher);
+        status.version.hash(&mut hasher);
+    }
+
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// fallback for paths without a route
+pub async fn route_not_found(method: Method, uri: Uri) -> (StatusCode, Json<PrinterResult<()>>) {
+    let error = PrinterError {
+        code: PrinterErrorCode::RouteNotFound,
+        message: format!("no route for {} {}", method, uri.path()),
+    };
+
+    (StatusCode::NOT_FOUND, Json(PrinterResult::err(error)))
+}
+
+/// fallback for routes that do not accept the method
+pub async fn method_not_allowed(method: Method, uri: Uri) -> (StatusCode, Json<PrinterResult<()>>) {
+    let error = PrinterError {
+        code: PrinterErrorCode::MethodNotAllowed,
+        message: format!("{} is not allowed for {}", method, uri.path()),
+    };
+
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        Json(PrinterResult::err(error)),
+    )
+}
+
+/// answer errors with a status code and the bare error, see 'http.error_status'
+static ERROR_STATUS: AtomicBool = AtomicBool::new(false);
+
+/// default response mode of requests without an 'envelope' in 'Accept'
+pub fn set_error_status(enabled: bool) {
+    ERROR_STATUS.store(enabled, Ordering::Relaxed);
+}
+
+/// 'Accept: application/json; envelope=none' answers errors with a status code,
+/// 'envelope=result' always answers with 200 and the result envelope
+fn error_status_requested(headers: &HeaderMap, default: bool) -> bool {
+    let accept = match headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) {
+        Some(accept) => accept,
+        None => return default,
+    };
+
+    for param in accept.split([',', ';']) {
+        match param.trim().split_once('=') {
+            Some(("envelope", "none")) => return true,
+            Some(("envelope", "result")) => return false,
+            _ => {}
+        }
+    }
+
+    return default;
+}
+
+/// http status code of an error
+pub fn error_status_code(code: PrinterErrorCode) -> StatusCode {
+    use PrinterErrorCode::*;
+
+    match code {
+        None => StatusCode::OK,
+        AuthFailed | AuthRequired | AuthTokenInvalid | AuthTokenTimeout | RefreshTokenInvalid => {
+            StatusCode::UNAUTHORIZED
+        }
+        FileNotFound | RouteNotFound | UploadNotFound => StatusCode::NOT_FOUND,
+        MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+        PrinterConfigParseError
+        | GcodeParseError
+        | UploadInvalid
+        | InvalidParameter
+        | TemperatureOutOfRange
+        | PathTraversal => StatusCode::BAD_REQUEST,
+        ErrorState | ShutdownState | StartupState | PrintJobRunning | PrintJobNotRunning
+        | JobQueueFull | NotHomed => StatusCode::CONFLICT,
+        GenericError | GcodeError | FileReadError | FileCapacityFull | McuError => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        Timeout => StatusCode::GATEWAY_TIMEOUT,
+    }
+}
+
+/// replaces the result envelope of failed requests with a status code and the
+/// bare error when requested, successful responses are not changed
+pub async fn response_envelope(request: Request, next: Next) -> Response {
+    let error_status =
+        error_status_requested(request.headers(), ERROR_STATUS.load(Ordering::Relaxed));
+
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+
+    if !error_status || !is_json {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    // only an object with exactly 'error' and 'result' is an envelope
+    let error = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| match v {
+            serde_json::Value::Object(mut map) if map.len() == 2 && map.contains_key("result") => {
+                map.remove("error")
+            }
+            _ => None,
+        })
+        .and_then(|error| serde_json::from_value::<PrinterError>(error).ok());
+
+    match error {
+        Some(error) if !matches!(error.code, PrinterErrorCode::None) => {
+            return (error_status_code(error.code), Json(error)).into_response();
+        }
+        _ => return Response::from_parts(parts, Body::from(body)),
+    }
+}
+
+#[tokio::test]
+async fn test_config_schema() {
+    use tower::ServiceExt;
+
+    let response = create_service_router()
+        .oneshot(
+            axum::http::Request::get("/config_schema")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), axum::http::StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let schema: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    let printer = schema
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["name"] == "printer")
+        .unwrap();
+    let max_velocity = printer["keys"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|k| k["name"] == "max_velocity")
+        .unwrap();
+
+    assert_eq!(max_velocity["value_type"], "number");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_dashboard_etag() {
+    use std::time::Duration;
+
+    use crate::config::InstanceConfig;
+
+    let gantry_path = std::env::temp_dir().join(format!("gantry-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&gantry_path).unwrap();
+
+    let config = InstanceConfig {
+        uuid: uuid::Uuid::new_v4().as_u128(),
+        ..Default::default()
+    };
+    let inst =
+        Arc::new(Instance::create(0, "printer".to_string(), config, gantry_path.clone()).await);
+    let instances = [inst.clone()];
+
+    // wait for startup to settle
+    while let crate::printer::State::Startup = inst.state().await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = dashboard(&instances, &HeaderMap::new()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let etag = response.headers()[header::ETAG].clone();
+
+    // unchanged
+    let mut headers = HeaderMap::new();
+    headers.insert(header::IF_NONE_MATCH, etag.clone());
+
+    let response = dashboard(&instances, &headers).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // changed
+    let since = inst.get_status().await.version;
+    inst.emergency_stop().await;
+    inst.status_longpoll(since, Duration::from_secs(5)).await;
+
+    let response = dashboard(&instances, &headers).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[header::ETAG], etag);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["printer"]["state"], "Shutdown");
+
+    // the same versions hash differently in another process
+    let unsalted = format!("\"{:016x}\"", DefaultHasher::new().finish());
+    assert_ne!(dashboard_etag(&BTreeMap::new()), unsalted);
+
+    let _ = std::fs::remove_dir_all(gantry_path);
+}
@@ -0,0 +1,25 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// time since unix epoch, zero if the clock is set before the epoch
+pub fn unix_duration() -> Duration {
+    to_unix_duration(SystemTime::now())
+}
+
+/// seconds since unix epoch
+pub fn unix_timestamp() -> u64 {
+    unix_duration().as_secs()
+}
+
+/// seconds since unix epoch with sub second precision
+pub fn unix_timestamp_f64() -> f64 {
+    unix_duration().as_secs_f64()
+}
+
+/// seconds since unix epoch of a file time or any other system time
+pub fn to_unix_timestamp(time: SystemTime) -> u64 {
+    to_unix_duration(time).as_secs()
+}
+
+fn to_unix_duration(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default()
+}